@@ -1,10 +1,14 @@
-use std::fmt::{Display, Formatter};
+use std::{
+    collections::HashMap,
+    fmt::{Display, Formatter},
+};
 
 use moonlight_common::{
     ServerState,
     stream::control::{ControllerButtons, ControllerCapabilities, KeyModifiers, MouseButton},
-    stream::video::{ColorSpace, VideoFormats},
+    stream::video::{ColorSpace, SunshineHdrMetadata, VideoFormats},
 };
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use ts_rs::TS;
 
@@ -18,6 +22,39 @@ pub struct ConfigJs {
     pub path_prefix: String,
 }
 
+/// The body of every failed api request
+#[derive(Serialize, Deserialize, Debug, TS, Clone)]
+#[ts(export, export_to = EXPORT_PATH)]
+pub struct ApiError {
+    /// A human readable message
+    pub error: String,
+    pub kind: ApiErrorKind,
+    /// The errors which caused this error
+    pub detail: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, TS, Clone, Copy, PartialEq, Eq)]
+#[ts(export, export_to = EXPORT_PATH)]
+pub enum ApiErrorKind {
+    Internal,
+    BadRequest,
+    Unauthorized,
+    Forbidden,
+    NotFound,
+    Conflict,
+    RateLimited,
+    /// The server already runs the maximum amount of streams
+    ServerAtCapacity,
+    ShuttingDown,
+    HostNotFound,
+    /// The host is offline or didn't answer in time
+    HostUnreachable,
+    HostNotPaired,
+    HostPaired,
+    /// The host answered with an error
+    HostError,
+}
+
 #[derive(Serialize, Deserialize, Debug, TS, Clone)]
 #[ts(export, export_to = EXPORT_PATH)]
 pub struct PostLoginRequest {
@@ -74,6 +111,9 @@ pub struct UndetailedHost {
     pub paired: PairStatus,
     /// None if offline else the state
     pub server_state: Option<HostState>,
+    pub http_port: u16,
+    /// The https port reported by the host, None if offline
+    pub https_port: Option<u16>,
 }
 
 #[derive(Serialize, Deserialize, Debug, TS)]
@@ -163,6 +203,12 @@ pub struct DeleteHostQuery {
     pub host_id: u32,
 }
 
+#[derive(Serialize, Deserialize, Debug, TS)]
+#[ts(export, export_to = EXPORT_PATH)]
+pub struct DeletePairQuery {
+    pub host_id: u32,
+}
+
 #[derive(Serialize, Deserialize, Debug, TS)]
 #[ts(export, export_to = EXPORT_PATH)]
 pub struct PostPairRequest {
@@ -188,18 +234,33 @@ pub enum PostPairResponse2 {
 #[ts(export, export_to = EXPORT_PATH)]
 pub struct PostWakeUpRequest {
     pub host_id: u32,
+    /// Wait until the host answers, fails if it doesn't answer within the `wake_timeout` of the host config or 60 seconds
+    #[serde(default)]
+    pub wait_online: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, TS)]
+#[ts(export, export_to = EXPORT_PATH)]
+pub struct GetStreamQuery {
+    /// Resumes the stream of this token instead of starting a new one
+    #[serde(default)]
+    pub session_token: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Debug, TS)]
 #[ts(export, export_to = EXPORT_PATH)]
 pub struct GetAppsQuery {
     pub host_id: u32,
+    #[serde(default)]
+    pub force_refresh: bool,
 }
 
 #[derive(Serialize, Deserialize, Debug, TS)]
 #[ts(export, export_to = EXPORT_PATH)]
 pub struct GetAppsResponse {
     pub apps: Vec<App>,
+    /// The host couldn't be reached and the last known apps are returned
+    pub stale: bool,
 }
 
 #[derive(Serialize, Deserialize, Debug, TS)]
@@ -211,6 +272,20 @@ pub struct GetAppImageQuery {
     pub force_refresh: bool,
 }
 
+#[derive(Serialize, Deserialize, Debug, Clone, TS)]
+#[ts(export, export_to = EXPORT_PATH)]
+pub struct DiscoveredHost {
+    pub name: String,
+    pub address: String,
+    pub http_port: u16,
+}
+
+#[derive(Serialize, Deserialize, Debug, TS)]
+#[ts(export, export_to = EXPORT_PATH)]
+pub struct GetDiscoverResponse {
+    pub hosts: Vec<DiscoveredHost>,
+}
+
 #[derive(Serialize, Deserialize, Debug, TS)]
 #[ts(export, export_to = EXPORT_PATH)]
 pub struct PostCancelRequest {
@@ -220,6 +295,7 @@ pub struct PostCancelRequest {
 #[derive(Serialize, Deserialize, Debug, TS)]
 #[ts(export, export_to = EXPORT_PATH)]
 pub struct PostCancelResponse {
+    /// If the host quit the running app
     pub success: bool,
 }
 
@@ -279,6 +355,31 @@ pub struct GetUsersResponse {
     pub users: Vec<DetailedUser>,
 }
 
+/// A stream which is currently running, only visible to admins
+#[derive(Serialize, Deserialize, Debug, Clone, TS)]
+#[ts(export, export_to = EXPORT_PATH)]
+pub struct AdminSession {
+    pub session_id: u32,
+    pub user_id: u32,
+    pub user_name: String,
+    pub host_id: u32,
+    pub host_name: String,
+    pub app_id: u32,
+    pub app_title: String,
+    /// Unix timestamp in seconds
+    pub started_at: u64,
+    /// The resolution and fps the stream was negotiated with, none until the client sent its settings
+    pub video_mode: Option<StreamVideoMode>,
+    /// The bitrate the streamer sent the last few seconds
+    pub bitrate_kbps: u32,
+}
+
+#[derive(Serialize, Deserialize, Debug, TS)]
+#[ts(export, export_to = EXPORT_PATH)]
+pub struct GetAdminSessionsResponse {
+    pub sessions: Vec<AdminSession>,
+}
+
 #[derive(Serialize, Deserialize, Debug, TS)]
 #[ts(export, export_to = EXPORT_PATH)]
 pub struct DetailedRole {
@@ -296,7 +397,7 @@ pub struct UndetailedRole {
     pub name: String,
 }
 
-#[derive(Serialize, Deserialize, Debug, TS)]
+#[derive(Serialize, Deserialize, Debug, Clone, TS)]
 #[ts(export, export_to = EXPORT_PATH)]
 pub struct StreamSettings {
     pub bitrate_kbps: u32,
@@ -307,9 +408,30 @@ pub struct StreamSettings {
     /// This is using the [VideoFormats]
     pub supported_codecs: u32,
     pub hdr: bool,
+    /// Only forward the audio of the host, the video is still negotiated with the host but never sent to the browser
+    #[serde(default)]
+    pub audio_only: bool,
 }
 
-#[derive(Serialize, Deserialize, Debug, TS)]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, TS)]
+#[ts(export, export_to = EXPORT_PATH)]
+pub struct MouseConfig {
+    /// Multiplies every relative movement
+    pub sensitivity: f64,
+    /// Faster movements get scaled more, 0 disables acceleration
+    pub acceleration: f64,
+}
+
+/// The resolution and fps a client requests when launching a stream
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, TS)]
+#[ts(export, export_to = EXPORT_PATH)]
+pub struct StreamVideoMode {
+    pub width: u32,
+    pub height: u32,
+    pub fps: u32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, TS)]
 #[ts(export, export_to = EXPORT_PATH)]
 pub struct StreamPermissions {
     pub allow_add_hosts: bool,
@@ -452,6 +574,37 @@ pub enum StreamSignalingMessage {
     AddIceCandidate(RtcIceCandidate),
 }
 
+/// The layout of the keyboard used in the browser.
+/// The browser sends the position of a key, so keys are remapped to type the labelled character on a host with a qwerty layout.
+#[derive(Serialize, Deserialize, Debug, TS, JsonSchema, Clone, Copy, PartialEq, Eq, Default)]
+#[ts(export, export_to = EXPORT_PATH)]
+pub enum KeyboardLayout {
+    #[default]
+    #[serde(rename = "qwerty")]
+    Qwerty,
+    #[serde(rename = "qwertz")]
+    Qwertz,
+    #[serde(rename = "azerty")]
+    Azerty,
+}
+
+/// The audio channels requested from the host
+#[derive(Serialize, Deserialize, Debug, TS, JsonSchema, Clone, Copy, PartialEq, Eq, Default)]
+#[ts(export, export_to = EXPORT_PATH)]
+pub enum StreamAudioMode {
+    /// Requests as many channels as the browser can play, at most 7.1 surround and stereo if the browser doesn't report it.
+    /// The host sends at most the channels of its audio device
+    #[default]
+    #[serde(rename = "match_host")]
+    MatchHost,
+    #[serde(rename = "stereo")]
+    Stereo,
+    #[serde(rename = "surround_51")]
+    Surround51,
+    #[serde(rename = "surround_71")]
+    Surround71,
+}
+
 #[derive(Serialize, Deserialize, Debug, TS)]
 #[ts(export, export_to = EXPORT_PATH)]
 pub enum TransportType {
@@ -467,22 +620,48 @@ pub enum StreamClientMessage {
         app_id: u32,
         video_frame_queue_size: usize,
         audio_sample_queue_size: usize,
+        /// Overrides the resolution and fps of the [StreamSettings] for this stream,
+        /// it's clamped to what the host supports
+        video_mode: Option<StreamVideoMode>,
+        /// Overrides the audio mode of the host config for this stream
+        #[serde(default)]
+        audio_mode: Option<StreamAudioMode>,
+        /// The most audio channels the browser can play, used by [StreamAudioMode::MatchHost]
+        #[serde(default)]
+        audio_channels: Option<u32>,
+        /// Extra query parameters for the launch request of the host, e.g. to start the app with other arguments
+        launch_parameters: Option<HashMap<String, String>>,
     },
     WebRtc(StreamSignalingMessage),
     SetTransport(TransportType),
+    /// Text from the clipboard of the browser which is typed into the host
+    Clipboard(String),
+    /// How relative mouse movement is scaled before it's sent to the host
+    SetMouseConfig(MouseConfig),
+    /// Overrides the keyboard layout of the host config for this stream
+    SetKeyboardLayout(KeyboardLayout),
+    /// The browser didn't decode any frame of the negotiated video format,
+    /// the streamer restarts the stream once with H264
+    VideoDecodeFailed,
+    /// Asks the host for a new keyframe, e.g. when the picture is corrupted.
+    /// The streamer ignores requests that come in too quickly
+    RequestIdr,
     StartStream {
         settings: StreamSettings,
     },
 }
 
-#[derive(Serialize, Deserialize, Debug, TS, Clone, Default)]
+#[derive(Serialize, Deserialize, Debug, TS, JsonSchema, Clone, Default)]
 #[ts(export, export_to = EXPORT_PATH)]
 pub struct RtcIceServer {
     #[serde(skip)]
     pub is_default: bool,
+    /// Urls like "stun:stun.l.google.com:19302" or "turn:yourip.com:3478?transport=udp"
     pub urls: Vec<String>,
+    /// The username for turn servers
     #[serde(default)]
     pub username: String,
+    /// The credential for turn servers
     #[serde(default)]
     pub credential: String,
 }
@@ -517,13 +696,182 @@ pub enum LogMessageType {
     InformError,
 }
 
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, TS)]
+#[ts(export, export_to = EXPORT_PATH)]
+#[serde(rename_all = "camelCase")]
+#[ts(rename_all = "camelCase")]
+pub enum StreamStage {
+    WakeHost,
+    LaunchStreamer,
+    /// One of the stages of moonlight connecting to the host, the name is in the message
+    HostConnection,
+    IceGathering,
+    WebRtcPeerNegotiation,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, TS)]
+#[ts(export, export_to = EXPORT_PATH)]
+#[serde(rename_all = "camelCase")]
+#[ts(rename_all = "camelCase")]
+pub enum StreamStageState {
+    Starting,
+    Completed,
+    Failed,
+}
+
+/// Why a stage failed, so the client can react to it instead of only showing the message
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, TS)]
+#[ts(export, export_to = EXPORT_PATH)]
+#[serde(rename_all = "camelCase")]
+#[ts(rename_all = "camelCase")]
+pub enum StreamStageError {
+    /// The host didn't respond or didn't come online after waking it
+    HostUnreachable,
+    /// Moonlight failed to connect to the host, the error code is in the message
+    HostConnectionFailed,
+    /// None of the stun and turn servers responded while gathering
+    IceGatheringFailed,
+    /// The WebRTC peer didn't connect even after restarting ice
+    WebRtcNegotiationFailed,
+}
+
+impl StreamServerMessage {
+    pub fn stage_starting(stage: StreamStage, message: impl Into<String>) -> Self {
+        Self::Stage {
+            stage,
+            state: StreamStageState::Starting,
+            error: None,
+            message: message.into(),
+            ty: None,
+        }
+    }
+
+    pub fn stage_completed(stage: StreamStage, message: impl Into<String>) -> Self {
+        Self::Stage {
+            stage,
+            state: StreamStageState::Completed,
+            error: None,
+            message: message.into(),
+            ty: None,
+        }
+    }
+
+    pub fn stage_failed(
+        stage: StreamStage,
+        error: StreamStageError,
+        message: impl Into<String>,
+        ty: LogMessageType,
+    ) -> Self {
+        Self::Stage {
+            stage,
+            state: StreamStageState::Failed,
+            error: Some(error),
+            message: message.into(),
+            ty: Some(ty),
+        }
+    }
+}
+
+// Stream Web Socket Protocol
+ts_consts!(
+    pub StreamProtocol(export_bindings_stream_protocol: EXPORT_PATH):
+
+    // Increase this when the messages of the stream web socket change in an incompatible way
+    pub const VERSION: u32 = 3;
+);
+
+/// The web socket subprotocol the browser requests for the stream web socket
+pub fn stream_web_socket_protocol(version: u32) -> String {
+    format!("moonlight-web-stream.v{version}")
+}
+
+// The close codes of the stream web socket, 4000 to 4999 are reserved for applications
+ts_consts!(
+    pub StreamCloseCode(export_bindings_stream_close_code: EXPORT_PATH) as u16:
+
+    pub const STOPPED: u16 = 4000;
+    pub const HOST_ENDED: u16 = 4001;
+    pub const ERROR: u16 = 4002;
+    pub const IDLE_TIMEOUT: u16 = 4003;
+    pub const CONNECTION_LOST: u16 = 4004;
+    pub const SERVER_SHUTDOWN: u16 = 4005;
+    pub const STREAMER_CRASHED: u16 = 4006;
+);
+
+/// Why a stream ended, the stream web socket is closed with its [StreamCloseCode]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StreamEndReason {
+    /// The client or the web server stopped the stream
+    Stopped,
+    /// The host ended the stream, e.g. because the app was quit
+    HostEnded,
+    /// The stream failed to start or the connection to the host broke
+    Error,
+    /// Nothing happened on the stream for too long
+    IdleTimeout,
+    /// The client disconnected and didn't come back in time
+    ConnectionLost,
+    /// The web server is shutting down
+    ServerShutdown,
+    /// The streamer crashed or stopped responding
+    StreamerCrashed,
+}
+
+impl StreamEndReason {
+    pub fn close_code(self) -> u16 {
+        match self {
+            Self::Stopped => StreamCloseCode::STOPPED,
+            Self::HostEnded => StreamCloseCode::HOST_ENDED,
+            Self::Error => StreamCloseCode::ERROR,
+            Self::IdleTimeout => StreamCloseCode::IDLE_TIMEOUT,
+            Self::ConnectionLost => StreamCloseCode::CONNECTION_LOST,
+            Self::ServerShutdown => StreamCloseCode::SERVER_SHUTDOWN,
+            Self::StreamerCrashed => StreamCloseCode::STREAMER_CRASHED,
+        }
+    }
+
+    /// Sent as the reason of the close frame, which must be shorter than 124 bytes
+    pub fn description(self) -> &'static str {
+        match self {
+            Self::Stopped => "The stream was stopped",
+            Self::HostEnded => "The host ended the session",
+            Self::Error => "The stream failed",
+            Self::IdleTimeout => "The stream was idle for too long",
+            Self::ConnectionLost => "The connection was lost",
+            Self::ServerShutdown => "The server is shutting down",
+            Self::StreamerCrashed => "The streamer crashed",
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, TS)]
 #[ts(export, export_to = EXPORT_PATH)]
 pub enum StreamServerMessage {
+    /// The page uses another protocol version than the server and must be reloaded
+    VersionMismatch {
+        server_version: u32,
+    },
     Setup {
         ice_servers: Vec<RtcIceServer>,
+        force_relay: bool,
+        /// The target latency of the WebRTC jitter buffer in the browser
+        jitter_buffer_target_ms: u32,
     },
     WebRtc(StreamSignalingMessage),
+    /// The signed token to resume this stream with if the web socket closes, see [GetStreamQuery]
+    Resumable {
+        session_token: String,
+    },
+    /// The settings the stream is started with after the host overrides, permissions and limits of the server were applied
+    EffectiveStreamSettings {
+        settings: StreamSettings,
+    },
+    /// What the streamer can forward, the supported codecs of the stream should be limited to these
+    StreamerCapabilities {
+        /// Use VideoSupportedCodec to figure this out
+        video_formats: u32,
+        audio_channels: u32,
+    },
     // Optional Info
     UpdateApp {
         app: App,
@@ -532,6 +880,16 @@ pub enum StreamServerMessage {
         message: String,
         ty: Option<LogMessageType>,
     },
+    /// The progress of starting the stream
+    Stage {
+        stage: StreamStage,
+        state: StreamStageState,
+        /// Why the stage failed, only set if the state is failed
+        error: Option<StreamStageError>,
+        /// A human readable description, e.g. "Starting Stage: RTSP Handshake"
+        message: String,
+        ty: Option<LogMessageType>,
+    },
     ConnectionComplete {
         capabilities: StreamCapabilities,
         /// Use VideoSupportedCodec to figure this out
@@ -554,8 +912,45 @@ pub enum StreamServerMessage {
 #[derive(Serialize, Deserialize, Debug, TS)]
 #[ts(export, export_to = EXPORT_PATH)]
 pub enum GeneralServerMessage {
-    ConnectionStatusUpdate { status: ConnectionStatus },
-    HdrModeUpdate { enabled: bool },
+    ConnectionStatusUpdate {
+        status: ConnectionStatus,
+    },
+    HdrModeUpdate {
+        enabled: bool,
+        metadata: Option<HdrMetadata>,
+    },
+}
+
+/// The static hdr metadata of the host display.
+/// Chromaticity coordinates are in units of 0.00002, luminance values in nits,
+/// except `min_display_luminance` which is in units of 0.0001 nits.
+#[derive(Serialize, Deserialize, Debug, TS, Clone, Copy, PartialEq, Eq)]
+#[ts(export, export_to = EXPORT_PATH)]
+pub struct HdrMetadata {
+    /// Red, green and blue primaries as x, y
+    pub display_primaries: [[u16; 2]; 3],
+    pub white_point: [u16; 2],
+    pub max_display_luminance: u16,
+    pub min_display_luminance: u16,
+    pub max_content_light_level: u16,
+    pub max_frame_average_light_level: u16,
+    pub max_full_frame_luminance: u16,
+}
+
+impl From<SunshineHdrMetadata> for HdrMetadata {
+    fn from(value: SunshineHdrMetadata) -> Self {
+        Self {
+            display_primaries: value
+                .display_primaries
+                .map(|primary| [primary.x, primary.y]),
+            white_point: [value.white_point.x, value.white_point.y],
+            max_display_luminance: value.max_display_luminance,
+            min_display_luminance: value.min_display_luminance,
+            max_content_light_level: value.max_content_light_level,
+            max_frame_average_light_level: value.max_frame_average_light_level,
+            max_full_frame_luminance: value.max_full_frame_luminance,
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, TS)]
@@ -603,12 +998,41 @@ pub enum StreamerStatsUpdate {
         min_streamer_processing_time_ms: f64,
         max_streamer_processing_time_ms: f64,
         avg_streamer_processing_time_ms: f64,
+        /// Frames received from the host since the last update
+        received_frames: u32,
+        /// Frames that couldn't be forwarded to the browser since the last update
+        failed_frames: u32,
+    },
+    Audio {
+        /// Samples received from the host since the last update
+        received_samples: u32,
+        /// Samples that couldn't be forwarded to the browser since the last update
+        failed_samples: u32,
     },
     BrowserRtt {
         /// The browser to the streamer
         /// Used with ws protocol to know backlog
         rtt_ms: f64,
     },
+    Bitrate {
+        /// The bitrate the host was asked to stream with, it can't be changed while streaming
+        requested_kbps: u32,
+        /// The video bitrate sent to the browser since the last update
+        streamed_kbps: f64,
+        /// The available bandwidth estimated by the browser (webrtc only)
+        estimated_kbps: Option<u32>,
+    },
+    /// Stats of the WebRTC peer of the streamer
+    WebRtc {
+        /// Everything sent over the selected candidate pair since the last update
+        sent_kbps: f64,
+        /// Everything received over the selected candidate pair since the last update
+        received_kbps: f64,
+        /// The video packets lost on the way to the browser, reported by the browser
+        packet_loss_percent: Option<f64>,
+        /// The candidate types of the selected pair like "host -> srflx", "relay" means turn is used
+        candidate_pair: Option<String>,
+    },
 }
 
 // Virtual-Key Codes
@@ -1,4 +1,5 @@
 use std::{
+    collections::HashMap,
     fmt::Display,
     net::{Ipv4Addr, SocketAddr, SocketAddrV4},
     num::ParseIntError,
@@ -6,14 +7,16 @@ use std::{
     time::Duration,
 };
 
+use ipnet::IpNet;
 use log::LevelFilter;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use thiserror::Error;
 
-use crate::api_bindings::RtcIceServer;
+use crate::api_bindings::{KeyboardLayout, RtcIceServer, StreamAudioMode};
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct Config {
     #[serde(default)]
     pub data_storage: StorageConfig,
@@ -26,22 +29,87 @@ pub struct Config {
     #[serde(default = "default_streamer_path")]
     pub streamer_path: String,
     #[serde(default)]
+    pub streamer: StreamerProcessConfig,
+    #[serde(default)]
     pub log: LogConfig,
+    #[serde(default)]
+    pub metrics: MetricsConfig,
+    #[serde(default)]
+    pub health: HealthConfig,
+    #[serde(default)]
+    pub discovery: DiscoveryConfig,
+    /// Overrides for specific hosts, keyed by the host address or the unique id of the host.
+    #[serde(default)]
+    pub hosts: HashMap<String, HostConfig>,
     // TODO: remove this on next major, it was replaced by roles
     #[deprecated]
     #[serde(default)]
     pub default_settings: Option<Value>,
 }
 
+impl Config {
+    /// Merges the overrides of the host onto the global defaults.
+    /// Overrides keyed by the unique id take precedence over the ones keyed by the address.
+    pub fn host_config(&self, address: &str, unique_id: Option<&str>) -> HostConfig {
+        let mut host_config = HostConfig {
+            http_port: Some(self.moonlight.default_http_port),
+            video_codecs: None,
+            preferred_video_codecs: None,
+            bitrate_kbps: None,
+            force_relay: Some(self.webrtc.force_relay),
+            mac_address: None,
+            wake_broadcast_address: None,
+            wake_timeout: None,
+            clipboard: None,
+            clipboard_max_bytes: None,
+            keyboard_layout: None,
+            key_remap: None,
+            audio_mode: None,
+            request_timeout: Some(self.moonlight.request_retry.timeout),
+            request_attempts: Some(self.moonlight.request_retry.attempts),
+        };
+
+        if let Some(address_config) = self.hosts.get(address) {
+            host_config.merge(address_config);
+        }
+        if let Some(unique_id_config) = unique_id.and_then(|unique_id| self.hosts.get(unique_id)) {
+            host_config.merge(unique_id_config);
+        }
+
+        host_config
+    }
+
+    /// The retry config for the requests to the host with the overrides of the host applied.
+    /// The unique id is only known once the host responded, so only overrides keyed by the address are used.
+    pub fn host_request_retry(&self, address: &str) -> HostRequestRetryConfig {
+        let host_config = self.host_config(address, None);
+
+        HostRequestRetryConfig {
+            attempts: host_config
+                .request_attempts
+                .unwrap_or(self.moonlight.request_retry.attempts),
+            timeout: host_config
+                .request_timeout
+                .unwrap_or(self.moonlight.request_retry.timeout),
+            ..self.moonlight.request_retry.clone()
+        }
+    }
+}
+
 impl Default for Config {
     fn default() -> Self {
         Self {
             data_storage: Default::default(),
             streamer_path: default_streamer_path(),
+            streamer: Default::default(),
             web_server: Default::default(),
             moonlight: Default::default(),
             webrtc: Default::default(),
             log: Default::default(),
+            metrics: Default::default(),
+            health: Default::default(),
+            discovery: Default::default(),
+            hosts: Default::default(),
             #[allow(deprecated)]
             default_settings: Default::default(),
         }
@@ -50,10 +118,38 @@ impl Default for Config {
 
 // -- Log
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct LogConfig {
+    /// One of "off", "error", "warn", "info", "debug" or "trace"
+    #[schemars(with = "String")]
     pub level_filter: LevelFilter,
+    /// Tracing directives like "webrtc=debug" which override the level filter for single modules.
+    /// They're applied after the `RUST_LOG` environment variable and also used by the streamer
+    #[serde(default)]
+    pub directives: Vec<String>,
     pub file_path: Option<String>,
+    /// When a new log file is started, the date and time is appended to the file name
+    #[serde(default)]
+    pub rotation: LogRotation,
+    /// How many rotated log files are kept, all if [None]
+    #[serde(default)]
+    pub max_log_files: Option<usize>,
+    /// Clears the log file on startup instead of appending to it, only used without rotation
+    #[serde(default)]
+    pub truncate: bool,
+    /// The format of the log file
+    #[serde(default)]
+    pub format: LogFormat,
+    /// The format of the logs printed to stdout
+    #[serde(default)]
+    pub stdout_format: LogFormat,
+    /// Headers whose values are redacted in the trace logs in addition to the default ones.
+    /// A `*` matches any part of the name, e.g. `x-*-token`.
+    #[serde(default)]
+    pub sensitive_headers: Vec<String>,
+    /// Query parameters whose values are redacted in the logged request uris in addition to the default ones
+    #[serde(default)]
+    pub sensitive_query_params: Vec<String>,
     #[serde(default = "default_dev_venator")]
     pub dev_venator: bool,
 }
@@ -62,12 +158,44 @@ impl Default for LogConfig {
     fn default() -> Self {
         Self {
             level_filter: default_level_filter(),
+            directives: Vec::new(),
             file_path: None,
+            rotation: Default::default(),
+            max_log_files: None,
+            truncate: false,
+            format: Default::default(),
+            stdout_format: Default::default(),
+            sensitive_headers: Vec::new(),
+            sensitive_query_params: Vec::new(),
             dev_venator: default_dev_venator(),
         }
     }
 }
 
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub enum LogRotation {
+    #[default]
+    #[serde(rename = "never")]
+    Never,
+    #[serde(rename = "minutely")]
+    Minutely,
+    #[serde(rename = "hourly")]
+    Hourly,
+    #[serde(rename = "daily")]
+    Daily,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub enum LogFormat {
+    /// Human readable, with colors if printed to a terminal
+    #[default]
+    #[serde(rename = "pretty")]
+    Pretty,
+    /// One json object per line
+    #[serde(rename = "json")]
+    Json,
+}
+
 fn default_level_filter() -> LevelFilter {
     LevelFilter::Info
 }
@@ -76,8 +204,87 @@ fn default_dev_venator() -> bool {
     false
 }
 
+// -- Metrics
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+pub struct MetricsConfig {
+    /// Export prometheus metrics under `/api/metrics`, only admins can access them
+    #[serde(default)]
+    pub enabled: bool,
+    /// Additionally export the metrics under `/metrics` on this address without authentication.
+    /// Only use an address which isn't reachable publicly.
+    #[serde(default)]
+    pub bind_address: Option<SocketAddr>,
+}
+
+// -- Health
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct HealthConfig {
+    /// The addresses of the hosts probed by `/healthz?deep=1`, the http port is taken from the host overrides.
+    /// The deep check succeeds if at least one of them answers.
+    #[serde(default)]
+    pub probe_hosts: Vec<String>,
+    /// How long a host has to answer the serverinfo request
+    #[serde(default = "default_health_probe_timeout")]
+    pub probe_timeout: Duration,
+}
+
+impl Default for HealthConfig {
+    fn default() -> Self {
+        Self {
+            probe_hosts: Vec::new(),
+            probe_timeout: default_health_probe_timeout(),
+        }
+    }
+}
+
+fn default_health_probe_timeout() -> Duration {
+    Duration::from_secs(3)
+}
+
+// -- Discovery
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct DiscoveryConfig {
+    /// Allow users to search for hosts in the local network using mDNS under `/api/discover`
+    #[serde(default)]
+    pub enabled: bool,
+    /// The mDNS service types which are browsed
+    #[serde(default = "default_discovery_service_types")]
+    pub service_types: Vec<String>,
+    /// How long to wait for hosts to answer
+    #[serde(default = "default_discovery_browse_duration")]
+    pub browse_duration: Duration,
+    /// How long the found hosts are cached so repeated requests don't flood the network
+    #[serde(default = "default_discovery_cache_ttl")]
+    pub cache_ttl: Duration,
+}
+
+impl Default for DiscoveryConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            service_types: default_discovery_service_types(),
+            browse_duration: default_discovery_browse_duration(),
+            cache_ttl: default_discovery_cache_ttl(),
+        }
+    }
+}
+
+fn default_discovery_service_types() -> Vec<String> {
+    // Used by GameStream and Sunshine
+    vec!["_nvstream._tcp.local.".to_string()]
+}
+fn default_discovery_browse_duration() -> Duration {
+    Duration::from_secs(3)
+}
+fn default_discovery_cache_ttl() -> Duration {
+    Duration::from_secs(30)
+}
+
 // -- Data Storage
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 #[serde(tag = "type")]
 #[serde(rename_all = "camelCase")]
 pub enum StorageConfig {
@@ -102,12 +309,14 @@ fn default_session_expiration_check_interval() -> Duration {
 
 // -- WebRTC Config
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct WebRtcConfig {
+    /// The stun and turn servers used by the browser and the streamer to find a connection
     #[serde(default = "default_ice_servers")]
     pub ice_servers: Vec<RtcIceServer>,
     #[serde(default)]
     pub ice_server_script: Option<String>,
+    /// The udp ports the streamer uses for WebRTC, useful for port forwarding
     #[serde(default)]
     pub port_range: Option<PortRange>,
     #[serde(default)]
@@ -116,6 +325,42 @@ pub struct WebRtcConfig {
     pub network_types: Vec<WebRtcNetworkType>,
     #[serde(default = "default_include_loopback_candidates")]
     pub include_loopback_candidates: bool,
+    /// Only use relay (TURN) candidates. Requires at least one turn server in `ice_servers`.
+    #[serde(default)]
+    pub force_relay: bool,
+    /// How long to wait for the peer to connect before restarting ice
+    #[serde(default = "default_negotiation_timeout")]
+    pub negotiation_timeout: Duration,
+    /// How often ice is restarted before the WebRTC connection is given up
+    #[serde(default = "default_ice_restart_attempts")]
+    pub ice_restart_attempts: u32,
+    /// How long the stun and turn servers get to respond while gathering candidates.
+    /// Servers that don't respond in time are logged and the negotiation continues without their candidates
+    #[serde(default = "default_ice_gathering_timeout")]
+    pub ice_gathering_timeout: Duration,
+    /// How long the browser buffers received video frames and audio samples to smooth out network jitter.
+    /// Frames are reordered and played out on time at the cost of this much latency, 0 plays them as soon as possible
+    #[serde(default)]
+    pub jitter_buffer_target: Duration,
+    /// How the data channels used for input deliver their messages
+    #[serde(default)]
+    pub input_channels: WebRtcInputChannelsConfig,
+    /// What happens when the video frame or audio sample queue is full
+    #[serde(default)]
+    pub frame_queue_policy: FrameQueuePolicy,
+    /// How often the stats of the WebRTC peer are sent to the browser
+    #[serde(default = "default_stats_interval")]
+    pub stats_interval: Duration,
+    /// A file with the dtls certificate of the streamer so its fingerprint stays the same across streams.
+    /// The certificate is generated if the file doesn't exist, if [None] every stream uses a new certificate
+    #[serde(default)]
+    pub dtls_certificate: Option<String>,
+    /// Only allow these srtp protection profiles in the order they're preferred, if [None] the webrtc defaults are used
+    #[serde(default)]
+    pub srtp_protection_profiles: Option<Vec<WebRtcSrtpProtectionProfile>>,
+    /// Which ice candidates of the streamer are sent to the browser and which of the browser are used
+    #[serde(default)]
+    pub candidate_filter: WebRtcCandidateFilterConfig,
 }
 
 impl Default for WebRtcConfig {
@@ -127,11 +372,72 @@ impl Default for WebRtcConfig {
             nat_1to1: None,
             network_types: default_network_types(),
             include_loopback_candidates: default_include_loopback_candidates(),
+            force_relay: false,
+            negotiation_timeout: default_negotiation_timeout(),
+            ice_restart_attempts: default_ice_restart_attempts(),
+            ice_gathering_timeout: default_ice_gathering_timeout(),
+            jitter_buffer_target: Duration::ZERO,
+            input_channels: Default::default(),
+            frame_queue_policy: Default::default(),
+            stats_interval: default_stats_interval(),
+            dtls_certificate: None,
+            srtp_protection_profiles: None,
+            candidate_filter: Default::default(),
         }
     }
 }
 
-#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+/// Filtering too much can make connecting impossible, it's meant to find out which candidates stall the negotiation
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+pub struct WebRtcCandidateFilterConfig {
+    /// Only use candidates of these types, if [None] all types are used
+    #[serde(default)]
+    pub candidate_types: Option<Vec<WebRtcIceCandidateType>>,
+    /// Drop candidates with an ipv6 address
+    #[serde(default)]
+    pub exclude_ipv6: bool,
+    /// Drop candidates with a link-local address like `169.254.0.0/16` or `fe80::/10`
+    #[serde(default)]
+    pub exclude_link_local: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub enum WebRtcIceCandidateType {
+    #[serde(rename = "host")]
+    Host,
+    #[serde(rename = "srflx")]
+    Srflx,
+    #[serde(rename = "prflx")]
+    Prflx,
+    #[serde(rename = "relay")]
+    Relay,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub enum WebRtcSrtpProtectionProfile {
+    #[serde(rename = "aes128_cm_hmac_sha1_80")]
+    Aes128CmHmacSha1_80,
+    #[serde(rename = "aes128_cm_hmac_sha1_32")]
+    Aes128CmHmacSha1_32,
+    #[serde(rename = "aead_aes128_gcm")]
+    AeadAes128Gcm,
+    #[serde(rename = "aead_aes256_gcm")]
+    AeadAes256Gcm,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub enum FrameQueuePolicy {
+    /// Drops the oldest frames to keep the latency low.
+    /// Dropped video frames are recovered by requesting a keyframe, queued keyframes are kept.
+    #[default]
+    #[serde(rename = "drop_oldest")]
+    DropOldest,
+    /// Waits until there's space in the queue, no frames are lost but the latency grows
+    #[serde(rename = "block")]
+    Block,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, JsonSchema)]
 pub enum WebRtcNetworkType {
     #[serde(rename = "udp4")]
     Udp4,
@@ -172,13 +478,13 @@ impl FromStr for WebRtcNetworkType {
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct WebRtcNat1To1Mapping {
     pub ips: Vec<String>,
     pub ice_candidate_type: WebRtcNat1To1IceCandidateType,
 }
 
-#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, JsonSchema)]
 pub enum WebRtcNat1To1IceCandidateType {
     #[serde(rename = "srflx")]
     Srflx,
@@ -186,12 +492,23 @@ pub enum WebRtcNat1To1IceCandidateType {
     Host,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, JsonSchema)]
 pub struct PortRange {
     pub min: u16,
     pub max: u16,
 }
 
+impl PortRange {
+    /// How many ports are in the range, both ends are included
+    pub fn port_count(&self) -> usize {
+        (self.max as usize + 1).saturating_sub(self.min as usize)
+    }
+
+    pub fn contains(&self, port: u16) -> bool {
+        (self.min..=self.max).contains(&port)
+    }
+}
+
 #[derive(Debug, Error)]
 pub enum PortRangeFromStrError {
     #[error("the port range must be of format \"MIN:MAX\"")]
@@ -236,14 +553,116 @@ fn default_network_types() -> Vec<WebRtcNetworkType> {
 fn default_include_loopback_candidates() -> bool {
     true
 }
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct WebRtcInputChannelsConfig {
+    #[serde(default = "default_input_channel_mouse_reliable")]
+    pub mouse_reliable: WebRtcDataChannelConfig,
+    #[serde(default = "default_input_channel_mouse_absolute")]
+    pub mouse_absolute: WebRtcDataChannelConfig,
+    #[serde(default = "default_input_channel_mouse_relative")]
+    pub mouse_relative: WebRtcDataChannelConfig,
+    #[serde(default = "default_input_channel_keyboard")]
+    pub keyboard: WebRtcDataChannelConfig,
+    #[serde(default = "default_input_channel_touch")]
+    pub touch: WebRtcDataChannelConfig,
+    /// Used for connecting and disconnecting controllers
+    #[serde(default = "default_input_channel_controllers")]
+    pub controllers: WebRtcDataChannelConfig,
+    /// Used for the state of each controller
+    #[serde(default = "default_input_channel_controller")]
+    pub controller: WebRtcDataChannelConfig,
+}
+
+impl Default for WebRtcInputChannelsConfig {
+    fn default() -> Self {
+        Self {
+            mouse_reliable: default_input_channel_mouse_reliable(),
+            mouse_absolute: default_input_channel_mouse_absolute(),
+            mouse_relative: default_input_channel_mouse_relative(),
+            keyboard: default_input_channel_keyboard(),
+            touch: default_input_channel_touch(),
+            controllers: default_input_channel_controllers(),
+            controller: default_input_channel_controller(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, JsonSchema)]
+pub struct WebRtcDataChannelConfig {
+    /// Lost messages are retransmitted
+    pub reliable: bool,
+    /// Messages arrive in the order they were sent
+    pub ordered: bool,
+}
+
+fn default_input_channel_mouse_reliable() -> WebRtcDataChannelConfig {
+    WebRtcDataChannelConfig {
+        reliable: true,
+        ordered: true,
+    }
+}
+fn default_input_channel_mouse_absolute() -> WebRtcDataChannelConfig {
+    WebRtcDataChannelConfig {
+        reliable: false,
+        ordered: false,
+    }
+}
+fn default_input_channel_mouse_relative() -> WebRtcDataChannelConfig {
+    WebRtcDataChannelConfig {
+        reliable: true,
+        ordered: false,
+    }
+}
+fn default_input_channel_keyboard() -> WebRtcDataChannelConfig {
+    WebRtcDataChannelConfig {
+        reliable: true,
+        ordered: true,
+    }
+}
+fn default_input_channel_touch() -> WebRtcDataChannelConfig {
+    WebRtcDataChannelConfig {
+        reliable: true,
+        ordered: true,
+    }
+}
+fn default_input_channel_controllers() -> WebRtcDataChannelConfig {
+    WebRtcDataChannelConfig {
+        reliable: true,
+        ordered: true,
+    }
+}
+fn default_input_channel_controller() -> WebRtcDataChannelConfig {
+    WebRtcDataChannelConfig {
+        reliable: false,
+        ordered: false,
+    }
+}
+
+fn default_negotiation_timeout() -> Duration {
+    Duration::from_secs(10)
+}
+fn default_ice_restart_attempts() -> u32 {
+    2
+}
+fn default_ice_gathering_timeout() -> Duration {
+    Duration::from_secs(5)
+}
+fn default_stats_interval() -> Duration {
+    Duration::from_secs(1)
+}
 
 // -- Web Server Config
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct WebServerConfig {
+    /// Either a single address or a list of addresses, e.g. to listen on ipv4 and ipv6
     #[serde(default = "default_bind_address")]
-    pub bind_address: SocketAddr,
+    pub bind_address: BindAddress,
+    /// Serve the web interface over https using this certificate
     pub certificate: Option<ConfigSsl>,
+    /// Obtain and renew the https certificate automatically, only used if `certificate` is not set
+    #[serde(default)]
+    pub acme: Option<ConfigAcme>,
     #[serde(default)]
     pub url_path_prefix: String,
     #[serde(default = "default_session_cookie_secure")]
@@ -255,19 +674,92 @@ pub struct WebServerConfig {
     pub default_user_id: Option<u32>,
     pub default_role_id: Option<u32>,
     pub forwarded_header: Option<ForwardedHeaders>,
+    /// Reverse proxies in front of the web server, e.g. `["127.0.0.1/32", "172.16.0.0/12"]`.
+    /// Only requests from these get their client ip from the `Forwarded` or `X-Forwarded-For` header
+    #[serde(default)]
+    #[schemars(with = "Vec<String>")]
+    pub trusted_proxies: Vec<IpNet>,
+    /// Signs the tokens streams are resumed with. If [None] a random secret is generated on startup
+    #[serde(default)]
+    pub stream_token_secret: Option<String>,
+    /// How long a stream token can be used to resume a stream, a new token is issued on every resume
+    #[serde(default = "default_stream_token_lifetime")]
+    pub stream_token_lifetime: Duration,
+    /// Limits login and pairing attempts per client ip, `null` disables the limit
+    #[serde(default = "default_rate_limit")]
+    pub rate_limit: Option<RateLimitConfig>,
+    /// Allow other origins to use the api, by default only the same origin can use it
+    #[serde(default)]
+    pub cors: Option<CorsConfig>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(untagged)]
+pub enum BindAddress {
+    Single(SocketAddr),
+    Multiple(Vec<SocketAddr>),
+}
+
+impl BindAddress {
+    pub fn addresses(&self) -> &[SocketAddr] {
+        match self {
+            Self::Single(address) => std::slice::from_ref(address),
+            Self::Multiple(addresses) => addresses,
+        }
+    }
+}
+
+impl From<Vec<SocketAddr>> for BindAddress {
+    fn from(mut addresses: Vec<SocketAddr>) -> Self {
+        if addresses.len() == 1 {
+            Self::Single(addresses.remove(0))
+        } else {
+            Self::Multiple(addresses)
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct ConfigSsl {
+    /// The path to the private key in the pem format
     pub private_key_pem: String,
+    /// The path to the certificate (chain) in the pem format, must match the private key
     pub certificate_pem: String,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct ConfigAcme {
+    /// The domain the certificate is issued for, it must point to this server
+    pub domain: String,
+    /// The contact email of the acme account
+    pub contact_email: String,
+    /// The directory where the acme account, the certificate and the private key are stored
+    #[serde(default = "default_acme_cache_dir")]
+    pub cache_dir: String,
+    /// The acme directory, by default Let's Encrypt
+    #[serde(default = "default_acme_directory_url")]
+    pub directory_url: String,
+    /// The address the http-01 challenges are served on, port 80 must be reachable from the internet for the domain
+    #[serde(default = "default_acme_challenge_bind_address")]
+    pub challenge_bind_address: SocketAddr,
+}
+
+fn default_acme_cache_dir() -> String {
+    "server/acme".to_string()
+}
+fn default_acme_directory_url() -> String {
+    "https://acme-v02.api.letsencrypt.org/directory".to_string()
+}
+fn default_acme_challenge_bind_address() -> SocketAddr {
+    SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, 80))
+}
+
 impl Default for WebServerConfig {
     fn default() -> Self {
         Self {
             bind_address: default_bind_address(),
             certificate: None,
+            acme: None,
             url_path_prefix: "".to_string(),
             session_cookie_secure: default_session_cookie_secure(),
             session_cookie_expiration: default_session_cookie_expiration(),
@@ -276,12 +768,88 @@ impl Default for WebServerConfig {
             default_user_id: None,
             default_role_id: None,
             forwarded_header: None,
+            trusted_proxies: Vec::new(),
+            stream_token_secret: None,
+            stream_token_lifetime: default_stream_token_lifetime(),
+            rate_limit: default_rate_limit(),
+            cors: None,
         }
     }
 }
 
-fn default_bind_address() -> SocketAddr {
-    SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, 8080))
+fn default_stream_token_lifetime() -> Duration {
+    Duration::from_hours(12)
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct RateLimitConfig {
+    /// How many attempts a client ip can make within the window
+    #[serde(default = "default_rate_limit_max_attempts")]
+    pub max_attempts: u32,
+    #[serde(default = "default_rate_limit_window")]
+    pub window: Duration,
+    /// How long a client ip is blocked after it made too many attempts
+    #[serde(default = "default_rate_limit_lockout")]
+    pub lockout: Duration,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        Self {
+            max_attempts: default_rate_limit_max_attempts(),
+            window: default_rate_limit_window(),
+            lockout: default_rate_limit_lockout(),
+        }
+    }
+}
+
+fn default_rate_limit() -> Option<RateLimitConfig> {
+    Some(RateLimitConfig::default())
+}
+fn default_rate_limit_max_attempts() -> u32 {
+    10
+}
+fn default_rate_limit_window() -> Duration {
+    Duration::from_mins(1)
+}
+fn default_rate_limit_lockout() -> Duration {
+    Duration::from_mins(5)
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct CorsConfig {
+    /// The origins which are allowed to make requests, e.g. `https://dashboard.example.com`, or `*` for every origin
+    pub allowed_origins: Vec<String>,
+    #[serde(default = "default_cors_allowed_methods")]
+    pub allowed_methods: Vec<String>,
+    #[serde(default = "default_cors_allowed_headers")]
+    pub allowed_headers: Vec<String>,
+    /// Allow the other origins to send cookies, required for session cookie authentication
+    #[serde(default)]
+    pub allow_credentials: bool,
+    /// How long browsers can cache the preflight response in seconds
+    #[serde(default)]
+    pub max_age: Option<usize>,
+}
+
+fn default_cors_allowed_methods() -> Vec<String> {
+    ["GET", "POST", "PATCH", "DELETE"]
+        .into_iter()
+        .map(str::to_string)
+        .collect()
+}
+fn default_cors_allowed_headers() -> Vec<String> {
+    ["Authorization", "Content-Type"]
+        .into_iter()
+        .map(str::to_string)
+        .collect()
+}
+
+fn default_bind_address() -> BindAddress {
+    BindAddress::Single(SocketAddr::V4(SocketAddrV4::new(
+        Ipv4Addr::UNSPECIFIED,
+        8080,
+    )))
 }
 fn default_session_cookie_secure() -> bool {
     false
@@ -292,7 +860,7 @@ fn default_session_cookie_expiration() -> Duration {
     Duration::from_secs(DAY_SECONDS)
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct ForwardedHeaders {
     pub username_header: String,
     #[serde(default = "default_forwarded_headers_auto_create_user")]
@@ -312,14 +880,139 @@ fn default_forwarded_headers_auto_create_user() -> bool {
     true
 }
 
+// -- Hosts
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+pub struct HostConfig {
+    /// The http port used when adding this host without specifying one
+    #[serde(default)]
+    pub http_port: Option<u16>,
+    /// Only allow these codecs for streams of this host
+    #[serde(default)]
+    pub video_codecs: Option<Vec<HostVideoCodec>>,
+    /// The codecs in the order they're preferred, the first one supported by the browser is used.
+    /// Codecs which aren't listed are only used if none of the listed ones are supported.
+    #[serde(default)]
+    pub preferred_video_codecs: Option<Vec<HostVideoCodec>>,
+    /// Overwrites the bitrate requested by the browser
+    #[serde(default)]
+    pub bitrate_kbps: Option<u32>,
+    /// Overwrites `webrtc.force_relay`
+    #[serde(default)]
+    pub force_relay: Option<bool>,
+    /// The mac address used for wake on lan, e.g. `aa:bb:cc:dd:ee:ff`, instead of the one reported by the host
+    #[serde(default)]
+    pub mac_address: Option<String>,
+    /// Where the wake on lan packet is sent to if `mac_address` is set, by default `255.255.255.255:9`
+    #[serde(default)]
+    pub wake_broadcast_address: Option<SocketAddr>,
+    /// Wake the host when starting a stream while it's offline and wait this long for it to come online
+    #[serde(default)]
+    pub wake_timeout: Option<Duration>,
+    /// Allow pasting the clipboard of the browser into the host, enabled by default
+    #[serde(default)]
+    pub clipboard: Option<bool>,
+    /// The biggest clipboard text in bytes which can be pasted into the host
+    #[serde(default)]
+    pub clipboard_max_bytes: Option<usize>,
+    /// The keyboard layout of the browsers, a stream can choose another one
+    #[serde(default)]
+    pub keyboard_layout: Option<KeyboardLayout>,
+    /// Remaps virtual key codes after the keyboard layout is applied, e.g. `{ "90": 89 }` types Y when Z is pressed
+    #[serde(default)]
+    pub key_remap: Option<HashMap<u16, u16>>,
+    /// The audio channels requested from the host, a stream can choose another one.
+    /// By default the channels of the host are used
+    #[serde(default)]
+    pub audio_mode: Option<StreamAudioMode>,
+    /// Overwrites `moonlight.request_retry.timeout`, only read from overrides keyed by the address
+    #[serde(default)]
+    pub request_timeout: Option<Duration>,
+    /// Overwrites `moonlight.request_retry.attempts`, only read from overrides keyed by the address
+    #[serde(default)]
+    pub request_attempts: Option<u32>,
+}
+
+impl HostConfig {
+    /// Overwrites all values which are set in the other config
+    pub fn merge(&mut self, other: &HostConfig) {
+        if let Some(http_port) = other.http_port {
+            self.http_port = Some(http_port);
+        }
+        if let Some(video_codecs) = &other.video_codecs {
+            self.video_codecs = Some(video_codecs.clone());
+        }
+        if let Some(preferred_video_codecs) = &other.preferred_video_codecs {
+            self.preferred_video_codecs = Some(preferred_video_codecs.clone());
+        }
+        if let Some(bitrate_kbps) = other.bitrate_kbps {
+            self.bitrate_kbps = Some(bitrate_kbps);
+        }
+        if let Some(force_relay) = other.force_relay {
+            self.force_relay = Some(force_relay);
+        }
+        if let Some(mac_address) = &other.mac_address {
+            self.mac_address = Some(mac_address.clone());
+        }
+        if let Some(wake_broadcast_address) = other.wake_broadcast_address {
+            self.wake_broadcast_address = Some(wake_broadcast_address);
+        }
+        if let Some(wake_timeout) = other.wake_timeout {
+            self.wake_timeout = Some(wake_timeout);
+        }
+        if let Some(clipboard) = other.clipboard {
+            self.clipboard = Some(clipboard);
+        }
+        if let Some(clipboard_max_bytes) = other.clipboard_max_bytes {
+            self.clipboard_max_bytes = Some(clipboard_max_bytes);
+        }
+        if let Some(keyboard_layout) = other.keyboard_layout {
+            self.keyboard_layout = Some(keyboard_layout);
+        }
+        if let Some(key_remap) = &other.key_remap {
+            self.key_remap = Some(key_remap.clone());
+        }
+        if let Some(audio_mode) = other.audio_mode {
+            self.audio_mode = Some(audio_mode);
+        }
+        if let Some(request_timeout) = other.request_timeout {
+            self.request_timeout = Some(request_timeout);
+        }
+        if let Some(request_attempts) = other.request_attempts {
+            self.request_attempts = Some(request_attempts);
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub enum HostVideoCodec {
+    #[serde(rename = "h264")]
+    H264,
+    #[serde(rename = "h265")]
+    H265,
+    #[serde(rename = "av1")]
+    Av1,
+}
+
 // -- Moonlight
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct MoonlightConfig {
+    /// The http port used when adding a host without specifying one
     #[serde(default = "default_moonlight_http_port")]
     pub default_http_port: u16,
     #[serde(default = "default_pair_device_name")]
     pub pair_device_name: String,
+    /// How long the app list of a host is cached.
+    /// If the host can't be reached the last known app list is used.
+    #[serde(default = "default_app_list_cache_ttl")]
+    pub app_list_cache_ttl: Duration,
+    /// How requests like the server info and app list are retried when the host can't be reached
+    #[serde(default)]
+    pub request_retry: HostRequestRetryConfig,
+    /// Also try the http port derived from the entered port when adding a host, in case the https port was entered
+    #[serde(default = "default_detect_ports")]
+    pub detect_ports: bool,
 }
 
 impl Default for MoonlightConfig {
@@ -327,6 +1020,36 @@ impl Default for MoonlightConfig {
         Self {
             default_http_port: default_moonlight_http_port(),
             pair_device_name: default_pair_device_name(),
+            app_list_cache_ttl: default_app_list_cache_ttl(),
+            request_retry: Default::default(),
+            detect_ports: default_detect_ports(),
+        }
+    }
+}
+
+/// Only connection errors and timeouts are retried, errors the host responded with are returned immediately
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct HostRequestRetryConfig {
+    /// How often a request is tried in total, 1 disables retrying
+    #[serde(default = "default_host_request_attempts")]
+    pub attempts: u32,
+    /// How long a single attempt may take before it's cancelled
+    #[serde(default = "default_host_request_timeout")]
+    pub timeout: Duration,
+    /// The wait before the first retry, it's doubled after every attempt
+    #[serde(default = "default_host_request_initial_backoff")]
+    pub initial_backoff: Duration,
+    #[serde(default = "default_host_request_max_backoff")]
+    pub max_backoff: Duration,
+}
+
+impl Default for HostRequestRetryConfig {
+    fn default() -> Self {
+        Self {
+            attempts: default_host_request_attempts(),
+            timeout: default_host_request_timeout(),
+            initial_backoff: default_host_request_initial_backoff(),
+            max_backoff: default_host_request_max_backoff(),
         }
     }
 }
@@ -339,6 +1062,213 @@ fn default_pair_device_name() -> String {
     "roth".to_string()
 }
 
+fn default_app_list_cache_ttl() -> Duration {
+    Duration::from_secs(30)
+}
+
+fn default_detect_ports() -> bool {
+    true
+}
+
+fn default_host_request_attempts() -> u32 {
+    3
+}
+fn default_host_request_timeout() -> Duration {
+    Duration::from_secs(10)
+}
+fn default_host_request_initial_backoff() -> Duration {
+    Duration::from_millis(250)
+}
+fn default_host_request_max_backoff() -> Duration {
+    Duration::from_secs(2)
+}
+
 fn default_streamer_path() -> String {
     "./streamer".to_string()
 }
+
+// -- Streamer Process
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct StreamerProcessConfig {
+    /// How often the web server pings the streamer over the ipc
+    #[serde(default = "default_heartbeat_interval")]
+    pub heartbeat_interval: Duration,
+    /// If the streamer doesn't send anything for this long it's considered dead and the stream is stopped
+    #[serde(default = "default_heartbeat_timeout")]
+    pub heartbeat_timeout: Duration,
+    /// How many messages can be queued in each direction of the ipc before sending waits
+    #[serde(default = "default_ipc_channel_capacity")]
+    pub ipc_channel_capacity: usize,
+    /// Compress ipc messages that are at least this many bytes big using zstd
+    #[serde(default)]
+    pub ipc_compression_threshold: Option<usize>,
+    /// The biggest ipc message in bytes, if a bigger one is received the stream will be stopped
+    #[serde(default = "default_ipc_max_frame_size")]
+    pub ipc_max_frame_size: usize,
+    /// How long the streamer has to exit after it stopped before it gets killed
+    #[serde(default = "default_stop_timeout")]
+    pub stop_timeout: Duration,
+    /// How long active streams have to stop when the server shuts down before their streamers get killed
+    #[serde(default = "default_shutdown_grace_period")]
+    pub shutdown_grace_period: Duration,
+    /// Restart the streamer if it crashes during a stream, if [None] the stream just ends
+    #[serde(default)]
+    pub restart: Option<StreamerRestartConfig>,
+    /// Keep the streamer running this long after the web socket closed so the client can resume the stream,
+    /// if [None] the stream stops when the web socket closes
+    #[serde(default)]
+    pub resume_timeout: Option<Duration>,
+    /// Extra arguments passed to the streamer, e.g. when `streamer_path` points to a wrapper script
+    #[serde(default)]
+    pub args: Vec<String>,
+    /// Extra environment variables set for the streamer
+    #[serde(default)]
+    pub env: HashMap<String, String>,
+    /// Write what the host sent into files for bug reports, if [None] nothing is recorded
+    #[serde(default)]
+    pub recording: Option<StreamRecordingConfig>,
+    /// How many streams can run at the same time, new streams are rejected once this is reached.
+    /// If [None] there's no limit
+    #[serde(default)]
+    pub max_sessions: Option<usize>,
+    /// Stop streams nobody uses anymore, if [None] streams only stop when the client disconnects
+    #[serde(default)]
+    pub idle_timeout: Option<StreamIdleTimeoutConfig>,
+    /// Log the frame types the host sends and warn about streams the browser can't decode, if [None] nothing is checked
+    #[serde(default)]
+    pub frame_diagnostics: Option<StreamFrameDiagnosticsConfig>,
+    /// Run the streamer inside the web server instead of spawning `streamer_path`.
+    /// Requires the web server to be built with the "in-process-streamer" feature and only allows one stream at a time
+    #[serde(default)]
+    pub in_process: bool,
+    /// The highest resolution, fps and bitrate any client can stream with
+    #[serde(default)]
+    pub limits: StreamLimitsConfig,
+}
+
+impl Default for StreamerProcessConfig {
+    fn default() -> Self {
+        Self {
+            heartbeat_interval: default_heartbeat_interval(),
+            heartbeat_timeout: default_heartbeat_timeout(),
+            ipc_channel_capacity: default_ipc_channel_capacity(),
+            ipc_compression_threshold: None,
+            ipc_max_frame_size: default_ipc_max_frame_size(),
+            stop_timeout: default_stop_timeout(),
+            shutdown_grace_period: default_shutdown_grace_period(),
+            restart: None,
+            resume_timeout: None,
+            args: Vec::new(),
+            env: HashMap::new(),
+            recording: None,
+            max_sessions: None,
+            idle_timeout: None,
+            frame_diagnostics: None,
+            in_process: false,
+            limits: Default::default(),
+        }
+    }
+}
+
+fn default_heartbeat_interval() -> Duration {
+    Duration::from_secs(5)
+}
+fn default_heartbeat_timeout() -> Duration {
+    Duration::from_secs(30)
+}
+fn default_ipc_channel_capacity() -> usize {
+    crate::ipc::DEFAULT_IPC_CHANNEL_CAPACITY
+}
+fn default_ipc_max_frame_size() -> usize {
+    crate::ipc::DEFAULT_IPC_MAX_FRAME_SIZE
+}
+fn default_stop_timeout() -> Duration {
+    Duration::from_secs(15)
+}
+fn default_shutdown_grace_period() -> Duration {
+    Duration::from_secs(20)
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct StreamerRestartConfig {
+    #[serde(default = "default_restart_max_retries")]
+    pub max_retries: u32,
+    /// The wait before the first restart, it's doubled for every following restart
+    #[serde(default = "default_restart_initial_backoff")]
+    pub initial_backoff: Duration,
+    #[serde(default = "default_restart_max_backoff")]
+    pub max_backoff: Duration,
+}
+
+impl Default for StreamerRestartConfig {
+    fn default() -> Self {
+        Self {
+            max_retries: default_restart_max_retries(),
+            initial_backoff: default_restart_initial_backoff(),
+            max_backoff: default_restart_max_backoff(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct StreamRecordingConfig {
+    /// The directory the recordings are written to, it's created if it doesn't exist
+    pub directory: String,
+    /// Stop recording a stream once its video or audio file reached this many bytes
+    #[serde(default = "default_recording_max_bytes")]
+    pub max_bytes: u64,
+    /// Stop recording a stream after this long
+    #[serde(default = "default_recording_max_duration")]
+    pub max_duration: Duration,
+}
+
+/// Applied after the host overrides and permissions, if a value is [None] it's not limited
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+pub struct StreamLimitsConfig {
+    /// The resolution is scaled down to fit `max_width` and `max_height` while keeping its aspect ratio
+    #[serde(default)]
+    pub max_width: Option<u32>,
+    #[serde(default)]
+    pub max_height: Option<u32>,
+    #[serde(default)]
+    pub max_fps: Option<u32>,
+    #[serde(default)]
+    pub max_bitrate_kbps: Option<u32>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+pub struct StreamIdleTimeoutConfig {
+    /// Stop the stream if the client didn't send any input for this long.
+    /// This also stops streams which are only watched, e.g. movies
+    #[serde(default)]
+    pub no_input: Option<Duration>,
+    /// Stop the stream if no video frame could be sent to the client for this long
+    #[serde(default)]
+    pub no_frames: Option<Duration>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+pub struct StreamFrameDiagnosticsConfig {
+    /// Warn if no idr frame arrived for this long.
+    /// Hosts usually only send idr frames when they're requested, so this is only useful for hosts with a fixed keyframe interval
+    #[serde(default)]
+    pub max_idr_gap: Option<Duration>,
+}
+
+fn default_recording_max_bytes() -> u64 {
+    512 * 1024 * 1024
+}
+fn default_recording_max_duration() -> Duration {
+    Duration::from_secs(5 * 60)
+}
+
+fn default_restart_max_retries() -> u32 {
+    3
+}
+fn default_restart_initial_backoff() -> Duration {
+    Duration::from_secs(1)
+}
+fn default_restart_max_backoff() -> Duration {
+    Duration::from_secs(30)
+}
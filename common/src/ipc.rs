@@ -1,33 +1,68 @@
-use std::marker::PhantomData;
+use std::{
+    collections::HashMap,
+    io,
+    marker::PhantomData,
+    sync::{
+        Arc,
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+    },
+    time::Duration,
+};
 
 use bytes::Bytes;
 use log::LevelFilter;
 use pem::Pem;
 use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use thiserror::Error;
 use tokio::{
     io::{
-        AsyncBufRead, AsyncBufReadExt, AsyncRead, AsyncWriteExt, BufReader, Lines, Stdin, Stdout,
+        AsyncBufRead, AsyncBufReadExt, AsyncRead, AsyncWriteExt, BufReader, DuplexStream, Stdin,
+        Stdout, duplex,
     },
     process::{ChildStderr, ChildStdin, ChildStdout},
     spawn,
     sync::mpsc::{Receiver, Sender, channel},
+    time::timeout,
 };
-use tracing::{Span, info, trace, warn};
+use tracing::{Level, Span, debug, error, info, trace, warn};
 
 use crate::{
-    api_bindings::{StreamClientMessage, StreamPermissions, StreamServerMessage},
-    config::WebRtcConfig,
+    api_bindings::{
+        StreamAudioMode, StreamClientMessage, StreamEndReason, StreamPermissions,
+        StreamServerMessage, StreamVideoMode,
+    },
+    config::{
+        HostConfig, StreamFrameDiagnosticsConfig, StreamIdleTimeoutConfig, StreamLimitsConfig,
+        StreamRecordingConfig, WebRtcConfig,
+    },
 };
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct StreamerConfig {
     pub webrtc: WebRtcConfig,
+    /// The per host overrides already merged with the global defaults
+    pub host: HostConfig,
     pub log_level: LevelFilter,
+    pub log_directives: Vec<String>,
+    pub ipc_compression_threshold: Option<usize>,
+    pub ipc_max_frame_size: usize,
+    /// How long the client has to resume the stream after its connection dropped
+    pub resume_timeout: Option<Duration>,
+    pub recording: Option<StreamRecordingConfig>,
+    pub idle_timeout: Option<StreamIdleTimeoutConfig>,
+    pub frame_diagnostics: Option<StreamFrameDiagnosticsConfig>,
+    pub limits: StreamLimitsConfig,
 }
 
 #[allow(clippy::large_enum_variant)]
 #[derive(Debug, Serialize, Deserialize)]
 pub enum ServerIpcMessage {
+    /// The first message sent to the streamer, the streamer refuses to start if `protocol_version` isn't its [IPC_PROTOCOL_VERSION].
+    /// This must stay the first variant and never change, so a streamer of any version can decode it
+    /// before the [ServerIpcMessage::Init] which it might not be able to decode
+    Hello {
+        protocol_version: u32,
+    },
     Init {
         config: StreamerConfig,
         host_address: String,
@@ -40,29 +75,101 @@ pub enum ServerIpcMessage {
         video_frame_queue_size: usize,
         audio_sample_queue_size: usize,
         permissions: StreamPermissions,
+        /// The already clamped video mode requested by the client
+        video_mode: Option<StreamVideoMode>,
+        /// The audio mode requested by the client or else the one of the host config
+        audio_mode: StreamAudioMode,
+        /// The most audio channels the browser can play, if it reported them
+        client_audio_channels: Option<u32>,
+        /// The already validated launch parameters requested by the client
+        launch_parameters: HashMap<String, String>,
     },
     WebSocket(StreamClientMessage),
     WebSocketTransport(Bytes),
+    /// The streamer must answer with a [StreamerIpcMessage::Pong]
+    Ping,
+    /// The streamer must answer with a [StreamerIpcMessage::Capabilities]
+    QueryCapabilities,
+    /// The streamer stops the stream and answers with [StreamerIpcMessage::Stop] once it's done
     Stop,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub enum StreamerIpcMessage {
+    /// The streamer was built with a different [IPC_PROTOCOL_VERSION] and will exit.
+    /// Like [ServerIpcMessage::Hello] this must stay the first variant and never change
+    ProtocolVersionMismatch {
+        streamer_version: u32,
+    },
     WebSocket(StreamServerMessage),
     WebSocketTransport(Bytes),
-    Stop,
+    Pong,
+    Capabilities(StreamerCapabilities),
+    Metrics(StreamerMetrics),
+    /// Sent once the stream and transport are stopped, the streamer exits shortly after this
+    Stop {
+        reason: StreamEndReason,
+    },
+}
+
+/// What the streamer can forward to the browser, independent of what the browser or host supports
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StreamerCapabilities {
+    /// The VideoFormats bits the WebRTC transport can packetize, the web socket transport forwards every format
+    pub video_formats: u32,
+    /// The maximum number of audio channels
+    pub audio_channels: u32,
+}
+
+/// Counters for the metrics of the web server, the values are the change since the last message
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct StreamerMetrics {
+    pub streamed_video_bytes: u64,
+    pub streamed_audio_bytes: u64,
+    pub webrtc_failures: u32,
+}
+
+/// Increase this when the ipc messages change in an incompatible way.
+/// The messages are encoded positionally, so this includes adding, removing or reordering a field or variant
+/// of any type they carry, e.g. [StreamerConfig], [WebRtcConfig], [HostConfig], [StreamClientMessage] or [StreamServerMessage]
+pub const IPC_PROTOCOL_VERSION: u32 = 19;
+
+pub const DEFAULT_IPC_CHANNEL_CAPACITY: usize = 10;
+
+/// The number of messages sent by all ipc channels of this process, used for metrics
+pub static IPC_MESSAGES_SENT: AtomicU64 = AtomicU64::new(0);
+/// The number of messages received by all ipc channels of this process, used for metrics
+pub static IPC_MESSAGES_RECEIVED: AtomicU64 = AtomicU64::new(0);
+
+/// The web server passes the ipc channel capacity to the streamer using this env variable,
+/// because the streamer needs it before it can receive the [ServerIpcMessage::Init]
+pub const IPC_CHANNEL_CAPACITY_ENV: &str = "MOONLIGHT_WEB_IPC_CHANNEL_CAPACITY";
+
+pub const DEFAULT_IPC_MAX_FRAME_SIZE: usize = 64 * 1024 * 1024;
+
+pub fn ipc_channel_capacity_from_env() -> usize {
+    std::env::var(IPC_CHANNEL_CAPACITY_ENV)
+        .ok()
+        .and_then(|capacity| capacity.parse().ok())
+        .filter(|capacity| *capacity > 0)
+        .unwrap_or(DEFAULT_IPC_CHANNEL_CAPACITY)
 }
 
 // We're using the:
 // Stdin: message passing
 // Stdout: message passing
 // Stderr: logging
+//
+// Messages are sent as frames: a 4 byte big endian length followed by a flags byte and the bincode encoded message.
+// Messages bigger than the compression threshold are compressed using zstd.
+// With the "ipc-json" feature every message is a single line of json instead.
 
 pub async fn create_child_ipc<Message, ChildMessage>(
     span: Span,
     stdin: ChildStdin,
     stdout: ChildStdout,
     stderr: Option<ChildStderr>,
+    channel_capacity: usize,
 ) -> (IpcSender<Message>, IpcReceiver<ChildMessage>)
 where
     Message: Send + Serialize + 'static,
@@ -70,89 +177,410 @@ where
 {
     if let Some(stderr) = stderr {
         // This is the log output of the streamer
-        let span = span.clone();
+        spawn(log_child_stderr(span.clone(), stderr));
+    }
 
-        spawn(async move {
-            let buf_reader = BufReader::new(stderr);
-            let mut lines = buf_reader.lines();
+    create_ipc(span, stdin, stdout, channel_capacity)
+}
 
-            while let Ok(Some(line)) = lines.next_line().await {
-                info!(parent: &span, "{line}");
-            }
-        });
-    }
+pub async fn create_process_ipc<ParentMessage, Message>(
+    span: Span,
+    stdin: Stdin,
+    stdout: Stdout,
+    channel_capacity: usize,
+) -> (IpcSender<Message>, IpcReceiver<ParentMessage>)
+where
+    ParentMessage: DeserializeOwned,
+    Message: Send + Serialize + 'static,
+{
+    create_ipc(span, stdout, stdin, channel_capacity)
+}
 
-    let (sender, receiver) = channel::<Message>(10);
+/// How many bytes an in memory ipc buffers in each direction before writing waits
+const IN_MEMORY_IPC_BUFFER_SIZE: usize = 64 * 1024;
 
-    spawn({
-        let span = span.clone();
+/// One end of an ipc inside a single process, e.g. when the streamer runs inside the web server
+#[derive(Debug)]
+pub struct InMemoryIpcEnd {
+    write: DuplexStream,
+    read: DuplexStream,
+}
 
-        async move {
-            ipc_sender(span.clone(), stdin, receiver).await;
-        }
-    });
+/// Creates both ends of an in memory ipc, what's sent into one end is received by the other end
+pub fn in_memory_ipc_pair() -> (InMemoryIpcEnd, InMemoryIpcEnd) {
+    let (write, other_read) = duplex(IN_MEMORY_IPC_BUFFER_SIZE);
+    let (other_write, read) = duplex(IN_MEMORY_IPC_BUFFER_SIZE);
 
     (
-        IpcSender {
-            sender,
-            span: span.clone(),
-        },
-        IpcReceiver {
-            errored: false,
-            read: create_lines(stdout),
-            phantom: Default::default(),
-            span,
+        InMemoryIpcEnd { write, read },
+        InMemoryIpcEnd {
+            write: other_write,
+            read: other_read,
         },
     )
 }
 
-pub async fn create_process_ipc<ParentMessage, Message>(
+/// Uses the same framing as the process ipc, so both sides can't tell the difference
+pub fn create_in_memory_ipc<Message, OtherMessage>(
     span: Span,
-    stdin: Stdin,
-    stdout: Stdout,
-) -> (IpcSender<Message>, IpcReceiver<ParentMessage>)
+    end: InMemoryIpcEnd,
+    channel_capacity: usize,
+) -> (IpcSender<Message>, IpcReceiver<OtherMessage>)
 where
-    ParentMessage: DeserializeOwned,
     Message: Send + Serialize + 'static,
+    OtherMessage: DeserializeOwned,
 {
-    let (sender, receiver) = channel::<Message>(10);
+    create_ipc(span, end.write, end.read, channel_capacity)
+}
+
+/// Sends messages into `write` and receives the messages of the other side from `read`
+fn create_ipc<Message, OtherMessage>(
+    span: Span,
+    write: impl AsyncWriteExt + Send + Unpin + 'static,
+    read: impl AsyncRead + Send + Unpin + 'static,
+    channel_capacity: usize,
+) -> (IpcSender<Message>, IpcReceiver<OtherMessage>)
+where
+    Message: Send + Serialize + 'static,
+    OtherMessage: DeserializeOwned,
+{
+    let (sender, receiver) = channel::<Message>(channel_capacity);
+    let compression_threshold = Arc::new(AtomicUsize::new(0));
 
     spawn({
         let span = span.clone();
+        let compression_threshold = compression_threshold.clone();
 
         async move {
-            ipc_sender(span.clone(), stdout, receiver).await;
+            ipc_sender(span.clone(), write, receiver, compression_threshold).await;
         }
     });
 
     (
         IpcSender {
             sender,
+            compression_threshold,
             span: span.clone(),
         },
         IpcReceiver {
             errored: false,
-            read: create_lines(stdin),
+            read: create_reader(read),
+            buffer: Vec::new(),
+            max_frame_size: DEFAULT_IPC_MAX_FRAME_SIZE,
             phantom: Default::default(),
             span,
         },
     )
 }
-fn create_lines(
+/// If no new line arrives in this time the buffered log record is emitted
+const STDERR_RECORD_FLUSH_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// Groups the lines of the streamer stderr into log records and logs them at the level the streamer used.
+/// Lines which don't start a new record (e.g. backtraces) are appended to the previous record.
+async fn log_child_stderr(span: Span, stderr: ChildStderr) {
+    let mut lines = BufReader::new(stderr).lines();
+
+    let mut record: Option<(Level, String)> = None;
+
+    loop {
+        let line = if record.is_some() {
+            match timeout(STDERR_RECORD_FLUSH_TIMEOUT, lines.next_line()).await {
+                Ok(line) => line,
+                Err(_) => {
+                    if let Some((level, message)) = record.take() {
+                        log_child_record(&span, level, &message);
+                    }
+                    continue;
+                }
+            }
+        } else {
+            lines.next_line().await
+        };
+
+        let Ok(Some(line)) = line else {
+            break;
+        };
+
+        match parse_child_log_line(&line) {
+            ChildLogLine::Record { level, message } => {
+                if let Some((level, message)) = record.take() {
+                    log_child_record(&span, level, &message);
+                }
+                record = Some((level, message.to_string()));
+            }
+            ChildLogLine::Continuation(line) => {
+                if let Some((_, message)) = &mut record {
+                    message.push('\n');
+                    message.push_str(line);
+                } else {
+                    record = Some((Level::INFO, line.to_string()));
+                }
+            }
+            ChildLogLine::Panic(line) => {
+                if let Some((level, message)) = record.take() {
+                    log_child_record(&span, level, &message);
+                }
+                record = Some((Level::ERROR, line.to_string()));
+            }
+        }
+    }
+
+    if let Some((level, message)) = record.take() {
+        log_child_record(&span, level, &message);
+    }
+}
+
+fn log_child_record(span: &Span, level: Level, message: &str) {
+    match level {
+        Level::ERROR => error!(parent: span, "{message}"),
+        Level::WARN => warn!(parent: span, "{message}"),
+        Level::INFO => info!(parent: span, "{message}"),
+        Level::DEBUG => debug!(parent: span, "{message}"),
+        _ => trace!(parent: span, "{message}"),
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum ChildLogLine<'a> {
+    /// A line written by the tracing fmt layer: "<timestamp> <LEVEL> <target>: <message>"
+    Record {
+        level: Level,
+        message: &'a str,
+    },
+    /// The start of a panic message, which doesn't go through tracing
+    Panic(&'a str),
+    Continuation(&'a str),
+}
+
+fn parse_child_log_line(line: &str) -> ChildLogLine<'_> {
+    if line.starts_with("thread '") && line.contains("panicked") {
+        return ChildLogLine::Panic(line);
+    }
+
+    let Some((timestamp, rest)) = line.split_once(' ') else {
+        return ChildLogLine::Continuation(line);
+    };
+    if !timestamp.starts_with(|c: char| c.is_ascii_digit()) {
+        return ChildLogLine::Continuation(line);
+    }
+
+    // The level is padded to 5 characters
+    let rest = rest.trim_start();
+    let Some((level, message)) = rest.split_once(' ') else {
+        return ChildLogLine::Continuation(line);
+    };
+
+    let level = match level {
+        "ERROR" => Level::ERROR,
+        "WARN" => Level::WARN,
+        "INFO" => Level::INFO,
+        "DEBUG" => Level::DEBUG,
+        "TRACE" => Level::TRACE,
+        _ => return ChildLogLine::Continuation(line),
+    };
+
+    ChildLogLine::Record { level, message }
+}
+
+fn create_reader(
     read: impl AsyncRead + Send + Unpin + 'static,
-) -> Lines<Box<dyn AsyncBufRead + Send + Unpin + 'static>> {
-    (Box::new(BufReader::new(read)) as Box<dyn AsyncBufRead + Send + Unpin + 'static>).lines()
+) -> Box<dyn AsyncBufRead + Send + Unpin + 'static> {
+    Box::new(BufReader::new(read))
+}
+
+#[cfg(not(feature = "ipc-json"))]
+mod framing {
+    use std::io::{self, ErrorKind, Read};
+
+    use serde::{Serialize, de::DeserializeOwned};
+    use thiserror::Error;
+    use tokio::io::{AsyncBufRead, AsyncReadExt};
+    use tracing::{Span, trace};
+
+    use super::IpcReceiveError;
+
+    const FLAG_ZSTD: u8 = 0b0000_0001;
+    const ZSTD_LEVEL: i32 = 3;
+
+    #[derive(Debug, Error)]
+    pub(super) enum FrameError {
+        #[error("the frame is empty")]
+        Empty,
+        #[error("bincode: {0}")]
+        Bincode(#[from] bincode::Error),
+        #[error("compression: {0}")]
+        Compression(#[from] io::Error),
+        #[error("the decompressed frame is bigger than {max_size} bytes")]
+        TooLarge { max_size: usize },
+    }
+
+    /// Frame layout: length (u32, big endian) of everything after it, flags (u8), body
+    pub(super) fn encode<Message>(
+        span: &Span,
+        message: &Message,
+        compression_threshold: Option<usize>,
+    ) -> Result<Vec<u8>, FrameError>
+    where
+        Message: Serialize,
+    {
+        let body = bincode::serialize(message)?;
+
+        let (flags, body) = match compression_threshold {
+            Some(threshold) if body.len() >= threshold => {
+                let compressed = zstd::bulk::compress(&body, ZSTD_LEVEL)?;
+
+                trace!(
+                    parent: span,
+                    "[Ipc]: compressed frame from {} to {} bytes (ratio {:.2})",
+                    body.len(),
+                    compressed.len(),
+                    compressed.len() as f64 / body.len() as f64
+                );
+
+                (FLAG_ZSTD, compressed)
+            }
+            _ => (0, body),
+        };
+
+        let mut frame = Vec::with_capacity(5 + body.len());
+        frame.extend_from_slice(&((body.len() + 1) as u32).to_be_bytes());
+        frame.push(flags);
+        frame.extend_from_slice(&body);
+
+        Ok(frame)
+    }
+
+    /// Returns false if the stream ended
+    pub(super) async fn read_frame(
+        read: &mut (impl AsyncBufRead + Unpin),
+        buffer: &mut Vec<u8>,
+        max_frame_size: usize,
+    ) -> Result<bool, IpcReceiveError> {
+        let len = match read.read_u32().await {
+            Ok(len) => len as usize,
+            Err(err) if err.kind() == ErrorKind::UnexpectedEof => return Ok(false),
+            Err(err) => return Err(err.into()),
+        };
+
+        if len > max_frame_size {
+            return Err(IpcReceiveError::FrameTooLarge {
+                size: len,
+                max_size: max_frame_size,
+            });
+        }
+
+        buffer.resize(len, 0);
+        read.read_exact(buffer).await?;
+
+        Ok(true)
+    }
+
+    /// A compressed frame may only decompress to `max_frame_size` bytes, so a small frame can't exhaust the memory
+    pub(super) fn decode<Message>(
+        frame: &[u8],
+        max_frame_size: usize,
+    ) -> Result<Message, FrameError>
+    where
+        Message: DeserializeOwned,
+    {
+        let Some((flags, body)) = frame.split_first() else {
+            return Err(FrameError::Empty);
+        };
+
+        if flags & FLAG_ZSTD != 0 {
+            let mut decompressed = Vec::new();
+            zstd::stream::read::Decoder::new(body)?
+                .take(max_frame_size as u64 + 1)
+                .read_to_end(&mut decompressed)?;
+
+            if decompressed.len() > max_frame_size {
+                return Err(FrameError::TooLarge {
+                    max_size: max_frame_size,
+                });
+            }
+
+            Ok(bincode::deserialize(&decompressed)?)
+        } else {
+            Ok(bincode::deserialize(body)?)
+        }
+    }
+}
+
+#[cfg(feature = "ipc-json")]
+mod framing {
+    use serde::{Serialize, de::DeserializeOwned};
+    use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt};
+    use tracing::Span;
+
+    use super::IpcReceiveError;
+
+    /// Compression isn't supported with json lines
+    pub(super) fn encode<Message>(
+        _span: &Span,
+        message: &Message,
+        _compression_threshold: Option<usize>,
+    ) -> Result<Vec<u8>, serde_json::Error>
+    where
+        Message: Serialize,
+    {
+        let mut frame = serde_json::to_vec(message)?;
+        frame.push(b'\n');
+
+        Ok(frame)
+    }
+
+    /// Returns false if the stream ended
+    pub(super) async fn read_frame(
+        read: &mut (impl AsyncBufRead + Unpin),
+        buffer: &mut Vec<u8>,
+        max_frame_size: usize,
+    ) -> Result<bool, IpcReceiveError> {
+        buffer.clear();
+
+        // Read at most one byte more than allowed so we know if the line is too long
+        let mut limited = read.take(max_frame_size as u64 + 1);
+        if limited.read_until(b'\n', buffer).await? == 0 {
+            return Ok(false);
+        }
+
+        if buffer.last() == Some(&b'\n') {
+            buffer.pop();
+        } else if buffer.len() > max_frame_size {
+            return Err(IpcReceiveError::FrameTooLarge {
+                size: buffer.len(),
+                max_size: max_frame_size,
+            });
+        }
+
+        Ok(true)
+    }
+
+    pub(super) fn decode<Message>(
+        frame: &[u8],
+        _max_frame_size: usize,
+    ) -> Result<Message, serde_json::Error>
+    where
+        Message: DeserializeOwned,
+    {
+        serde_json::from_slice(frame)
+    }
 }
 
 async fn ipc_sender<Message>(
     span: Span,
     mut write: impl AsyncWriteExt + Unpin,
     mut receiver: Receiver<Message>,
+    compression_threshold: Arc<AtomicUsize>,
 ) where
     Message: Serialize,
 {
     while let Some(value) = receiver.recv().await {
-        let mut json = match serde_json::to_string(&value) {
+        let compression_threshold = match compression_threshold.load(Ordering::Relaxed) {
+            0 => None,
+            threshold => Some(threshold),
+        };
+
+        let frame = match framing::encode(&span, &value, compression_threshold) {
             Ok(value) => value,
             Err(err) => {
                 warn!(parent: &span,"[Ipc]: failed to encode message: {err:?}");
@@ -160,12 +588,10 @@ async fn ipc_sender<Message>(
             }
         };
 
-        trace!(parent: &span, "[Ipc] sending {json}");
-
-        json.push('\n');
+        trace!(parent: &span, "[Ipc] sending frame with {} bytes", frame.len());
 
-        if let Err(err) = write.write_all(json.as_bytes()).await {
-            warn!(parent: &span, "failed to write message length: {err:?}");
+        if let Err(err) = write.write_all(&frame).await {
+            warn!(parent: &span, "failed to write message: {err:?}");
             return;
         };
 
@@ -179,6 +605,8 @@ async fn ipc_sender<Message>(
 #[derive(Debug)]
 pub struct IpcSender<Message> {
     sender: Sender<Message>,
+    /// 0 means compression is disabled
+    compression_threshold: Arc<AtomicUsize>,
     span: Span,
 }
 
@@ -186,6 +614,7 @@ impl<Message> Clone for IpcSender<Message> {
     fn clone(&self) -> Self {
         Self {
             sender: self.sender.clone(),
+            compression_threshold: self.compression_threshold.clone(),
             span: self.span.clone(),
         }
     }
@@ -198,18 +627,44 @@ where
     pub async fn send(&mut self, message: Message) {
         if self.sender.send(message).await.is_err() {
             warn!(parent: &self.span, "failed to send message");
+        } else {
+            IPC_MESSAGES_SENT.fetch_add(1, Ordering::Relaxed);
         }
     }
     pub fn blocking_send(&mut self, message: Message) {
         if self.sender.blocking_send(message).is_err() {
             warn!(parent: &self.span, "failed to send message");
+        } else {
+            IPC_MESSAGES_SENT.fetch_add(1, Ordering::Relaxed);
         }
     }
+
+    /// Messages with an encoded size of at least this many bytes will be compressed.
+    /// The receiving side always knows how to decompress a message.
+    pub fn set_compression_threshold(&self, threshold: Option<usize>) {
+        self.compression_threshold
+            .store(threshold.unwrap_or(0), Ordering::Relaxed);
+    }
+
+    /// The ipc is closed when the other process stopped reading or exited
+    pub fn is_closed(&self) -> bool {
+        self.sender.is_closed()
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum IpcReceiveError {
+    #[error("failed to read from the ipc: {0}")]
+    Io(#[from] io::Error),
+    #[error("received a frame of {size} bytes which exceeds the maximum of {max_size} bytes")]
+    FrameTooLarge { size: usize, max_size: usize },
 }
 
 pub struct IpcReceiver<Message> {
     errored: bool,
-    read: Lines<Box<dyn AsyncBufRead + Send + Unpin>>,
+    read: Box<dyn AsyncBufRead + Send + Unpin>,
+    buffer: Vec<u8>,
+    max_frame_size: usize,
     phantom: PhantomData<Message>,
     span: Span,
 }
@@ -218,27 +673,44 @@ impl<Message> IpcReceiver<Message>
 where
     Message: DeserializeOwned,
 {
+    /// Frames bigger than this will close the ipc instead of being read into memory
+    pub fn set_max_frame_size(&mut self, max_frame_size: usize) {
+        self.max_frame_size = max_frame_size;
+    }
+
+    /// If the ipc was closed because of an error instead of the other side closing it
+    pub fn is_errored(&self) -> bool {
+        self.errored
+    }
+
     pub async fn recv(&mut self) -> Option<Message> {
         if self.errored {
             return None;
         }
 
-        let line = match self.read.next_line().await {
-            Ok(Some(value)) => value,
-            Ok(None) => return None,
+        match framing::read_frame(&mut self.read, &mut self.buffer, self.max_frame_size).await {
+            Ok(true) => {}
+            Ok(false) => {
+                debug!(parent: &self.span, "[Ipc]: the other side closed the ipc");
+                return None;
+            }
             Err(err) => {
                 self.errored = true;
 
-                warn!(parent: &self.span, "failed to read next line {err:?}");
+                error!(parent: &self.span, "[Ipc]: closing the ipc because of an error: {err}");
 
                 return None;
             }
         };
 
-        trace!(parent: &self.span, "received {line}");
+        trace!(parent: &self.span, "received frame with {} bytes", self.buffer.len());
+
+        match framing::decode::<Message>(&self.buffer, self.max_frame_size) {
+            Ok(value) => {
+                IPC_MESSAGES_RECEIVED.fetch_add(1, Ordering::Relaxed);
 
-        match serde_json::from_str::<Message>(&line) {
-            Ok(value) => Some(value),
+                Some(value)
+            }
             Err(err) => {
                 warn!(parent: &self.span, "failed to deserialize message: {err:?}");
 
@@ -247,3 +719,319 @@ where
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::{collections::HashMap, fmt::Debug, time::Duration};
+
+    use bytes::Bytes;
+    use log::LevelFilter;
+    use pem::Pem;
+    use serde::{Serialize, de::DeserializeOwned};
+    use tokio::io::{AsyncWriteExt, duplex};
+    use tracing::{Level, Span};
+
+    use crate::{
+        api_bindings::{
+            LogMessageType, StreamAudioMode, StreamClientMessage, StreamEndReason,
+            StreamPermissions, StreamServerMessage, StreamVideoMode, TransportType,
+        },
+        config::{HostConfig, StreamFrameDiagnosticsConfig, StreamLimitsConfig, WebRtcConfig},
+        ipc::{
+            ChildLogLine, IPC_PROTOCOL_VERSION, IpcReceiver, IpcSender, ServerIpcMessage,
+            StreamerCapabilities, StreamerConfig, StreamerIpcMessage, StreamerMetrics,
+            create_in_memory_ipc, create_ipc, in_memory_ipc_pair, parse_child_log_line,
+        },
+    };
+
+    /// Both ends of an in memory ipc, messages sent by the first are received by the second
+    fn ipc_pair<Message>() -> (IpcSender<Message>, IpcReceiver<Message>)
+    where
+        Message: Serialize + DeserializeOwned + Send + 'static,
+    {
+        let (end, other_end) = in_memory_ipc_pair();
+
+        let (sender, _) = create_in_memory_ipc::<Message, Message>(Span::none(), end, 10);
+        let (_, receiver) = create_in_memory_ipc::<Message, Message>(Span::none(), other_end, 10);
+
+        (sender, receiver)
+    }
+
+    /// The messages don't implement [PartialEq], so they're compared by their debug output
+    async fn assert_round_trip<Message>(
+        messages: Vec<Message>,
+        compression_threshold: Option<usize>,
+    ) where
+        Message: Serialize + DeserializeOwned + Debug + Send + 'static,
+    {
+        let (mut sender, mut receiver) = ipc_pair::<Message>();
+        sender.set_compression_threshold(compression_threshold);
+
+        for message in messages {
+            let expected = format!("{message:?}");
+            sender.send(message).await;
+
+            let received = receiver.recv().await.expect("failed to receive message");
+            assert_eq!(format!("{received:?}"), expected);
+        }
+    }
+
+    fn server_messages() -> Vec<ServerIpcMessage> {
+        vec![
+            ServerIpcMessage::Hello {
+                protocol_version: IPC_PROTOCOL_VERSION,
+            },
+            ServerIpcMessage::Init {
+                config: StreamerConfig {
+                    webrtc: WebRtcConfig::default(),
+                    host: HostConfig::default(),
+                    log_level: LevelFilter::Debug,
+                    log_directives: vec!["webrtc=debug".to_string()],
+                    ipc_compression_threshold: Some(1024),
+                    ipc_max_frame_size: 1024 * 1024,
+                    resume_timeout: Some(Duration::from_secs(30)),
+                    recording: None,
+                    idle_timeout: None,
+                    frame_diagnostics: Some(StreamFrameDiagnosticsConfig {
+                        max_idr_gap: Some(Duration::from_secs(10)),
+                    }),
+                    limits: StreamLimitsConfig {
+                        max_width: Some(1920),
+                        max_height: Some(1080),
+                        max_fps: Some(60),
+                        max_bitrate_kbps: None,
+                    },
+                },
+                host_address: "192.168.1.20".to_string(),
+                host_http_port: 47989,
+                client_unique_id: Some("0123456789ABCDEF".to_string()),
+                client_private_key: Pem::new("PRIVATE KEY", vec![1, 2, 3, 4]),
+                client_certificate: Pem::new("CERTIFICATE", vec![5, 6, 7, 8]),
+                server_certificate: Pem::new("CERTIFICATE", vec![9, 10, 11, 12]),
+                app_id: 881448767,
+                video_frame_queue_size: 3,
+                audio_sample_queue_size: 20,
+                permissions: StreamPermissions {
+                    allow_add_hosts: true,
+                    maximum_bitrate_kbps: Some(20000),
+                    allow_codec_h264: true,
+                    allow_codec_h265: true,
+                    allow_codec_av1: false,
+                    allow_hdr: false,
+                    allow_transport_webrtc: true,
+                    allow_transport_websockets: false,
+                },
+                video_mode: Some(StreamVideoMode {
+                    width: 1280,
+                    height: 720,
+                    fps: 60,
+                }),
+                audio_mode: StreamAudioMode::Surround51,
+                client_audio_channels: Some(6),
+                launch_parameters: HashMap::from([("game".to_string(), "portal 2".to_string())]),
+            },
+            ServerIpcMessage::WebSocket(StreamClientMessage::SetTransport(TransportType::WebRTC)),
+            ServerIpcMessage::WebSocket(StreamClientMessage::Clipboard("hello".to_string())),
+            ServerIpcMessage::WebSocketTransport(Bytes::from_static(&[0, 1, 2, 255])),
+            ServerIpcMessage::WebSocketTransport(Bytes::new()),
+            ServerIpcMessage::Ping,
+            ServerIpcMessage::QueryCapabilities,
+            ServerIpcMessage::Stop,
+        ]
+    }
+
+    fn streamer_messages() -> Vec<StreamerIpcMessage> {
+        vec![
+            StreamerIpcMessage::WebSocket(StreamServerMessage::DebugLog {
+                message: "Moonlight Stream".to_string(),
+                ty: Some(LogMessageType::InformError),
+            }),
+            StreamerIpcMessage::WebSocketTransport(Bytes::from(vec![7; 64 * 1024])),
+            StreamerIpcMessage::Pong,
+            StreamerIpcMessage::Capabilities(StreamerCapabilities {
+                video_formats: 0b1,
+                audio_channels: 2,
+            }),
+            StreamerIpcMessage::Metrics(StreamerMetrics {
+                streamed_video_bytes: 1 << 40,
+                streamed_audio_bytes: 1234,
+                webrtc_failures: 1,
+            }),
+            StreamerIpcMessage::ProtocolVersionMismatch {
+                streamer_version: IPC_PROTOCOL_VERSION + 1,
+            },
+            StreamerIpcMessage::Stop {
+                reason: StreamEndReason::HostEnded,
+            },
+        ]
+    }
+
+    #[tokio::test]
+    async fn server_message_round_trip() {
+        assert_round_trip(server_messages(), None).await;
+    }
+
+    #[tokio::test]
+    async fn streamer_message_round_trip() {
+        assert_round_trip(streamer_messages(), None).await;
+    }
+
+    #[tokio::test]
+    async fn compressed_round_trip() {
+        assert_round_trip(server_messages(), Some(16)).await;
+        assert_round_trip(streamer_messages(), Some(16)).await;
+    }
+
+    /// How the messages of another version could look, only [ServerIpcMessage::Hello] is the same
+    #[derive(Serialize)]
+    enum OtherVersionServerIpcMessage {
+        Hello { protocol_version: u32 },
+        Init { host_port: u8 },
+    }
+
+    #[tokio::test]
+    async fn hello_of_other_version() {
+        let (end, other_end) = in_memory_ipc_pair();
+        let (mut sender, _) =
+            create_in_memory_ipc::<OtherVersionServerIpcMessage, ()>(Span::none(), end, 10);
+        let (_, mut receiver) =
+            create_in_memory_ipc::<(), ServerIpcMessage>(Span::none(), other_end, 10);
+
+        sender
+            .send(OtherVersionServerIpcMessage::Hello {
+                protocol_version: IPC_PROTOCOL_VERSION + 1,
+            })
+            .await;
+        sender
+            .send(OtherVersionServerIpcMessage::Init { host_port: 1 })
+            .await;
+
+        // The version can be checked before the init which can't be decoded
+        match receiver.recv().await {
+            Some(ServerIpcMessage::Hello { protocol_version }) => {
+                assert_eq!(protocol_version, IPC_PROTOCOL_VERSION + 1)
+            }
+            other => panic!("expected hello, got {other:?}"),
+        }
+        assert!(receiver.recv().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn malformed_frame() {
+        let (mut write, read) = duplex(1024);
+        let (unused_write, _unused_read) = duplex(1024);
+
+        let (_, mut receiver) = create_ipc::<StreamerIpcMessage, ServerIpcMessage>(
+            Span::none(),
+            unused_write,
+            read,
+            10,
+        );
+
+        #[cfg(not(feature = "ipc-json"))]
+        let frame: &[u8] = &[0, 0, 0, 3, 0, 255, 255];
+        #[cfg(feature = "ipc-json")]
+        let frame: &[u8] = b"{not json\n";
+
+        write.write_all(frame).await.unwrap();
+        assert!(receiver.recv().await.is_none());
+        assert!(!receiver.is_errored());
+    }
+
+    #[tokio::test]
+    async fn frame_too_large() {
+        let (mut write, read) = duplex(1024);
+        let (unused_write, _unused_read) = duplex(1024);
+
+        let (_, mut receiver) = create_ipc::<StreamerIpcMessage, ServerIpcMessage>(
+            Span::none(),
+            unused_write,
+            read,
+            10,
+        );
+        receiver.set_max_frame_size(16);
+
+        #[cfg(not(feature = "ipc-json"))]
+        let frame: &[u8] = &[0, 0, 1, 0];
+        #[cfg(feature = "ipc-json")]
+        let frame: &[u8] = &[b'a'; 64];
+
+        write.write_all(frame).await.unwrap();
+        assert!(receiver.recv().await.is_none());
+        assert!(receiver.is_errored());
+    }
+
+    #[cfg(not(feature = "ipc-json"))]
+    #[test]
+    fn decompressed_frame_too_large() {
+        use super::framing::{FrameError, decode};
+
+        let mut frame = vec![0b0000_0001];
+        frame.extend(zstd::bulk::compress(&[0u8; 64 * 1024], 3).unwrap());
+        assert!(frame.len() < 1024);
+
+        assert!(matches!(
+            decode::<ServerIpcMessage>(&frame, 1024),
+            Err(FrameError::TooLarge { max_size: 1024 })
+        ));
+    }
+
+    #[tokio::test]
+    async fn closed() {
+        let (write, read) = duplex(1024);
+        let (unused_write, _unused_read) = duplex(1024);
+
+        let (_, mut receiver) = create_ipc::<StreamerIpcMessage, ServerIpcMessage>(
+            Span::none(),
+            unused_write,
+            read,
+            10,
+        );
+
+        drop(write);
+        assert!(receiver.recv().await.is_none());
+        assert!(!receiver.is_errored());
+    }
+
+    #[test]
+    fn record() {
+        assert_eq!(
+            parse_child_log_line("2025-01-01T00:00:00.000000Z  WARN streamer: something happened"),
+            ChildLogLine::Record {
+                level: Level::WARN,
+                message: "streamer: something happened"
+            }
+        );
+        assert_eq!(
+            parse_child_log_line("2025-01-01T00:00:00.000000Z ERROR streamer::video: failed"),
+            ChildLogLine::Record {
+                level: Level::ERROR,
+                message: "streamer::video: failed"
+            }
+        );
+    }
+
+    #[test]
+    fn panic() {
+        assert_eq!(
+            parse_child_log_line("thread 'main' panicked at streamer/src/main.rs:10:5:"),
+            ChildLogLine::Panic("thread 'main' panicked at streamer/src/main.rs:10:5:")
+        );
+    }
+
+    #[test]
+    fn continuation() {
+        assert_eq!(
+            parse_child_log_line("   0: std::backtrace::Backtrace::create"),
+            ChildLogLine::Continuation("   0: std::backtrace::Backtrace::create")
+        );
+        assert_eq!(
+            parse_child_log_line("             at ./src/main.rs:10:5"),
+            ChildLogLine::Continuation("             at ./src/main.rs:10:5")
+        );
+        assert_eq!(
+            parse_child_log_line("failed to create host"),
+            ChildLogLine::Continuation("failed to create host")
+        );
+    }
+}
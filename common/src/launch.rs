@@ -0,0 +1,94 @@
+use std::{collections::HashMap, fmt::Write};
+
+use thiserror::Error;
+
+pub const MAX_LAUNCH_PARAMETERS: usize = 16;
+pub const MAX_LAUNCH_PARAMETER_NAME_LENGTH: usize = 32;
+pub const MAX_LAUNCH_PARAMETER_VALUE_LENGTH: usize = 256;
+
+/// Query parameters the launch request already uses, these can't be overwritten by the client
+const RESERVED_LAUNCH_PARAMETERS: &[&str] = &[
+    "uniqueid",
+    "uuid",
+    "appid",
+    "mode",
+    "additionalstates",
+    "sops",
+    "rikey",
+    "rikeyid",
+    "localaudioplaymode",
+    "surroundaudioinfo",
+    "surroundparams",
+    "remotecontrollersbitmap",
+    "gcmap",
+    "gcpersist",
+    "corever",
+    "hdrmode",
+];
+
+#[derive(Debug, Error)]
+pub enum LaunchParameterError {
+    #[error("too many launch parameters, at most {MAX_LAUNCH_PARAMETERS} are allowed")]
+    TooMany,
+    #[error(
+        "invalid launch parameter name \"{0}\", it must be 1 to {MAX_LAUNCH_PARAMETER_NAME_LENGTH} letters, digits, '_' or '-'"
+    )]
+    InvalidName(String),
+    #[error("the launch parameter \"{0}\" is used by the launch request itself")]
+    ReservedName(String),
+    #[error(
+        "the value of the launch parameter \"{0}\" must be at most {MAX_LAUNCH_PARAMETER_VALUE_LENGTH} bytes without control characters"
+    )]
+    InvalidValue(String),
+}
+
+/// Checks that the parameters can be safely appended to the launch request of the host
+pub fn validate_launch_parameters(
+    parameters: &HashMap<String, String>,
+) -> Result<(), LaunchParameterError> {
+    if parameters.len() > MAX_LAUNCH_PARAMETERS {
+        return Err(LaunchParameterError::TooMany);
+    }
+
+    for (name, value) in parameters {
+        if name.is_empty()
+            || name.len() > MAX_LAUNCH_PARAMETER_NAME_LENGTH
+            || !name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+        {
+            return Err(LaunchParameterError::InvalidName(name.clone()));
+        }
+        if RESERVED_LAUNCH_PARAMETERS.contains(&name.to_ascii_lowercase().as_str())
+            || name.to_ascii_lowercase().starts_with("clienthdr")
+        {
+            return Err(LaunchParameterError::ReservedName(name.clone()));
+        }
+        if value.len() > MAX_LAUNCH_PARAMETER_VALUE_LENGTH || value.chars().any(char::is_control) {
+            return Err(LaunchParameterError::InvalidValue(name.clone()));
+        }
+    }
+
+    Ok(())
+}
+
+/// Encodes already validated parameters as `&name=value` pairs for the launch query
+pub fn encode_launch_parameters(parameters: &HashMap<String, String>) -> String {
+    let mut parameters = parameters.iter().collect::<Vec<_>>();
+    parameters.sort();
+
+    let mut query = String::new();
+    for (name, value) in parameters {
+        let _ = write!(query, "&{name}=");
+
+        for byte in value.bytes() {
+            if byte.is_ascii_alphanumeric() || matches!(byte, b'-' | b'_' | b'.' | b'~') {
+                query.push(byte as char);
+            } else {
+                let _ = write!(query, "%{byte:02X}");
+            }
+        }
+    }
+
+    query
+}
@@ -2,12 +2,16 @@ use log::warn;
 use moonlight_common::stream::video::VideoFormats;
 use serde::Serialize;
 
-use crate::api_bindings::{StreamPermissions, StreamSettings};
+use crate::{
+    api_bindings::{StreamPermissions, StreamSettings, StreamVideoMode},
+    config::{HostConfig, HostVideoCodec, StreamLimitsConfig},
+};
 
 pub mod api_bindings;
 pub mod api_bindings_ext;
 pub mod config;
 pub mod ipc;
+pub mod launch;
 
 /// Applies the permissions / restrictions to the current settings of the user.
 /// This won't error, it'll just overwrite it, because the GUI should indicate those restrictions.
@@ -51,6 +55,130 @@ pub fn apply_permissions_to_settings(
     // Transport restrictions are handled in the streamer
 }
 
+/// Applies the per host overrides from the config to the current settings of the user.
+/// This should be done before applying the permissions so that the permissions still restrict the result.
+pub fn apply_host_config_to_settings(host_config: &HostConfig, settings: &mut StreamSettings) {
+    if let Some(bitrate_kbps) = host_config.bitrate_kbps {
+        settings.bitrate_kbps = bitrate_kbps;
+    }
+
+    if let Some(video_codecs) = &host_config.video_codecs {
+        let mut allowed_codecs = VideoFormats::empty();
+        for codec in video_codecs {
+            allowed_codecs |= host_video_codec_mask(*codec);
+        }
+
+        let supported_codecs = VideoFormats::from_bits_truncate(settings.supported_codecs);
+        settings.supported_codecs = (supported_codecs & allowed_codecs).bits();
+    }
+}
+
+/// Only keeps the most preferred codec of the host which is still supported.
+/// The host always picks the best codec it can, so this is the only way to make it use another one.
+/// This should be done after applying the permissions so that a forbidden codec isn't chosen.
+pub fn apply_codec_preference_to_settings(host_config: &HostConfig, settings: &mut StreamSettings) {
+    let Some(preferred_video_codecs) = &host_config.preferred_video_codecs else {
+        return;
+    };
+
+    let supported_codecs = VideoFormats::from_bits_truncate(settings.supported_codecs);
+    for codec in preferred_video_codecs {
+        let preferred_codecs = supported_codecs & host_video_codec_mask(*codec);
+
+        if !preferred_codecs.is_empty() {
+            settings.supported_codecs = preferred_codecs.bits();
+            return;
+        }
+    }
+}
+
+/// Scales the resolution down to fit the limits while keeping its aspect ratio and limits the fps.
+/// Width and height are kept even because most encoders require it.
+pub fn apply_stream_limits_to_video_mode(
+    limits: &StreamLimitsConfig,
+    video_mode: &mut StreamVideoMode,
+) {
+    let mut scale = 1.0f64;
+    if let Some(max_width) = limits.max_width
+        && video_mode.width > max_width
+    {
+        scale = scale.min(max_width as f64 / video_mode.width as f64);
+    }
+    if let Some(max_height) = limits.max_height
+        && video_mode.height > max_height
+    {
+        scale = scale.min(max_height as f64 / video_mode.height as f64);
+    }
+
+    if scale < 1.0 {
+        video_mode.width = ((video_mode.width as f64 * scale) as u32).max(2) & !1;
+        video_mode.height = ((video_mode.height as f64 * scale) as u32).max(2) & !1;
+    }
+
+    if let Some(max_fps) = limits.max_fps {
+        video_mode.fps = video_mode.fps.min(max_fps);
+    }
+}
+
+/// Applies the limits of the server config, this should be done after everything else so nothing can exceed them
+pub fn apply_stream_limits_to_settings(limits: &StreamLimitsConfig, settings: &mut StreamSettings) {
+    let mut video_mode = StreamVideoMode {
+        width: settings.width,
+        height: settings.height,
+        fps: settings.fps,
+    };
+    apply_stream_limits_to_video_mode(limits, &mut video_mode);
+
+    settings.width = video_mode.width;
+    settings.height = video_mode.height;
+    settings.fps = video_mode.fps;
+
+    if let Some(max_bitrate_kbps) = limits.max_bitrate_kbps {
+        settings.bitrate_kbps = settings.bitrate_kbps.min(max_bitrate_kbps);
+    }
+}
+
+/// The bitrate requested from the host in audio only mode
+pub const AUDIO_ONLY_VIDEO_BITRATE_KBPS: u32 = 500;
+
+/// Moonlight can't stream without video, so in audio only mode the host is asked for the cheapest video stream possible.
+/// This should be done last so that nothing raises the bitrate again.
+pub fn apply_audio_only_to_settings(settings: &mut StreamSettings) {
+    if !settings.audio_only {
+        return;
+    }
+
+    settings.bitrate_kbps = settings.bitrate_kbps.min(AUDIO_ONLY_VIDEO_BITRATE_KBPS);
+    settings.hdr = false;
+
+    // Every host supports h264, the other codecs only take longer to set up
+    let supported_codecs = VideoFormats::from_bits_truncate(settings.supported_codecs);
+    let h264 = supported_codecs & VideoFormats::MASK_H264;
+    if !h264.is_empty() {
+        settings.supported_codecs = h264.bits();
+    }
+}
+
+/// The video mode requested when launching the stream overrides the one of the settings
+pub fn apply_video_mode_to_settings(
+    video_mode: Option<StreamVideoMode>,
+    settings: &mut StreamSettings,
+) {
+    if let Some(video_mode) = video_mode {
+        settings.width = video_mode.width;
+        settings.height = video_mode.height;
+        settings.fps = video_mode.fps;
+    }
+}
+
+fn host_video_codec_mask(codec: HostVideoCodec) -> VideoFormats {
+    match codec {
+        HostVideoCodec::H264 => VideoFormats::MASK_H264,
+        HostVideoCodec::H265 => VideoFormats::MASK_H265,
+        HostVideoCodec::Av1 => VideoFormats::MASK_AV1,
+    }
+}
+
 pub fn serialize_json<T>(message: &T) -> Option<String>
 where
     T: Serialize,
@@ -0,0 +1,470 @@
+//! Obtains and renews the https certificate using acme (e.g. Let's Encrypt) with http-01 challenges
+
+use std::{
+    cmp::Ordering,
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+
+use actix_web::{
+    App as ActixApp, HttpResponse, HttpServer, get,
+    web::{Data, Path as UrlPath},
+};
+use anyhow::{Context, anyhow, bail};
+use common::config::ConfigAcme;
+use instant_acme::{
+    Account, AccountCredentials, AuthorizationStatus, ChallengeType, Identifier, NewAccount,
+    NewOrder, OrderStatus,
+};
+use log::{error, info, warn};
+use openssl::{
+    asn1::Asn1Time,
+    ec::{EcGroup, EcKey},
+    error::ErrorStack,
+    hash::MessageDigest,
+    nid::Nid,
+    pkey::{PKey, Private},
+    ssl::{SniError, SslAcceptor, SslAcceptorBuilder, SslContext, SslMethod},
+    stack::Stack,
+    x509::{X509, X509NameBuilder, X509ReqBuilder, extension::SubjectAlternativeName},
+};
+use tokio::{fs, io::AsyncWriteExt, spawn, time::sleep};
+
+/// Renew the certificate if it expires in less than this many days
+const RENEW_BEFORE_DAYS: u32 = 30;
+const RENEW_CHECK_INTERVAL: Duration = Duration::from_hours(12);
+const RENEW_RETRY_INTERVAL: Duration = Duration::from_hours(1);
+
+const ACCOUNT_FILE: &str = "account.json";
+const CERTIFICATE_FILE: &str = "certificate.pem";
+const PRIVATE_KEY_FILE: &str = "private_key.pem";
+
+type Challenges = Arc<RwLock<HashMap<String, String>>>;
+
+struct Certificate {
+    chain: Vec<X509>,
+    private_key: PKey<Private>,
+    context: SslContext,
+}
+
+impl Certificate {
+    fn from_pem(chain_pem: &[u8], private_key_pem: &[u8]) -> Result<Self, ErrorStack> {
+        let chain = X509::stack_from_pem(chain_pem)?;
+        let private_key = PKey::private_key_from_pem(private_key_pem)?;
+
+        let mut builder = SslAcceptor::mozilla_intermediate(SslMethod::tls())?;
+        set_certificate(&mut builder, &chain, &private_key)?;
+        let context = builder.build().into_context();
+
+        Ok(Self {
+            chain,
+            private_key,
+            context,
+        })
+    }
+
+    fn expires_soon(&self) -> Result<bool, ErrorStack> {
+        let Some(leaf) = self.chain.first() else {
+            return Ok(true);
+        };
+
+        let renew_at = Asn1Time::days_from_now(RENEW_BEFORE_DAYS)?;
+
+        Ok(leaf.not_after().compare(&renew_at)? == Ordering::Less)
+    }
+}
+
+fn set_certificate(
+    builder: &mut SslAcceptorBuilder,
+    chain: &[X509],
+    private_key: &PKey<Private>,
+) -> Result<(), ErrorStack> {
+    builder.set_private_key(private_key)?;
+    if let Some((leaf, intermediates)) = chain.split_first() {
+        builder.set_certificate(leaf)?;
+        for intermediate in intermediates {
+            builder.add_extra_chain_cert(intermediate.clone())?;
+        }
+    }
+    builder.check_private_key()
+}
+
+/// A certificate which is renewed in the background.
+/// Renewed certificates are used for new connections, existing connections are not affected.
+#[derive(Clone)]
+pub struct AcmeCertificate {
+    current: Arc<RwLock<Certificate>>,
+}
+
+impl AcmeCertificate {
+    /// Starts the http-01 challenge server, loads the cached certificate or obtains a new one and starts renewing it in the background.
+    pub async fn start(config: ConfigAcme) -> Result<Self, anyhow::Error> {
+        let challenges = Challenges::default();
+        start_challenge_server(&config, challenges.clone())?;
+
+        let certificate = match load_cached_certificate(&config).await {
+            Ok(Some(certificate)) => certificate,
+            Ok(None) => obtain_certificate(&config, &challenges).await?,
+            Err(err) => {
+                warn!("[Acme]: Failed to load cached certificate, obtaining a new one: {err:?}");
+                obtain_certificate(&config, &challenges).await?
+            }
+        };
+
+        let this = Self {
+            current: Arc::new(RwLock::new(certificate)),
+        };
+
+        spawn({
+            let this = this.clone();
+            async move {
+                this.renew_loop(config, challenges).await;
+            }
+        });
+
+        Ok(this)
+    }
+
+    /// Creates a new acceptor which always uses the most recent certificate
+    pub fn create_acceptor(&self) -> Result<SslAcceptorBuilder, anyhow::Error> {
+        let mut builder = SslAcceptor::mozilla_intermediate(SslMethod::tls())?;
+
+        {
+            let current = self
+                .current
+                .read()
+                .map_err(|_| anyhow!("the acme certificate lock was poisoned"))?;
+            set_certificate(&mut builder, &current.chain, &current.private_key)?;
+        }
+
+        let current = self.current.clone();
+        builder.set_servername_callback(move |ssl, _| {
+            let Ok(current) = current.read() else {
+                return Err(SniError::ALERT_FATAL);
+            };
+
+            ssl.set_ssl_context(&current.context)
+                .map_err(|_| SniError::ALERT_FATAL)
+        });
+
+        Ok(builder)
+    }
+
+    async fn renew_loop(self, config: ConfigAcme, challenges: Challenges) {
+        let mut wait = RENEW_CHECK_INTERVAL;
+        loop {
+            sleep(wait).await;
+
+            let expires_soon = match self.current.read() {
+                Ok(current) => current.expires_soon(),
+                Err(_) => {
+                    error!("[Acme]: The certificate lock was poisoned, stopping renewal");
+                    return;
+                }
+            };
+
+            match expires_soon {
+                Ok(false) => {
+                    wait = RENEW_CHECK_INTERVAL;
+                    continue;
+                }
+                Ok(true) => {}
+                Err(err) => {
+                    warn!(
+                        "[Acme]: Failed to check the expiration of the certificate, renewing it: {err}"
+                    );
+                }
+            }
+
+            info!("[Acme]: Renewing certificate for {}", config.domain);
+            match obtain_certificate(&config, &challenges).await {
+                Ok(certificate) => {
+                    if let Ok(mut current) = self.current.write() {
+                        *current = certificate;
+                    }
+                    wait = RENEW_CHECK_INTERVAL;
+                }
+                Err(err) => {
+                    error!(
+                        "[Acme]: Failed to renew certificate, retrying in {RENEW_RETRY_INTERVAL:?}: {err:?}"
+                    );
+                    wait = RENEW_RETRY_INTERVAL;
+                }
+            }
+        }
+    }
+}
+
+fn start_challenge_server(
+    config: &ConfigAcme,
+    challenges: Challenges,
+) -> Result<(), anyhow::Error> {
+    let server = HttpServer::new(move || {
+        ActixApp::new()
+            .app_data(Data::new(challenges.clone()))
+            .service(acme_challenge)
+    })
+    .workers(1)
+    .bind(config.challenge_bind_address)
+    .with_context(|| {
+        format!(
+            "failed to bind the acme challenge server to {}",
+            config.challenge_bind_address
+        )
+    })?
+    .run();
+
+    info!(
+        "[Acme]: Serving http-01 challenges on {}",
+        config.challenge_bind_address
+    );
+
+    spawn(async move {
+        if let Err(err) = server.await {
+            error!("[Acme]: The challenge server stopped: {err}");
+        }
+    });
+
+    Ok(())
+}
+
+#[get("/.well-known/acme-challenge/{token}")]
+async fn acme_challenge(challenges: Data<Challenges>, token: UrlPath<String>) -> HttpResponse {
+    let key_authorization = challenges
+        .read()
+        .ok()
+        .and_then(|challenges| challenges.get(token.as_str()).cloned());
+
+    match key_authorization {
+        Some(key_authorization) => HttpResponse::Ok()
+            .content_type("application/octet-stream")
+            .body(key_authorization),
+        None => HttpResponse::NotFound().finish(),
+    }
+}
+
+fn cache_path(config: &ConfigAcme, file: &str) -> PathBuf {
+    Path::new(&config.cache_dir).join(file)
+}
+
+async fn load_cached_certificate(
+    config: &ConfigAcme,
+) -> Result<Option<Certificate>, anyhow::Error> {
+    let chain_path = cache_path(config, CERTIFICATE_FILE);
+    let private_key_path = cache_path(config, PRIVATE_KEY_FILE);
+
+    if !fs::try_exists(&chain_path).await? || !fs::try_exists(&private_key_path).await? {
+        return Ok(None);
+    }
+
+    let certificate = Certificate::from_pem(
+        &fs::read(&chain_path).await?,
+        &fs::read(&private_key_path).await?,
+    )?;
+
+    if certificate.expires_soon()? {
+        info!("[Acme]: The cached certificate expires soon");
+        return Ok(None);
+    }
+
+    info!("[Acme]: Using cached certificate from {chain_path:?}");
+
+    Ok(Some(certificate))
+}
+
+async fn load_or_create_account(config: &ConfigAcme) -> Result<Account, anyhow::Error> {
+    let account_path = cache_path(config, ACCOUNT_FILE);
+
+    if fs::try_exists(&account_path).await? {
+        let credentials: AccountCredentials =
+            serde_json::from_slice(&fs::read(&account_path).await?)?;
+
+        return Ok(Account::from_credentials(credentials).await?);
+    }
+
+    info!(
+        "[Acme]: Creating new acme account for {}",
+        config.contact_email
+    );
+
+    let contact = format!("mailto:{}", config.contact_email);
+    let (account, credentials) = Account::create(
+        &NewAccount {
+            contact: &[&contact],
+            terms_of_service_agreed: true,
+            only_return_existing: false,
+        },
+        &config.directory_url,
+        None,
+    )
+    .await?;
+
+    fs::create_dir_all(&config.cache_dir).await?;
+    write_private(&account_path, &serde_json::to_vec(&credentials)?).await?;
+
+    Ok(account)
+}
+
+async fn obtain_certificate(
+    config: &ConfigAcme,
+    challenges: &Challenges,
+) -> Result<Certificate, anyhow::Error> {
+    info!("[Acme]: Obtaining certificate for {}", config.domain);
+
+    let account = load_or_create_account(config).await?;
+
+    let mut order = account
+        .new_order(&NewOrder {
+            identifiers: &[Identifier::Dns(config.domain.clone())],
+        })
+        .await?;
+
+    let authorizations = order.authorizations().await?;
+    let mut tokens = Vec::new();
+    for authorization in &authorizations {
+        match authorization.status {
+            AuthorizationStatus::Pending => {}
+            AuthorizationStatus::Valid => continue,
+            status => bail!("the acme authorization has an unexpected status: {status:?}"),
+        }
+
+        let challenge = authorization
+            .challenges
+            .iter()
+            .find(|challenge| challenge.r#type == ChallengeType::Http01)
+            .context("the acme server didn't offer a http-01 challenge")?;
+
+        let key_authorization = order.key_authorization(challenge);
+        if let Ok(mut challenges) = challenges.write() {
+            challenges.insert(
+                challenge.token.clone(),
+                key_authorization.as_str().to_string(),
+            );
+        }
+        tokens.push(challenge.token.clone());
+
+        order.set_challenge_ready(&challenge.url).await?;
+    }
+
+    let result = finish_order(config, &mut order).await;
+
+    if let Ok(mut challenges) = challenges.write() {
+        for token in tokens {
+            challenges.remove(&token);
+        }
+    }
+
+    let (chain_pem, private_key_pem) = result?;
+
+    let certificate = Certificate::from_pem(chain_pem.as_bytes(), &private_key_pem)?;
+
+    fs::create_dir_all(&config.cache_dir).await?;
+    fs::write(cache_path(config, CERTIFICATE_FILE), &chain_pem).await?;
+    write_private(&cache_path(config, PRIVATE_KEY_FILE), &private_key_pem).await?;
+
+    info!("[Acme]: Obtained certificate for {}", config.domain);
+
+    Ok(certificate)
+}
+
+/// Returns the certificate chain and the private key in the pem format
+async fn finish_order(
+    config: &ConfigAcme,
+    order: &mut instant_acme::Order,
+) -> Result<(String, Vec<u8>), anyhow::Error> {
+    let mut delay = Duration::from_millis(250);
+    let mut tries = 0;
+    let status = loop {
+        sleep(delay).await;
+
+        let state = order.refresh().await?;
+        if let OrderStatus::Ready | OrderStatus::Invalid = state.status {
+            break state.status;
+        }
+
+        tries += 1;
+        if tries >= 10 {
+            bail!(
+                "the acme order didn't get ready in time: {:?}",
+                state.status
+            );
+        }
+        delay *= 2;
+    };
+
+    if status != OrderStatus::Ready {
+        bail!(
+            "the acme order is invalid, is the challenge server reachable on port 80 of the domain?"
+        );
+    }
+
+    let private_key = PKey::from_ec_key(EcKey::generate(&EcGroup::from_curve_name(
+        Nid::X9_62_PRIME256V1,
+    )?)?)?;
+    let csr = create_csr(&config.domain, &private_key)?;
+
+    order.finalize(&csr).await?;
+
+    let mut tries = 0;
+    let chain_pem = loop {
+        if let Some(chain_pem) = order.certificate().await? {
+            break chain_pem;
+        }
+
+        tries += 1;
+        if tries >= 10 {
+            bail!("the acme server didn't issue the certificate in time");
+        }
+        sleep(Duration::from_secs(1)).await;
+    };
+
+    Ok((chain_pem, private_key.private_key_to_pem_pkcs8()?))
+}
+
+/// Writes a file which only the owner can read, e.g. keys or the account credentials
+async fn write_private(path: &Path, contents: &[u8]) -> Result<(), std::io::Error> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    options.mode(0o600);
+
+    let mut file = options.open(path).await?;
+
+    // The mode only applies to new files, so files of older versions have to be fixed
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+
+        file.set_permissions(std::fs::Permissions::from_mode(0o600))
+            .await?;
+    }
+
+    file.write_all(contents).await?;
+    file.flush().await?;
+
+    Ok(())
+}
+
+fn create_csr(domain: &str, private_key: &PKey<Private>) -> Result<Vec<u8>, ErrorStack> {
+    let mut name = X509NameBuilder::new()?;
+    name.append_entry_by_nid(Nid::COMMONNAME, domain)?;
+    let name = name.build();
+
+    let mut request = X509ReqBuilder::new()?;
+    request.set_subject_name(&name)?;
+    request.set_pubkey(private_key)?;
+
+    let mut extensions = Stack::new()?;
+    extensions.push(
+        SubjectAlternativeName::new()
+            .dns(domain)
+            .build(&request.x509v3_context(None))?,
+    )?;
+    request.add_extensions(&extensions)?;
+
+    request.sign(private_key, MessageDigest::sha256())?;
+
+    request.build().to_der()
+}
@@ -0,0 +1,36 @@
+use actix_web::{
+    HttpResponse, delete, get,
+    web::{Data, Json, Path},
+};
+use common::api_bindings::GetAdminSessionsResponse;
+use tracing::info;
+
+use crate::app::{App, AppError, sessions::SessionId, user::Admin};
+
+/// Lists all running streams of every user
+#[get("/admin/sessions")]
+pub async fn list_sessions(
+    app: Data<App>,
+    _admin: Admin,
+) -> Result<Json<GetAdminSessionsResponse>, AppError> {
+    Ok(Json(GetAdminSessionsResponse {
+        sessions: app.sessions().list(),
+    }))
+}
+
+/// Asks the streamer of the session to stop, the stream ends like it was stopped by the client
+#[delete("/admin/sessions/{id}")]
+pub async fn stop_session(
+    app: Data<App>,
+    _admin: Admin,
+    id: Path<u32>,
+) -> Result<HttpResponse, AppError> {
+    let id = SessionId(id.into_inner());
+
+    if !app.sessions().stop(id).await {
+        return Err(AppError::StreamSessionNotFound);
+    }
+    info!("[Admin]: stopped stream session {}", id.0);
+
+    Ok(HttpResponse::Ok().finish())
+}
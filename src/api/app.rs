@@ -21,9 +21,10 @@ async fn get_apps(
 
     let mut host = user.host(host_id).await?;
 
-    let apps = host.list_apps(&mut user).await?;
+    let (apps, stale) = host.list_apps(&mut user, query.force_refresh).await?;
 
     Ok(Json(GetAppsResponse {
+        stale,
         apps: apps
             .into_iter()
             .map(|app| api_bindings::App {
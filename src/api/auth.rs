@@ -15,6 +15,8 @@ use std::{pin::Pin, time::Duration};
 use crate::app::{
     App, AppError,
     auth::{SessionToken, UserAuth},
+    client_ip::client_ip,
+    rate_limit::RateLimitedAction,
     user::{Admin, AuthenticatedUser},
 };
 
@@ -112,8 +114,17 @@ impl FromRequest for Admin {
 #[post("/login")]
 async fn login(
     app: Data<App>,
+    req: HttpRequest,
     Json(request): Json<PostLoginRequest>,
 ) -> Result<HttpResponse, Error> {
+    let client_ip = client_ip(&app.config().web_server.trusted_proxies, &req);
+    if let Some(rate_limit) = &app.config().web_server.rate_limit
+        && let Some(client_ip) = client_ip
+    {
+        app.rate_limiter()
+            .attempt(rate_limit, RateLimitedAction::Login, client_ip)?;
+    }
+
     let user = if app.config().web_server.first_login_create_admin {
         match app
             .try_add_first_login(request.name.clone(), request.password.clone())
@@ -137,6 +148,11 @@ async fn login(
         .await?
     };
 
+    if let Some(client_ip) = client_ip {
+        app.rate_limiter()
+            .reset(RateLimitedAction::Login, client_ip);
+    }
+
     let session_expiration = app.config().web_server.session_cookie_expiration;
 
     let session = user.new_session(session_expiration).await?;
@@ -188,7 +204,7 @@ pub async fn auth_middleware(
 
 pub fn build_cookie<'a>(app: &'a App, expiration: Duration, session_str: &'a str) -> Cookie<'a> {
     Cookie::build(COOKIE_SESSION_TOKEN_NAME, session_str)
-        .path(&app.config().web_server.url_path_prefix)
+        .path(app.config().web_server.url_path_prefix.clone())
         .same_site(SameSite::Strict)
         .http_only(true) // not accessible via js
         .secure(app.config().web_server.session_cookie_secure)
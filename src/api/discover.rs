@@ -0,0 +1,21 @@
+use actix_web::{
+    get,
+    web::{Data, Json},
+};
+use common::api_bindings::GetDiscoverResponse;
+
+use crate::app::{App, AppError, user::AuthenticatedUser};
+
+#[get("/discover")]
+pub async fn discover_hosts(
+    app: Data<App>,
+    _user: AuthenticatedUser,
+) -> Result<Json<GetDiscoverResponse>, AppError> {
+    if !app.config().discovery.enabled {
+        return Err(AppError::DiscoveryDisabled);
+    }
+
+    let hosts = app.discover_hosts().await?;
+
+    Ok(Json(GetDiscoverResponse { hosts }))
+}
@@ -1,12 +1,14 @@
+use std::{sync::atomic::Ordering, time::Duration};
+
 use actix_web::{
-    HttpResponse, delete, get, patch, post,
+    HttpRequest, HttpResponse, delete, get, patch, post,
     rt::spawn,
     web::{Data, Json, Query},
 };
 use common::api_bindings::{
-    DeleteHostQuery, GetHostQuery, GetHostResponse, GetHostsResponse, PatchHostRequest,
-    PostHostRequest, PostHostResponse, PostPairRequest, PostPairResponse1, PostPairResponse2,
-    PostWakeUpRequest, UndetailedHost,
+    DeleteHostQuery, DeletePairQuery, GetHostQuery, GetHostResponse, GetHostsResponse,
+    PatchHostRequest, PostHostRequest, PostHostResponse, PostPairRequest, PostPairResponse1,
+    PostPairResponse2, PostWakeUpRequest, UndetailedHost,
 };
 use futures::future::try_join_all;
 use moonlight_common::{crypto::openssl::OpenSSLCryptoBackend, http::pair::PairPin};
@@ -16,7 +18,9 @@ use crate::{
     api::response_streaming::StreamedResponse,
     app::{
         App, AppError,
+        client_ip::client_ip,
         host::HostId,
+        rate_limit::RateLimitedAction,
         storage::StorageHostModify,
         user::{AuthenticatedUser, RoleType, UserId},
     },
@@ -91,14 +95,15 @@ async fn post_host(
     mut user: AuthenticatedUser,
     Json(request): Json<PostHostRequest>,
 ) -> Result<Json<PostHostResponse>, AppError> {
-    let mut host = user
-        .host_add(
-            request.address,
-            request
-                .http_port
-                .unwrap_or(app.config().moonlight.default_http_port),
-        )
-        .await?;
+    let config = app.config();
+    let http_port = request.http_port.unwrap_or_else(|| {
+        config
+            .host_config(&request.address, None)
+            .http_port
+            .unwrap_or(config.moonlight.default_http_port)
+    });
+
+    let mut host = user.host_add(request.address, http_port).await?;
 
     Ok(Json(PostHostResponse {
         host: host.detailed_host(&mut user).await?,
@@ -147,19 +152,37 @@ async fn delete_host(
 
 #[post("/pair")]
 async fn pair_host(
+    app: Data<App>,
+    req: HttpRequest,
     mut user: AuthenticatedUser,
     Json(request): Json<PostPairRequest>,
 ) -> Result<StreamedResponse<PostPairResponse1, PostPairResponse2>, AppError> {
     let host_id = HostId(request.host_id);
 
+    if let Some(rate_limit) = &app.config().web_server.rate_limit
+        && let Some(client_ip) = client_ip(&app.config().web_server.trusted_proxies, &req)
+    {
+        app.rate_limiter()
+            .attempt(rate_limit, RateLimitedAction::Pair, client_ip)?;
+    }
+
+    app.metrics()
+        .pair_attempts_total
+        .fetch_add(1, Ordering::Relaxed);
+
     let mut host = user.host(host_id).await?;
 
+    // A second click would race this pairing and fail
+    let pairing_guard = app.rate_limiter().start_pairing(host_id)?;
+
     let pin = PairPin::new_random(&OpenSSLCryptoBackend)?;
 
     let (stream_response, stream_sender) =
         StreamedResponse::new(PostPairResponse1::Pin(pin.to_string()));
 
     spawn(async move {
+        let _pairing_guard = pairing_guard;
+
         let result = host.pair(&mut user, pin).await;
 
         let result = match result {
@@ -188,16 +211,50 @@ async fn pair_host(
     Ok(stream_response)
 }
 
+#[delete("/pair")]
+async fn unpair_host(
+    mut user: AuthenticatedUser,
+    Query(query): Query<DeletePairQuery>,
+) -> Result<HttpResponse, AppError> {
+    let host_id = HostId(query.host_id);
+
+    let mut host = user.host(host_id).await?;
+
+    host.unpair(&mut user).await?;
+
+    warn!(
+        "Unpaired host {host_id:?}, the client might still be listed in the host and should be removed there"
+    );
+
+    Ok(HttpResponse::Ok().finish())
+}
+
 #[post("/host/wake")]
 async fn wake_host(
+    app: Data<App>,
     mut user: AuthenticatedUser,
     Json(request): Json<PostWakeUpRequest>,
 ) -> Result<HttpResponse, AppError> {
+    const DEFAULT_WAKE_TIMEOUT: Duration = Duration::from_secs(60);
+
     let host_id = HostId(request.host_id);
 
-    let host = user.host(host_id).await?;
+    let mut host = user.host(host_id).await?;
 
     host.wake(&mut user).await?;
 
+    if request.wait_online {
+        let (address, _) = host.address_port(&mut user).await?;
+        let wake_timeout = app
+            .config()
+            .host_config(&address, None)
+            .wake_timeout
+            .unwrap_or(DEFAULT_WAKE_TIMEOUT);
+
+        if !host.wait_online(&mut user, wake_timeout).await? {
+            return Err(AppError::WakeTimeout);
+        }
+    }
+
     Ok(HttpResponse::Ok().finish())
 }
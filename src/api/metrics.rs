@@ -0,0 +1,27 @@
+use actix_web::{HttpResponse, get, web::Data};
+
+use crate::app::{App, AppError, user::Admin};
+
+const PROMETHEUS_CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+/// Only available for admins and only if `metrics.enabled` is set
+#[get("/metrics")]
+pub async fn get_metrics(app: Data<App>, _admin: Admin) -> Result<HttpResponse, AppError> {
+    if !app.config().metrics.enabled {
+        return Ok(HttpResponse::NotFound().finish());
+    }
+
+    Ok(metrics_response(&app))
+}
+
+/// Served without authentication on `metrics.bind_address`
+#[get("/metrics")]
+pub async fn get_public_metrics(app: Data<App>) -> HttpResponse {
+    metrics_response(&app)
+}
+
+fn metrics_response(app: &App) -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type(PROMETHEUS_CONTENT_TYPE)
+        .body(app.metrics().render(app.config().streamer.max_sessions))
+}
@@ -6,17 +6,25 @@ use actix_web::{
 };
 
 use crate::api::{
+    admin::{list_sessions, stop_session},
     app::{get_app_image, get_apps},
     auth::auth_middleware,
-    host::{delete_host, get_host, list_hosts, pair_host, patch_host, post_host, wake_host},
+    discover::discover_hosts,
+    host::{
+        delete_host, get_host, list_hosts, pair_host, patch_host, post_host, unpair_host, wake_host,
+    },
+    metrics::get_metrics,
     role::{add_role, delete_role, get_role, list_roles, patch_role},
     settings::{get_default_settings, get_permissions},
     user::{add_user, delete_user, get_user, list_users, patch_user},
 };
 
+pub mod admin;
 pub mod app;
 pub mod auth;
+pub mod discover;
 pub mod host;
+pub mod metrics;
 pub mod role;
 pub mod settings;
 pub mod stream;
@@ -42,6 +50,8 @@ pub fn api_service() -> impl HttpServiceFactory {
             wake_host,
             delete_host,
             pair_host,
+            unpair_host,
+            discover_hosts,
         ])
         .service(services![
             // -- Apps
@@ -74,4 +84,13 @@ pub fn api_service() -> impl HttpServiceFactory {
             stream::start_host,
             stream::cancel_host,
         ])
+        .service(services![
+            // -- Metrics
+            get_metrics,
+        ])
+        .service(services![
+            // -- Admin
+            list_sessions,
+            stop_session,
+        ])
 }
@@ -1,30 +1,63 @@
 use std::{
+    collections::HashMap,
+    io,
     process::Stdio,
-    sync::atomic::{AtomicUsize, Ordering},
+    sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    },
     time::Duration,
 };
 
 use actix_web::{
-    Error, HttpRequest, HttpResponse, get, post, rt as actix_rt,
-    web::{Data, Json, Payload},
+    Error, HttpRequest, HttpResponse, get,
+    http::header::{self, HeaderValue},
+    post, rt as actix_rt,
+    web::{Data, Json, Payload, Query},
 };
-use actix_ws::{Closed, Message, Session};
+use actix_ws::{CloseCode, CloseReason, Closed, Message, MessageStream, Session};
+#[cfg(feature = "in-process-streamer")]
+use common::ipc::{create_in_memory_ipc, in_memory_ipc_pair};
 use common::{
     api_bindings::{
-        LogMessageType, PostCancelRequest, PostCancelResponse, StreamClientMessage,
-        StreamServerMessage,
+        GetStreamQuery, LogMessageType, PostCancelRequest, PostCancelResponse, StreamAudioMode,
+        StreamClientMessage, StreamEndReason, StreamPermissions, StreamProtocol,
+        StreamServerMessage, StreamStage, StreamStageError, StreamVideoMode,
+        stream_web_socket_protocol,
+    },
+    apply_stream_limits_to_video_mode,
+    config::{Config, HostConfig, WebRtcConfig},
+    ipc::{
+        IPC_CHANNEL_CAPACITY_ENV, IPC_PROTOCOL_VERSION, IpcReceiver, IpcSender, ServerIpcMessage,
+        StreamerConfig, StreamerIpcMessage, create_child_ipc,
     },
-    ipc::{ServerIpcMessage, StreamerConfig, StreamerIpcMessage, create_child_ipc},
+    launch::validate_launch_parameters,
     serialize_json,
 };
 use log::{debug, error, info, warn};
-use tokio::{process::Command, spawn, time::sleep};
-use tracing::{Level, instrument, span};
+use tokio::{
+    process::{Child, Command},
+    select, spawn,
+    sync::{
+        Mutex,
+        mpsc::{UnboundedReceiver, unbounded_channel},
+        watch,
+    },
+    time::{sleep, timeout},
+};
+use tracing::{Level, Span, instrument, span};
 
+#[cfg(feature = "in-process-streamer")]
+use crate::app::in_process_streamer::{InProcessStreamer, InProcessStreamerError};
 use crate::app::{
-    App, AppError,
+    App, AppError, ShutdownState,
+    auth::SessionToken,
     host::{AppId, HostId},
-    user::AuthenticatedUser,
+    metrics::Metrics,
+    resumable::{Resumable, ResumableStreams},
+    sessions::{RegisteredSession, SessionInfo},
+    storage::StorageHostPairInfo,
+    user::{AuthenticatedUser, UserId},
 };
 
 #[get("/host/stream")]
@@ -32,10 +65,39 @@ use crate::app::{
 pub async fn start_host(
     web_app: Data<App>,
     mut user: AuthenticatedUser,
+    Query(query): Query<GetStreamQuery>,
     request: HttpRequest,
     payload: Payload,
 ) -> Result<HttpResponse, Error> {
-    let (response, mut session, mut stream) = actix_ws::handle(&request, payload)?;
+    if web_app.is_shutting_down() {
+        return Err(AppError::ShuttingDown.into());
+    }
+
+    // Tampered or expired tokens are rejected before the upgrade
+    let resume_token = match &query.session_token {
+        Some(session_token) => Some(web_app.stream_tokens().verify(session_token, user.id())?),
+        None => None,
+    };
+
+    // A resumed stream is already counted as active
+    if resume_token.is_none()
+        && let Some(max_sessions) = web_app.config().streamer.max_sessions
+        && web_app.metrics().active_sessions.load(Ordering::Relaxed) >= max_sessions as u64
+    {
+        return Err(AppError::ServerAtCapacity { max_sessions }.into());
+    }
+
+    let (web_socket_protocol, protocol_compatible) = negotiate_stream_protocol(&request);
+
+    let (mut response, mut session, mut stream) = actix_ws::handle(&request, payload)?;
+    // Browsers close the web socket if none of their requested subprotocols is accepted
+    if let Some(web_socket_protocol) = &web_socket_protocol
+        && let Ok(value) = HeaderValue::from_str(web_socket_protocol)
+    {
+        response
+            .headers_mut()
+            .insert(header::SEC_WEBSOCKET_PROTOCOL, value);
+    }
 
     let client_unique_id = user.host_unique_id().await?;
 
@@ -43,6 +105,36 @@ pub async fn start_host(
 
     let web_app = web_app.clone();
     actix_rt::spawn(async move {
+        if !protocol_compatible {
+            warn!(
+                "[Stream]: closing a web socket with the incompatible protocol {web_socket_protocol:?}, expected {}",
+                stream_web_socket_protocol(StreamProtocol::VERSION)
+            );
+
+            // Pages without a protocol are too old to know about the version mismatch message
+            let message = if web_socket_protocol.is_some() {
+                StreamServerMessage::VersionMismatch {
+                    server_version: StreamProtocol::VERSION,
+                }
+            } else {
+                StreamServerMessage::DebugLog {
+                    message: "The server was updated, please reload the page".to_string(),
+                    ty: Some(LogMessageType::FatalDescription),
+                }
+            };
+
+            let _ = send_ws_message(&mut session, message).await;
+            let _ = session
+                .close(Some(close_reason(StreamEndReason::Error)))
+                .await;
+            return;
+        }
+
+        if let Some(resume_token) = resume_token {
+            resume_stream(&web_app, user.id(), session, stream, resume_token).await;
+            return;
+        }
+
         // -- Init and Configure
         let message;
         loop {
@@ -69,22 +161,85 @@ pub async fn start_host(
             }
         };
 
-        let StreamClientMessage::Init {
+        let (
             host_id,
             app_id,
             video_frame_queue_size,
             audio_sample_queue_size,
-        } = message
-        else {
-            let _ = session.close(None).await;
+            video_mode,
+            audio_mode,
+            audio_channels,
+            launch_parameters,
+        ) = match message {
+            StreamClientMessage::Init {
+                host_id,
+                app_id,
+                video_frame_queue_size,
+                audio_sample_queue_size,
+                video_mode,
+                audio_mode,
+                audio_channels,
+                launch_parameters,
+            } => (
+                host_id,
+                app_id,
+                video_frame_queue_size,
+                audio_sample_queue_size,
+                video_mode,
+                audio_mode,
+                audio_channels,
+                launch_parameters.unwrap_or_default(),
+            ),
+            _ => {
+                let _ = session
+                    .close(Some(close_reason(StreamEndReason::Error)))
+                    .await;
 
-            warn!("WebSocket didn't send init as first message, closing it");
-            return;
+                warn!("WebSocket didn't send init as first message, closing it");
+                return;
+            }
         };
 
         let host_id = HostId(host_id);
         let app_id = AppId(app_id);
 
+        if let Err(err) = validate_launch_parameters(&launch_parameters) {
+            warn!("[Stream]: rejected the launch parameters of a stream: {err}");
+
+            let _ = send_ws_message(
+                &mut session,
+                StreamServerMessage::DebugLog {
+                    message: format!("Failed to start stream: {err}"),
+                    ty: Some(LogMessageType::FatalDescription),
+                },
+            )
+            .await;
+            let _ = session
+                .close(Some(close_reason(StreamEndReason::Error)))
+                .await;
+            return;
+        }
+
+        // Another stream might have started since the check before the upgrade
+        let max_sessions = web_app.config().streamer.max_sessions;
+        let Some(active_session) = web_app.metrics().start_session(max_sessions) else {
+            let max_sessions = max_sessions.unwrap_or_default();
+            warn!("[Stream]: rejected a stream because {max_sessions} streams are already running");
+
+            let _ = send_ws_message(
+                &mut session,
+                StreamServerMessage::DebugLog {
+                    message: AppError::ServerAtCapacity { max_sessions }.to_string(),
+                    ty: Some(LogMessageType::FatalDescription),
+                },
+            )
+            .await;
+            let _ = session
+                .close(Some(close_reason(StreamEndReason::Error)))
+                .await;
+            return;
+        };
+
         // -- Collect host data
         let mut host = match user.host(host_id).await {
             Ok(host) => host,
@@ -98,7 +253,9 @@ pub async fn start_host(
                     },
                 )
                 .await;
-                let _ = session.close(None).await;
+                let _ = session
+                    .close(Some(close_reason(StreamEndReason::Error)))
+                    .await;
                 return;
             }
             Err(err) => {
@@ -112,15 +269,17 @@ pub async fn start_host(
                     },
                 )
                 .await;
-                let _ = session.close(None).await;
+                let _ = session
+                    .close(Some(close_reason(StreamEndReason::Error)))
+                    .await;
                 return;
             }
         };
 
-        let apps = match host.list_apps(&mut user).await {
-            Ok(apps) => apps,
+        let (address, http_port) = match host.address_port(&mut user).await {
+            Ok(address_port) => address_port,
             Err(err) => {
-                warn!("failed to start stream for host {host_id:?} (at list_apps): {err}");
+                warn!("failed to start stream for host {host_id:?} (at get address_port): {err}");
 
                 let _ = send_ws_message(
                     &mut session,
@@ -130,44 +289,108 @@ pub async fn start_host(
                     },
                 )
                 .await;
-                let _ = session.close(None).await;
+                let _ = session
+                    .close(Some(close_reason(StreamEndReason::Error)))
+                    .await;
                 return;
             }
         };
 
-        let Some(app) = apps.into_iter().find(|app| app.id == app_id) else {
-            warn!("failed to start stream for host {host_id:?} because the app couldn't be found!");
-
+        // -- Wake the host if it's configured and offline
+        if let Some(wake_timeout) = web_app.config().host_config(&address, None).wake_timeout
+            && matches!(host.unique_id(&mut user).await, Ok(None))
+        {
             let _ = send_ws_message(
                 &mut session,
-                StreamServerMessage::DebugLog {
-                    message: "Failed to start stream because the app was not found".to_string(),
-                    ty: Some(LogMessageType::FatalDescription),
-                },
+                StreamServerMessage::stage_starting(
+                    StreamStage::WakeHost,
+                    "Starting Stage: Wake Host",
+                ),
             )
             .await;
-            let _ = session.close(None).await;
-            return;
-        };
 
-        let (address, http_port) = match host.address_port(&mut user).await {
-            Ok(address_port) => address_port,
+            let online = match host.wake(&mut user).await {
+                Ok(()) => host.wait_online(&mut user, wake_timeout).await,
+                Err(err) => Err(err),
+            };
+
+            match online {
+                Ok(true) => {
+                    let _ = send_ws_message(
+                        &mut session,
+                        StreamServerMessage::stage_completed(
+                            StreamStage::WakeHost,
+                            "Completed Stage: Wake Host",
+                        ),
+                    )
+                    .await;
+                }
+                result => {
+                    if let Err(err) = result {
+                        warn!("failed to wake host {host_id:?}: {err}");
+                    }
+
+                    let _ = send_ws_message(
+                        &mut session,
+                        StreamServerMessage::stage_failed(
+                            StreamStage::WakeHost,
+                            StreamStageError::HostUnreachable,
+                            "Failed Stage: Wake Host, the host didn't come online",
+                            LogMessageType::FatalDescription,
+                        ),
+                    )
+                    .await;
+                    let _ = session
+                        .close(Some(close_reason(StreamEndReason::Error)))
+                        .await;
+                    return;
+                }
+            }
+        }
+
+        let apps = match host.list_apps(&mut user, false).await {
+            Ok((apps, _)) => apps,
             Err(err) => {
-                warn!("failed to start stream for host {host_id:?} (at get address_port): {err}");
+                warn!("failed to start stream for host {host_id:?} (at list_apps): {err}");
 
-                let _ = send_ws_message(
-                    &mut session,
+                let message = if let AppError::Moonlight(_) | AppError::HostRequest(_) = err {
+                    StreamServerMessage::stage_failed(
+                        StreamStage::HostConnection,
+                        StreamStageError::HostUnreachable,
+                        "Failed to start stream because the host couldn't be reached",
+                        LogMessageType::FatalDescription,
+                    )
+                } else {
                     StreamServerMessage::DebugLog {
                         message: "Failed to start stream because of a server error".to_string(),
                         ty: Some(LogMessageType::FatalDescription),
-                    },
-                )
-                .await;
-                let _ = session.close(None).await;
+                    }
+                };
+                let _ = send_ws_message(&mut session, message).await;
+                let _ = session
+                    .close(Some(close_reason(StreamEndReason::Error)))
+                    .await;
                 return;
             }
         };
 
+        let Some(app) = apps.into_iter().find(|app| app.id == app_id) else {
+            warn!("failed to start stream for host {host_id:?} because the app couldn't be found!");
+
+            let _ = send_ws_message(
+                &mut session,
+                StreamServerMessage::DebugLog {
+                    message: "Failed to start stream because the app was not found".to_string(),
+                    ty: Some(LogMessageType::FatalDescription),
+                },
+            )
+            .await;
+            let _ = session
+                .close(Some(close_reason(StreamEndReason::Error)))
+                .await;
+            return;
+        };
+
         let pair_info = match host.pair_info(&mut user).await {
             Ok(pair_info) => pair_info,
             Err(err) => {
@@ -182,12 +405,76 @@ pub async fn start_host(
                     },
                 )
                 .await;
-                let _ = session.close(None).await;
+                let _ = session
+                    .close(Some(close_reason(StreamEndReason::Error)))
+                    .await;
                 return;
             }
         };
+        // Use the same unique id as when pairing
+        let client_unique_id = pair_info
+            .client_unique_id
+            .clone()
+            .unwrap_or(client_unique_id);
+
+        let unique_id = match host.unique_id(&mut user).await {
+            Ok(unique_id) => unique_id,
+            Err(err) => {
+                warn!(
+                    "failed to get unique id of host {host_id:?}, only using address overrides from the config: {err}"
+                );
+                None
+            }
+        };
+
+        // -- Clamp the requested video mode to what the host supports
+        let video_mode = match video_mode {
+            Some(requested) => {
+                let max_luma_pixels = match host.detailed_host(&mut user).await {
+                    Ok(host) => host.max_luma_pixels_hevc,
+                    Err(err) => {
+                        warn!(
+                            "failed to get the supported video modes of host {host_id:?}, only applying general limits: {err}"
+                        );
+                        0
+                    }
+                };
+
+                let mut video_mode = clamp_video_mode(requested, max_luma_pixels);
+                apply_stream_limits_to_video_mode(
+                    &web_app.config().streamer.limits,
+                    &mut video_mode,
+                );
+                if video_mode != requested {
+                    warn!(
+                        "[Stream]: host {host_id:?} or the server limits don't allow the requested video mode {requested:?}, using {video_mode:?}"
+                    );
+
+                    let _ = send_ws_message(
+                        &mut session,
+                        StreamServerMessage::DebugLog {
+                            message: format!(
+                                "The host or server doesn't allow {}x{}x{}, using {}x{}x{} instead",
+                                requested.width,
+                                requested.height,
+                                requested.fps,
+                                video_mode.width,
+                                video_mode.height,
+                                video_mode.fps
+                            ),
+                            ty: Some(LogMessageType::InformError),
+                        },
+                    )
+                    .await;
+                }
+
+                Some(video_mode)
+            }
+            None => None,
+        };
 
         // -- Send App info
+        let app_title = app.title.clone();
         let _ = send_ws_message(
             &mut session,
             StreamServerMessage::UpdateApp { app: app.into() },
@@ -204,41 +491,33 @@ pub async fn start_host(
         )
         .await;
 
-        // Spawn child
-        let (mut child, stdin, stdout) = match Command::new(&web_app.config().streamer_path)
-            .stdin(Stdio::piped())
-            .stdout(Stdio::piped())
-            .stderr(Stdio::piped())
-            .kill_on_drop(true)
-            .spawn()
-        {
-            Ok(mut child) => {
-                if let Some(stdin) = child.stdin.take()
-                    && let Some(stdout) = child.stdout.take()
-                {
-                    (child, stdin, stdout)
-                } else {
-                    error!("[Stream]: streamer process didn't include a stdin or stdout");
-
-                    let _ = send_ws_message(
-                        &mut session,
-                        StreamServerMessage::DebugLog {
-                            message: "Failed to start stream because of a server error".to_string(),
-                            ty: Some(LogMessageType::FatalDescription),
-                        },
-                    )
-                    .await;
-                    let _ = session.close(None).await;
-
-                    if let Err(err) = child.kill().await {
-                        warn!("[Stream]: failed to kill child: {err}");
-                    }
+        let config = web_app.config();
+        let host_config = config.host_config(&address, unique_id.as_deref());
+        let audio_mode = audio_mode.or(host_config.audio_mode).unwrap_or_default();
+        let streamer_init = StreamerInit {
+            host_address: address,
+            host_http_port: http_port,
+            client_unique_id,
+            pair_info,
+            app_id,
+            video_frame_queue_size,
+            audio_sample_queue_size,
+            permissions,
+            host_config,
+            video_mode,
+            audio_mode,
+            client_audio_channels: audio_channels,
+            launch_parameters,
+        };
 
-                    return;
-                }
-            }
+        let streamer = match start_streamer(&config, &streamer_init).await {
+            Ok(streamer) => streamer,
             Err(err) => {
-                error!("[Stream]: failed to spawn streamer process: {err}");
+                error!("[Stream]: {err}");
+                web_app
+                    .metrics()
+                    .streamer_spawn_failures_total
+                    .fetch_add(1, Ordering::Relaxed);
 
                 let _ = send_ws_message(
                     &mut session,
@@ -248,116 +527,896 @@ pub async fn start_host(
                     },
                 )
                 .await;
-                let _ = session.close(None).await;
+                let _ = session
+                    .close(Some(close_reason(StreamEndReason::Error)))
+                    .await;
                 return;
             }
         };
 
-        // Create ipc
-        static CHILD_COUNTER: AtomicUsize = AtomicUsize::new(0);
-        let id = CHILD_COUNTER.fetch_add(1, Ordering::Relaxed);
-        let span = span!(Level::INFO, "ipc", child_id = id);
+        // The streamer might get restarted, so the ws always has to send into the current one
+        let ipc_sender = Arc::new(Mutex::new(streamer.ipc_sender.clone()));
 
-        let (mut ipc_sender, mut ipc_receiver) = create_child_ipc::<
-            ServerIpcMessage,
-            StreamerIpcMessage,
-        >(span, stdin, stdout, child.stderr.take())
-        .await;
+        // Make the stream visible to admins
+        let user_name = match user.detailed_user().await {
+            Ok(detailed_user) => detailed_user.name,
+            Err(err) => {
+                warn!(
+                    "[Stream]: failed to get the name of user {:?}: {err}",
+                    user.id()
+                );
+                String::new()
+            }
+        };
+        let host_name = match host.undetailed_host_cached(&mut user).await {
+            Ok(undetailed_host) => undetailed_host.name,
+            Err(err) => {
+                warn!("[Stream]: failed to get the name of host {host_id:?}: {err}");
+                String::new()
+            }
+        };
+        let registered_session = web_app.sessions().register(SessionInfo {
+            user_id: user.id(),
+            user_name,
+            host_id,
+            host_name,
+            app_id,
+            app_title,
+            video_mode,
+            ipc_sender: ipc_sender.clone(),
+        });
+        debug!(
+            "[Stream]: registered stream session {:?}",
+            registered_session.id()
+        );
+
+        // The web socket of a resumed stream is handed to the relay
+        let (session_sender, mut sessions) = unbounded_channel();
+        let resumable = match config.streamer.resume_timeout {
+            Some(resume_timeout) => match SessionToken::new().and_then(|token| {
+                let signed = web_app.stream_tokens().sign(
+                    token,
+                    user.id(),
+                    config.web_server.stream_token_lifetime,
+                )?;
+                Ok((token, signed))
+            }) {
+                Ok((token, signed)) => {
+                    let _ = send_ws_message(
+                        &mut session,
+                        StreamServerMessage::Resumable {
+                            session_token: signed,
+                        },
+                    )
+                    .await;
+
+                    Some(Resumable {
+                        token,
+                        user_id: user.id(),
+                        resume_timeout,
+                        sessions: session_sender,
+                    })
+                }
+                Err(err) => {
+                    warn!(
+                        "[Stream]: failed to create a session token, the stream can't be resumed: {err}"
+                    );
+                    None
+                }
+            },
+            None => None,
+        };
+        let resume_token = resumable.as_ref().map(|resumable| resumable.token);
 
         // Redirect ipc message into ws
+        let metrics = web_app.metrics().clone();
+        let mut shutdown = web_app.subscribe_shutdown();
         spawn({
-            let mut ipc_sender = ipc_sender.clone();
+            let ipc_sender = ipc_sender.clone();
+            let resumable_streams = web_app.resumable_streams().clone();
             async move {
-                let mut warned_closed = false;
-                while let Some(message) = ipc_receiver.recv().await {
-                    match message {
-                        StreamerIpcMessage::WebSocket(message) => {
-                            if let Err(Closed) = send_ws_message(&mut session, message).await
-                                && !warned_closed
-                            {
-                                warn!(
-                                    "[Ipc]: Tried to send a ws message (text) but the socket is already closed"
-                                );
-                                ipc_sender.send(ServerIpcMessage::Stop).await;
-                                warned_closed = true;
+                let _active_session = active_session;
+                let registered_session = registered_session;
+                let mut streamer = streamer;
+                let mut restarts = 0;
+
+                let end_reason = loop {
+                    let end = relay_streamer_messages(
+                        &mut session,
+                        &mut sessions,
+                        resume_token.is_some(),
+                        &mut streamer,
+                        &config,
+                        &metrics,
+                        &registered_session,
+                        &mut shutdown,
+                    )
+                    .await;
+                    info!("[Ipc]: ipc receiver is closed");
+
+                    stop_streamer(&mut streamer.handle, config.streamer.stop_timeout).await;
+
+                    let (errored, not_responding) = match end {
+                        StreamerEnd::Stopped(reason) => break reason,
+                        StreamerEnd::Crashed {
+                            errored,
+                            not_responding,
+                        } => (errored, not_responding),
+                    };
+                    metrics
+                        .streamer_crashes_total
+                        .fetch_add(1, Ordering::Relaxed);
+
+                    let restart = config.streamer.restart.as_ref();
+                    if let Some(restart) = restart
+                        && restarts < restart.max_retries
+                        && *shutdown.borrow() == ShutdownState::Running
+                    {
+                        let backoff = restart
+                            .initial_backoff
+                            .saturating_mul(2u32.saturating_pow(restarts))
+                            .min(restart.max_backoff);
+                        restarts += 1;
+
+                        warn!(
+                            "[Stream]: streamer crashed, restarting it in {backoff:?} (attempt {restarts} of {})",
+                            restart.max_retries
+                        );
+                        let _ = send_ws_message(
+                            &mut session,
+                            StreamServerMessage::DebugLog {
+                                message: format!(
+                                    "The streamer crashed, restarting it (attempt {restarts} of {})",
+                                    restart.max_retries
+                                ),
+                                ty: Some(LogMessageType::InformError),
+                            },
+                        )
+                        .await;
+
+                        sleep(backoff).await;
+
+                        match start_streamer(&config, &streamer_init).await {
+                            Ok(new_streamer) => {
+                                // The new streamer will send a new setup and the client negotiates the transport again
+                                *ipc_sender.lock().await = new_streamer.ipc_sender.clone();
+                                streamer = new_streamer;
+                                continue;
                             }
-                        }
-                        StreamerIpcMessage::WebSocketTransport(data) => {
-                            if let Err(Closed) = session.binary(data).await
-                                && !warned_closed
-                            {
-                                warn!(
-                                    "[Ipc]: Tried to send a ws message (binary) but the socket is already closed"
-                                );
-                                ipc_sender.send(ServerIpcMessage::Stop).await;
-                                warned_closed = true;
+                            Err(err) => {
+                                error!("[Stream]: failed to restart streamer: {err}");
+                                metrics
+                                    .streamer_spawn_failures_total
+                                    .fetch_add(1, Ordering::Relaxed);
                             }
                         }
-                        StreamerIpcMessage::Stop => {
-                            debug!("[Ipc]: ipc receiver stopped by streamer");
-                            break;
-                        }
                     }
-                }
-                info!("[Ipc]: ipc receiver is closed");
 
-                // Wait for the child to shutdown
-                sleep(Duration::from_secs(10)).await;
+                    let message = if not_responding {
+                        Some("The streamer stopped responding")
+                    } else if errored || restart.is_some() {
+                        Some("Lost the connection to the streamer")
+                    } else {
+                        None
+                    };
+                    if let Some(message) = message {
+                        let _ = send_ws_message(
+                            &mut session,
+                            StreamServerMessage::DebugLog {
+                                message: message.to_string(),
+                                ty: Some(LogMessageType::FatalDescription),
+                            },
+                        )
+                        .await;
+                    }
+
+                    break StreamEndReason::StreamerCrashed;
+                };
 
-                // close the websocket when the streamer crashed / disconnected / whatever
-                if let Err(err) = session.close(None).await {
-                    warn!("failed to close streamer web socket: {err}");
+                // The stream ended, so it can't be resumed anymore
+                drop(sessions);
+                if let Some(token) = resume_token {
+                    resumable_streams.remove(&token);
                 }
 
-                // kill the streamer
-                if let Err(err) = child.kill().await {
-                    warn!("failed to kill streamer child: {err}");
+                // close the websocket when the streamer crashed / disconnected / whatever
+                info!("[Stream]: stream ended: {end_reason:?}");
+                if let Err(err) = session.close(Some(close_reason(end_reason))).await {
+                    warn!("failed to close streamer web socket: {err}");
                 }
             }
         });
 
-        // Send init into ipc
-        ipc_sender
-            .send(ServerIpcMessage::Init {
-                config: StreamerConfig {
-                    webrtc: web_app.config().webrtc.clone(),
-                    log_level: web_app.config().log.level_filter,
-                },
-                host_address: address,
-                host_http_port: http_port,
-                client_unique_id: Some(client_unique_id),
-                client_private_key: pair_info.client_private_key,
-                client_certificate: pair_info.client_certificate,
-                server_certificate: pair_info.server_certificate,
-                app_id: app_id.0,
-                video_frame_queue_size,
-                audio_sample_queue_size,
-                permissions,
-            })
+        forward_client_messages(
+            stream,
+            ipc_sender,
+            resumable,
+            web_app.resumable_streams().clone(),
+        )
+        .await;
+    });
+
+    Ok(response)
+}
+
+/// Tells the client why the stream ended, so it can show a fitting message
+fn close_reason(reason: StreamEndReason) -> CloseReason {
+    CloseReason {
+        code: CloseCode::Other(reason.close_code()),
+        description: Some(reason.description().to_string()),
+    }
+}
+
+/// Redirects the ws messages into the ipc until the web socket closes
+async fn forward_client_messages(
+    mut stream: MessageStream,
+    ipc_sender: Arc<Mutex<IpcSender<ServerIpcMessage>>>,
+    resumable: Option<Resumable>,
+    resumable_streams: Arc<ResumableStreams>,
+) {
+    let mut closed_by_client = false;
+
+    while let Some(Ok(message)) = stream.recv().await {
+        match message {
+            Message::Text(text) => {
+                let Ok(message) = serde_json::from_str::<StreamClientMessage>(&text) else {
+                    warn!("[Stream]: failed to deserialize from json");
+                    break;
+                };
+
+                ipc_sender
+                    .lock()
+                    .await
+                    .send(ServerIpcMessage::WebSocket(message))
+                    .await;
+            }
+            Message::Binary(binary) => {
+                ipc_sender
+                    .lock()
+                    .await
+                    .send(ServerIpcMessage::WebSocketTransport(binary))
+                    .await;
+            }
+            Message::Close(_) => {
+                closed_by_client = true;
+                break;
+            }
+            _ => {}
+        }
+    }
+
+    if let Some(resumable) = resumable {
+        if closed_by_client {
+            // Only streams whose connection dropped are resumed
+            ipc_sender.lock().await.send(ServerIpcMessage::Stop).await;
+        } else {
+            detach_stream(&resumable_streams, resumable, ipc_sender);
+        }
+    }
+}
+
+/// Keeps the stream running for the resume timeout and stops it if the client doesn't resume it
+fn detach_stream(
+    resumable_streams: &Arc<ResumableStreams>,
+    resumable: Resumable,
+    ipc_sender: Arc<Mutex<IpcSender<ServerIpcMessage>>>,
+) {
+    // The relay already stopped, e.g. because the stream ended
+    if resumable.sessions.is_closed() {
+        return;
+    }
+
+    let token = resumable.token;
+    let resume_timeout = resumable.resume_timeout;
+
+    info!(
+        "[Stream]: web socket closed, waiting {resume_timeout:?} for the client to resume the stream"
+    );
+    let detach_id = resumable_streams.detach(resumable, ipc_sender);
+
+    let resumable_streams = resumable_streams.clone();
+    spawn(async move {
+        sleep(resume_timeout).await;
+
+        let expired = resumable_streams.take_if(&token, |stream| stream.detach_id == detach_id);
+
+        if let Some(stream) = expired {
+            info!("[Stream]: the stream wasn't resumed within {resume_timeout:?}, stopping it");
+            stream
+                .ipc_sender
+                .lock()
+                .await
+                .send(ServerIpcMessage::Stop)
+                .await;
+        }
+    });
+}
+
+async fn resume_stream(
+    app: &App,
+    user_id: UserId,
+    mut session: Session,
+    stream: MessageStream,
+    token: SessionToken,
+) {
+    if app.is_shutting_down() {
+        let _ = session
+            .close(Some(close_reason(StreamEndReason::ServerShutdown)))
             .await;
+        return;
+    }
 
-        // Redirect ws message into ipc
-        while let Some(Ok(message)) = stream.recv().await {
-            match message {
-                Message::Text(text) => {
-                    let Ok(message) = serde_json::from_str::<StreamClientMessage>(&text) else {
-                        warn!("[Stream]: failed to deserialize from json");
-                        return;
-                    };
+    // Only the user who started the stream can resume it
+    let detached = app
+        .resumable_streams()
+        .take_if(&token, |stream| stream.resumable.user_id == user_id);
 
-                    ipc_sender.send(ServerIpcMessage::WebSocket(message)).await;
-                }
-                Message::Binary(binary) => {
-                    ipc_sender
-                        .send(ServerIpcMessage::WebSocketTransport(binary))
-                        .await;
+    let Some(detached) = detached else {
+        info!("[Stream]: client tried to resume a stream which doesn't exist anymore");
+
+        let _ = send_ws_message(
+            &mut session,
+            StreamServerMessage::DebugLog {
+                message: "Failed to resume the stream because it already ended".to_string(),
+                ty: Some(LogMessageType::FatalDescription),
+            },
+        )
+        .await;
+        let _ = session
+            .close(Some(close_reason(StreamEndReason::ConnectionLost)))
+            .await;
+        return;
+    };
+
+    // The old token might expire while the stream keeps running
+    match app.stream_tokens().sign(
+        token,
+        user_id,
+        app.config().web_server.stream_token_lifetime,
+    ) {
+        Ok(session_token) => {
+            let _ = send_ws_message(
+                &mut session,
+                StreamServerMessage::Resumable { session_token },
+            )
+            .await;
+        }
+        Err(err) => {
+            warn!("[Stream]: failed to renew the stream token: {err}");
+        }
+    }
+
+    if let Err(err) = detached.resumable.sessions.send(session) {
+        // The stream ended while resuming it
+        let _ = err
+            .0
+            .close(Some(close_reason(StreamEndReason::ConnectionLost)))
+            .await;
+        return;
+    }
+    info!("[Stream]: resumed stream");
+
+    forward_client_messages(
+        stream,
+        detached.ipc_sender,
+        Some(detached.resumable),
+        app.resumable_streams().clone(),
+    )
+    .await;
+}
+
+/// Everything needed to send the [ServerIpcMessage::Init] to a (restarted) streamer
+struct StreamerInit {
+    host_address: String,
+    host_http_port: u16,
+    client_unique_id: String,
+    pair_info: StorageHostPairInfo,
+    app_id: AppId,
+    video_frame_queue_size: usize,
+    audio_sample_queue_size: usize,
+    permissions: StreamPermissions,
+    host_config: HostConfig,
+    video_mode: Option<StreamVideoMode>,
+    audio_mode: StreamAudioMode,
+    client_audio_channels: Option<u32>,
+    launch_parameters: HashMap<String, String>,
+}
+
+impl StreamerInit {
+    fn to_message(&self, config: &Config) -> ServerIpcMessage {
+        ServerIpcMessage::Init {
+            config: StreamerConfig {
+                webrtc: WebRtcConfig {
+                    force_relay: self
+                        .host_config
+                        .force_relay
+                        .unwrap_or(config.webrtc.force_relay),
+                    ..config.webrtc.clone()
+                },
+                host: self.host_config.clone(),
+                log_level: config.log.level_filter,
+                log_directives: config.log.directives.clone(),
+                ipc_compression_threshold: config.streamer.ipc_compression_threshold,
+                ipc_max_frame_size: config.streamer.ipc_max_frame_size,
+                resume_timeout: config.streamer.resume_timeout,
+                recording: config.streamer.recording.clone(),
+                idle_timeout: config.streamer.idle_timeout.clone(),
+                frame_diagnostics: config.streamer.frame_diagnostics.clone(),
+                limits: config.streamer.limits.clone(),
+            },
+            host_address: self.host_address.clone(),
+            host_http_port: self.host_http_port,
+            client_unique_id: Some(self.client_unique_id.clone()),
+            client_private_key: self.pair_info.client_private_key.clone(),
+            client_certificate: self.pair_info.client_certificate.clone(),
+            server_certificate: self.pair_info.server_certificate.clone(),
+            app_id: self.app_id.0,
+            video_frame_queue_size: self.video_frame_queue_size,
+            audio_sample_queue_size: self.audio_sample_queue_size,
+            permissions: self.permissions.clone(),
+            video_mode: self.video_mode,
+            audio_mode: self.audio_mode,
+            client_audio_channels: self.client_audio_channels,
+            launch_parameters: self.launch_parameters.clone(),
+        }
+    }
+}
+
+const MAX_VIDEO_DIMENSION: u32 = 7680;
+const MAX_VIDEO_FPS: u32 = 240;
+
+/// Limits the video mode to sizes encoders can handle and to the amount of pixels the host advertised.
+/// Width and height are kept even because most encoders require it.
+fn clamp_video_mode(requested: StreamVideoMode, max_luma_pixels: u32) -> StreamVideoMode {
+    let mut width = requested.width.clamp(2, MAX_VIDEO_DIMENSION);
+    let mut height = requested.height.clamp(2, MAX_VIDEO_DIMENSION);
+
+    // Scale down while keeping the aspect ratio
+    let pixels = width as u64 * height as u64;
+    if max_luma_pixels > 0 && pixels > max_luma_pixels as u64 {
+        let scale = (max_luma_pixels as f64 / pixels as f64).sqrt();
+        width = ((width as f64 * scale) as u32).max(2);
+        height = ((height as f64 * scale) as u32).max(2);
+    }
+
+    StreamVideoMode {
+        width: width & !1,
+        height: height & !1,
+        fps: requested.fps.clamp(1, MAX_VIDEO_FPS),
+    }
+}
+
+struct Streamer {
+    handle: StreamerHandle,
+    ipc_sender: IpcSender<ServerIpcMessage>,
+    ipc_receiver: IpcReceiver<StreamerIpcMessage>,
+}
+
+enum StreamerHandle {
+    Process(Child),
+    #[cfg(feature = "in-process-streamer")]
+    InProcess(InProcessStreamer),
+}
+
+impl StreamerHandle {
+    /// Waits until the streamer exited and returns how it exited
+    async fn wait(&mut self) -> io::Result<String> {
+        match self {
+            Self::Process(child) => child.wait().await.map(|status| status.to_string()),
+            #[cfg(feature = "in-process-streamer")]
+            Self::InProcess(streamer) => {
+                streamer.wait().await;
+                Ok("in process streamer finished".to_string())
+            }
+        }
+    }
+
+    fn start_kill(&mut self) -> io::Result<()> {
+        match self {
+            Self::Process(child) => child.start_kill(),
+            #[cfg(feature = "in-process-streamer")]
+            Self::InProcess(streamer) => {
+                streamer.start_kill();
+                Ok(())
+            }
+        }
+    }
+
+    async fn kill(&mut self) -> io::Result<()> {
+        self.start_kill()?;
+        self.wait().await.map(|_| ())
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+enum StartStreamerError {
+    #[error("failed to spawn streamer process: {0}")]
+    Spawn(#[from] io::Error),
+    #[error("streamer process didn't include a stdin or stdout")]
+    MissingStdio,
+    #[cfg(feature = "in-process-streamer")]
+    #[error("{0}")]
+    InProcess(#[from] InProcessStreamerError),
+    #[cfg(not(feature = "in-process-streamer"))]
+    #[error(
+        "streamer.in_process is enabled but the web server wasn't built with the \"in-process-streamer\" feature"
+    )]
+    InProcessUnsupported,
+}
+
+fn create_ipc_span() -> Span {
+    static CHILD_COUNTER: AtomicUsize = AtomicUsize::new(0);
+    let id = CHILD_COUNTER.fetch_add(1, Ordering::Relaxed);
+
+    span!(Level::INFO, "ipc", child_id = id)
+}
+
+/// Spawns the streamer, creates the ipc and sends the init message
+async fn start_streamer(
+    config: &Config,
+    init: &StreamerInit,
+) -> Result<Streamer, StartStreamerError> {
+    if config.streamer.in_process {
+        return start_in_process_streamer(config, init).await;
+    }
+
+    debug!(
+        "[Stream]: spawning streamer: {:?} {:?} with env {:?}",
+        config.streamer_path,
+        config.streamer.args,
+        // The values might contain secrets
+        config.streamer.env.keys().collect::<Vec<_>>()
+    );
+
+    let mut child = Command::new(&config.streamer_path)
+        .args(&config.streamer.args)
+        .envs(&config.streamer.env)
+        .env(
+            IPC_CHANNEL_CAPACITY_ENV,
+            config.streamer.ipc_channel_capacity.to_string(),
+        )
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()?;
+
+    let (Some(stdin), Some(stdout)) = (child.stdin.take(), child.stdout.take()) else {
+        if let Err(err) = child.kill().await {
+            warn!("[Stream]: failed to kill child: {err}");
+        }
+
+        return Err(StartStreamerError::MissingStdio);
+    };
+
+    // Create ipc
+    let (ipc_sender, ipc_receiver) = create_child_ipc::<ServerIpcMessage, StreamerIpcMessage>(
+        create_ipc_span(),
+        stdin,
+        stdout,
+        child.stderr.take(),
+        config.streamer.ipc_channel_capacity,
+    )
+    .await;
+
+    Ok(init_streamer(
+        config,
+        init,
+        StreamerHandle::Process(child),
+        ipc_sender,
+        ipc_receiver,
+    )
+    .await)
+}
+
+/// Runs the streamer on a thread of the web server, the ipc stays the same
+#[cfg(feature = "in-process-streamer")]
+async fn start_in_process_streamer(
+    config: &Config,
+    init: &StreamerInit,
+) -> Result<Streamer, StartStreamerError> {
+    debug!("[Stream]: starting in process streamer");
+
+    let span = create_ipc_span();
+    let (end, streamer_end) = in_memory_ipc_pair();
+
+    let streamer = InProcessStreamer::spawn(
+        span.clone(),
+        streamer_end,
+        config.streamer.ipc_channel_capacity,
+    )?;
+    let (ipc_sender, ipc_receiver) = create_in_memory_ipc::<ServerIpcMessage, StreamerIpcMessage>(
+        span,
+        end,
+        config.streamer.ipc_channel_capacity,
+    );
+
+    Ok(init_streamer(
+        config,
+        init,
+        StreamerHandle::InProcess(streamer),
+        ipc_sender,
+        ipc_receiver,
+    )
+    .await)
+}
+
+#[cfg(not(feature = "in-process-streamer"))]
+async fn start_in_process_streamer(
+    _config: &Config,
+    _init: &StreamerInit,
+) -> Result<Streamer, StartStreamerError> {
+    Err(StartStreamerError::InProcessUnsupported)
+}
+
+/// Configures the ipc, starts the heartbeat and sends the init message
+async fn init_streamer(
+    config: &Config,
+    init: &StreamerInit,
+    handle: StreamerHandle,
+    mut ipc_sender: IpcSender<ServerIpcMessage>,
+    mut ipc_receiver: IpcReceiver<StreamerIpcMessage>,
+) -> Streamer {
+    ipc_sender.set_compression_threshold(config.streamer.ipc_compression_threshold);
+    ipc_receiver.set_max_frame_size(config.streamer.ipc_max_frame_size);
+
+    // Ping the streamer so we notice if it hangs
+    spawn({
+        let mut ipc_sender = ipc_sender.clone();
+        let heartbeat_interval = config.streamer.heartbeat_interval;
+        async move {
+            loop {
+                sleep(heartbeat_interval).await;
+
+                if ipc_sender.is_closed() {
+                    break;
                 }
-                _ => {}
+                ipc_sender.send(ServerIpcMessage::Ping).await;
             }
         }
     });
 
-    Ok(response)
+    // Send init into ipc, the hello comes first so a streamer of another version can still tell us
+    ipc_sender
+        .send(ServerIpcMessage::Hello {
+            protocol_version: IPC_PROTOCOL_VERSION,
+        })
+        .await;
+    ipc_sender.send(init.to_message(config)).await;
+    ipc_sender.send(ServerIpcMessage::QueryCapabilities).await;
+
+    Streamer {
+        handle,
+        ipc_sender,
+        ipc_receiver,
+    }
+}
+
+#[derive(Debug)]
+enum StreamerEnd {
+    /// The streamer acknowledged a stop or the web socket was closed
+    Stopped(StreamEndReason),
+    /// The ipc closed without the streamer stopping the stream
+    Crashed { errored: bool, not_responding: bool },
+}
+
+async fn relay_streamer_messages(
+    session: &mut Session,
+    sessions: &mut UnboundedReceiver<Session>,
+    resumable: bool,
+    streamer: &mut Streamer,
+    config: &Config,
+    metrics: &Metrics,
+    registered_session: &RegisteredSession,
+    shutdown: &mut watch::Receiver<ShutdownState>,
+) -> StreamerEnd {
+    // Set once we asked the streamer to stop, it takes precedence over the reason of the streamer
+    let mut stop_reason = None;
+    // The web socket of a resumable stream closed, the messages are dropped until it's resumed
+    let mut detached = false;
+
+    // The server might have started to shut down while this stream was starting
+    shutdown.mark_changed();
+
+    loop {
+        let received = select! {
+            received = timeout(
+                config.streamer.heartbeat_timeout,
+                streamer.ipc_receiver.recv(),
+            ) => received,
+            Some(new_session) = sessions.recv() => {
+                *session = new_session;
+                detached = false;
+                continue;
+            }
+            Ok(()) = shutdown.changed() => {
+                let state = *shutdown.borrow_and_update();
+                match state {
+                    ShutdownState::Running => {}
+                    ShutdownState::Draining => {
+                        if stop_reason.is_none() {
+                            info!("[Stream]: stopping stream because the server is shutting down");
+                            streamer.ipc_sender.send(ServerIpcMessage::Stop).await;
+                            stop_reason = Some(StreamEndReason::ServerShutdown);
+                        }
+                    }
+                    ShutdownState::Killing => {
+                        warn!("[Stream]: streamer didn't stop within the shutdown grace period, killing it");
+                        if let Err(err) = streamer.handle.start_kill() {
+                            warn!("failed to kill streamer child: {err}");
+                        }
+                        return StreamerEnd::Stopped(StreamEndReason::ServerShutdown);
+                    }
+                }
+                continue;
+            }
+        };
+
+        let message = match received {
+            Ok(Some(message)) => message,
+            Ok(None) => {
+                if let Some(reason) = stop_reason {
+                    return StreamerEnd::Stopped(reason);
+                }
+                return StreamerEnd::Crashed {
+                    errored: streamer.ipc_receiver.is_errored(),
+                    not_responding: false,
+                };
+            }
+            Err(_) => {
+                warn!(
+                    "[Ipc]: streamer didn't respond for {:?}, stopping it",
+                    config.streamer.heartbeat_timeout
+                );
+
+                if let Some(reason) = stop_reason {
+                    return StreamerEnd::Stopped(reason);
+                }
+                return StreamerEnd::Crashed {
+                    errored: false,
+                    not_responding: true,
+                };
+            }
+        };
+
+        match message {
+            StreamerIpcMessage::WebSocket(_) | StreamerIpcMessage::WebSocketTransport(_)
+                if detached => {}
+            StreamerIpcMessage::WebSocket(message) => {
+                if let StreamServerMessage::EffectiveStreamSettings { settings } = &message {
+                    registered_session.set_video_mode(StreamVideoMode {
+                        width: settings.width,
+                        height: settings.height,
+                        fps: settings.fps,
+                    });
+                }
+
+                if let Err(Closed) = send_ws_message(session, message).await
+                    && stop_reason.is_none()
+                {
+                    if resumable {
+                        debug!(
+                            "[Ipc]: web socket closed, waiting for the client to resume the stream"
+                        );
+                        detached = true;
+                        continue;
+                    }
+
+                    warn!(
+                        "[Ipc]: Tried to send a ws message (text) but the socket is already closed"
+                    );
+                    streamer.ipc_sender.send(ServerIpcMessage::Stop).await;
+                    stop_reason = Some(StreamEndReason::Stopped);
+                }
+            }
+            StreamerIpcMessage::WebSocketTransport(data) => {
+                if let Err(Closed) = session.binary(data).await
+                    && stop_reason.is_none()
+                {
+                    if resumable {
+                        debug!(
+                            "[Ipc]: web socket closed, waiting for the client to resume the stream"
+                        );
+                        detached = true;
+                        continue;
+                    }
+
+                    warn!(
+                        "[Ipc]: Tried to send a ws message (binary) but the socket is already closed"
+                    );
+                    streamer.ipc_sender.send(ServerIpcMessage::Stop).await;
+                    stop_reason = Some(StreamEndReason::Stopped);
+                }
+            }
+            StreamerIpcMessage::Pong => {}
+            StreamerIpcMessage::Capabilities(capabilities) => {
+                debug!("[Stream]: streamer capabilities: {capabilities:?}");
+
+                if !detached {
+                    let _ = send_ws_message(
+                        session,
+                        StreamServerMessage::StreamerCapabilities {
+                            video_formats: capabilities.video_formats,
+                            audio_channels: capabilities.audio_channels,
+                        },
+                    )
+                    .await;
+                }
+            }
+            StreamerIpcMessage::Metrics(streamer_metrics) => {
+                metrics.add_streamer_metrics(&streamer_metrics);
+                registered_session.add_streamer_metrics(&streamer_metrics);
+            }
+            StreamerIpcMessage::ProtocolVersionMismatch { streamer_version } => {
+                error!(
+                    "[Stream]: streamer protocol version mismatch: the web server uses version {IPC_PROTOCOL_VERSION}, but the streamer uses version {streamer_version}"
+                );
+
+                let _ = send_ws_message(
+                    session,
+                    StreamServerMessage::DebugLog {
+                        message: "Failed to start stream because the streamer protocol version doesn't match the web server, make sure both are from the same release".to_string(),
+                        ty: Some(LogMessageType::FatalDescription),
+                    },
+                )
+                .await;
+
+                // The other messages of the streamer might not be readable, so don't wait for its stop
+                return StreamerEnd::Stopped(StreamEndReason::Error);
+            }
+            StreamerIpcMessage::Stop { reason } => {
+                // The streamer sends this after it stopped the stream and closed the transport,
+                // either as an answer to our stop or because the stream ended
+                debug!("[Ipc]: ipc receiver stopped by streamer: {reason:?}");
+                return StreamerEnd::Stopped(stop_reason.unwrap_or(reason));
+            }
+        }
+    }
+}
+
+/// Waits for the streamer to exit by itself and kills it if it takes too long
+async fn stop_streamer(handle: &mut StreamerHandle, stop_timeout: Duration) {
+    let exited = match timeout(stop_timeout, handle.wait()).await {
+        Ok(Ok(status)) => {
+            debug!("[Stream]: streamer exited with {status}");
+            true
+        }
+        Ok(Err(err)) => {
+            warn!("[Stream]: failed to wait for the streamer to exit: {err}");
+            false
+        }
+        Err(_) => {
+            warn!("[Stream]: streamer didn't exit within {stop_timeout:?}, killing it");
+            false
+        }
+    };
+
+    if !exited && let Err(err) = handle.kill().await {
+        warn!("failed to kill streamer child: {err}");
+    }
+}
+
+/// Returns the subprotocol to accept and if the client speaks the same stream protocol version
+fn negotiate_stream_protocol(request: &HttpRequest) -> (Option<String>, bool) {
+    let expected = stream_web_socket_protocol(StreamProtocol::VERSION);
+
+    let requested = request
+        .headers()
+        .get_all(header::SEC_WEBSOCKET_PROTOCOL)
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+        .filter(|protocol| !protocol.is_empty())
+        .collect::<Vec<_>>();
+
+    if requested.contains(&expected.as_str()) {
+        (Some(expected), true)
+    } else {
+        (
+            requested.first().map(|protocol| protocol.to_string()),
+            false,
+        )
+    }
 }
 
 async fn send_ws_message(sender: &mut Session, message: StreamServerMessage) -> Result<(), Closed> {
@@ -377,7 +1436,11 @@ pub async fn cancel_host(
 
     let mut host = user.host(host_id).await?;
 
-    host.cancel_app(&mut user).await?;
+    // The host answers with a failure e.g. if the app was started by another client
+    let success = host.cancel_app(&mut user).await?;
+    if !success {
+        warn!("[Stream]: host {host_id:?} refused to quit the running app");
+    }
 
-    Ok(Json(PostCancelResponse { success: true }))
+    Ok(Json(PostCancelResponse { success }))
 }
@@ -0,0 +1,223 @@
+use std::net::{IpAddr, SocketAddr};
+
+use actix_web::{HttpRequest, http::header};
+use ipnet::IpNet;
+
+/// The ip of the client that made the request.
+/// If the request came from a trusted proxy the ip is taken from the `Forwarded` or `X-Forwarded-For` header,
+/// otherwise these headers are ignored so clients can't spoof their ip.
+pub fn client_ip(trusted_proxies: &[IpNet], request: &HttpRequest) -> Option<IpAddr> {
+    let peer_ip = request.peer_addr()?.ip();
+
+    if !is_trusted(trusted_proxies, peer_ip) {
+        return Some(peer_ip);
+    }
+
+    let forwarded = forwarded_for(request);
+    if forwarded.is_empty() {
+        return Some(peer_ip);
+    }
+
+    // Every proxy appends the address it received the request from, so the first untrusted one from the right is the client
+    forwarded
+        .iter()
+        .rev()
+        .find(|ip| !is_trusted(trusted_proxies, **ip))
+        .or(forwarded.first())
+        .copied()
+}
+
+fn is_trusted(trusted_proxies: &[IpNet], ip: IpAddr) -> bool {
+    // Ipv4 clients of a dual stack socket show up as ipv4 mapped ipv6 addresses
+    let ip = ip.to_canonical();
+
+    trusted_proxies.iter().any(|proxy| proxy.contains(&ip))
+}
+
+/// The forwarded addresses from the `Forwarded` header or, if it doesn't exist, the `X-Forwarded-For` header
+fn forwarded_for(request: &HttpRequest) -> Vec<IpAddr> {
+    let headers = request.headers();
+
+    let forwarded = headers
+        .get_all(header::FORWARDED)
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .filter_map(|element| {
+            element.split(';').find_map(|pair| {
+                let (key, value) = pair.trim().split_once('=')?;
+                key.eq_ignore_ascii_case("for").then_some(value)
+            })
+        })
+        .filter_map(parse_node)
+        .collect::<Vec<_>>();
+
+    if !forwarded.is_empty() {
+        return forwarded;
+    }
+
+    headers
+        .get_all("X-Forwarded-For")
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .filter_map(parse_node)
+        .collect()
+}
+
+/// Parses nodes like `192.0.2.60`, `"192.0.2.60:4711"` or `"[2001:db8::1]:4711"`.
+/// Obfuscated nodes like `unknown` or `_hidden` can't be parsed.
+fn parse_node(node: &str) -> Option<IpAddr> {
+    let node = node.trim().trim_matches('"');
+
+    if let Ok(ip) = node.parse::<IpAddr>() {
+        return Some(ip);
+    }
+    if let Ok(address) = node.parse::<SocketAddr>() {
+        return Some(address.ip());
+    }
+
+    node.strip_prefix('[')
+        .and_then(|node| node.split_once(']'))
+        .and_then(|(ip, _)| ip.parse().ok())
+}
+
+#[cfg(test)]
+mod test {
+    use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+    use actix_web::{http::header, test::TestRequest};
+    use ipnet::IpNet;
+
+    use crate::app::client_ip::{client_ip, parse_node};
+
+    fn trusted_proxies() -> Vec<IpNet> {
+        vec!["10.0.0.0/8".parse().unwrap()]
+    }
+
+    fn ip(ip: &str) -> IpAddr {
+        ip.parse().unwrap()
+    }
+
+    #[test]
+    fn untrusted_peer_with_headers() {
+        let request = TestRequest::default()
+            .peer_addr("203.0.113.9:5000".parse().unwrap())
+            .insert_header((header::FORWARDED, "for=198.51.100.1"))
+            .insert_header(("X-Forwarded-For", "198.51.100.2"))
+            .to_http_request();
+
+        assert_eq!(
+            client_ip(&trusted_proxies(), &request),
+            Some(ip("203.0.113.9"))
+        );
+    }
+
+    #[test]
+    fn trusted_peer_without_headers() {
+        let request = TestRequest::default()
+            .peer_addr("10.0.0.1:5000".parse().unwrap())
+            .to_http_request();
+
+        assert_eq!(
+            client_ip(&trusted_proxies(), &request),
+            Some(ip("10.0.0.1"))
+        );
+    }
+
+    #[test]
+    fn chain_of_trusted_proxies() {
+        let request = TestRequest::default()
+            .peer_addr("10.0.0.3:5000".parse().unwrap())
+            .insert_header((
+                "X-Forwarded-For",
+                "203.0.113.5, 198.51.100.7, 10.0.0.1, 10.0.0.2",
+            ))
+            .to_http_request();
+
+        // The first address is set by the client and could be spoofed
+        assert_eq!(
+            client_ip(&trusted_proxies(), &request),
+            Some(ip("198.51.100.7"))
+        );
+    }
+
+    #[test]
+    fn only_trusted_proxies_in_chain() {
+        let request = TestRequest::default()
+            .peer_addr("10.0.0.3:5000".parse().unwrap())
+            .insert_header(("X-Forwarded-For", "10.0.0.1, 10.0.0.2"))
+            .to_http_request();
+
+        assert_eq!(
+            client_ip(&trusted_proxies(), &request),
+            Some(ip("10.0.0.1"))
+        );
+    }
+
+    #[test]
+    fn forwarded_is_preferred() {
+        let request = TestRequest::default()
+            .peer_addr("10.0.0.1:5000".parse().unwrap())
+            .insert_header((
+                header::FORWARDED,
+                "for=198.51.100.1;proto=https, for=\"[2001:db8::1]:4711\"",
+            ))
+            .insert_header(("X-Forwarded-For", "198.51.100.2"))
+            .to_http_request();
+
+        assert_eq!(
+            client_ip(&trusted_proxies(), &request),
+            Some(ip("2001:db8::1"))
+        );
+    }
+
+    #[test]
+    fn ipv4_mapped_peer() {
+        let peer = SocketAddr::new(
+            IpAddr::V6(Ipv4Addr::new(10, 0, 0, 1).to_ipv6_mapped()),
+            5000,
+        );
+        let request = TestRequest::default()
+            .peer_addr(peer)
+            .insert_header(("X-Forwarded-For", "198.51.100.1"))
+            .to_http_request();
+
+        assert_eq!(
+            client_ip(&trusted_proxies(), &request),
+            Some(ip("198.51.100.1"))
+        );
+    }
+
+    #[test]
+    fn obfuscated_forwarded_nodes() {
+        let request = TestRequest::default()
+            .peer_addr("10.0.0.1:5000".parse().unwrap())
+            .insert_header((header::FORWARDED, "for=unknown, for=\"_hidden\""))
+            .to_http_request();
+
+        assert_eq!(
+            client_ip(&trusted_proxies(), &request),
+            Some(ip("10.0.0.1"))
+        );
+    }
+
+    #[test]
+    fn parse_nodes() {
+        assert_eq!(parse_node("192.0.2.60"), Some(ip("192.0.2.60")));
+        assert_eq!(parse_node(" \"192.0.2.60:4711\" "), Some(ip("192.0.2.60")));
+        assert_eq!(
+            parse_node("\"[2001:db8::1]:4711\""),
+            Some(ip("2001:db8::1"))
+        );
+        assert_eq!(parse_node("\"[2001:db8::1]\""), Some(ip("2001:db8::1")));
+        assert_eq!(parse_node("2001:db8::1"), Some(ip("2001:db8::1")));
+        assert_eq!(
+            parse_node("::ffff:192.0.2.60"),
+            Some(IpAddr::V6(Ipv6Addr::new(
+                0, 0, 0, 0, 0, 0xffff, 0xc000, 0x023c
+            )))
+        );
+        assert_eq!(parse_node("unknown"), None);
+        assert_eq!(parse_node("\"_hidden\""), None);
+        assert_eq!(parse_node(""), None);
+    }
+}
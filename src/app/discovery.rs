@@ -0,0 +1,113 @@
+use std::collections::HashMap;
+
+use common::{api_bindings::DiscoveredHost, config::DiscoveryConfig};
+use futures::future::join_all;
+use mdns_sd::{Receiver, ServiceDaemon, ServiceEvent};
+use tokio::{
+    sync::Mutex,
+    time::{Instant, timeout_at},
+};
+use tracing::{debug, warn};
+
+/// Finds hosts in the local network using mDNS
+#[derive(Default)]
+pub struct Discovery {
+    // A tokio mutex so concurrent requests wait for the same browse instead of starting their own
+    cache: Mutex<Option<(Instant, Vec<DiscoveredHost>)>>,
+}
+
+impl Discovery {
+    pub async fn discover(
+        &self,
+        config: &DiscoveryConfig,
+    ) -> Result<Vec<DiscoveredHost>, mdns_sd::Error> {
+        let mut cache = self.cache.lock().await;
+
+        if let Some((discovered_at, hosts)) = cache.as_ref()
+            && discovered_at.elapsed() < config.cache_ttl
+        {
+            return Ok(hosts.clone());
+        }
+
+        let hosts = browse(config).await?;
+        *cache = Some((Instant::now(), hosts.clone()));
+
+        Ok(hosts)
+    }
+}
+
+async fn browse(config: &DiscoveryConfig) -> Result<Vec<DiscoveredHost>, mdns_sd::Error> {
+    let daemon = ServiceDaemon::new()?;
+
+    let mut receivers = Vec::new();
+    for service_type in &config.service_types {
+        receivers.push((service_type.as_str(), daemon.browse(service_type)?));
+    }
+
+    let deadline = Instant::now() + config.browse_duration;
+    let found = join_all(
+        receivers
+            .iter()
+            .map(|(service_type, receiver)| collect_hosts(service_type, receiver, deadline)),
+    )
+    .await;
+
+    if let Err(err) = daemon.shutdown() {
+        warn!("[Discovery]: failed to shutdown the mdns daemon: {err}");
+    }
+
+    // The same host might be found using multiple service types
+    let mut hosts = HashMap::new();
+    for host in found.into_iter().flatten() {
+        hosts.insert((host.address.clone(), host.http_port), host);
+    }
+
+    let mut hosts = hosts.into_values().collect::<Vec<_>>();
+    hosts.sort_by(|a, b| a.name.cmp(&b.name));
+
+    Ok(hosts)
+}
+
+async fn collect_hosts(
+    service_type: &str,
+    receiver: &Receiver<ServiceEvent>,
+    deadline: Instant,
+) -> Vec<DiscoveredHost> {
+    let mut hosts = Vec::new();
+
+    while let Ok(Ok(event)) = timeout_at(deadline, receiver.recv_async()).await {
+        let ServiceEvent::ServiceResolved(info) = event else {
+            continue;
+        };
+
+        // Prefer ipv4 because GameStream hosts don't always listen on ipv6
+        let addresses = info.get_addresses();
+        let Some(address) = addresses
+            .iter()
+            .find(|address| address.is_ipv4())
+            .or_else(|| addresses.iter().next())
+        else {
+            continue;
+        };
+
+        let name = info
+            .get_fullname()
+            .strip_suffix(service_type)
+            .unwrap_or(info.get_hostname())
+            .trim_end_matches('.')
+            .to_string();
+
+        debug!(
+            "[Discovery]: found host {name} at {address}:{}",
+            info.get_port()
+        );
+
+        hosts.push(DiscoveredHost {
+            name,
+            address: address.to_string(),
+            http_port: info.get_port(),
+        });
+    }
+
+    hosts
+}
@@ -1,7 +1,13 @@
-use std::fmt::{Debug, Formatter};
+use std::{
+    fmt::{Debug, Formatter},
+    time::{Duration, Instant},
+};
 
 use actix_web::web::Bytes;
-use common::api_bindings::{self, DetailedHost, HostOwner, HostState, PairStatus, UndetailedHost};
+use common::{
+    api_bindings::{self, DetailedHost, HostOwner, HostState, PairStatus, UndetailedHost},
+    config::HostRequestRetryConfig,
+};
 use moonlight_common::{
     crypto::openssl::OpenSSLCryptoBackend,
     high::{
@@ -14,11 +20,16 @@ use moonlight_common::{
         server_info::ServerInfoResponse,
     },
 };
+use tokio::time::sleep;
+use tracing::{debug, warn};
 
 use crate::app::{
     AppError, AppInner, AppRef, MoonlightClient,
+    host_ports::is_port_reachable,
+    host_request::{HostRequestError, retry_host_request},
     storage::{StorageHost, StorageHostModify, StorageHostPairInfo},
     user::{AuthenticatedUser, RoleType, UserId},
+    wake::{DEFAULT_WAKE_BROADCAST_ADDRESS, parse_mac_address, send_magic_packet},
 };
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
@@ -40,6 +51,7 @@ impl Debug for Host {
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct AppId(pub u32);
 
+#[derive(Clone)]
 pub struct App {
     pub id: AppId,
     pub title: String,
@@ -139,6 +151,8 @@ impl Host {
                 PairStatus::NotPaired
             },
             server_state: None,
+            http_port: storage.http_port,
+            https_port: None,
         })
     }
 
@@ -149,14 +163,23 @@ impl Host {
         // app, https_capable, client, host, port, client_info
         f: impl AsyncFnOnce(&mut Self, &MoonlightHost<MoonlightClient>) -> R,
     ) -> Result<R, AppError> {
-        let user_unique_id = user.host_unique_id().await?;
         let host_data = self.storage_host(app).await?;
 
+        // Always use the unique id the host was paired with, the host might know the client by it
+        let unique_id = match host_data
+            .pair_info
+            .as_ref()
+            .and_then(|pair_info| pair_info.client_unique_id.clone())
+        {
+            Some(unique_id) => unique_id,
+            None => user.host_unique_id().await?,
+        };
+
         // TODO: put this globally somewhere and retrieve it?
         let host = MoonlightHost::<MoonlightClient>::new(
             host_data.address.clone(),
             host_data.http_port,
-            Some(user_unique_id),
+            Some(unique_id),
         )?;
 
         if let Some(pair_info) = host_data.pair_info {
@@ -171,6 +194,12 @@ impl Host {
         Ok(f(self, &host).await)
     }
 
+    async fn request_retry(&self, app: &AppInner) -> Result<HostRequestRetryConfig, AppError> {
+        let storage = self.storage_host(app).await?;
+
+        Ok(app.config().host_request_retry(&storage.address))
+    }
+
     async fn storage_host(&self, app: &AppInner) -> Result<StorageHost, AppError> {
         if let Some(host) = self.cache_storage.as_ref() {
             return Ok(host.clone());
@@ -205,13 +234,24 @@ impl Host {
         host.pair_info.ok_or(AppError::HostNotPaired)
     }
 
-    fn is_offline<T>(
-        &self,
-        result: Result<T, MoonlightClientError>,
-    ) -> Result<Option<T>, AppError> {
+    /// None = Offline
+    pub async fn unique_id(
+        &mut self,
+        user: &mut AuthenticatedUser,
+    ) -> Result<Option<String>, AppError> {
+        self.can_use(user).await?;
+
+        let app = self.app.access()?;
+
+        let info = self.host_info(&app, user).await?;
+
+        Ok(info.map(|info| info.unique_id.to_string()))
+    }
+
+    fn is_offline<T>(&self, result: Result<T, HostRequestError>) -> Result<Option<T>, AppError> {
         match result {
             Ok(value) => Ok(Some(value)),
-            Err(MoonlightClientError::Offline) => Ok(None),
+            Err(err) if err.is_offline() => Ok(None),
             Err(err) => Err(err.into()),
         }
     }
@@ -229,8 +269,12 @@ impl Host {
             return Ok(Some(cache.clone()));
         }
 
+        let retry = self.request_retry(&app).await?;
+
         self.use_client(app, user, async |this, host| {
-            let info = match this.is_offline(host.server_info().await) {
+            let info = match this.is_offline(
+                retry_host_request(&retry, "server info", async || host.server_info().await).await,
+            ) {
                 Ok(Some(value)) => value,
                 err => return err,
             };
@@ -260,6 +304,8 @@ impl Host {
                 owner,
                 paired: PairStatus::from_paired(info.paired),
                 server_state: Some(HostState::from(info.state)),
+                http_port: storage.http_port,
+                https_port: Some(info.https_port),
             }),
             Ok(None) => {
                 let host = self.storage_host(&app).await?;
@@ -276,6 +322,8 @@ impl Host {
                     owner,
                     paired,
                     server_state: None,
+                    http_port: host.http_port,
+                    https_port: None,
                 })
             }
             Err(err) => Err(err),
@@ -378,6 +426,16 @@ impl Host {
             return Err(AppError::HostPaired);
         }
 
+        // The last step of pairing uses https, it would only fail after the pin was entered
+        let storage = self.storage_host(&app).await?;
+        if !is_port_reachable(&storage.address, info.https_port).await {
+            return Err(AppError::HostHttpsUnreachable {
+                port: info.https_port,
+            });
+        }
+
+        let client_unique_id = user.host_unique_id().await?;
+
         let modify = self
             .use_client(&app, user, async |this, host| {
                 let (client_identifier, client_secret) = OpenSSLCryptoBackend
@@ -411,6 +469,7 @@ impl Host {
                         client_certificate: client_identifier.to_pem(),
                         client_private_key: client_secret.to_pem(),
                         server_certificate: server_identifier.to_pem(),
+                        client_unique_id: Some(client_unique_id),
                     })),
                     cache_name: Some(host_name),
                     cache_mac: Some(mac),
@@ -422,6 +481,39 @@ impl Host {
         self.modify(user, modify).await
     }
 
+    /// Removes the client certificate and key of this host, the host has to be paired again afterwards.
+    /// The host itself isn't notified, so the old client might still be listed in the host until it's removed there.
+    pub async fn unpair(&mut self, user: &mut AuthenticatedUser) -> Result<(), AppError> {
+        self.can_use(user).await?;
+
+        let app = self.app.access()?;
+
+        let storage = self.storage_host(&app).await?;
+        if storage.pair_info.is_none() {
+            return Err(AppError::HostNotPaired);
+        }
+
+        self.cache_host_info = None;
+        {
+            let mut app_images = app.app_image_cache.write().await;
+            app_images.retain(|(_, host_id, _), _| *host_id != self.id);
+        }
+        {
+            let mut app_lists = app.app_list_cache.write().await;
+            app_lists.retain(|(_, host_id), _| *host_id != self.id);
+        }
+
+        drop(app);
+        self.modify(
+            user,
+            StorageHostModify {
+                pair_info: Some(None),
+                ..Default::default()
+            },
+        )
+        .await
+    }
+
     pub async fn wake(&self, user: &mut AuthenticatedUser) -> Result<(), AppError> {
         self.can_use(user).await?;
 
@@ -429,7 +521,18 @@ impl Host {
 
         let storage = self.storage_host(&app).await?;
 
-        if let Some(mac) = storage.cache.mac {
+        // The unique id is unknown while the host is offline, so only the address overrides can be used
+        let host_config = app.config().host_config(&storage.address, None);
+
+        if let Some(mac) = host_config.mac_address {
+            let mac = parse_mac_address(&mac).ok_or(AppError::InvalidMacAddress(mac))?;
+            let target = host_config
+                .wake_broadcast_address
+                .unwrap_or(DEFAULT_WAKE_BROADCAST_ADDRESS);
+
+            send_magic_packet(mac, target).await?;
+            Ok(())
+        } else if let Some(mac) = storage.cache.mac {
             broadcast_magic_packet(mac).await?;
             Ok(())
         } else {
@@ -437,19 +540,89 @@ impl Host {
         }
     }
 
-    pub async fn list_apps(&mut self, user: &mut AuthenticatedUser) -> Result<Vec<App>, AppError> {
+    /// Polls the host until it answers or the timeout elapses.
+    /// Returns if the host is online.
+    pub async fn wait_online(
+        &mut self,
+        user: &mut AuthenticatedUser,
+        timeout: Duration,
+    ) -> Result<bool, AppError> {
+        const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+        self.can_use(user).await?;
+
+        let start = Instant::now();
+        loop {
+            // The host might answer with errors while it's booting
+            match self.unique_id(user).await {
+                Ok(Some(_)) => return Ok(true),
+                Ok(None) => {}
+                Err(err) => debug!("host {:?} is not ready yet: {err}", self.id),
+            }
+
+            if start.elapsed() >= timeout {
+                return Ok(false);
+            }
+            sleep(POLL_INTERVAL).await;
+        }
+    }
+
+    /// Returns the apps and if they're stale because the host couldn't be reached
+    pub async fn list_apps(
+        &mut self,
+        user: &mut AuthenticatedUser,
+        force_refresh: bool,
+    ) -> Result<(Vec<App>, bool), AppError> {
         self.can_use(user).await?;
 
         let app = self.app.access()?;
 
-        self.use_client(&app, user, async |_this, host| {
-            let apps = host.app_list().await?;
+        let cache_key = (user.id(), self.id);
+        let cache_ttl = app.config().moonlight.app_list_cache_ttl;
+        if !force_refresh {
+            let app_lists = app.app_list_cache.read().await;
+            if let Some((fetched_at, apps)) = app_lists.get(&cache_key)
+                && fetched_at.elapsed() < cache_ttl
+            {
+                return Ok((apps.clone(), false));
+            }
+        }
 
-            let apps = apps.into_iter().map(App::from).collect::<Vec<_>>();
+        let retry = self.request_retry(&app).await?;
 
-            Ok(apps)
-        })
-        .await?
+        let result = self
+            .use_client(&app, user, async |_this, host| {
+                let apps =
+                    retry_host_request(&retry, "app list", async || host.app_list().await).await?;
+
+                let apps = apps.into_iter().map(App::from).collect::<Vec<_>>();
+
+                Ok::<_, AppError>(apps)
+            })
+            .await
+            .and_then(|result| result);
+
+        match result {
+            Ok(apps) => {
+                let mut app_lists = app.app_list_cache.write().await;
+                app_lists.insert(cache_key, (Instant::now(), apps.clone()));
+
+                Ok((apps, false))
+            }
+            Err(err) => {
+                let app_lists = app.app_list_cache.read().await;
+                if let Some((_, apps)) = app_lists.get(&cache_key) {
+                    warn!(
+                        "failed to list apps of host {:?}, using the last known apps: {err}",
+                        self.id
+                    );
+
+                    return Ok((apps.clone(), true));
+                }
+
+                Err(err)
+            }
+        }
     }
     pub async fn app_image(
         &mut self,
@@ -471,9 +644,14 @@ impl Host {
             }
         }
 
+        let retry = self.request_retry(&app).await?;
+
         let app_image = self
             .use_client(&app, user, async |_this, host| {
-                let image = host.request_app_image(app_id.0).await?;
+                let image = retry_host_request(&retry, "app image", async || {
+                    host.request_app_image(app_id.0).await
+                })
+                .await?;
 
                 Ok::<_, AppError>(image)
             })
@@ -513,6 +691,10 @@ impl Host {
                 let mut app_images = app.app_image_cache.write().await;
                 app_images.retain(|(_, host_id, _), _| *host_id != self.id);
             }
+            {
+                let mut app_lists = app.app_list_cache.write().await;
+                app_lists.retain(|(_, host_id), _| *host_id != self.id);
+            }
 
             drop(app);
             self.delete_no_auth().await
@@ -0,0 +1,37 @@
+//! Sunshine and GameStream derive their ports from one base port, the https port is 5 below the http port.
+//! Users sometimes enter the https port or a host uses custom ports, so the ports are detected when adding a host.
+
+use std::time::Duration;
+
+use tokio::{net::TcpStream, time::timeout};
+
+/// The default http port is 47989 and the default https port 47984
+pub const HTTPS_PORT_OFFSET: u16 = 5;
+
+/// How long the https port of a host gets to accept a connection
+pub const HTTPS_PROBE_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// The https port a host with this http port uses if its ports weren't changed individually
+pub fn standard_https_port(http_port: u16) -> Option<u16> {
+    http_port.checked_sub(HTTPS_PORT_OFFSET)
+}
+
+/// The http ports tried in order when adding a host.
+/// If the entered port is the https port of the host its http port is 5 above it.
+pub fn http_port_candidates(http_port: u16, detect: bool) -> Vec<u16> {
+    let mut candidates = vec![http_port];
+
+    if detect && let Some(derived) = http_port.checked_add(HTTPS_PORT_OFFSET) {
+        candidates.push(derived);
+    }
+
+    candidates
+}
+
+/// Checks if the port accepts tcp connections, the https port needs a client certificate so this is all that can be checked before pairing
+pub async fn is_port_reachable(address: &str, port: u16) -> bool {
+    matches!(
+        timeout(HTTPS_PROBE_TIMEOUT, TcpStream::connect((address, port))).await,
+        Ok(Ok(_))
+    )
+}
@@ -0,0 +1,76 @@
+//! Retries the http requests to a host which failed because it couldn't be reached, e.g. while it's busy or waking up
+
+use std::io;
+
+use common::config::HostRequestRetryConfig;
+use moonlight_common::high::MoonlightClientError;
+use tokio::time::{sleep, timeout};
+use tracing::{debug, warn};
+
+use crate::app::is_connection_error;
+
+#[derive(Debug, thiserror::Error)]
+#[error("{request} request failed after {attempts} attempts: {source}")]
+pub struct HostRequestError {
+    pub request: &'static str,
+    pub attempts: u32,
+    #[source]
+    pub source: MoonlightClientError,
+}
+
+impl HostRequestError {
+    pub fn is_offline(&self) -> bool {
+        matches!(self.source, MoonlightClientError::Offline)
+    }
+}
+
+/// Only errors where the host didn't respond are worth retrying, an error response would just be sent again
+fn is_retryable(err: &MoonlightClientError) -> bool {
+    matches!(err, MoonlightClientError::Offline) || is_connection_error(err)
+}
+
+/// Runs the request until it succeeds, fails with an error that isn't retryable or all attempts are used up.
+/// Every attempt is cancelled after the configured timeout.
+pub async fn retry_host_request<T>(
+    config: &HostRequestRetryConfig,
+    request: &'static str,
+    mut f: impl AsyncFnMut() -> Result<T, MoonlightClientError>,
+) -> Result<T, HostRequestError> {
+    let attempts = config.attempts.max(1);
+    let mut backoff = config.initial_backoff;
+
+    let mut attempt = 1;
+    loop {
+        let result = match timeout(config.timeout, f()).await {
+            Ok(result) => result,
+            Err(_) => Err(MoonlightClientError::Backend(Box::new(io::Error::new(
+                io::ErrorKind::TimedOut,
+                format!("the host didn't respond within {:?}", config.timeout),
+            )))),
+        };
+
+        let err = match result {
+            Ok(value) => return Ok(value),
+            Err(err) if attempt < attempts && is_retryable(&err) => err,
+            Err(source) => {
+                if attempt > 1 {
+                    warn!("[Host]: {request} request failed after {attempt} attempts: {source}");
+                }
+
+                return Err(HostRequestError {
+                    request,
+                    attempts: attempt,
+                    source,
+                });
+            }
+        };
+
+        debug!(
+            "[Host]: {request} request failed (attempt {attempt} of {attempts}), retrying in {backoff:?}: {err}"
+        );
+
+        sleep(backoff).await;
+        backoff = (backoff * 2).min(config.max_backoff);
+        attempt += 1;
+    }
+}
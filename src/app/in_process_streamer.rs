@@ -0,0 +1,127 @@
+//! Runs the streamer on a thread of the web server instead of spawning a separate process
+
+use std::{
+    io, mem,
+    sync::atomic::{AtomicBool, Ordering},
+    thread,
+};
+
+use common::ipc::{InMemoryIpcEnd, ServerIpcMessage, StreamerIpcMessage, create_in_memory_ipc};
+use streamer::run_streamer;
+use tokio::{runtime::Builder, select, sync::oneshot};
+use tracing::{Instrument, Span, error};
+
+/// Moonlight only supports one stream per process
+static RUNNING: AtomicBool = AtomicBool::new(false);
+
+#[derive(Debug, thiserror::Error)]
+pub enum InProcessStreamerError {
+    #[error(
+        "the in process streamer is already running a stream or was killed, only one stream is supported"
+    )]
+    Busy,
+    #[error("failed to start the streamer thread: {0}")]
+    Io(#[from] io::Error),
+}
+
+/// Allows a new in process streamer once the current one is dropped, even if it panicked
+struct RunningGuard;
+
+impl Drop for RunningGuard {
+    fn drop(&mut self) {
+        RUNNING.store(false, Ordering::Release);
+    }
+}
+
+pub struct InProcessStreamer {
+    kill: Option<oneshot::Sender<()>>,
+    finished: Option<oneshot::Receiver<()>>,
+}
+
+impl InProcessStreamer {
+    /// Starts the streamer with its own runtime so it can't block the web server
+    pub fn spawn(
+        span: Span,
+        end: InMemoryIpcEnd,
+        channel_capacity: usize,
+    ) -> Result<Self, InProcessStreamerError> {
+        if RUNNING.swap(true, Ordering::AcqRel) {
+            return Err(InProcessStreamerError::Busy);
+        }
+
+        let (kill, kill_receiver) = oneshot::channel::<()>();
+        let (finished_sender, finished) = oneshot::channel::<()>();
+
+        let spawned = thread::Builder::new()
+            .name("streamer".to_string())
+            .spawn(move || {
+                // Dropped in reverse order: the next streamer is allowed before anyone is notified
+                let _finished = finished_sender;
+                let running = RunningGuard;
+
+                let runtime = match Builder::new_multi_thread().enable_all().build() {
+                    Ok(runtime) => runtime,
+                    Err(err) => {
+                        error!(parent: &span, "[Stream]: failed to create the streamer runtime: {err}");
+                        return;
+                    }
+                };
+
+                let killed = runtime.block_on({
+                    let span = span.clone();
+
+                    async move {
+                        let (ipc_sender, ipc_receiver) = create_in_memory_ipc::<
+                            StreamerIpcMessage,
+                            ServerIpcMessage,
+                        >(span.clone(), end, channel_capacity);
+
+                        select! {
+                            _ = run_streamer(ipc_sender, ipc_receiver, false).instrument(span) => false,
+                            _ = kill_receiver => true,
+                        }
+                    }
+                });
+
+                if killed {
+                    // The moonlight connection wasn't stopped, so it might still use the runtime in its callbacks
+                    // and a new connection would fail. Unlike a process exit nothing cleans this up.
+                    error!(
+                        parent: &span,
+                        "[Stream]: the in process streamer was killed while moonlight might still be connected, no more streams can be started until the web server restarts"
+                    );
+                    mem::forget(runtime);
+                    mem::forget(running);
+                    return;
+                }
+
+                // Stops the tasks the streamer left behind, like a process exit would
+                runtime.shutdown_background();
+            });
+
+        if let Err(err) = spawned {
+            RUNNING.store(false, Ordering::Release);
+            return Err(err.into());
+        }
+
+        Ok(Self {
+            kill: Some(kill),
+            finished: Some(finished),
+        })
+    }
+
+    /// Waits until the streamer thread finished
+    pub async fn wait(&mut self) {
+        if let Some(finished) = &mut self.finished {
+            let _ = finished.await;
+            self.finished = None;
+        }
+    }
+
+    /// Stops the streamer without waiting for it to clean up
+    pub fn start_kill(&mut self) {
+        if let Some(kill) = self.kill.take() {
+            let _ = kill.send(());
+        }
+    }
+}
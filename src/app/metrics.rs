@@ -0,0 +1,146 @@
+use std::{
+    fmt::Write,
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+};
+
+use common::ipc::{IPC_MESSAGES_RECEIVED, IPC_MESSAGES_SENT, StreamerMetrics};
+
+/// Counters and gauges exported in the prometheus text format
+#[derive(Debug, Default)]
+pub struct Metrics {
+    pub active_sessions: AtomicU64,
+    pub sessions_total: AtomicU64,
+    pub streamer_spawn_failures_total: AtomicU64,
+    pub streamer_crashes_total: AtomicU64,
+    pub pair_attempts_total: AtomicU64,
+    pub webrtc_failures_total: AtomicU64,
+    pub streamed_video_bytes_total: AtomicU64,
+    pub streamed_audio_bytes_total: AtomicU64,
+}
+
+impl Metrics {
+    /// Counts the session as active until the returned guard is dropped.
+    /// Fails if `max_sessions` streams are already running.
+    pub fn start_session(self: &Arc<Self>, max_sessions: Option<usize>) -> Option<ActiveSession> {
+        if let Some(max_sessions) = max_sessions {
+            self.active_sessions
+                .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |active| {
+                    (active < max_sessions as u64).then_some(active + 1)
+                })
+                .ok()?;
+        } else {
+            self.active_sessions.fetch_add(1, Ordering::Relaxed);
+        }
+        self.sessions_total.fetch_add(1, Ordering::Relaxed);
+
+        Some(ActiveSession {
+            metrics: self.clone(),
+        })
+    }
+
+    pub fn add_streamer_metrics(&self, metrics: &StreamerMetrics) {
+        self.streamed_video_bytes_total
+            .fetch_add(metrics.streamed_video_bytes, Ordering::Relaxed);
+        self.streamed_audio_bytes_total
+            .fetch_add(metrics.streamed_audio_bytes, Ordering::Relaxed);
+        self.webrtc_failures_total
+            .fetch_add(metrics.webrtc_failures as u64, Ordering::Relaxed);
+    }
+
+    pub fn render(&self, max_sessions: Option<usize>) -> String {
+        let metrics: [(&str, &str, &str, &AtomicU64); 10] = [
+            (
+                "moonlight_web_active_sessions",
+                "gauge",
+                "The number of currently running streams",
+                &self.active_sessions,
+            ),
+            (
+                "moonlight_web_sessions_total",
+                "counter",
+                "The number of started streams",
+                &self.sessions_total,
+            ),
+            (
+                "moonlight_web_streamer_spawn_failures_total",
+                "counter",
+                "The number of times the streamer process couldn't be started",
+                &self.streamer_spawn_failures_total,
+            ),
+            (
+                "moonlight_web_streamer_crashes_total",
+                "counter",
+                "The number of times the streamer crashed or stopped responding",
+                &self.streamer_crashes_total,
+            ),
+            (
+                "moonlight_web_pair_attempts_total",
+                "counter",
+                "The number of times pairing with a host was started",
+                &self.pair_attempts_total,
+            ),
+            (
+                "moonlight_web_webrtc_failures_total",
+                "counter",
+                "The number of failed WebRTC connections",
+                &self.webrtc_failures_total,
+            ),
+            (
+                "moonlight_web_streamed_video_bytes_total",
+                "counter",
+                "The number of video bytes sent to browsers",
+                &self.streamed_video_bytes_total,
+            ),
+            (
+                "moonlight_web_streamed_audio_bytes_total",
+                "counter",
+                "The number of audio bytes sent to browsers",
+                &self.streamed_audio_bytes_total,
+            ),
+            (
+                "moonlight_web_ipc_messages_sent_total",
+                "counter",
+                "The number of ipc messages sent to streamers",
+                &IPC_MESSAGES_SENT,
+            ),
+            (
+                "moonlight_web_ipc_messages_received_total",
+                "counter",
+                "The number of ipc messages received from streamers",
+                &IPC_MESSAGES_RECEIVED,
+            ),
+        ];
+
+        let mut text = String::new();
+        for (name, ty, help, value) in metrics {
+            let _ = writeln!(text, "# HELP {name} {help}");
+            let _ = writeln!(text, "# TYPE {name} {ty}");
+            let _ = writeln!(text, "{name} {}", value.load(Ordering::Relaxed));
+        }
+
+        if let Some(max_sessions) = max_sessions {
+            let name = "moonlight_web_max_sessions";
+            let _ = writeln!(
+                text,
+                "# HELP {name} The number of streams which can run at the same time"
+            );
+            let _ = writeln!(text, "# TYPE {name} gauge");
+            let _ = writeln!(text, "{name} {max_sessions}");
+        }
+
+        text
+    }
+}
+
+pub struct ActiveSession {
+    metrics: Arc<Metrics>,
+}
+
+impl Drop for ActiveSession {
+    fn drop(&mut self) {
+        self.metrics.active_sessions.fetch_sub(1, Ordering::Relaxed);
+    }
+}
@@ -1,38 +1,71 @@
 use std::{
     collections::HashMap,
+    error::Error as StdError,
     io, mem,
     ops::Deref,
-    sync::{Arc, Weak},
+    sync::{Arc, RwLock as SyncRwLock, Weak, atomic::Ordering},
+    time::{Duration, Instant},
 };
 
-use actix_web::{ResponseError, http::StatusCode, web::Bytes};
-use common::config::Config;
+use actix_web::{
+    HttpResponse, ResponseError,
+    http::{StatusCode, header::RETRY_AFTER},
+    web::Bytes,
+};
+use common::{
+    api_bindings::{ApiError, ApiErrorKind, DiscoveredHost},
+    config::Config,
+    ipc::ServerIpcMessage,
+};
 use futures_concurrency::future::RaceOk;
 use hex::FromHexError;
 use moonlight_common::{high::MoonlightClientError, http::client::tokio_hyper::TokioHyperClient};
 use openssl::error::ErrorStack;
+use serde_json::Value;
 use thiserror::Error;
-use tokio::sync::RwLock;
+use tokio::{
+    sync::{RwLock, watch},
+    time::{sleep, timeout},
+};
 use tracing::{error, info, warn};
 
 use crate::app::{
     auth::{SessionToken, UserAuth},
+    discovery::Discovery,
     host::{AppId, HostId},
+    host_request::HostRequestError,
+    metrics::Metrics,
     password::StoragePassword,
+    rate_limit::{RateLimiter, retry_after_secs},
+    resumable::ResumableStreams,
     role::{Role, RoleId},
+    sessions::SessionRegistry,
     storage::{
         Either, Storage, StorageHostModify, StorageRoleAdd, StorageRoleDefaultSettings,
         StorageRolePermissions, StorageUserAdd, create_storage,
     },
+    stream_token::StreamTokenSigner,
     user::{Admin, AuthenticatedUser, RoleType, User, UserId},
 };
 
 pub mod auth;
+pub mod client_ip;
+pub mod discovery;
 pub mod host;
+pub mod host_ports;
+pub mod host_request;
+#[cfg(feature = "in-process-streamer")]
+pub mod in_process_streamer;
+pub mod metrics;
 pub mod password;
+pub mod rate_limit;
+pub mod resumable;
 pub mod role;
+pub mod sessions;
 pub mod storage;
+pub mod stream_token;
 pub mod user;
+pub mod wake;
 
 #[derive(Debug, Error)]
 pub enum AppError {
@@ -42,6 +75,8 @@ pub enum AppError {
     UserNotFound,
     #[error("the role was not found")]
     RoleNotFound,
+    #[error("the stream session was not found")]
+    StreamSessionNotFound,
     #[error("more than one user already exists")]
     FirstUserAlreadyExists,
     #[error("the config option first_login_create_admin is not true")]
@@ -61,6 +96,10 @@ pub enum AppError {
     SessionTokenNotFound,
     #[error("the action is not allowed because the user is not authorized, 401")]
     Unauthorized,
+    #[error("the stream token is invalid")]
+    StreamTokenInvalid,
+    #[error("the stream token expired")]
+    StreamTokenExpired,
     #[error("using a custom header for authorization is disabled")]
     HeaderAuthDisabled,
     // --
@@ -80,6 +119,22 @@ pub enum AppError {
     #[error("the authorization header is not a bearer")]
     BadRequest,
     // --
+    #[error("the server is shutting down")]
+    ShuttingDown,
+    #[error("too many attempts, retry in {retry_after:?}")]
+    RateLimited { retry_after: Duration },
+    #[error("the server is at capacity, only {max_sessions} streams can run at the same time")]
+    ServerAtCapacity { max_sessions: usize },
+    #[error("the host is already being paired")]
+    PairingInProgress,
+    #[error("the mac address {0:?} in the host config is invalid")]
+    InvalidMacAddress(String),
+    #[error("the host didn't come online after it was woken up")]
+    WakeTimeout,
+    #[error("the https port {port} of the host isn't reachable")]
+    HostHttpsUnreachable { port: u16 },
+    #[error("host discovery is disabled in the config")]
+    DiscoveryDisabled,
     #[error("openssl error occured: {0}")]
     OpenSSL(#[from] ErrorStack),
     #[error("hex error occured: {0}")]
@@ -88,6 +143,96 @@ pub enum AppError {
     Io(#[from] io::Error),
     #[error("moonlight error: {0}")]
     Moonlight(#[from] MoonlightClientError),
+    #[error("moonlight error: {0}")]
+    HostRequest(#[from] HostRequestError),
+    #[error("mdns error: {0}")]
+    Mdns(#[from] mdns_sd::Error),
+}
+
+impl AppError {
+    pub fn kind(&self) -> ApiErrorKind {
+        match self {
+            Self::HostNotFound => ApiErrorKind::HostNotFound,
+            Self::HostNotPaired => ApiErrorKind::HostNotPaired,
+            Self::HostPaired => ApiErrorKind::HostPaired,
+            Self::UserNotFound
+            | Self::RoleNotFound
+            | Self::StreamSessionNotFound
+            | Self::DiscoveryDisabled => ApiErrorKind::NotFound,
+            Self::UserAlreadyExists | Self::PairingInProgress => ApiErrorKind::Conflict,
+            Self::CredentialsWrong
+            | Self::SessionTokenNotFound
+            | Self::Unauthorized
+            | Self::StreamTokenInvalid
+            | Self::StreamTokenExpired
+            | Self::HeaderAuthDisabled => ApiErrorKind::Unauthorized,
+            Self::Forbidden => ApiErrorKind::Forbidden,
+            Self::Hex(_)
+            | Self::AuthorizationNotBearer
+            | Self::HeaderAuthMalformed
+            | Self::BearerMalformed
+            | Self::PasswordEmpty
+            | Self::UserNameEmpty
+            | Self::BadRequest => ApiErrorKind::BadRequest,
+            Self::ShuttingDown => ApiErrorKind::ShuttingDown,
+            Self::RateLimited { .. } => ApiErrorKind::RateLimited,
+            Self::ServerAtCapacity { .. } => ApiErrorKind::ServerAtCapacity,
+            Self::WakeTimeout
+            | Self::HostHttpsUnreachable { .. }
+            | Self::Moonlight(MoonlightClientError::Offline) => ApiErrorKind::HostUnreachable,
+            Self::Moonlight(err) if is_connection_error(err) => ApiErrorKind::HostUnreachable,
+            Self::Moonlight(_) => ApiErrorKind::HostError,
+            Self::HostRequest(err) if err.is_offline() || is_connection_error(err) => {
+                ApiErrorKind::HostUnreachable
+            }
+            Self::HostRequest(_) => ApiErrorKind::HostError,
+            Self::AppDestroyed
+            | Self::FirstUserAlreadyExists
+            | Self::FirstLoginCreateAdminNotSet
+            | Self::InvalidMacAddress(_)
+            | Self::OpenSSL(_)
+            | Self::Io(_)
+            | Self::Mdns(_) => ApiErrorKind::Internal,
+        }
+    }
+
+    /// All errors which caused this error, e.g. the http error of a moonlight error
+    fn detail(&self) -> Option<String> {
+        let mut details = Vec::new();
+
+        let mut source = self.source();
+        while let Some(err) = source {
+            details.push(err.to_string());
+            source = err.source();
+        }
+
+        (!details.is_empty()).then(|| details.join(": "))
+    }
+}
+
+/// Checks if the host couldn't be reached, e.g. because it's offline
+fn is_connection_error(err: &(dyn StdError + 'static)) -> bool {
+    let mut source = Some(err);
+    while let Some(err) = source {
+        if let Some(err) = err.downcast_ref::<io::Error>()
+            && matches!(
+                err.kind(),
+                io::ErrorKind::ConnectionRefused
+                    | io::ErrorKind::ConnectionReset
+                    | io::ErrorKind::ConnectionAborted
+                    | io::ErrorKind::TimedOut
+                    | io::ErrorKind::HostUnreachable
+                    | io::ErrorKind::NetworkUnreachable
+                    | io::ErrorKind::AddrNotAvailable
+            )
+        {
+            return true;
+        }
+
+        source = err.source();
+    }
+
+    false
 }
 
 impl ResponseError for AppError {
@@ -101,10 +246,13 @@ impl ResponseError for AppError {
             Self::HostPaired => StatusCode::NOT_MODIFIED,
             Self::UserNotFound => StatusCode::NOT_FOUND,
             Self::RoleNotFound => StatusCode::NOT_FOUND,
+            Self::StreamSessionNotFound => StatusCode::NOT_FOUND,
             Self::UserAlreadyExists => StatusCode::CONFLICT,
             Self::CredentialsWrong => StatusCode::UNAUTHORIZED,
             Self::SessionTokenNotFound => StatusCode::UNAUTHORIZED,
             Self::Unauthorized => StatusCode::UNAUTHORIZED,
+            Self::StreamTokenInvalid => StatusCode::UNAUTHORIZED,
+            Self::StreamTokenExpired => StatusCode::UNAUTHORIZED,
             Self::Forbidden => StatusCode::FORBIDDEN,
             Self::OpenSSL(_) => StatusCode::INTERNAL_SERVER_ERROR,
             Self::HeaderAuthDisabled => StatusCode::UNAUTHORIZED,
@@ -115,10 +263,57 @@ impl ResponseError for AppError {
             Self::PasswordEmpty => StatusCode::BAD_REQUEST,
             Self::UserNameEmpty => StatusCode::BAD_REQUEST,
             Self::BadRequest => StatusCode::BAD_REQUEST,
+            Self::ShuttingDown => StatusCode::SERVICE_UNAVAILABLE,
+            Self::RateLimited { .. } => StatusCode::TOO_MANY_REQUESTS,
+            Self::ServerAtCapacity { .. } => StatusCode::TOO_MANY_REQUESTS,
+            Self::PairingInProgress => StatusCode::CONFLICT,
+            Self::InvalidMacAddress(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            Self::WakeTimeout => StatusCode::GATEWAY_TIMEOUT,
+            Self::HostHttpsUnreachable { .. } => StatusCode::BAD_GATEWAY,
+            Self::DiscoveryDisabled => StatusCode::NOT_FOUND,
             Self::Moonlight(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            Self::HostRequest(_) => StatusCode::INTERNAL_SERVER_ERROR,
             Self::Io(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            Self::Mdns(_) => StatusCode::INTERNAL_SERVER_ERROR,
         }
     }
+
+    fn error_response(&self) -> HttpResponse {
+        let mut response = HttpResponse::build(self.status_code());
+
+        if let Self::RateLimited { retry_after } = self {
+            response.insert_header((RETRY_AFTER, retry_after_secs(*retry_after)));
+        }
+
+        response.json(ApiError {
+            error: self.to_string(),
+            kind: self.kind(),
+            detail: self.detail(),
+        })
+    }
+}
+
+fn collect_changed_options(changed: &mut Vec<String>, path: &str, old: &Value, new: &Value) {
+    match (old, new) {
+        (Value::Object(old), Value::Object(new)) => {
+            for (key, old_value) in old {
+                let key_path = if path.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{path}.{key}")
+                };
+
+                match new.get(key) {
+                    Some(new_value) => {
+                        collect_changed_options(changed, &key_path, old_value, new_value)
+                    }
+                    None => changed.push(key_path),
+                }
+            }
+        }
+        (old, new) if old != new => changed.push(path.to_string()),
+        _ => {}
+    }
 }
 
 #[derive(Clone)]
@@ -133,9 +328,35 @@ impl AppRef {
 }
 
 struct AppInner {
-    config: Config,
+    config: SyncRwLock<Arc<Config>>,
     storage: Arc<dyn Storage + Send + Sync>,
     app_image_cache: RwLock<HashMap<(UserId, HostId, AppId), Bytes>>,
+    app_list_cache: RwLock<HashMap<(UserId, HostId), (Instant, Vec<host::App>)>>,
+    metrics: Arc<Metrics>,
+    sessions: Arc<SessionRegistry>,
+    resumable_streams: Arc<ResumableStreams>,
+    rate_limiter: Arc<RateLimiter>,
+    stream_tokens: StreamTokenSigner,
+    discovery: Discovery,
+    shutdown: watch::Sender<ShutdownState>,
+}
+
+impl AppInner {
+    fn config(&self) -> Arc<Config> {
+        self.config
+            .read()
+            .expect("the config lock was poisoned")
+            .clone()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShutdownState {
+    Running,
+    /// New streams are rejected and the active ones should stop
+    Draining,
+    /// The grace period is over, the remaining streamers should be killed
+    Killing,
 }
 
 pub type MoonlightClient = TokioHyperClient;
@@ -146,10 +367,21 @@ pub struct App {
 
 impl App {
     pub async fn new(config: Config) -> Result<Self, anyhow::Error> {
+        let stream_tokens =
+            StreamTokenSigner::new(config.web_server.stream_token_secret.as_deref())?;
+
         let app = AppInner {
             storage: create_storage(config.data_storage.clone()).await?,
-            config,
+            config: SyncRwLock::new(Arc::new(config)),
             app_image_cache: Default::default(),
+            app_list_cache: Default::default(),
+            metrics: Default::default(),
+            sessions: Default::default(),
+            resumable_streams: Default::default(),
+            rate_limiter: Default::default(),
+            stream_tokens,
+            discovery: Default::default(),
+            shutdown: watch::Sender::new(ShutdownState::Running),
         };
 
         Ok(Self {
@@ -163,8 +395,141 @@ impl App {
         }
     }
 
-    pub fn config(&self) -> &Config {
-        &self.inner.config
+    /// Returns a snapshot of the current config.
+    /// The config might be replaced by [App::reload_config] at any time, so don't hold onto it for longer than needed.
+    pub fn config(&self) -> Arc<Config> {
+        self.inner.config()
+    }
+
+    pub fn metrics(&self) -> &Arc<Metrics> {
+        &self.inner.metrics
+    }
+
+    pub fn sessions(&self) -> &Arc<SessionRegistry> {
+        &self.inner.sessions
+    }
+
+    pub fn resumable_streams(&self) -> &Arc<ResumableStreams> {
+        &self.inner.resumable_streams
+    }
+
+    pub fn rate_limiter(&self) -> &Arc<RateLimiter> {
+        &self.inner.rate_limiter
+    }
+
+    pub fn stream_tokens(&self) -> &StreamTokenSigner {
+        &self.inner.stream_tokens
+    }
+
+    pub async fn discover_hosts(&self) -> Result<Vec<DiscoveredHost>, AppError> {
+        let config = self.config();
+
+        Ok(self.inner.discovery.discover(&config.discovery).await?)
+    }
+
+    /// Only used by cli commands, all hosts of all users are returned
+    pub async fn all_hosts_no_auth(&self) -> Result<Vec<StorageHost>, AppError> {
+        self.inner.storage.list_hosts().await
+    }
+
+    pub async fn flush_storage(&self) {
+        self.inner.storage.flush().await;
+    }
+
+    pub fn is_shutting_down(&self) -> bool {
+        *self.inner.shutdown.borrow() != ShutdownState::Running
+    }
+    pub fn subscribe_shutdown(&self) -> watch::Receiver<ShutdownState> {
+        self.inner.shutdown.subscribe()
+    }
+
+    /// Stops all active streams and waits up to the grace period for them to end.
+    /// Streamers which are still running after that are killed.
+    ///
+    /// Returns how many streams were drained and how many were killed.
+    pub async fn drain_streams(&self, grace_period: Duration) -> (u64, u64) {
+        const POLL_INTERVAL: Duration = Duration::from_millis(100);
+        const KILL_TIMEOUT: Duration = Duration::from_secs(5);
+
+        let active_sessions = &self.inner.metrics.active_sessions;
+        let wait_for_streams = async || {
+            while active_sessions.load(Ordering::Relaxed) > 0 {
+                sleep(POLL_INTERVAL).await;
+            }
+        };
+
+        let started_with = active_sessions.load(Ordering::Relaxed);
+        self.inner.shutdown.send_replace(ShutdownState::Draining);
+
+        // Nobody is connected to detached streams, so there is no need to wait for them
+        let detached = self.inner.resumable_streams.take_all();
+        if !detached.is_empty() {
+            info!(
+                "[Server]: stopping {} detached streams which can't be resumed anymore",
+                detached.len()
+            );
+        }
+        for stream in detached {
+            stream
+                .ipc_sender
+                .lock()
+                .await
+                .send(ServerIpcMessage::Stop)
+                .await;
+        }
+
+        if timeout(grace_period, wait_for_streams()).await.is_ok() {
+            return (started_with, 0);
+        }
+
+        let remaining = active_sessions.load(Ordering::Relaxed);
+        self.inner.shutdown.send_replace(ShutdownState::Killing);
+
+        if timeout(KILL_TIMEOUT, wait_for_streams()).await.is_err() {
+            warn!("[Server]: some streams didn't end after their streamer was killed");
+        }
+
+        (started_with.saturating_sub(remaining), remaining)
+    }
+
+    /// Applies the hot reloadable subset of the new config:
+    /// - `log.level_filter` and `log.directives`
+    /// - `webrtc.ice_servers` (only for new streams)
+    /// - `moonlight`
+    /// - `hosts` (only for new streams)
+    ///
+    /// All other options are left unchanged.
+    /// Returns the options which differ from the new config and require a restart.
+    pub fn reload_config(&self, new_config: &Config) -> Vec<String> {
+        let mut config = self
+            .inner
+            .config
+            .write()
+            .expect("the config lock was poisoned");
+
+        let mut reloaded = Config::clone(&config);
+        reloaded.log.level_filter = new_config.log.level_filter;
+        reloaded.log.directives = new_config.log.directives.clone();
+        reloaded.webrtc.ice_servers = new_config.webrtc.ice_servers.clone();
+        reloaded.moonlight = new_config.moonlight.clone();
+        reloaded.hosts = new_config.hosts.clone();
+
+        let mut requires_restart = Vec::new();
+        match (
+            serde_json::to_value(&reloaded),
+            serde_json::to_value(new_config),
+        ) {
+            (Ok(reloaded), Ok(new_config)) => {
+                collect_changed_options(&mut requires_restart, "", &reloaded, &new_config);
+            }
+            (Err(err), _) | (_, Err(err)) => {
+                warn!("failed to compare the reloaded config: {err}");
+            }
+        }
+
+        *config = Arc::new(reloaded);
+
+        requires_restart
     }
 
     /// Handles all logic related to adding the first user:
@@ -282,8 +647,8 @@ impl App {
                 let user = match self.user_by_name(username).await {
                     Ok(user) => user,
                     Err(AppError::UserNotFound) => {
-                        let Some(config_forwarded_headers) =
-                            &self.config().web_server.forwarded_header
+                        let config = self.config();
+                        let Some(config_forwarded_headers) = &config.web_server.forwarded_header
                         else {
                             return Err(AppError::Unauthorized);
                         };
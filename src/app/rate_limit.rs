@@ -0,0 +1,134 @@
+use std::{
+    collections::{HashMap, HashSet},
+    net::IpAddr,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use common::config::RateLimitConfig;
+
+use crate::app::{AppError, host::HostId};
+
+/// Forget clients which didn't make an attempt for a while once this many are tracked
+const CLEANUP_THRESHOLD: usize = 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RateLimitedAction {
+    Login,
+    Pair,
+}
+
+#[derive(Debug)]
+struct ClientAttempts {
+    window_start: Instant,
+    count: u32,
+    locked_until: Option<Instant>,
+}
+
+#[derive(Debug, Default)]
+pub struct RateLimiter {
+    attempts: Mutex<HashMap<(RateLimitedAction, IpAddr), ClientAttempts>>,
+    pairing_hosts: Mutex<HashSet<HostId>>,
+}
+
+impl RateLimiter {
+    /// Counts the attempt and fails with [AppError::RateLimited] if the client made too many attempts
+    pub fn attempt(
+        &self,
+        config: &RateLimitConfig,
+        action: RateLimitedAction,
+        ip: IpAddr,
+    ) -> Result<(), AppError> {
+        let now = Instant::now();
+
+        let mut attempts = self.attempts.lock().expect("rate limiter lock poisoned");
+
+        if attempts.len() >= CLEANUP_THRESHOLD {
+            attempts.retain(|_, client| {
+                client.locked_until.is_some_and(|until| until > now)
+                    || now.duration_since(client.window_start) < config.window
+            });
+        }
+
+        let client = attempts
+            .entry((action, ip))
+            .or_insert_with(|| ClientAttempts {
+                window_start: now,
+                count: 0,
+                locked_until: None,
+            });
+
+        if let Some(locked_until) = client.locked_until {
+            if locked_until > now {
+                return Err(AppError::RateLimited {
+                    retry_after: locked_until - now,
+                });
+            }
+            client.locked_until = None;
+            client.window_start = now;
+            client.count = 0;
+        }
+
+        if now.duration_since(client.window_start) >= config.window {
+            client.window_start = now;
+            client.count = 0;
+        }
+
+        client.count += 1;
+        if client.count > config.max_attempts {
+            client.locked_until = Some(now + config.lockout);
+
+            return Err(AppError::RateLimited {
+                retry_after: config.lockout,
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Forgets the previous attempts, e.g. after a successful login
+    pub fn reset(&self, action: RateLimitedAction, ip: IpAddr) {
+        let mut attempts = self.attempts.lock().expect("rate limiter lock poisoned");
+
+        if let Some(client) = attempts.get(&(action, ip))
+            && client.locked_until.is_none()
+        {
+            attempts.remove(&(action, ip));
+        }
+    }
+
+    /// Only one pairing can happen per host at a time, the host is pairing until the guard is dropped
+    pub fn start_pairing(self: &Arc<Self>, host_id: HostId) -> Result<PairingGuard, AppError> {
+        let mut pairing_hosts = self
+            .pairing_hosts
+            .lock()
+            .expect("rate limiter lock poisoned");
+
+        if !pairing_hosts.insert(host_id) {
+            return Err(AppError::PairingInProgress);
+        }
+
+        Ok(PairingGuard {
+            rate_limiter: self.clone(),
+            host_id,
+        })
+    }
+}
+
+pub struct PairingGuard {
+    rate_limiter: Arc<RateLimiter>,
+    host_id: HostId,
+}
+
+impl Drop for PairingGuard {
+    fn drop(&mut self) {
+        if let Ok(mut pairing_hosts) = self.rate_limiter.pairing_hosts.lock() {
+            pairing_hosts.remove(&self.host_id);
+        }
+    }
+}
+
+/// The value of the `Retry-After` header in seconds, rounded up
+pub fn retry_after_secs(retry_after: Duration) -> u64 {
+    retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0)
+}
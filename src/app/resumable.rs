@@ -0,0 +1,88 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        Arc, Mutex as SyncMutex, MutexGuard,
+        atomic::{AtomicUsize, Ordering},
+    },
+    time::Duration,
+};
+
+use actix_ws::Session;
+use common::ipc::{IpcSender, ServerIpcMessage};
+use tokio::sync::{Mutex, mpsc::UnboundedSender};
+
+use crate::app::{auth::SessionToken, user::UserId};
+
+/// Everything needed to resume a stream with a new web socket
+pub struct Resumable {
+    pub token: SessionToken,
+    pub user_id: UserId,
+    pub resume_timeout: Duration,
+    pub sessions: UnboundedSender<Session>,
+}
+
+pub struct DetachedStream {
+    pub resumable: Resumable,
+    /// Identifies this detach, so the timeout won't stop a stream which was resumed and detached again
+    pub detach_id: usize,
+    pub ipc_sender: Arc<Mutex<IpcSender<ServerIpcMessage>>>,
+}
+
+/// Streams whose web socket closed, they can be resumed with their session token until the resume timeout
+#[derive(Default)]
+pub struct ResumableStreams {
+    next_detach_id: AtomicUsize,
+    streams: SyncMutex<HashMap<SessionToken, DetachedStream>>,
+}
+
+impl ResumableStreams {
+    /// Returns the detach id of the stream
+    pub fn detach(
+        &self,
+        resumable: Resumable,
+        ipc_sender: Arc<Mutex<IpcSender<ServerIpcMessage>>>,
+    ) -> usize {
+        let detach_id = self.next_detach_id.fetch_add(1, Ordering::Relaxed);
+
+        self.lock().insert(
+            resumable.token,
+            DetachedStream {
+                resumable,
+                detach_id,
+                ipc_sender,
+            },
+        );
+
+        detach_id
+    }
+
+    /// Removes the stream if the predicate matches
+    pub fn take_if(
+        &self,
+        token: &SessionToken,
+        predicate: impl FnOnce(&DetachedStream) -> bool,
+    ) -> Option<DetachedStream> {
+        let mut streams = self.lock();
+
+        if streams.get(token).is_some_and(predicate) {
+            streams.remove(token)
+        } else {
+            None
+        }
+    }
+
+    pub fn remove(&self, token: &SessionToken) {
+        self.lock().remove(token);
+    }
+
+    /// Removes all streams, e.g. because the server shuts down and they can't be resumed anymore
+    pub fn take_all(&self) -> Vec<DetachedStream> {
+        self.lock().drain().map(|(_, stream)| stream).collect()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, HashMap<SessionToken, DetachedStream>> {
+        self.streams
+            .lock()
+            .expect("resumable streams lock poisoned")
+    }
+}
@@ -0,0 +1,158 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        Arc, Mutex as SyncMutex, MutexGuard,
+        atomic::{AtomicU32, Ordering},
+    },
+    time::{Duration, Instant, SystemTime},
+};
+
+use common::{
+    api_bindings::{AdminSession, StreamVideoMode},
+    ipc::{IpcSender, ServerIpcMessage, StreamerMetrics},
+};
+use tokio::sync::Mutex;
+
+use crate::app::{
+    host::{AppId, HostId},
+    stream_token::unix_secs,
+    user::UserId,
+};
+
+/// The bitrate is averaged over at least this long, the streamer sends its metrics about every second
+const BITRATE_WINDOW: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SessionId(pub u32);
+
+/// Everything known about a stream when its streamer was started
+pub struct SessionInfo {
+    pub user_id: UserId,
+    pub user_name: String,
+    pub host_id: HostId,
+    pub host_name: String,
+    pub app_id: AppId,
+    pub app_title: String,
+    pub video_mode: Option<StreamVideoMode>,
+    /// The streamer might get restarted, so this always has to point to the current one
+    pub ipc_sender: Arc<Mutex<IpcSender<ServerIpcMessage>>>,
+}
+
+struct SessionEntry {
+    info: SessionInfo,
+    started_at: u64,
+    video_mode: Option<StreamVideoMode>,
+    bitrate_kbps: u32,
+    window_start: Instant,
+    window_bytes: u64,
+}
+
+/// All streams which are currently running, used by the admin api
+#[derive(Default)]
+pub struct SessionRegistry {
+    next_id: AtomicU32,
+    sessions: SyncMutex<HashMap<SessionId, SessionEntry>>,
+}
+
+impl SessionRegistry {
+    /// Lists the session until the returned guard is dropped
+    pub fn register(self: &Arc<Self>, info: SessionInfo) -> RegisteredSession {
+        let id = SessionId(self.next_id.fetch_add(1, Ordering::Relaxed));
+
+        let entry = SessionEntry {
+            video_mode: info.video_mode,
+            info,
+            started_at: unix_secs(SystemTime::now()),
+            bitrate_kbps: 0,
+            window_start: Instant::now(),
+            window_bytes: 0,
+        };
+        self.lock().insert(id, entry);
+
+        RegisteredSession {
+            registry: self.clone(),
+            id,
+        }
+    }
+
+    pub fn list(&self) -> Vec<AdminSession> {
+        let mut sessions = self
+            .lock()
+            .iter()
+            .map(|(id, entry)| AdminSession {
+                session_id: id.0,
+                user_id: entry.info.user_id.0,
+                user_name: entry.info.user_name.clone(),
+                host_id: entry.info.host_id.0,
+                host_name: entry.info.host_name.clone(),
+                app_id: entry.info.app_id.0,
+                app_title: entry.info.app_title.clone(),
+                started_at: entry.started_at,
+                video_mode: entry.video_mode,
+                bitrate_kbps: entry.bitrate_kbps,
+            })
+            .collect::<Vec<_>>();
+        sessions.sort_by_key(|session| session.session_id);
+
+        sessions
+    }
+
+    /// Asks the streamer of the session to stop, returns false if the session doesn't exist
+    pub async fn stop(&self, id: SessionId) -> bool {
+        let ipc_sender = match self.lock().get(&id) {
+            Some(entry) => entry.info.ipc_sender.clone(),
+            None => return false,
+        };
+
+        ipc_sender.lock().await.send(ServerIpcMessage::Stop).await;
+
+        true
+    }
+
+    fn lock(&self) -> MutexGuard<'_, HashMap<SessionId, SessionEntry>> {
+        self.sessions.lock().expect("sessions lock poisoned")
+    }
+}
+
+/// Removes the session from the registry when dropped
+pub struct RegisteredSession {
+    registry: Arc<SessionRegistry>,
+    id: SessionId,
+}
+
+impl RegisteredSession {
+    pub fn id(&self) -> SessionId {
+        self.id
+    }
+
+    /// The client negotiated the settings of the stream
+    pub fn set_video_mode(&self, video_mode: StreamVideoMode) {
+        if let Some(entry) = self.registry.lock().get_mut(&self.id) {
+            entry.video_mode = Some(video_mode);
+        }
+    }
+
+    pub fn add_streamer_metrics(&self, metrics: &StreamerMetrics) {
+        let mut sessions = self.registry.lock();
+        let Some(entry) = sessions.get_mut(&self.id) else {
+            return;
+        };
+
+        entry.window_bytes += metrics.streamed_video_bytes + metrics.streamed_audio_bytes;
+
+        let elapsed = entry.window_start.elapsed();
+        if elapsed >= BITRATE_WINDOW {
+            let kbps = entry.window_bytes as f64 * 8.0 / 1000.0 / elapsed.as_secs_f64();
+
+            entry.bitrate_kbps = kbps as u32;
+            entry.window_start = Instant::now();
+            entry.window_bytes = 0;
+        }
+    }
+}
+
+impl Drop for RegisteredSession {
+    fn drop(&mut self) {
+        self.registry.lock().remove(&self.id);
+    }
+}
@@ -309,6 +309,7 @@ fn host_from_json(host_id: HostId, host: &V2Host) -> StorageHost {
             client_certificate: pair_info.client_certificate,
             client_private_key: pair_info.client_private_key,
             server_certificate: pair_info.server_certificate,
+            client_unique_id: pair_info.client_unique_id,
         }),
         cache: StorageHostCache {
             name: host.cache.name.clone(),
@@ -686,6 +687,7 @@ impl Storage for JsonStorage {
                 client_private_key: pair_info.client_private_key,
                 client_certificate: pair_info.client_certificate,
                 server_certificate: pair_info.server_certificate,
+                client_unique_id: pair_info.client_unique_id,
             }),
             cache: V2HostCache {
                 name: host.cache.name,
@@ -716,6 +718,7 @@ impl Storage for JsonStorage {
                 client_private_key: pair_info.client_private_key,
                 client_certificate: pair_info.client_certificate,
                 server_certificate: pair_info.server_certificate,
+                client_unique_id: pair_info.client_unique_id,
             }),
             cache: StorageHostCache {
                 name: host.cache.name,
@@ -747,6 +750,7 @@ impl Storage for JsonStorage {
                 client_private_key: new_pair_info.client_private_key,
                 client_certificate: new_pair_info.client_certificate,
                 server_certificate: new_pair_info.server_certificate,
+                client_unique_id: new_pair_info.client_unique_id,
             });
         }
         if let Some(new_cache_name) = modify.cache_name {
@@ -798,4 +802,21 @@ impl Storage for JsonStorage {
 
         Ok(user_hosts)
     }
+
+    async fn list_hosts(&self) -> Result<Vec<StorageHost>, AppError> {
+        let hosts = self.hosts.read().await;
+
+        let mut all_hosts = Vec::new();
+        for (host_id, host) in &*hosts {
+            let host = host.read().await;
+
+            all_hosts.push(host_from_json(HostId(*host_id), &host));
+        }
+
+        Ok(all_hosts)
+    }
+
+    async fn flush(&self) {
+        self.store().await;
+    }
 }
@@ -55,6 +55,7 @@ fn migrate_certificates_v1_to_v2(v1: V1HostPairInfo) -> Option<V2HostPairInfo> {
         client_private_key: v1.client_private_key.parse().ok()?,
         client_certificate: v1.client_certificate.parse().ok()?,
         server_certificate: v1.server_certificate.parse().ok()?,
+        client_unique_id: None,
     })
 }
 
@@ -141,6 +142,9 @@ pub struct V2HostPairInfo {
     pub client_private_key: Pem,
     pub client_certificate: Pem,
     pub server_certificate: Pem,
+    /// Missing if the host was paired before this was stored
+    #[serde(default)]
+    pub client_unique_id: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -146,6 +146,8 @@ pub struct StorageHostPairInfo {
     pub client_private_key: Pem,
     pub client_certificate: Pem,
     pub server_certificate: Pem,
+    /// The unique id the host was paired with, all requests to the host use it
+    pub client_unique_id: Option<String>,
 }
 #[derive(Default, Clone)]
 pub struct StorageHostModify {
@@ -222,4 +224,10 @@ pub trait Storage {
         &self,
         query: StorageQueryHosts,
     ) -> Result<Vec<(HostId, Option<StorageHost>)>, AppError>;
+
+    /// Returns all hosts regardless of their owner
+    async fn list_hosts(&self) -> Result<Vec<StorageHost>, AppError>;
+
+    /// Writes all changes immediately, e.g. before the process exits
+    async fn flush(&self);
 }
@@ -0,0 +1,211 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use openssl::{
+    error::ErrorStack,
+    hash::MessageDigest,
+    memcmp,
+    pkey::{PKey, Private},
+    rand::rand_bytes,
+    sign::Signer,
+};
+
+use crate::app::{AppError, auth::SessionToken, user::UserId};
+
+const GENERATED_SECRET_SIZE: usize = 32;
+
+/// Signs the tokens streams are resumed with, so they can't be forged and stop working once they expire
+pub struct StreamTokenSigner {
+    key: PKey<Private>,
+}
+
+impl StreamTokenSigner {
+    /// Uses a random secret if none is given, so the tokens only work until the server restarts
+    pub fn new(secret: Option<&str>) -> Result<Self, ErrorStack> {
+        let key = match secret {
+            Some(secret) => PKey::hmac(secret.as_bytes())?,
+            None => {
+                let mut secret = [0; GENERATED_SECRET_SIZE];
+                rand_bytes(&mut secret)?;
+
+                PKey::hmac(&secret)?
+            }
+        };
+
+        Ok(Self { key })
+    }
+
+    /// Creates a token of the format `<id>.<expires unix secs>.<hmac>` which only the user can use
+    pub fn sign(
+        &self,
+        id: SessionToken,
+        user_id: UserId,
+        lifetime: Duration,
+    ) -> Result<String, AppError> {
+        let expires = unix_secs(SystemTime::now() + lifetime);
+
+        let mut id_bytes = [0; _];
+        let payload = format!("{}.{expires}", id.encode(&mut id_bytes));
+        let signature = self.signature(&payload, user_id)?;
+
+        Ok(format!("{payload}.{}", hex::encode(signature)))
+    }
+
+    /// Checks the signature and expiry of the token and returns the id of the stream
+    pub fn verify(&self, token: &str, user_id: UserId) -> Result<SessionToken, AppError> {
+        let (payload, signature) = token.rsplit_once('.').ok_or(AppError::StreamTokenInvalid)?;
+        let signature = hex::decode(signature).map_err(|_| AppError::StreamTokenInvalid)?;
+
+        let expected = self.signature(payload, user_id)?;
+        if signature.len() != expected.len() || !memcmp::eq(&signature, &expected) {
+            return Err(AppError::StreamTokenInvalid);
+        }
+
+        let (id, expires) = payload
+            .split_once('.')
+            .ok_or(AppError::StreamTokenInvalid)?;
+        let expires = expires
+            .parse::<u64>()
+            .map_err(|_| AppError::StreamTokenInvalid)?;
+        if unix_secs(SystemTime::now()) > expires {
+            return Err(AppError::StreamTokenExpired);
+        }
+
+        SessionToken::decode(id).map_err(|_| AppError::StreamTokenInvalid)
+    }
+
+    fn signature(&self, payload: &str, user_id: UserId) -> Result<Vec<u8>, ErrorStack> {
+        let mut signer = Signer::new(MessageDigest::sha256(), &self.key)?;
+        signer.update(payload.as_bytes())?;
+        signer.update(&user_id.0.to_be_bytes())?;
+
+        signer.sign_to_vec()
+    }
+}
+
+pub(crate) fn unix_secs(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, SystemTime};
+
+    use crate::app::{
+        AppError,
+        auth::SessionToken,
+        stream_token::{StreamTokenSigner, unix_secs},
+        user::UserId,
+    };
+
+    const LIFETIME: Duration = Duration::from_secs(60);
+
+    fn signer() -> StreamTokenSigner {
+        StreamTokenSigner::new(Some("secret")).unwrap()
+    }
+
+    #[test]
+    fn round_trip() {
+        let signer = signer();
+        let id = SessionToken::new().unwrap();
+
+        let token = signer.sign(id, UserId(1), LIFETIME).unwrap();
+
+        assert_eq!(signer.verify(&token, UserId(1)).unwrap(), id);
+    }
+
+    #[test]
+    fn tampered_signature() {
+        let signer = signer();
+        let token = signer
+            .sign(SessionToken::new().unwrap(), UserId(1), LIFETIME)
+            .unwrap();
+
+        let (payload, signature) = token.rsplit_once('.').unwrap();
+        let flipped = if signature.starts_with('0') { '1' } else { '0' };
+        let tampered = format!("{payload}.{flipped}{}", &signature[1..]);
+
+        assert!(matches!(
+            signer.verify(&tampered, UserId(1)),
+            Err(AppError::StreamTokenInvalid)
+        ));
+    }
+
+    #[test]
+    fn tampered_expiry() {
+        let signer = signer();
+        let token = signer
+            .sign(SessionToken::new().unwrap(), UserId(1), LIFETIME)
+            .unwrap();
+
+        let mut parts = token.split('.');
+        let (id, _, signature) = (
+            parts.next().unwrap(),
+            parts.next().unwrap(),
+            parts.next().unwrap(),
+        );
+        let tampered = format!("{id}.{}.{signature}", u64::MAX);
+
+        assert!(matches!(
+            signer.verify(&tampered, UserId(1)),
+            Err(AppError::StreamTokenInvalid)
+        ));
+    }
+
+    #[test]
+    fn other_secret() {
+        let token = signer()
+            .sign(SessionToken::new().unwrap(), UserId(1), LIFETIME)
+            .unwrap();
+
+        let other = StreamTokenSigner::new(Some("other secret")).unwrap();
+
+        assert!(matches!(
+            other.verify(&token, UserId(1)),
+            Err(AppError::StreamTokenInvalid)
+        ));
+    }
+
+    #[test]
+    fn wrong_user() {
+        let signer = signer();
+        let token = signer
+            .sign(SessionToken::new().unwrap(), UserId(1), LIFETIME)
+            .unwrap();
+
+        assert!(matches!(
+            signer.verify(&token, UserId(2)),
+            Err(AppError::StreamTokenInvalid)
+        ));
+    }
+
+    #[test]
+    fn expired() {
+        let signer = signer();
+        let id = SessionToken::new().unwrap();
+
+        let mut id_bytes = [0; _];
+        let expires = unix_secs(SystemTime::now() - LIFETIME);
+        let payload = format!("{}.{expires}", id.encode(&mut id_bytes));
+        let signature = signer.signature(&payload, UserId(1)).unwrap();
+        let token = format!("{payload}.{}", hex::encode(signature));
+
+        assert!(matches!(
+            signer.verify(&token, UserId(1)),
+            Err(AppError::StreamTokenExpired)
+        ));
+    }
+
+    #[test]
+    fn malformed() {
+        let signer = signer();
+
+        for token in ["", "abc", "abc.def", "abc.123.zz"] {
+            assert!(matches!(
+                signer.verify(token, UserId(1)),
+                Err(AppError::StreamTokenInvalid)
+            ));
+        }
+    }
+}
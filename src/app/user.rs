@@ -5,7 +5,10 @@ use std::{
     time::Duration,
 };
 
-use common::api_bindings::{self, DetailedUser};
+use common::{
+    api_bindings::{self, DetailedUser},
+    config::HostRequestRetryConfig,
+};
 use moonlight_common::{
     high::MoonlightClientError,
     http::{
@@ -15,12 +18,15 @@ use moonlight_common::{
     },
 };
 use serde::{Deserialize, Serialize};
+use tracing::{debug, info, warn};
 use uuid::Uuid;
 
 use crate::app::{
     AppError, AppRef, MoonlightClient,
     auth::{SessionToken, UserAuth},
     host::{Host, HostId},
+    host_ports::{http_port_candidates, is_port_reachable, standard_https_port},
+    host_request::retry_host_request,
     password::StoragePassword,
     role::{Role, RoleId},
     storage::{
@@ -81,6 +87,11 @@ impl User {
         self.id
     }
 
+    /// Only used by cli commands, whoever can run them already has access to the data
+    pub fn authenticate_unchecked(self) -> AuthenticatedUser {
+        AuthenticatedUser { inner: self }
+    }
+
     async fn storage_user(&mut self) -> Result<Arc<StorageUser>, AppError> {
         if let Some(storage) = self.cache_storage.as_ref() {
             return Ok(storage.clone());
@@ -323,25 +334,75 @@ impl AuthenticatedUser {
         let client = MoonlightClient::with_defaults()
             .map_err(|err| MoonlightClientError::Backend(Box::new(err)))?;
 
-        let info = match client
-            .send_http::<ServerInfoEndpoint>(
-                ClientInfo {
-                    uuid: Uuid::new_v4(),
-                    unique_id,
-                },
-                &format!("{}:{}", address, http_port),
-                &ServerInfoRequest {},
-            )
-            .await
+        let app = self.app.access()?;
+        let config = app.config();
+        let retry = config.host_request_retry(&address);
+
+        let mut found = None;
+        for (index, port) in http_port_candidates(http_port, config.moonlight.detect_ports)
+            .into_iter()
+            .enumerate()
         {
-            Ok(info) => info,
-            Err(err) if err.is_connect() => {
-                return Err(AppError::HostNotFound);
+            // Only the entered port is retried, the other ones are just a guess
+            let retry = if index == 0 {
+                retry.clone()
+            } else {
+                HostRequestRetryConfig {
+                    attempts: 1,
+                    ..retry.clone()
+                }
+            };
+
+            let result = retry_host_request(&retry, "server info", async || {
+                client
+                    .send_http::<ServerInfoEndpoint>(
+                        ClientInfo {
+                            uuid: Uuid::new_v4(),
+                            unique_id: unique_id.clone(),
+                        },
+                        &format!("{}:{}", address, port),
+                        &ServerInfoRequest {},
+                    )
+                    .await
+                    .map_err(|err| {
+                        if err.is_connect() {
+                            MoonlightClientError::Offline
+                        } else {
+                            MoonlightClientError::Backend(Box::new(err))
+                        }
+                    })
+            })
+            .await;
+
+            match result {
+                Ok(info) => {
+                    found = Some((port, info));
+                    break;
+                }
+                Err(err) if err.is_offline() => {
+                    debug!("[Host]: {address} didn't respond on http port {port}");
+                }
+                Err(err) => return Err(err.into()),
             }
-            Err(err) => return Err(MoonlightClientError::Backend(Box::new(err)).into()),
+        }
+
+        let Some((http_port, info)) = found else {
+            return Err(AppError::HostNotFound);
         };
+        info!("[Host]: {address} responded on http port {http_port}");
 
-        let app = self.app.access()?;
+        if standard_https_port(http_port) != Some(info.https_port) {
+            info!(
+                "[Host]: {address} uses the https port {} which isn't derived from its http port {http_port}",
+                info.https_port
+            );
+        }
+        if !is_port_reachable(&address, info.https_port).await {
+            warn!(
+                "[Host]: the https port {} of {address} isn't reachable, pairing and streaming will fail until it's forwarded or allowed by the firewall",
+                info.https_port
+            );
+        }
 
         let host = app
             .storage
@@ -0,0 +1,50 @@
+use std::{
+    io,
+    net::{Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4},
+};
+
+use tokio::net::UdpSocket;
+
+/// Used if the host config has a mac address but no broadcast address
+pub const DEFAULT_WAKE_BROADCAST_ADDRESS: SocketAddr =
+    SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::BROADCAST, 9));
+
+/// Parses mac addresses like `aa:bb:cc:dd:ee:ff` or `aa-bb-cc-dd-ee-ff`
+pub fn parse_mac_address(value: &str) -> Option<[u8; 6]> {
+    let mut mac = [0; 6];
+
+    let mut parts = value.split([':', '-']);
+    for byte in &mut mac {
+        let part = parts.next()?;
+        if part.len() != 2 {
+            return None;
+        }
+
+        *byte = u8::from_str_radix(part, 16).ok()?;
+    }
+
+    if parts.next().is_some() {
+        return None;
+    }
+
+    Some(mac)
+}
+
+/// Sends the wake on lan magic packet: 6 times 0xFF followed by the mac address 16 times
+pub async fn send_magic_packet(mac: [u8; 6], target: SocketAddr) -> Result<(), io::Error> {
+    let mut packet = [0xFF; 6 + 16 * 6];
+    for chunk in packet[6..].chunks_exact_mut(6) {
+        chunk.copy_from_slice(&mac);
+    }
+
+    let bind_address = match target {
+        SocketAddr::V4(_) => SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0)),
+        SocketAddr::V6(_) => SocketAddr::from((Ipv6Addr::UNSPECIFIED, 0)),
+    };
+
+    let socket = UdpSocket::bind(bind_address).await?;
+    socket.set_broadcast(true)?;
+    socket.send_to(&packet, target).await?;
+
+    Ok(())
+}
@@ -7,7 +7,7 @@ use clap::{Args, Parser, Subcommand};
 use common::{
     api_bindings::RtcIceServer,
     config::{
-        Config, ConfigSsl, ForwardedHeaders, PortRange, WebRtcNat1To1IceCandidateType,
+        BindAddress, Config, ConfigSsl, ForwardedHeaders, PortRange, WebRtcNat1To1IceCandidateType,
         WebRtcNat1To1Mapping, WebRtcNetworkType,
     },
 };
@@ -64,10 +64,37 @@ pub enum Command {
     /// Runs the server (default if no command specified)
     Run,
     /// Prints the config into stdout in json format
-    PrintConfig,
+    PrintConfig {
+        /// Prints the config file after removing comments, trailing commas and expanding environment variables
+        /// instead of the parsed config, useful to find out why the config can't be parsed
+        #[arg(long)]
+        raw: bool,
+    },
+    /// Prints the json schema of the config into stdout, can be used by editors for validation and autocompletion
+    ConfigSchema,
+    /// Checks the config for problems and exits with a non zero exit code if any were found
+    Validate,
+    /// Pairs a host without the web interface, the host is added if it doesn't exist yet.
+    /// The pin is printed and has to be entered on the host.
+    Pair {
+        /// The address of the host
+        address: String,
+        /// The http port of the host, by default the one from the host overrides or `moonlight.default_http_port`
+        #[arg(long)]
+        http_port: Option<u16>,
+        /// The name of the user the host is paired for
+        #[arg(long)]
+        user: String,
+    },
+    /// Lists all hosts with their pairing status and checks if they're reachable
+    ListHosts {
+        /// Prints the hosts as json instead of a table
+        #[arg(long)]
+        json: bool,
+    },
 }
 
-#[derive(Args)]
+#[derive(Args, Clone)]
 pub struct CliConfig {
     /// Overwrites `webrtc.port_range`. Specify like this: "MIN:MAX".
     #[arg(long, env = "WEBRTC_PORT_RANGE")]
@@ -84,9 +111,9 @@ pub struct CliConfig {
     /// Overwrites `webrtc.include_loopback_candidates`.
     #[arg(long, env = "WEBRTC_INCLUDE_LOOPBACK_CANDIDATES")]
     pub webrtc_include_loopback_candidates: Option<bool>,
-    /// Overwrites `web_server.bind_address`.
-    #[arg(long, env = "BIND_ADDRESS")]
-    pub bind_address: Option<SocketAddr>,
+    /// Overwrites `web_server.bind_address`. Multiple addresses can be separated with a comma.
+    #[arg(long, env = "BIND_ADDRESS", value_delimiter = ',')]
+    pub bind_address: Option<Vec<SocketAddr>>,
     /// Overwrites `web_server.certificate.certificate_pem`.
     #[arg(long, env = "SSL_CERTIFICATE")]
     pub ssl_certificate: Option<String>,
@@ -141,7 +168,7 @@ impl CliConfig {
             config.webrtc.include_loopback_candidates = webrtc_include_loopback_candidates;
         }
         if let Some(bind_address) = self.bind_address {
-            config.web_server.bind_address = bind_address;
+            config.web_server.bind_address = BindAddress::from(bind_address);
         }
         match (self.ssl_certificate, self.ssl_private_key) {
             (Some(certificate), Some(private_key)) => {
@@ -0,0 +1,182 @@
+use std::time::Duration;
+
+use anyhow::Context;
+use common::config::Config;
+use futures::future::join_all;
+use moonlight_common::{
+    crypto::openssl::OpenSSLCryptoBackend, high::tokio::MoonlightHost, http::pair::PairPin,
+};
+use serde::Serialize;
+use tokio::time::timeout;
+
+use crate::app::{App, AppError, MoonlightClient, storage::StorageHost};
+
+/// Pairs the host for the user using the same path as the `/pair` endpoint
+pub async fn pair_host(
+    config: Config,
+    address: String,
+    http_port: Option<u16>,
+    user_name: String,
+) -> Result<(), anyhow::Error> {
+    let http_port = http_port.unwrap_or_else(|| {
+        config
+            .host_config(&address, None)
+            .http_port
+            .unwrap_or(config.moonlight.default_http_port)
+    });
+
+    let app = App::new(config).await?;
+
+    let mut user = app
+        .user_by_name(&user_name)
+        .await
+        .with_context(|| format!("failed to find the user {user_name:?}"))?
+        .authenticate_unchecked();
+
+    let mut existing_host = None;
+    for host in user.hosts().await? {
+        if host.address_port(&mut user).await? == (address.clone(), http_port) {
+            existing_host = Some(host);
+            break;
+        }
+    }
+
+    let mut host = match existing_host {
+        Some(host) => host,
+        None => {
+            println!("Adding the host {address}:{http_port}");
+
+            user.host_add(address.clone(), http_port)
+                .await
+                .context("failed to add the host")?
+        }
+    };
+
+    let pin = PairPin::new_random(&OpenSSLCryptoBackend).map_err(AppError::from)?;
+    println!("Enter the pin {pin} on the host {address}:{http_port}");
+
+    host.pair(&mut user, pin)
+        .await
+        .context("failed to pair the host")?;
+
+    app.flush_storage().await;
+
+    println!("Paired the host {address}:{http_port}");
+
+    Ok(())
+}
+
+#[derive(Serialize)]
+struct ListedHost {
+    id: u32,
+    name: String,
+    /// None if every user can access this host
+    owner: Option<u32>,
+    address: String,
+    http_port: u16,
+    paired: bool,
+    /// The unique id this server identifies itself with to the host
+    client_unique_id: Option<String>,
+    /// Only known if the host is reachable
+    unique_id: Option<String>,
+    reachability: Reachability,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "snake_case")]
+enum Reachability {
+    Ok,
+    Timeout,
+    Error(String),
+}
+
+/// Prints all hosts in the data file and probes them with a `serverinfo` request
+pub async fn list_hosts(config: Config, json: bool) -> Result<(), anyhow::Error> {
+    let probe_timeout = config.health.probe_timeout;
+
+    let app = App::new(config).await?;
+
+    let mut hosts = app
+        .all_hosts_no_auth()
+        .await
+        .context("failed to load hosts")?;
+    hosts.sort_by_key(|host| host.id.0);
+
+    let hosts = join_all(
+        hosts
+            .into_iter()
+            .map(|host| probe_host(host, probe_timeout)),
+    )
+    .await;
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&hosts)?);
+        return Ok(());
+    }
+
+    println!(
+        "{:<10} {:<20} {:<24} {:<6} {:<8} {:<20} {:<38} REACHABLE",
+        "ID", "NAME", "ADDRESS", "OWNER", "PAIRED", "CLIENT UNIQUE ID", "HOST UNIQUE ID"
+    );
+    for host in hosts {
+        let reachability = match &host.reachability {
+            Reachability::Ok => "ok".to_string(),
+            Reachability::Timeout => "timeout".to_string(),
+            Reachability::Error(err) => format!("error: {err}"),
+        };
+
+        println!(
+            "{:<10} {:<20} {:<24} {:<6} {:<8} {:<20} {:<38} {reachability}",
+            host.id,
+            host.name,
+            format!("{}:{}", host.address, host.http_port),
+            host.owner
+                .map(|owner| owner.to_string())
+                .unwrap_or_else(|| "all".to_string()),
+            if host.paired { "yes" } else { "no" },
+            host.client_unique_id.as_deref().unwrap_or("-"),
+            host.unique_id.as_deref().unwrap_or("-"),
+        );
+    }
+
+    Ok(())
+}
+
+async fn probe_host(host: StorageHost, probe_timeout: Duration) -> ListedHost {
+    let mut listed = ListedHost {
+        id: host.id.0,
+        name: host.cache.name,
+        owner: host.owner.map(|owner| owner.0),
+        address: host.address,
+        http_port: host.http_port,
+        paired: host.pair_info.is_some(),
+        client_unique_id: host
+            .pair_info
+            .and_then(|pair_info| pair_info.client_unique_id),
+        unique_id: None,
+        reachability: Reachability::Timeout,
+    };
+
+    let client = match MoonlightHost::<MoonlightClient>::new(
+        listed.address.clone(),
+        listed.http_port,
+        listed.client_unique_id.clone(),
+    ) {
+        Ok(client) => client,
+        Err(err) => {
+            listed.reachability = Reachability::Error(err.to_string());
+            return listed;
+        }
+    };
+
+    listed.reachability = match timeout(probe_timeout, client.server_info()).await {
+        Ok(Ok(info)) => {
+            listed.unique_id = Some(info.unique_id.to_string());
+            Reachability::Ok
+        }
+        Ok(Err(err)) => Reachability::Error(err.to_string()),
+        Err(_) => Reachability::Timeout,
+    };
+
+    listed
+}
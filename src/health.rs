@@ -0,0 +1,115 @@
+use std::time::Duration;
+
+use actix_web::{
+    HttpResponse,
+    dev::HttpServiceFactory,
+    get, services,
+    web::{Data, Query},
+};
+use futures::future::join_all;
+use log::debug;
+use moonlight_common::high::tokio::MoonlightHost;
+use serde::{Deserialize, Serialize};
+use tokio::time::timeout;
+
+use crate::app::{App, MoonlightClient};
+
+pub fn health_service() -> impl HttpServiceFactory {
+    services![healthz]
+}
+
+#[derive(Deserialize)]
+struct HealthQuery {
+    /// Also probe the configured hosts with `deep=1`
+    #[serde(default)]
+    deep: Option<String>,
+}
+
+#[derive(Serialize)]
+struct HealthResponse {
+    healthy: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    hosts: Option<Vec<HostHealth>>,
+}
+
+#[derive(Serialize)]
+struct HostHealth {
+    address: String,
+    reachable: bool,
+}
+
+/// Responding at all means the config is loaded and the server is bound.
+/// The deep variant additionally requires at least one of `health.probe_hosts` to answer.
+#[get("/healthz")]
+async fn healthz(app: Data<App>, Query(query): Query<HealthQuery>) -> HttpResponse {
+    let deep = query
+        .deep
+        .is_some_and(|deep| !matches!(deep.as_str(), "0" | "false"));
+
+    if app.is_shutting_down() {
+        return HttpResponse::ServiceUnavailable().json(HealthResponse {
+            healthy: false,
+            hosts: None,
+        });
+    }
+
+    if !deep {
+        return HttpResponse::Ok().json(HealthResponse {
+            healthy: true,
+            hosts: None,
+        });
+    }
+
+    let config = app.config();
+
+    let hosts = join_all(config.health.probe_hosts.iter().map(|address| {
+        let config = &config;
+        async move {
+            let http_port = config
+                .host_config(address, None)
+                .http_port
+                .unwrap_or(config.moonlight.default_http_port);
+
+            HostHealth {
+                address: address.clone(),
+                reachable: probe_host(address, http_port, config.health.probe_timeout).await,
+            }
+        }
+    }))
+    .await;
+
+    // Nothing to probe is not a reason to restart the server
+    let healthy = hosts.is_empty() || hosts.iter().any(|host| host.reachable);
+
+    let mut response = if healthy {
+        HttpResponse::Ok()
+    } else {
+        HttpResponse::ServiceUnavailable()
+    };
+    response.json(HealthResponse {
+        healthy,
+        hosts: Some(hosts),
+    })
+}
+
+async fn probe_host(address: &str, http_port: u16, probe_timeout: Duration) -> bool {
+    let host = match MoonlightHost::<MoonlightClient>::new(address.to_string(), http_port, None) {
+        Ok(host) => host,
+        Err(err) => {
+            debug!("[Health]: failed to create client for host {address}: {err}");
+            return false;
+        }
+    };
+
+    match timeout(probe_timeout, host.server_info()).await {
+        Ok(Ok(_)) => true,
+        Ok(Err(err)) => {
+            debug!("[Health]: host {address}:{http_port} is not reachable: {err}");
+            false
+        }
+        Err(_) => {
+            debug!("[Health]: host {address}:{http_port} didn't answer in time");
+            false
+        }
+    }
+}
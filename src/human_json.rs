@@ -1,13 +1,20 @@
-//! Human Json: strip comments and remove trailing comma's
+//! Human Json: strip comments, remove trailing comma's and expand environment variables
+//!
+//! The transforms keep every line at the same position so errors of the json parser point to the right line:
+//! - `# Comment`, `// Comment` and `/* Comment */` are replaced with spaces
+//! - A trailing `,` before `}` or `]` is replaced with a space
+//! - `${ENV_VAR}` and `${ENV_VAR:-default}` are expanded by [expand_env_vars]
+//!
+//! Everything inside of json strings is kept as is.
 
-use std::str::Chars;
+use std::{env, str::Chars};
 
 use log::warn;
+use thiserror::Error;
 
+/// Like [try_preprocess_human_json], but falls back to the unchanged json
 pub fn preprocess_human_json(json: String) -> String {
-    let preprocessor = JsonPreprocessor::new(&json);
-
-    match preprocessor.preprocess() {
+    match try_preprocess_human_json(&json) {
         Ok(value) => value,
         Err(err) => {
             warn!(
@@ -18,6 +25,18 @@ pub fn preprocess_human_json(json: String) -> String {
     }
 }
 
+#[derive(Debug, Error, PartialEq)]
+pub enum HumanJsonError {
+    #[error("the string starting at line {line} is missing its closing quote")]
+    UnterminatedString { line: usize },
+    #[error("the comment starting at line {line} is missing its closing \"*/\"")]
+    UnterminatedComment { line: usize },
+}
+
+pub fn try_preprocess_human_json(json: &str) -> Result<String, HumanJsonError> {
+    JsonPreprocessor::new(json).preprocess()
+}
+
 struct JsonPreprocessor<'a> {
     iter: Chars<'a>,
     peek_len: usize,
@@ -43,14 +62,14 @@ impl<'a> JsonPreprocessor<'a> {
         }
     }
 
-    pub fn preprocess(mut self) -> Result<String, anyhow::Error> {
+    pub fn preprocess(mut self) -> Result<String, HumanJsonError> {
         while self.validate_next()? {}
 
         Ok(self.new_string)
     }
 
     /// Bool says if it should continue
-    fn validate_next(&mut self) -> Result<bool, anyhow::Error> {
+    fn validate_next(&mut self) -> Result<bool, HumanJsonError> {
         self.validate_empty()?;
 
         let Some([next_char]) = self.peek()? else {
@@ -59,12 +78,13 @@ impl<'a> JsonPreprocessor<'a> {
 
         match next_char {
             '"' => {
+                let line = self.current_line();
                 self.next::<1>(None)?;
 
                 let mut escaped = false;
                 loop {
                     let Some([next_char]) = self.peek()? else {
-                        return Ok(false);
+                        return Err(HumanJsonError::UnterminatedString { line });
                     };
 
                     if !escaped {
@@ -110,8 +130,9 @@ impl<'a> JsonPreprocessor<'a> {
         Ok(true)
     }
 
-    fn validate_empty(&mut self) -> Result<(), anyhow::Error> {
+    fn validate_empty(&mut self) -> Result<(), HumanJsonError> {
         let mut state = CommentState::None;
+        let mut comment_line = 0;
 
         loop {
             match state {
@@ -133,6 +154,7 @@ impl<'a> JsonPreprocessor<'a> {
                         }
                         ['/', '*'] => {
                             state = CommentState::MultiLine;
+                            comment_line = self.current_line();
 
                             self.next::<2>(Some([' ', ' ']))?;
                         }
@@ -158,7 +180,7 @@ impl<'a> JsonPreprocessor<'a> {
                 }
                 CommentState::MultiLine => {
                     let Some(next_chars) = self.peek::<2>()? else {
-                        return Ok(());
+                        return Err(HumanJsonError::UnterminatedComment { line: comment_line });
                     };
 
                     if next_chars == ['*', '/'] {
@@ -178,7 +200,12 @@ impl<'a> JsonPreprocessor<'a> {
         }
     }
 
-    fn peek<const N: usize>(&mut self) -> Result<Option<[char; N]>, anyhow::Error> {
+    /// The line of the next char, the preprocessed text has the same lines as the original
+    fn current_line(&self) -> usize {
+        self.new_string.matches('\n').count() + 1
+    }
+
+    fn peek<const N: usize>(&mut self) -> Result<Option<[char; N]>, HumanJsonError> {
         let mut chars = [' '; N];
 
         while self.peek_len < N {
@@ -197,7 +224,7 @@ impl<'a> JsonPreprocessor<'a> {
     fn next<const N: usize>(
         &mut self,
         insert_instead: Option<[char; N]>,
-    ) -> Result<Option<[char; N]>, anyhow::Error> {
+    ) -> Result<Option<[char; N]>, HumanJsonError> {
         let result = self.peek()?;
 
         if let Some(actual) = result {
@@ -218,9 +245,128 @@ impl<'a> JsonPreprocessor<'a> {
     }
 }
 
+#[derive(Debug, Error)]
+pub enum EnvVarError {
+    #[error("the environment variable \"{name}\" used in the config is not set and has no default")]
+    Missing { name: String },
+    #[error("the environment variable name \"{name}\" used in the config is invalid")]
+    InvalidName { name: String },
+    #[error("a \"${{\" in the config is missing its closing \"}}\"")]
+    Unterminated,
+}
+
+/// Expands `${ENV_VAR}` and `${ENV_VAR:-default}` with the values from the process environment.
+/// Use `$${` to write a literal `${`.
+///
+/// Inside json strings the value gets escaped, outside of strings it's inserted as is, e.g. for numbers.
+/// This should run after [preprocess_human_json] so that variables inside comments are ignored.
+pub fn expand_env_vars(json: &str) -> Result<String, EnvVarError> {
+    expand_env_vars_with(json, |name| env::var(name).ok())
+}
+
+fn expand_env_vars_with(
+    json: &str,
+    lookup: impl Fn(&str) -> Option<String>,
+) -> Result<String, EnvVarError> {
+    let mut new_string = String::with_capacity(json.len());
+
+    let mut in_string = false;
+    let mut escaped = false;
+
+    let mut rest = json;
+    while let Some(next_char) = rest.chars().next() {
+        if let Some(after) = rest.strip_prefix("$${") {
+            new_string.push_str("${");
+            rest = after;
+            continue;
+        }
+
+        if let Some(after) = rest.strip_prefix("${") {
+            let Some(end) = after.find('}') else {
+                return Err(EnvVarError::Unterminated);
+            };
+            let token = &after[..end];
+            rest = &after[(end + 1)..];
+
+            let (name, default) = match token.split_once(":-") {
+                Some((name, default)) => (name, Some(default)),
+                None => (token, None),
+            };
+
+            if !is_valid_env_var_name(name) {
+                return Err(EnvVarError::InvalidName {
+                    name: name.to_string(),
+                });
+            }
+
+            let value = match (lookup(name), default) {
+                (Some(value), _) => value,
+                (None, Some(default)) => default.to_string(),
+                (None, None) => {
+                    return Err(EnvVarError::Missing {
+                        name: name.to_string(),
+                    });
+                }
+            };
+
+            if in_string {
+                push_json_string_escaped(&mut new_string, &value);
+            } else {
+                new_string.push_str(&value);
+            }
+            continue;
+        }
+
+        if in_string {
+            if escaped {
+                escaped = false;
+            } else if next_char == '\\' {
+                escaped = true;
+            } else if next_char == '"' {
+                in_string = false;
+            }
+        } else if next_char == '"' {
+            in_string = true;
+        }
+
+        new_string.push(next_char);
+        rest = &rest[next_char.len_utf8()..];
+    }
+
+    Ok(new_string)
+}
+
+fn is_valid_env_var_name(name: &str) -> bool {
+    let mut chars = name.chars();
+
+    chars
+        .next()
+        .is_some_and(|first| first.is_ascii_alphabetic() || first == '_')
+        && chars.all(|char| char.is_ascii_alphanumeric() || char == '_')
+}
+
+fn push_json_string_escaped(new_string: &mut String, value: &str) {
+    for char in value.chars() {
+        match char {
+            '"' => new_string.push_str("\\\""),
+            '\\' => new_string.push_str("\\\\"),
+            '\n' => new_string.push_str("\\n"),
+            '\r' => new_string.push_str("\\r"),
+            '\t' => new_string.push_str("\\t"),
+            char if char.is_control() => {
+                new_string.push_str(&format!("\\u{:04x}", char as u32));
+            }
+            char => new_string.push(char),
+        }
+    }
+}
+
 #[cfg(test)]
 mod test {
-    use crate::human_json::preprocess_human_json;
+    use crate::human_json::{
+        EnvVarError, HumanJsonError, expand_env_vars_with, preprocess_human_json,
+        try_preprocess_human_json,
+    };
 
     #[test]
     fn test_empty_json() {
@@ -471,4 +617,133 @@ mod test {
 
         assert_eq!(preprocess_human_json(human).as_str(), expected);
     }
+
+    #[test]
+    fn test_unterminated_string() {
+        let human = "{\n\"key\": \"value\n}";
+
+        assert_eq!(
+            try_preprocess_human_json(human),
+            Err(HumanJsonError::UnterminatedString { line: 2 })
+        );
+        // Falls back to the unchanged json
+        assert_eq!(preprocess_human_json(human.to_string()).as_str(), human);
+    }
+
+    #[test]
+    fn test_unterminated_multiline_comment() {
+        let human = "{\n\"key\": \"value\"\n/* comment\n}";
+
+        assert_eq!(
+            try_preprocess_human_json(human),
+            Err(HumanJsonError::UnterminatedComment { line: 3 })
+        );
+    }
+
+    #[test]
+    fn test_comment_at_end_of_file() {
+        let human = "{}\n// comment";
+        let expected = "{}\n          ";
+
+        assert_eq!(
+            try_preprocess_human_json(human)
+                .expect("failed to preprocess")
+                .as_str(),
+            expected
+        );
+    }
+
+    fn test_env(name: &str) -> Option<String> {
+        match name {
+            "TURN_CREDENTIAL" => Some("secret".to_string()),
+            "PORT" => Some("8080".to_string()),
+            "QUOTED" => Some("a\"b\\c".to_string()),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_env_var_in_string() {
+        let json = r#"{"credential": "${TURN_CREDENTIAL}"}"#;
+        let expected = r#"{"credential": "secret"}"#;
+
+        assert_eq!(
+            expand_env_vars_with(json, test_env)
+                .expect("failed to expand env vars")
+                .as_str(),
+            expected
+        );
+    }
+
+    #[test]
+    fn test_env_var_outside_string() {
+        let json = r#"{"port": ${PORT}}"#;
+        let expected = r#"{"port": 8080}"#;
+
+        assert_eq!(
+            expand_env_vars_with(json, test_env)
+                .expect("failed to expand env vars")
+                .as_str(),
+            expected
+        );
+    }
+
+    #[test]
+    fn test_env_var_escaped_in_string() {
+        let json = r#"{"value": "${QUOTED}"}"#;
+        let expected = r#"{"value": "a\"b\\c"}"#;
+
+        assert_eq!(
+            expand_env_vars_with(json, test_env)
+                .expect("failed to expand env vars")
+                .as_str(),
+            expected
+        );
+    }
+
+    #[test]
+    fn test_env_var_default() {
+        let json = r#"{"user": "${TURN_USERNAME:-moonlight}", "port": ${PORT:-1}}"#;
+        let expected = r#"{"user": "moonlight", "port": 8080}"#;
+
+        assert_eq!(
+            expand_env_vars_with(json, test_env)
+                .expect("failed to expand env vars")
+                .as_str(),
+            expected
+        );
+    }
+
+    #[test]
+    fn test_env_var_literal() {
+        let json = r#"{"value": "$${NOT_EXPANDED}"}"#;
+        let expected = r#"{"value": "${NOT_EXPANDED}"}"#;
+
+        assert_eq!(
+            expand_env_vars_with(json, test_env)
+                .expect("failed to expand env vars")
+                .as_str(),
+            expected
+        );
+    }
+
+    #[test]
+    fn test_env_var_missing() {
+        let json = r#"{"credential": "${MISSING_CREDENTIAL}"}"#;
+
+        assert!(matches!(
+            expand_env_vars_with(json, test_env),
+            Err(EnvVarError::Missing { name }) if name == "MISSING_CREDENTIAL"
+        ));
+    }
+
+    #[test]
+    fn test_env_var_unterminated() {
+        let json = r#""credential": "${TURN_CREDENTIAL""#;
+
+        assert!(matches!(
+            expand_env_vars_with(json, test_env),
+            Err(EnvVarError::Unterminated)
+        ));
+    }
 }
@@ -1,19 +1,31 @@
-use common::config::Config;
-use openssl::ssl::{SslAcceptor, SslFiletype, SslMethod};
+use actix_cors::Cors;
+use anyhow::{Context, bail};
+use common::config::{Config, CorsConfig, LogConfig, LogFormat, LogRotation, PortRange};
+use openssl::ssl::{SslAcceptor, SslAcceptorBuilder, SslFiletype, SslMethod};
 use std::{
     fs::OpenOptions,
     io::{self, ErrorKind, IsTerminal},
-    path::PathBuf,
-    str::FromStr,
+    net::{IpAddr, SocketAddr},
+    path::{Path, PathBuf},
+    process,
+    sync::OnceLock,
+};
+use tokio::{
+    fs::{self},
+    select, spawn,
 };
-use tokio::fs::{self};
 use tracing::{Level, Span, level_filters::LevelFilter, span, warn};
 use tracing_actix_web::{RootSpanBuilder, TracingLogger};
-use tracing_appender::non_blocking;
+use tracing_appender::{
+    non_blocking,
+    rolling::{RollingFileAppender, Rotation},
+};
 use tracing_subscriber::{
-    EnvFilter, Registry,
+    EnvFilter, Layer, Registry,
+    filter::{Directive, ParseError},
     fmt::{self, format::FmtSpan},
-    layer::SubscriberExt,
+    layer::{Layered, SubscriberExt},
+    reload,
     util::SubscriberInitExt,
 };
 use venator::Venator;
@@ -21,76 +33,163 @@ use venator::Venator;
 use actix_web::{
     App as ActixApp, HttpServer,
     body::MessageBody,
-    dev::{ServiceRequest, ServiceResponse},
-    http::header::HeaderMap,
+    dev::{ServerHandle, ServiceRequest, ServiceResponse},
+    http::{Uri, header::HeaderMap},
     middleware::{self},
     web::{Data, scope},
 };
 use tracing::{error, info, trace};
 
 use crate::{
-    api::api_service,
-    app::App,
-    cli::{Cli, Command},
-    human_json::preprocess_human_json,
+    acme::AcmeCertificate,
+    api::{api_service, metrics::get_public_metrics},
+    app::{App, client_ip::client_ip},
+    cli::{Cli, CliConfig, Command},
+    commands::{list_hosts, pair_host},
+    health::health_service,
+    human_json::{EnvVarError, expand_env_vars, preprocess_human_json, try_preprocess_human_json},
+    validate::{port_range_problems, sanitize_ice_servers, validate_config_file},
     web::{web_config_js_service, web_service},
 };
 
+mod acme;
 mod api;
 mod app;
 mod web;
 
 mod cli;
+mod commands;
+mod health;
 mod human_json;
+mod validate;
 
 #[actix_web::main]
 async fn main() {
     let cli = Cli::load();
 
     // Load Config
-    let config_path = PathBuf::from_str(&cli.config_path).expect("invalid config file path");
-    let config = match fs::read_to_string(&config_path).await {
-        Ok(mut value) => {
-            value = preprocess_human_json(value);
+    let config_path = PathBuf::from(&cli.config_path);
+
+    if let Some(Command::ConfigSchema) = cli.command {
+        let schema = schemars::schema_for!(Config);
+        let json =
+            serde_json::to_string_pretty(&schema).expect("failed to serialize config schema");
+        println!("{json}");
+        return;
+    }
+    if let Some(Command::PrintConfig { raw: true }) = cli.command {
+        let value = match fs::read_to_string(&config_path).await {
+            Ok(value) => value,
+            Err(err) => {
+                eprintln!("Failed to read the config {config_path:?}: {err}");
+                process::exit(1);
+            }
+        };
 
-            let mut config = serde_json::from_str(&value).expect("invalid file");
-            cli.options.apply(&mut config);
+        let value = match try_preprocess_human_json(&value) {
+            Ok(value) => value,
+            Err(err) => {
+                eprintln!("Failed to preprocess the config: {err}");
+                process::exit(1);
+            }
+        };
+        let value = match expand_env_vars(&value) {
+            Ok(value) => value,
+            Err(err) => {
+                eprintln!("Failed to expand the environment variables of the config: {err}");
+                process::exit(1);
+            }
+        };
+
+        println!("{value}");
+        return;
+    }
+    if let Some(Command::Validate) = cli.command {
+        let problems = validate_config_file(&config_path, cli.options);
+        if problems.is_empty() {
+            println!("The config {config_path:?} is valid");
+            return;
+        }
+
+        eprintln!(
+            "Found {} problem(s) in the config {config_path:?}:",
+            problems.len()
+        );
+        for problem in problems {
+            eprintln!("- {problem}");
+        }
+        process::exit(1);
+    }
+
+    let config = match fs::read_to_string(&config_path).await {
+        Ok(value) => {
+            let mut config = match parse_config(value.clone()) {
+                Ok(config) => config,
+                Err(err) => {
+                    print_config_error(&config_path, &value, &err);
+                    process::exit(1);
+                }
+            };
+            cli.options.clone().apply(&mut config);
             config
         }
         Err(err) if err.kind() == ErrorKind::NotFound => {
             let mut new_config = Config::default();
-            cli.options.apply(&mut new_config);
-
-            let value_str =
-                serde_json::to_string_pretty(&new_config).expect("failed to serialize file");
+            cli.options.clone().apply(&mut new_config);
 
-            if let Some(parent) = config_path.parent() {
-                fs::create_dir_all(parent)
-                    .await
-                    .expect("failed to create directories to file");
+            if let Err(err) = write_default_config(&config_path, &new_config).await {
+                eprintln!("Failed to write the default config to {config_path:?}: {err:#}");
+                process::exit(1);
             }
-            fs::write(config_path, value_str)
-                .await
-                .expect("failed to write default file");
 
             new_config
         }
-        Err(err) => panic!("failed to read file: {err}"),
+        Err(err) => {
+            eprintln!("Failed to read the config {config_path:?}: {err}");
+            process::exit(1);
+        }
     };
 
     match cli.command {
-        Some(Command::PrintConfig) => {
+        Some(Command::PrintConfig { raw: false }) => {
             let json =
                 serde_json::to_string_pretty(&config).expect("failed to serialize config to json");
             println!("{json}");
             return;
         }
+        Some(Command::Pair {
+            address,
+            http_port,
+            user,
+        }) => {
+            if let Err(err) = pair_host(config, address, http_port, user).await {
+                eprintln!("{err:?}");
+                process::exit(1);
+            }
+            return;
+        }
+        Some(Command::ListHosts { json }) => {
+            if let Err(err) = list_hosts(config, json).await {
+                eprintln!("{err:?}");
+                process::exit(1);
+            }
+            return;
+        }
         None | Some(Command::Run) => {
             // Fallthrough
         }
+        Some(Command::ConfigSchema | Command::Validate | Command::PrintConfig { raw: true }) => {
+            unreachable!("this command is handled before loading the config")
+        }
     }
 
-    let guard = init_log(&config);
+    let (guard, log_filter_handle) = match init_log(&config) {
+        Ok(value) => value,
+        Err(err) => {
+            eprintln!("Failed to open the log file: {err:#}");
+            process::exit(1);
+        }
+    };
 
     #[allow(deprecated)]
     if config.default_settings.is_some() {
@@ -99,15 +198,61 @@ async fn main() {
         );
     }
 
-    if let Err(err) = start(config).await {
+    let reloader = ConfigReloader {
+        config_path,
+        cli_options: cli.options,
+        log_filter_handle,
+    };
+
+    if let Err(err) = start(config, reloader).await {
         error!("{err:?}");
+
+        drop(guard);
+        process::exit(1);
     }
 
     drop(guard);
 }
 
-fn init_log(config: &Config) -> Option<non_blocking::WorkerGuard> {
-    let config_level_filter = match config.log.level_filter {
+async fn write_default_config(config_path: &Path, config: &Config) -> Result<(), anyhow::Error> {
+    let value = serde_json::to_string_pretty(config).context("failed to serialize the config")?;
+
+    if let Some(parent) = config_path.parent() {
+        fs::create_dir_all(parent)
+            .await
+            .with_context(|| format!("failed to create the directory {parent:?}"))?;
+    }
+    fs::write(config_path, value).await?;
+
+    Ok(())
+}
+
+/// Prints the error and the line of the config which caused it
+fn print_config_error(config_path: &Path, value: &str, err: &ParseConfigError) {
+    eprintln!("Failed to parse the config {config_path:?}: {err}");
+
+    // The preprocessing keeps the lines in place, so the location also matches the original file
+    let Some((line, column)) = err.location() else {
+        return;
+    };
+    let Some(text) = value.lines().nth(line - 1) else {
+        return;
+    };
+
+    let number = line.to_string();
+    eprintln!();
+    eprintln!("{number} | {text}");
+    eprintln!(
+        "{} | {}^",
+        " ".repeat(number.len()),
+        " ".repeat(column.saturating_sub(1))
+    );
+}
+
+type LogFilterHandle = reload::Handle<EnvFilter, Layered<Option<Venator>, Registry>>;
+
+fn create_env_filter(log_config: &LogConfig) -> EnvFilter {
+    let config_level_filter = match log_config.level_filter {
         log::LevelFilter::Off => LevelFilter::OFF,
         log::LevelFilter::Error => LevelFilter::ERROR,
         log::LevelFilter::Info => LevelFilter::INFO,
@@ -116,7 +261,7 @@ fn init_log(config: &Config) -> Option<non_blocking::WorkerGuard> {
         log::LevelFilter::Trace => LevelFilter::TRACE,
     };
 
-    let env_filter = EnvFilter::builder()
+    let mut env_filter = EnvFilter::builder()
         .with_default_directive(config_level_filter.into())
         .from_env_lossy()
         // Add default directives
@@ -131,45 +276,116 @@ fn init_log(config: &Config) -> Option<non_blocking::WorkerGuard> {
                 .expect("failed to add mio tracing directive"),
         );
 
+    // Invalid directives are already rejected when parsing the config
+    for directive in &log_config.directives {
+        if let Ok(directive) = directive.parse() {
+            env_filter = env_filter.add_directive(directive);
+        }
+    }
+
+    env_filter
+}
+
+fn init_log(
+    config: &Config,
+) -> Result<(Option<non_blocking::WorkerGuard>, LogFilterHandle), anyhow::Error> {
+    let env_filter = create_env_filter(&config.log);
+    let env_filter_str = env_filter.to_string();
+    let (env_filter, log_filter_handle) = reload::Layer::new(env_filter);
+
     #[cfg(windows)]
     enable_ansi_windows();
 
-    let stdout_layer = fmt::layer()
-        .with_span_events(FmtSpan::CLOSE)
-        .with_ansi(io::stdout().is_terminal());
+    let stdout_layer = match config.log.stdout_format {
+        LogFormat::Pretty => fmt::layer()
+            .with_span_events(FmtSpan::CLOSE)
+            .with_ansi(io::stdout().is_terminal())
+            .boxed(),
+        LogFormat::Json => fmt::layer().json().with_span_events(FmtSpan::CLOSE).boxed(),
+    };
 
     let (file_layer, guard) = if let Some(log_file) = &config.log.file_path {
-        let file = OpenOptions::new()
-            .create(true)
-            .write(true)
-            .truncate(true)
-            .open(log_file)
-            .expect("failed to open log file");
-
-        let (writer, guard) = non_blocking(file);
-
-        let fmt_layer = fmt::layer()
-            .with_span_events(FmtSpan::FULL)
-            .with_writer(writer)
-            .with_ansi(false);
+        let (writer, guard) = open_log_file(&config.log, log_file)?;
+
+        let fmt_layer = match config.log.format {
+            LogFormat::Pretty => fmt::layer()
+                .with_span_events(FmtSpan::FULL)
+                .with_writer(writer)
+                .with_ansi(false)
+                .boxed(),
+            // Includes the fields of the current span and its parents, e.g. the http request
+            LogFormat::Json => fmt::layer()
+                .json()
+                .with_span_events(FmtSpan::FULL)
+                .with_writer(writer)
+                .boxed(),
+        };
 
         (Some(fmt_layer), Some(guard))
     } else {
         (None, None)
     };
 
+    let _ = SENSITIVE_HEADERS.set(config.log.sensitive_headers.clone());
+    let _ = SENSITIVE_QUERY_PARAMS.set(config.log.sensitive_query_params.clone());
+
     let venator = config.log.dev_venator.then(Venator::default);
 
     Registry::default()
         .with(venator)
-        .with(env_filter.clone())
+        .with(env_filter)
         .with(file_layer)
         .with(stdout_layer)
         .init();
 
-    trace!("Using env_filter: {env_filter}");
+    trace!("Using env_filter: {env_filter_str}");
+
+    Ok((guard, log_filter_handle))
+}
+
+fn open_log_file(
+    config: &LogConfig,
+    log_file: &str,
+) -> Result<(non_blocking::NonBlocking, non_blocking::WorkerGuard), anyhow::Error> {
+    let rotation = match config.rotation {
+        LogRotation::Never => {
+            let file = OpenOptions::new()
+                .create(true)
+                .append(!config.truncate)
+                .write(true)
+                .truncate(config.truncate)
+                .open(log_file)
+                .with_context(|| format!("failed to open {log_file:?}"))?;
+
+            return Ok(non_blocking(file));
+        }
+        LogRotation::Minutely => Rotation::MINUTELY,
+        LogRotation::Hourly => Rotation::HOURLY,
+        LogRotation::Daily => Rotation::DAILY,
+    };
+
+    let log_file = Path::new(log_file);
+    let directory = log_file
+        .parent()
+        .filter(|directory| !directory.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    let file_name = log_file
+        .file_name()
+        .and_then(|file_name| file_name.to_str())
+        .with_context(|| format!("the log file path {log_file:?} has no valid file name"))?;
+
+    let mut appender = RollingFileAppender::builder()
+        .rotation(rotation)
+        .filename_prefix(file_name);
+    if let Some(max_log_files) = config.max_log_files {
+        appender = appender.max_log_files(max_log_files);
+    }
 
-    guard
+    let appender = appender
+        .build(directory)
+        .with_context(|| format!("failed to open the rotating log file in {directory:?}"))?;
+
+    Ok(non_blocking(appender))
 }
 
 #[cfg(windows)]
@@ -189,18 +405,61 @@ fn enable_ansi_windows() {
     }
 }
 
+/// The headers from the config which are redacted additionally to [DEFAULT_SENSITIVE_HEADERS]
+static SENSITIVE_HEADERS: OnceLock<Vec<String>> = OnceLock::new();
+
+/// The query parameters from the config which are redacted additionally to [DEFAULT_SENSITIVE_QUERY_PARAMS]
+static SENSITIVE_QUERY_PARAMS: OnceLock<Vec<String>> = OnceLock::new();
+
+const DEFAULT_SENSITIVE_QUERY_PARAMS: &[&str] = &[
+    "uniqueid",
+    "uuid",
+    "token",
+    "access_token",
+    "session_token",
+    "password",
+    "secret",
+];
+
+const DEFAULT_SENSITIVE_HEADERS: &[&str] = &[
+    "authorization",
+    "proxy-authorization",
+    "cookie",
+    "set-cookie",
+    "x-api-key",
+    "x-*-token",
+    "x-*-key",
+    "x-*-secret",
+];
+
 struct ActixDebugSpan;
 
 impl ActixDebugSpan {
+    fn client_ip(request: &ServiceRequest) -> Option<IpAddr> {
+        let app = request.app_data::<Data<App>>()?;
+
+        client_ip(&app.config().web_server.trusted_proxies, request.request())
+    }
+
     fn sanitize_headers(headers: &HeaderMap) -> Vec<(String, String)> {
-        const SENSITIVE: &[&str] = &["authorization", "cookie", "set-cookie"];
+        let configured = SENSITIVE_HEADERS
+            .get()
+            .map(Vec::as_slice)
+            .unwrap_or_default();
 
         headers
             .iter()
             .map(|(name, value)| {
                 let name_str = name.as_str().to_string();
+                let name_lower = name_str.to_ascii_lowercase();
 
-                let value_str = if SENSITIVE.contains(&name_str.to_ascii_lowercase().as_str()) {
+                let is_sensitive = DEFAULT_SENSITIVE_HEADERS
+                    .iter()
+                    .copied()
+                    .chain(configured.iter().map(String::as_str))
+                    .any(|pattern| header_matches(&pattern.to_ascii_lowercase(), &name_lower));
+
+                let value_str = if is_sensitive {
                     "<redacted>".to_string()
                 } else {
                     value.to_str().unwrap_or("<binary>").to_string()
@@ -210,6 +469,51 @@ impl ActixDebugSpan {
             })
             .collect()
     }
+
+    fn sanitize_uri(uri: &Uri) -> String {
+        let Some(query) = uri.query() else {
+            return uri.to_string();
+        };
+
+        let configured = SENSITIVE_QUERY_PARAMS
+            .get()
+            .map(Vec::as_slice)
+            .unwrap_or_default();
+
+        let query = query
+            .split('&')
+            .map(|pair| {
+                let key = pair.split_once('=').map(|(key, _)| key).unwrap_or(pair);
+
+                let is_sensitive = DEFAULT_SENSITIVE_QUERY_PARAMS
+                    .iter()
+                    .copied()
+                    .chain(configured.iter().map(String::as_str))
+                    .any(|param| param.eq_ignore_ascii_case(key));
+
+                if is_sensitive {
+                    format!("{key}=<redacted>")
+                } else {
+                    pair.to_string()
+                }
+            })
+            .collect::<Vec<_>>()
+            .join("&");
+
+        format!("{}?{query}", uri.path())
+    }
+}
+
+/// Matches the lowercase header name, a `*` in the pattern matches anything
+fn header_matches(pattern: &str, name: &str) -> bool {
+    match pattern.split_once('*') {
+        Some((prefix, suffix)) => {
+            name.len() >= prefix.len() + suffix.len()
+                && name.starts_with(prefix)
+                && name.ends_with(suffix)
+        }
+        None => pattern == name,
+    }
 }
 
 impl RootSpanBuilder for ActixDebugSpan {
@@ -219,16 +523,17 @@ impl RootSpanBuilder for ActixDebugSpan {
                 Level::TRACE,
                 "http_request",
                 method = %request.method(),
-                uri = %request.uri(),
+                uri = %Self::sanitize_uri(request.uri()),
                 headers = ?Self::sanitize_headers(request.headers()),
                 peer_addr = ?request.peer_addr(),
+                client_ip = ?Self::client_ip(request),
             )
         } else {
             span!(
                 Level::DEBUG,
                 "http_request",
                 method = %request.method(),
-                uri = %request.uri(),
+                uri = %Self::sanitize_uri(request.uri()),
             )
         }
     }
@@ -239,13 +544,193 @@ impl RootSpanBuilder for ActixDebugSpan {
     }
 }
 
-async fn start(config: Config) -> Result<(), anyhow::Error> {
+#[derive(Debug, thiserror::Error)]
+enum ParseConfigError {
+    #[error("{0}")]
+    EnvVar(#[from] EnvVarError),
+    #[error("{0}")]
+    Json(#[from] serde_json::Error),
+    /// Includes the path of the field, e.g. `webrtc.ice_servers[0].urls`
+    #[error("{0}")]
+    Field(#[from] serde_path_to_error::Error<serde_json::Error>),
+    #[error("log.directives: invalid directive {directive:?}: {err}")]
+    LogDirective { directive: String, err: ParseError },
+}
+
+impl ParseConfigError {
+    /// The line and column (both starting at 1) of the error in the config
+    fn location(&self) -> Option<(usize, usize)> {
+        let err = match self {
+            Self::EnvVar(_) | Self::LogDirective { .. } => return None,
+            Self::Json(err) => err,
+            Self::Field(err) => err.inner(),
+        };
+
+        (err.line() > 0).then_some((err.line(), err.column()))
+    }
+}
+
+fn parse_config(value: String) -> Result<Config, ParseConfigError> {
+    let value = preprocess_human_json(value);
+    let value = expand_env_vars(&value)?;
+
+    let mut deserializer = serde_json::Deserializer::from_str(&value);
+    let config: Config = serde_path_to_error::deserialize(&mut deserializer)?;
+    deserializer.end()?;
+
+    for directive in &config.log.directives {
+        if let Err(err) = directive.parse::<Directive>() {
+            return Err(ParseConfigError::LogDirective {
+                directive: directive.clone(),
+                err,
+            });
+        }
+    }
+
+    Ok(config)
+}
+
+/// Reloads the hot reloadable subset of the config when requested, see [App::reload_config].
+struct ConfigReloader {
+    config_path: PathBuf,
+    cli_options: CliConfig,
+    log_filter_handle: LogFilterHandle,
+}
+
+impl ConfigReloader {
+    async fn reload(&self, app: &App) {
+        info!("[Config]: Reloading config from {:?}", self.config_path);
+
+        let mut new_config = match fs::read_to_string(&self.config_path).await {
+            Ok(value) => match parse_config(value) {
+                Ok(mut new_config) => {
+                    self.cli_options.clone().apply(&mut new_config);
+                    new_config
+                }
+                Err(err) => {
+                    error!("[Config]: Failed to parse config, keeping the old config: {err}");
+                    return;
+                }
+            },
+            Err(err) => {
+                error!("[Config]: Failed to read config, keeping the old config: {err}");
+                return;
+            }
+        };
+
+        let had_ice_servers = !new_config.webrtc.ice_servers.is_empty();
+        for problem in sanitize_ice_servers(&mut new_config.webrtc.ice_servers) {
+            warn!("[Config]: {problem}, ignoring it");
+        }
+        if had_ice_servers && new_config.webrtc.ice_servers.is_empty() {
+            error!("[Config]: None of the ice servers are valid, keeping the old config");
+            return;
+        }
+
+        if let Err(err) = self
+            .log_filter_handle
+            .reload(create_env_filter(&new_config.log))
+        {
+            warn!("[Config]: Failed to reload the log level filter: {err}");
+        }
+
+        let requires_restart = app.reload_config(&new_config);
+        for option in requires_restart {
+            warn!("[Config]: Changing \"{option}\" requires restart, ignoring it");
+        }
+
+        info!("[Config]: Reloaded config");
+    }
+
+    #[cfg(unix)]
+    fn spawn_on_sighup(self, app: Data<App>) {
+        use tokio::signal::unix::{SignalKind, signal};
+
+        let mut sighup = match signal(SignalKind::hangup()) {
+            Ok(value) => value,
+            Err(err) => {
+                warn!("[Config]: Failed to listen for SIGHUP, config reloading is disabled: {err}");
+                return;
+            }
+        };
+
+        spawn(async move {
+            while sighup.recv().await.is_some() {
+                self.reload(&app).await;
+            }
+        });
+    }
+
+    #[cfg(not(unix))]
+    fn spawn_on_sighup(self, _app: Data<App>) {}
+}
+
+/// Without a config the returned cors isn't used, so the api stays same origin only
+fn create_cors(config: Option<&CorsConfig>) -> Cors {
+    let mut cors = Cors::default();
+
+    let Some(config) = config else {
+        return cors;
+    };
+
+    for origin in &config.allowed_origins {
+        cors = if origin == "*" {
+            cors.allow_any_origin()
+        } else {
+            cors.allowed_origin(origin)
+        };
+    }
+
+    cors = cors
+        .allowed_methods(config.allowed_methods.iter().map(String::as_str))
+        .allowed_headers(config.allowed_headers.iter().map(String::as_str))
+        .max_age(config.max_age);
+
+    if config.allow_credentials {
+        cors = cors.supports_credentials();
+    }
+
+    cors
+}
+
+type CreateSslAcceptor = Box<dyn Fn() -> Result<SslAcceptorBuilder, anyhow::Error>>;
+
+async fn start(mut config: Config, reloader: ConfigReloader) -> Result<(), anyhow::Error> {
+    let had_ice_servers = !config.webrtc.ice_servers.is_empty();
+    for problem in sanitize_ice_servers(&mut config.webrtc.ice_servers) {
+        warn!("[Config]: {problem}, ignoring it");
+    }
+    if had_ice_servers && config.webrtc.ice_servers.is_empty() {
+        bail!("none of the ice servers in webrtc.ice_servers are valid");
+    }
+
+    let port_range_problems = port_range_problems(&config);
+    if !port_range_problems.is_empty() {
+        bail!("{}", port_range_problems.join(", "));
+    }
+    if let Some(PortRange { min, max }) = config.webrtc.port_range {
+        info!(
+            "[Config]: WebRTC only uses the udp ports {min} to {max}, they must be open in the firewall"
+        );
+    } else if config.webrtc.nat_1to1.is_some() {
+        warn!(
+            "[Config]: webrtc.nat_1to1 is set without webrtc.port_range, WebRTC uses random udp ports which have to be forwarded"
+        );
+    }
+
     let app = App::new(config.clone()).await?;
     let app = Data::new(app);
 
-    let bind_address = app.config().web_server.bind_address;
-    let server = HttpServer::new({
+    reloader.spawn_on_sighup(app.clone());
+
+    let bind_addresses = app.config().web_server.bind_address.addresses().to_vec();
+    if bind_addresses.is_empty() {
+        bail!("no bind address is configured in web_server.bind_address");
+    }
+
+    let mut server = HttpServer::new({
         let url_path_prefix = config.web_server.url_path_prefix.clone();
+        let cors_config = config.web_server.cors.clone();
         let app = app.clone();
 
         move || {
@@ -263,29 +748,145 @@ async fn start(config: Config) -> Result<(), anyhow::Error> {
                                 .add(("Pragma", "no-cache"))
                                 .add(("Expires", "0")),
                         )
+                        .wrap(middleware::Condition::new(
+                            cors_config.is_some(),
+                            create_cors(cors_config.as_ref()),
+                        ))
                         .service(api_service())
+                        .service(health_service())
                         .service(web_config_js_service())
                         .service(web_service()),
                 )
         }
     });
 
-    if let Some(certificate) = app.config().web_server.certificate.as_ref() {
+    // Every bind uses the same certificate so tls is applied consistently across all addresses
+    let create_ssl_acceptor: Option<CreateSslAcceptor> = if let Some(certificate) =
+        app.config().web_server.certificate.clone()
+    {
         info!("[Server]: Running Https Server with ssl tls");
 
-        let mut builder = SslAcceptor::mozilla_intermediate(SslMethod::tls())
-            .expect("failed to create ssl tls acceptor");
-        builder
-            .set_private_key_file(&certificate.private_key_pem, SslFiletype::PEM)
-            .expect("failed to set private key");
-        builder
-            .set_certificate_chain_file(&certificate.certificate_pem)
-            .expect("failed to set certificate");
+        Some(Box::new(move || {
+            let mut builder = SslAcceptor::mozilla_intermediate(SslMethod::tls())
+                .context("failed to create ssl tls acceptor")?;
+            builder
+                    .set_private_key_file(&certificate.private_key_pem, SslFiletype::PEM)
+                    .with_context(|| {
+                        format!(
+                            "failed to load the private key {:?} (web_server.certificate.private_key_pem)",
+                            certificate.private_key_pem
+                        )
+                    })?;
+            builder
+                    .set_certificate_chain_file(&certificate.certificate_pem)
+                    .with_context(|| {
+                        format!(
+                            "failed to load the certificate {:?} (web_server.certificate.certificate_pem)",
+                            certificate.certificate_pem
+                        )
+                    })?;
 
-        server.bind_openssl(bind_address, builder)?.run().await?;
+            Ok(builder)
+        }))
+    } else if let Some(acme) = app.config().web_server.acme.clone() {
+        info!("[Server]: Running Https Server with ssl tls using acme");
+
+        let acme_certificate = AcmeCertificate::start(acme).await?;
+
+        Some(Box::new(move || acme_certificate.create_acceptor()))
     } else {
-        server.bind(bind_address)?.run().await?;
+        None
+    };
+
+    for bind_address in bind_addresses {
+        server = match &create_ssl_acceptor {
+            Some(create_ssl_acceptor) => server
+                .bind_openssl(bind_address, create_ssl_acceptor()?)
+                .with_context(|| format!("failed to bind to {bind_address}"))?,
+            None => server
+                .bind(bind_address)
+                .with_context(|| format!("failed to bind to {bind_address}"))?,
+        };
+
+        let scheme = if create_ssl_acceptor.is_some() {
+            "https"
+        } else {
+            "http"
+        };
+        info!("[Server]: Listening on {scheme}://{bind_address}");
+    }
+
+    if let Some(metrics_bind_address) = app.config().metrics.bind_address {
+        start_metrics_server(app.clone(), metrics_bind_address)?;
+    }
+
+    // Signals are handled by us so active streams can be drained before the server stops
+    let server = server.disable_signals().run();
+    spawn_graceful_shutdown(app.clone(), server.handle());
+
+    server.await?;
+
+    Ok(())
+}
+
+fn spawn_graceful_shutdown(app: Data<App>, server: ServerHandle) {
+    spawn(async move {
+        if let Err(err) = wait_for_shutdown_signal().await {
+            warn!(
+                "[Server]: Failed to listen for shutdown signals, graceful shutdown is disabled: {err}"
+            );
+            return;
+        }
+
+        let grace_period = app.config().streamer.shutdown_grace_period;
+        info!("[Server]: Shutting down, waiting up to {grace_period:?} for active streams to stop");
+
+        let (drained, killed) = app.drain_streams(grace_period).await;
+        info!("[Server]: Drained {drained} streams, killed {killed} streamers");
+
+        server.stop(true).await;
+    });
+}
+
+#[cfg(unix)]
+async fn wait_for_shutdown_signal() -> Result<(), io::Error> {
+    use tokio::signal::{
+        ctrl_c,
+        unix::{SignalKind, signal},
+    };
+
+    let mut sigterm = signal(SignalKind::terminate())?;
+
+    select! {
+        _ = sigterm.recv() => Ok(()),
+        result = ctrl_c() => result,
     }
+}
+
+#[cfg(not(unix))]
+async fn wait_for_shutdown_signal() -> Result<(), io::Error> {
+    tokio::signal::ctrl_c().await
+}
+
+/// Serves the metrics without authentication on a separate address so they aren't exposed publicly
+fn start_metrics_server(app: Data<App>, bind_address: SocketAddr) -> Result<(), anyhow::Error> {
+    let server = HttpServer::new(move || {
+        ActixApp::new()
+            .app_data(app.clone())
+            .service(get_public_metrics)
+    })
+    .workers(1)
+    .bind(bind_address)
+    .with_context(|| format!("failed to bind the metrics server to {bind_address}"))?
+    .run();
+
+    info!("[Server]: Serving metrics on http://{bind_address}/metrics");
+
+    spawn(async move {
+        if let Err(err) = server.await {
+            error!("[Server]: metrics server stopped: {err}");
+        }
+    });
 
     Ok(())
 }
@@ -0,0 +1,342 @@
+//! Semantic checks for the config which can't be expressed with serde
+
+use std::{
+    fs::{self, OpenOptions},
+    net::Ipv6Addr,
+    path::Path,
+};
+
+use common::{
+    api_bindings::RtcIceServer,
+    config::{Config, ConfigSsl, PortRange},
+};
+use openssl::{pkey::PKey, x509::X509};
+
+use crate::{app::wake::parse_mac_address, cli::CliConfig, parse_config};
+
+/// Loads the config like the server would and returns all problems found in it.
+pub fn validate_config_file(config_path: &Path, cli_options: CliConfig) -> Vec<String> {
+    let value = match fs::read_to_string(config_path) {
+        Ok(value) => value,
+        Err(err) => return vec![format!("failed to read config file {config_path:?}: {err}")],
+    };
+
+    let mut config = match parse_config(value) {
+        Ok(config) => config,
+        Err(err) => return vec![format!("failed to parse config file: {err}")],
+    };
+    cli_options.apply(&mut config);
+
+    validate_config(&config)
+}
+
+pub fn validate_config(config: &Config) -> Vec<String> {
+    let mut problems = Vec::new();
+
+    let bind_addresses = config.web_server.bind_address.addresses();
+    if bind_addresses.is_empty() {
+        problems.push("web_server.bind_address: no address is configured".to_string());
+    }
+    for (index, bind_address) in bind_addresses.iter().enumerate() {
+        if bind_addresses[..index].contains(bind_address) {
+            problems.push(format!(
+                "web_server.bind_address: the address {bind_address} is listed multiple times"
+            ));
+        }
+    }
+
+    if let Some(certificate) = &config.web_server.certificate {
+        validate_certificate(&mut problems, certificate);
+
+        if config.web_server.acme.is_some() {
+            problems.push(
+                "web_server.acme: is ignored because web_server.certificate is set".to_string(),
+            );
+        }
+    }
+    if let Some(acme) = &config.web_server.acme
+        && acme.domain.is_empty()
+    {
+        problems.push("web_server.acme.domain: the domain must not be empty".to_string());
+    }
+
+    if let Some(cors) = &config.web_server.cors {
+        for origin in &cors.allowed_origins {
+            if origin != "*" && !(origin.starts_with("http://") || origin.starts_with("https://")) {
+                problems.push(format!(
+                    "web_server.cors.allowed_origins: the origin {origin:?} must start with http:// or https://"
+                ));
+            }
+            if origin.ends_with('/') {
+                problems.push(format!(
+                    "web_server.cors.allowed_origins: the origin {origin:?} must not end with a /"
+                ));
+            }
+        }
+        if cors.allow_credentials && cors.allowed_origins.iter().any(|origin| origin == "*") {
+            problems.push(
+                "web_server.cors: allow_credentials with the origin * allows every website to use the api with the session of the user"
+                    .to_string(),
+            );
+        }
+    }
+
+    problems.extend(port_range_problems(config));
+
+    if config.webrtc.ice_servers.is_empty() && config.webrtc.ice_server_script.is_none() {
+        problems.push("webrtc.ice_servers: no ice servers are configured".to_string());
+    }
+    for (index, ice_server) in config.webrtc.ice_servers.iter().enumerate() {
+        if ice_server.urls.is_empty() {
+            problems.push(format!("webrtc.ice_servers[{index}]: has no urls"));
+        }
+    }
+    problems.extend(sanitize_ice_servers(&mut config.webrtc.ice_servers.clone()));
+
+    if config.streamer.in_process && !cfg!(feature = "in-process-streamer") {
+        problems.push(
+            "streamer.in_process: the web server wasn't built with the \"in-process-streamer\" feature"
+                .to_string(),
+        );
+    }
+
+    for (key, host_config) in &config.hosts {
+        if let Some(mac_address) = &host_config.mac_address
+            && parse_mac_address(mac_address).is_none()
+        {
+            problems.push(format!(
+                "hosts.{key}.mac_address: {mac_address:?} is not a valid mac address"
+            ));
+        }
+    }
+
+    if let Some(file_path) = &config.log.file_path {
+        validate_log_file(&mut problems, Path::new(file_path));
+    }
+    if config.log.max_log_files == Some(0) {
+        problems.push("log.max_log_files: at least one log file must be kept".to_string());
+    }
+
+    problems
+}
+
+/// Checks that the WebRTC port range is valid and big enough for `streamer.max_sessions`.
+/// The server refuses to start with these problems because every stream would fail to connect.
+pub fn port_range_problems(config: &Config) -> Vec<String> {
+    let mut problems = Vec::new();
+
+    let Some(port_range @ PortRange { min, max }) = config.webrtc.port_range else {
+        return problems;
+    };
+
+    if min == 0 {
+        problems.push("webrtc.port_range: the minimum port must not be 0".to_string());
+    }
+    if min > max {
+        problems.push(format!(
+            "webrtc.port_range: the minimum port {min} is bigger than the maximum port {max}"
+        ));
+        return problems;
+    }
+
+    // Every stream binds at least one udp port of the range
+    if let Some(max_sessions) = config.streamer.max_sessions
+        && port_range.port_count() < max_sessions
+    {
+        problems.push(format!(
+            "webrtc.port_range: the {} ports from {min} to {max} aren't enough for the {max_sessions} streams of streamer.max_sessions, every stream needs at least one port",
+            port_range.port_count()
+        ));
+    }
+
+    problems
+}
+
+/// Removes invalid and duplicate urls and the servers which have no urls left.
+/// Returns a description of every removed url.
+pub fn sanitize_ice_servers(ice_servers: &mut Vec<RtcIceServer>) -> Vec<String> {
+    let mut problems = Vec::new();
+    let mut seen = Vec::<(String, String, String)>::new();
+
+    for (index, ice_server) in ice_servers.iter_mut().enumerate() {
+        let had_urls = !ice_server.urls.is_empty();
+
+        ice_server.urls.retain(|url| {
+            if let Err(err) = validate_ice_server_url(url) {
+                problems.push(format!(
+                    "webrtc.ice_servers[{index}]: the url {url:?} is invalid: {err}"
+                ));
+                return false;
+            }
+
+            let key = (
+                url.clone(),
+                ice_server.username.clone(),
+                ice_server.credential.clone(),
+            );
+            if seen.contains(&key) {
+                problems.push(format!(
+                    "webrtc.ice_servers[{index}]: the url {url:?} is listed multiple times"
+                ));
+                return false;
+            }
+            seen.push(key);
+
+            true
+        });
+
+        if had_urls && ice_server.urls.is_empty() {
+            problems.push(format!(
+                "webrtc.ice_servers[{index}]: has no valid urls left"
+            ));
+        }
+    }
+
+    ice_servers.retain(|ice_server| !ice_server.urls.is_empty());
+
+    problems
+}
+
+/// Checks urls like `stun:host:port` or `turn:host:port?transport=udp`, see RFC 7064 and RFC 7065
+fn validate_ice_server_url(url: &str) -> Result<(), &'static str> {
+    let Some((scheme, rest)) = url.split_once(':') else {
+        return Err("missing the scheme, e.g. stun: or turn:");
+    };
+    let is_turn = match scheme {
+        "stun" | "stuns" => false,
+        "turn" | "turns" => true,
+        _ => return Err("the scheme must be stun, stuns, turn or turns"),
+    };
+    if rest.starts_with("//") {
+        return Err("ice urls don't have a // after the scheme");
+    }
+
+    let (address, query) = match rest.split_once('?') {
+        Some((address, query)) => (address, Some(query)),
+        None => (rest, None),
+    };
+    match query {
+        None => {}
+        Some(_) if !is_turn => return Err("stun urls don't have a query"),
+        Some("transport=udp" | "transport=tcp") => {}
+        Some(_) => return Err("the only query allowed is transport=udp or transport=tcp"),
+    }
+
+    // Ipv6 hosts are written in brackets like [::1]:3478
+    let (host, port) = if let Some(address) = address.strip_prefix('[') {
+        let (host, port) = address.split_once(']').ok_or("missing the closing ]")?;
+        if host.parse::<Ipv6Addr>().is_err() {
+            return Err("the host is not a valid ipv6 address");
+        }
+        (host, port.strip_prefix(':'))
+    } else {
+        let (host, port) = match address.split_once(':') {
+            Some((host, port)) => (host, Some(port)),
+            None => (address, None),
+        };
+        if host.is_empty() {
+            return Err("the host is empty");
+        }
+        if !host
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-'))
+        {
+            return Err("the host contains invalid characters");
+        }
+        (host, port)
+    };
+    if let Some(port) = port
+        && !port.parse::<u16>().is_ok_and(|port| port != 0)
+    {
+        return Err("the port must be a number between 1 and 65535");
+    }
+
+    Ok(())
+}
+
+fn validate_certificate(problems: &mut Vec<String>, certificate: &ConfigSsl) {
+    let certificate_pem = match fs::read(&certificate.certificate_pem) {
+        Ok(value) => value,
+        Err(err) => {
+            problems.push(format!(
+                "web_server.certificate.certificate_pem: failed to read {:?}: {err}",
+                certificate.certificate_pem
+            ));
+            return;
+        }
+    };
+    let private_key_pem = match fs::read(&certificate.private_key_pem) {
+        Ok(value) => value,
+        Err(err) => {
+            problems.push(format!(
+                "web_server.certificate.private_key_pem: failed to read {:?}: {err}",
+                certificate.private_key_pem
+            ));
+            return;
+        }
+    };
+
+    let certificate = match X509::from_pem(&certificate_pem) {
+        Ok(value) => value,
+        Err(err) => {
+            problems.push(format!(
+                "web_server.certificate.certificate_pem: invalid certificate: {err}"
+            ));
+            return;
+        }
+    };
+    let private_key = match PKey::private_key_from_pem(&private_key_pem) {
+        Ok(value) => value,
+        Err(err) => {
+            problems.push(format!(
+                "web_server.certificate.private_key_pem: invalid private key: {err}"
+            ));
+            return;
+        }
+    };
+
+    match certificate.public_key() {
+        Ok(public_key) if public_key.public_eq(&private_key) => {}
+        Ok(_) => {
+            problems.push(
+                "web_server.certificate: the private key doesn't match the certificate".to_string(),
+            );
+        }
+        Err(err) => {
+            problems.push(format!(
+                "web_server.certificate.certificate_pem: failed to get the public key of the certificate: {err}"
+            ));
+        }
+    }
+}
+
+fn validate_log_file(problems: &mut Vec<String>, file_path: &Path) {
+    if file_path.exists() {
+        // Only check if it's writable, truncating is up to the server
+        if let Err(err) = OpenOptions::new().append(true).open(file_path) {
+            problems.push(format!(
+                "log.file_path: the file {file_path:?} is not writable: {err}"
+            ));
+        }
+        return;
+    }
+
+    let parent = match file_path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+
+    match fs::metadata(parent) {
+        Ok(metadata) if metadata.permissions().readonly() => {
+            problems.push(format!(
+                "log.file_path: the directory {parent:?} is not writable"
+            ));
+        }
+        Ok(_) => {}
+        Err(err) => {
+            problems.push(format!(
+                "log.file_path: the directory {parent:?} doesn't exist: {err}"
+            ));
+        }
+    }
+}
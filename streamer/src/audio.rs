@@ -1,14 +1,43 @@
-use std::sync::Weak;
+use std::{
+    sync::{Arc, Weak},
+    time::{Duration, Instant},
+};
 
-use log::{debug, error, warn};
+use common::{
+    api_bindings::{StreamAudioMode, StreamerStatsUpdate},
+    ipc::StreamerMetrics,
+};
+use log::{debug, error, info, warn};
 use moonlight_common::stream::audio::{
     AudioConfig, AudioDecoder, AudioFrame, OpusMultistreamConfig,
 };
 
-use crate::StreamConnection;
+use crate::{StreamConnection, recording::RecordingFile, transport::OutboundPacket};
+
+/// The audio config requested from the host, the host sends at most the channels of its audio device
+pub(crate) fn audio_mode_config(
+    audio_mode: StreamAudioMode,
+    client_audio_channels: Option<u32>,
+) -> AudioConfig {
+    match audio_mode {
+        StreamAudioMode::Stereo => AudioConfig::STEREO,
+        StreamAudioMode::Surround51 => AudioConfig::SURROUND_51,
+        StreamAudioMode::Surround71 => AudioConfig::SURROUND_71,
+        // Stereo unless the browser can play more
+        StreamAudioMode::MatchHost => match client_audio_channels {
+            Some(channels) if channels >= 8 => AudioConfig::SURROUND_71,
+            Some(channels) if channels >= 6 => AudioConfig::SURROUND_51,
+            _ => AudioConfig::STEREO,
+        },
+    }
+}
 
 pub(crate) struct StreamAudioDecoder {
     pub(crate) stream: Weak<StreamConnection>,
+    pub(crate) audio_config: AudioConfig,
+    pub(crate) stats: AudioStats,
+    pub(crate) recording_name: String,
+    pub(crate) recording: Option<RecordingFile>,
 }
 
 impl AudioDecoder for StreamAudioDecoder {
@@ -18,11 +47,29 @@ impl AudioDecoder for StreamAudioDecoder {
             return -1;
         };
 
+        if audio_config != self.audio_config {
+            info!(
+                "[Stream]: the host selected the audio config {audio_config:?} instead of {:?}",
+                self.audio_config
+            );
+        }
+        info!(
+            "[Stream]: streaming audio with {} channels",
+            stream_config.channel_count
+        );
+
         {
             let mut stream_info = stream.stream_setup.blocking_lock();
+            stream_info.audio_config = Some(audio_config);
             stream_info.audio = Some(stream_config.clone());
         }
 
+        self.recording = stream
+            .config
+            .recording
+            .as_ref()
+            .and_then(|config| RecordingFile::create(config, &self.recording_name, "opus"));
+
         stream.runtime.clone().block_on(async move {
             let mut sender = stream.transport_sender.lock().await;
             if let Some(sender) = sender.as_mut() {
@@ -43,20 +90,87 @@ impl AudioDecoder for StreamAudioDecoder {
             return;
         };
 
-        stream.runtime.clone().block_on(async move {
-            let mut stream = stream.transport_sender.lock().await;
+        if let Some(recording) = &mut self.recording {
+            recording.write_packet(sample.buffer);
+        }
+
+        let failed = stream.runtime.clone().block_on(async {
+            let mut sender = stream.transport_sender.lock().await;
 
-            if let Some(stream) = stream.as_mut() {
-                if let Err(err) = stream.send_audio_sample(sample.buffer).await {
+            if let Some(sender) = sender.as_mut() {
+                if let Err(err) = sender.send_audio_sample(sample.buffer).await {
                     warn!("Failed to send audio sample: {err}");
+                    return true;
                 }
             } else {
                 debug!("Dropping audio packet because of missing transport");
             }
+
+            false
         });
+
+        if !failed {
+            self.stats.streamed_bytes += sample.buffer.len() as u64;
+        }
+        self.stats.analyze(&stream, failed);
     }
 
     fn config(&self) -> AudioConfig {
-        AudioConfig::STEREO
+        self.audio_config
+    }
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct AudioStats {
+    last_send: Option<Instant>,
+    sample_count: usize,
+    failed_sample_count: usize,
+    streamed_bytes: u64,
+}
+
+impl AudioStats {
+    fn analyze(&mut self, stream: &Arc<StreamConnection>, failed: bool) {
+        self.sample_count += 1;
+        if failed {
+            self.failed_sample_count += 1;
+        }
+
+        // Send in 1 sec intervall
+        if self
+            .last_send
+            .map(|last_send| last_send + Duration::from_secs(1) < Instant::now())
+            .unwrap_or(true)
+        {
+            let received_samples = self.sample_count as u32;
+            let failed_samples = self.failed_sample_count as u32;
+            let streamed_bytes = self.streamed_bytes;
+
+            let stream = stream.clone();
+            stream.runtime.clone().spawn(async move {
+                stream
+                    .try_send_packet(
+                        OutboundPacket::Stats(StreamerStatsUpdate::Audio {
+                            received_samples,
+                            failed_samples,
+                        }),
+                        "audio samples",
+                        false,
+                    )
+                    .await;
+
+                stream
+                    .send_metrics(StreamerMetrics {
+                        streamed_audio_bytes: streamed_bytes,
+                        ..Default::default()
+                    })
+                    .await;
+            });
+
+            self.sample_count = 0;
+            self.failed_sample_count = 0;
+            self.streamed_bytes = 0;
+
+            self.last_send = Some(Instant::now());
+        }
     }
 }
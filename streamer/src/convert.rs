@@ -1,8 +1,9 @@
 use common::{
     api_bindings::{RtcIceServer, RtcSdpType},
-    config::{WebRtcNat1To1IceCandidateType, WebRtcNetworkType},
+    config::{WebRtcNat1To1IceCandidateType, WebRtcNetworkType, WebRtcSrtpProtectionProfile},
 };
 use webrtc::{
+    dtls::extension::extension_use_srtp::SrtpProtectionProfile,
     ice::network_type::NetworkType,
     ice_transport::{ice_candidate_type::RTCIceCandidateType, ice_server::RTCIceServer},
     peer_connection::sdp::sdp_type::RTCSdpType,
@@ -41,3 +42,18 @@ pub fn into_webrtc_network_type(value: WebRtcNetworkType) -> NetworkType {
         WebRtcNetworkType::Tcp6 => NetworkType::Tcp6,
     }
 }
+
+pub fn into_webrtc_srtp_protection_profile(
+    value: WebRtcSrtpProtectionProfile,
+) -> SrtpProtectionProfile {
+    match value {
+        WebRtcSrtpProtectionProfile::Aes128CmHmacSha1_80 => {
+            SrtpProtectionProfile::Srtp_Aes128_Cm_Hmac_Sha1_80
+        }
+        WebRtcSrtpProtectionProfile::Aes128CmHmacSha1_32 => {
+            SrtpProtectionProfile::Srtp_Aes128_Cm_Hmac_Sha1_32
+        }
+        WebRtcSrtpProtectionProfile::AeadAes128Gcm => SrtpProtectionProfile::Srtp_Aead_Aes_128_Gcm,
+        WebRtcSrtpProtectionProfile::AeadAes256Gcm => SrtpProtectionProfile::Srtp_Aead_Aes_256_Gcm,
+    }
+}
@@ -0,0 +1,193 @@
+use std::{collections::VecDeque, fmt::Write, time::Instant};
+
+use common::config::StreamFrameDiagnosticsConfig;
+use log::{debug, warn};
+use moonlight_common::stream::video::{FrameType, VideoDecodeUnit, VideoFormat};
+
+use crate::video::decode_unit_len;
+
+/// While no idr arrived the warning is repeated after this many P-frames, so a stream that never gets one keeps showing up in the log
+const PFRAMES_WITHOUT_IDR_WARN_INTERVAL: u64 = 120;
+/// How many runs of the same frame type are kept for the log
+const FRAME_TYPE_HISTORY_LEN: usize = 16;
+
+/// Watches the frame types the host sends for the usual causes of a black screen in the browser
+pub struct FrameDiagnostics {
+    config: StreamFrameDiagnosticsConfig,
+    format: Option<VideoFormat>,
+    /// Runs of frame types, `(is_idr, count)`
+    history: VecDeque<(bool, u64)>,
+    received_idr: bool,
+    pframes_without_idr: u64,
+    last_idr: Option<Instant>,
+    idr_gap_warned: bool,
+    unparseable_frames: u64,
+}
+
+impl FrameDiagnostics {
+    pub fn new(config: StreamFrameDiagnosticsConfig) -> Self {
+        Self {
+            config,
+            format: None,
+            history: VecDeque::with_capacity(FRAME_TYPE_HISTORY_LEN),
+            received_idr: false,
+            pframes_without_idr: 0,
+            last_idr: None,
+            idr_gap_warned: false,
+            unparseable_frames: 0,
+        }
+    }
+
+    /// The decoder of the browser has to wait for a new idr after every setup
+    pub fn setup(&mut self, format: VideoFormat) {
+        *self = Self {
+            format: Some(format),
+            ..Self::new(self.config.clone())
+        };
+    }
+
+    pub fn on_frame(&mut self, unit: &VideoDecodeUnit<&[u8]>) {
+        let is_idr = matches!(unit.frame_type, FrameType::Idr);
+        self.push_history(is_idr);
+
+        if let Some(problem) = self.parse_problem(unit) {
+            self.unparseable_frames += 1;
+            warn!(
+                "[Stream]: received a {} that can't be parsed ({} so far): {problem}",
+                frame_type_name(is_idr),
+                self.unparseable_frames
+            );
+        }
+
+        if is_idr {
+            self.on_idr();
+        } else {
+            self.on_pframe();
+        }
+    }
+
+    fn on_idr(&mut self) {
+        if self.pframes_without_idr > 0 {
+            warn!(
+                "[Stream]: received {} P-frames with no preceding IDR",
+                self.pframes_without_idr
+            );
+        }
+
+        if let Some(max_idr_gap) = self.config.max_idr_gap
+            && let Some(last_idr) = self.last_idr
+            && last_idr.elapsed() > max_idr_gap
+        {
+            warn!(
+                "[Stream]: received an IDR after {:?}, which is longer than the allowed gap of {max_idr_gap:?}",
+                last_idr.elapsed()
+            );
+        }
+
+        debug!("[Stream]: received an IDR, frame types: {}", self.history());
+
+        self.received_idr = true;
+        self.pframes_without_idr = 0;
+        self.last_idr = Some(Instant::now());
+        self.idr_gap_warned = false;
+    }
+
+    fn on_pframe(&mut self) {
+        if !self.received_idr {
+            self.pframes_without_idr += 1;
+
+            if self.pframes_without_idr % PFRAMES_WITHOUT_IDR_WARN_INTERVAL == 0 {
+                warn!(
+                    "[Stream]: received {} P-frames with no preceding IDR, the browser can't decode them",
+                    self.pframes_without_idr
+                );
+            }
+            return;
+        }
+
+        if let Some(max_idr_gap) = self.config.max_idr_gap
+            && let Some(last_idr) = self.last_idr
+            && !self.idr_gap_warned
+            && last_idr.elapsed() > max_idr_gap
+        {
+            self.idr_gap_warned = true;
+            warn!(
+                "[Stream]: no IDR was received for {max_idr_gap:?}, frame types: {}",
+                self.history()
+            );
+        }
+    }
+
+    fn push_history(&mut self, is_idr: bool) {
+        // IDRs always start a new run so every one of them shows up
+        if !is_idr
+            && let Some((last_is_idr, count)) = self.history.back_mut()
+            && !*last_is_idr
+        {
+            *count += 1;
+            return;
+        }
+
+        if self.history.len() == FRAME_TYPE_HISTORY_LEN {
+            self.history.pop_front();
+        }
+        self.history.push_back((is_idr, 1));
+    }
+
+    /// The recent frame types, e.g. `I P x120 I P x42`
+    fn history(&self) -> String {
+        let mut text = String::new();
+        for (is_idr, count) in &self.history {
+            if !text.is_empty() {
+                text.push(' ');
+            }
+
+            text.push(if *is_idr { 'I' } else { 'P' });
+            if *count > 1 {
+                let _ = write!(text, " x{count}");
+            }
+        }
+
+        text
+    }
+
+    /// Only checks the start of the frame, the browser will report anything else
+    fn parse_problem(&self, unit: &VideoDecodeUnit<&[u8]>) -> Option<&'static str> {
+        if decode_unit_len(unit) == 0 {
+            return Some("the frame is empty");
+        }
+
+        let data = unit
+            .buffers
+            .iter()
+            .map(|buffer| buffer.data)
+            .find(|data| !data.is_empty())?;
+
+        match self.format? {
+            VideoFormat::H264
+            | VideoFormat::H264High8_444
+            | VideoFormat::H265
+            | VideoFormat::H265Main10
+            | VideoFormat::H265Rext8_444
+            | VideoFormat::H265Rext10_444 => {
+                if !data.starts_with(&[0, 0, 1]) && !data.starts_with(&[0, 0, 0, 1]) {
+                    return Some("the frame doesn't start with an annex b start code");
+                }
+            }
+            VideoFormat::Av1Main8
+            | VideoFormat::Av1Main10
+            | VideoFormat::Av1High8_444
+            | VideoFormat::Av1High10_444 => {
+                if data[0] & 0x80 != 0 {
+                    return Some("the forbidden bit of the first obu header is set");
+                }
+            }
+        }
+
+        None
+    }
+}
+
+fn frame_type_name(is_idr: bool) -> &'static str {
+    if is_idr { "IDR" } else { "P-frame" }
+}
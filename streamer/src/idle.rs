@@ -0,0 +1,87 @@
+use std::{
+    fmt::{self, Display, Formatter},
+    sync::atomic::{AtomicU64, Ordering},
+    time::{Duration, Instant},
+};
+
+use common::config::StreamIdleTimeoutConfig;
+
+/// How often the idle timeouts are checked
+pub const IDLE_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, Copy)]
+pub enum IdleReason {
+    NoInput(Duration),
+    NoFrames(Duration),
+}
+
+impl Display for IdleReason {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NoInput(duration) => write!(f, "no input for {duration:?}"),
+            Self::NoFrames(duration) => write!(f, "no video frames sent for {duration:?}"),
+        }
+    }
+}
+
+/// Remembers when the client sent input and when a video frame was sent to the client
+#[derive(Debug)]
+pub struct IdleTracker {
+    created: Instant,
+    last_input_ms: AtomicU64,
+    last_frame_ms: AtomicU64,
+}
+
+impl Default for IdleTracker {
+    fn default() -> Self {
+        Self {
+            created: Instant::now(),
+            last_input_ms: AtomicU64::new(0),
+            last_frame_ms: AtomicU64::new(0),
+        }
+    }
+}
+
+impl IdleTracker {
+    fn now_ms(&self) -> u64 {
+        self.created.elapsed().as_millis() as u64
+    }
+
+    /// Restarts both timeouts, e.g. when a new stream starts
+    pub fn reset(&self) {
+        let now = self.now_ms();
+        self.last_input_ms.store(now, Ordering::Relaxed);
+        self.last_frame_ms.store(now, Ordering::Relaxed);
+    }
+
+    pub fn on_input(&self) {
+        self.last_input_ms.store(self.now_ms(), Ordering::Relaxed);
+    }
+
+    pub fn on_frame(&self) {
+        self.last_frame_ms.store(self.now_ms(), Ordering::Relaxed);
+    }
+
+    /// Returns why the stream should be stopped if one of the timeouts elapsed
+    pub fn idle_reason(&self, config: &StreamIdleTimeoutConfig) -> Option<IdleReason> {
+        let now = self.now_ms();
+        let since = |last: &AtomicU64| {
+            Duration::from_millis(now.saturating_sub(last.load(Ordering::Relaxed)))
+        };
+
+        if let Some(no_frames) = config.no_frames {
+            let idle = since(&self.last_frame_ms);
+            if idle >= no_frames {
+                return Some(IdleReason::NoFrames(idle));
+            }
+        }
+        if let Some(no_input) = config.no_input {
+            let idle = since(&self.last_input_ms);
+            if idle >= no_input {
+                return Some(IdleReason::NoInput(idle));
+            }
+        }
+
+        None
+    }
+}
@@ -0,0 +1,62 @@
+use std::collections::HashMap;
+
+use common::{
+    api_bindings::{KeyboardLayout, StreamKeys},
+    config::HostConfig,
+};
+
+/// Remaps the virtual key codes sent by the browser before they're sent to the host.
+///
+/// Edge cases which can't be fixed by remapping:
+/// - AltGr is sent as right alt (browsers on windows also send a left control), characters typed with it depend on the layout of the host
+/// - Dead keys are sent as the key at their position, the host composes the character using its own layout
+#[derive(Debug, Default)]
+pub struct KeyRemap {
+    keys: HashMap<u16, u16>,
+}
+
+impl KeyRemap {
+    pub fn new(layout: KeyboardLayout, custom: Option<&HashMap<u16, u16>>) -> Self {
+        let mut keys = layout_keys(layout)
+            .iter()
+            .copied()
+            .collect::<HashMap<_, _>>();
+
+        if let Some(custom) = custom {
+            keys.extend(custom);
+        }
+
+        Self { keys }
+    }
+
+    pub fn from_host_config(host_config: &HostConfig, layout: Option<KeyboardLayout>) -> Self {
+        Self::new(
+            layout.or(host_config.keyboard_layout).unwrap_or_default(),
+            host_config.key_remap.as_ref(),
+        )
+    }
+
+    pub fn remap(&self, key: u16) -> u16 {
+        self.keys.get(&key).copied().unwrap_or(key)
+    }
+}
+
+/// Which key is sent for the key at a position, the browser uses the qwerty names for positions
+fn layout_keys(layout: KeyboardLayout) -> &'static [(u16, u16)] {
+    match layout {
+        KeyboardLayout::Qwerty => &[],
+        KeyboardLayout::Qwertz => &[
+            (StreamKeys::VK_KEY_Y, StreamKeys::VK_KEY_Z),
+            (StreamKeys::VK_KEY_Z, StreamKeys::VK_KEY_Y),
+        ],
+        KeyboardLayout::Azerty => &[
+            (StreamKeys::VK_KEY_A, StreamKeys::VK_KEY_Q),
+            (StreamKeys::VK_KEY_Q, StreamKeys::VK_KEY_A),
+            (StreamKeys::VK_KEY_W, StreamKeys::VK_KEY_Z),
+            (StreamKeys::VK_KEY_Z, StreamKeys::VK_KEY_W),
+            (StreamKeys::VK_OEM_1, StreamKeys::VK_KEY_M),
+            (StreamKeys::VK_KEY_M, StreamKeys::VK_OEM_COMMA),
+            (StreamKeys::VK_OEM_COMMA, StreamKeys::VK_OEM_1),
+        ],
+    }
+}
@@ -0,0 +1,1691 @@
+#![feature(async_fn_traits)]
+
+use std::{
+    io,
+    sync::{
+        Arc, Weak,
+        atomic::{AtomicBool, AtomicU32, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+use common::{
+    api_bindings::{
+        GeneralClientMessage, GeneralServerMessage, HdrMetadata, LogMessageType, StreamAudioMode,
+        StreamClientMessage, StreamPermissions, StreamSettings, StreamVideoMode, TransportType,
+    },
+    apply_audio_only_to_settings, apply_codec_preference_to_settings,
+    apply_host_config_to_settings, apply_permissions_to_settings, apply_stream_limits_to_settings,
+    apply_video_mode_to_settings,
+    config::StreamIdleTimeoutConfig,
+    ipc::{
+        IPC_PROTOCOL_VERSION, IpcReceiver, IpcSender, ServerIpcMessage, StreamerCapabilities,
+        StreamerConfig, StreamerIpcMessage, StreamerMetrics,
+    },
+    launch::encode_launch_parameters,
+};
+use moonlight_common::{
+    MoonlightError,
+    crypto::openssl::OpenSSLCryptoBackend,
+    high::{MoonlightClientError, StreamConfigError, tokio::MoonlightHost},
+    http::{
+        ClientIdentifier, ClientSecret, ServerIdentifier, client::tokio_hyper::TokioHyperClient,
+    },
+    stream::{
+        AesIv, AesKey, EncryptionFlags, HostFeatures, MoonlightStreamSettings, StreamingConfig,
+        audio::{AudioConfig, OpusMultistreamConfig},
+        c::{
+            MoonlightInstance, MoonlightStream,
+            bindings::{ConnectionStatus, Stage},
+            connection::ConnectionListenerC,
+        },
+        connection::ConnectionListener,
+        control::{ActiveGamepads, ControllerButtons},
+        video::{
+            ColorRange, ColorSpace, SunshineHdrMetadata, VideoFormat, VideoFormats, VideoSetup,
+        },
+    },
+};
+use tokio::{
+    runtime::Handle,
+    spawn,
+    sync::{Mutex, Notify, RwLock},
+    task::spawn_blocking,
+    time::sleep,
+};
+use tracing::level_filters::LevelFilter;
+use tracing::{debug, error, info, trace, warn};
+
+use common::api_bindings::{
+    StreamCapabilities, StreamEndReason, StreamServerMessage, StreamStage, StreamStageError,
+};
+use tracing_subscriber::{EnvFilter, Registry, fmt, layer::SubscriberExt, util::SubscriberInitExt};
+
+use crate::{
+    audio::{StreamAudioDecoder, audio_mode_config},
+    diagnostics::FrameDiagnostics,
+    dynamic_ice_servers::load_dynamic_ice_servers,
+    idle::{IDLE_CHECK_INTERVAL, IdleTracker},
+    keyboard::KeyRemap,
+    mouse::RelativeMouse,
+    recording::recording_name,
+    transport::{
+        InboundPacket, OutboundPacket, TransportError, TransportEvent, TransportEvents,
+        TransportSender, web_socket,
+        webrtc::{self},
+    },
+    video::StreamVideoDecoder,
+};
+
+pub type RequestClient = TokioHyperClient;
+
+pub const TIMEOUT_DURATION: Duration = Duration::from_secs(10);
+
+/// Used if the host config doesn't set `clipboard_max_bytes`
+const DEFAULT_CLIPBOARD_MAX_BYTES: usize = 8192;
+
+/// Keyframes are big, so the client can't request them more often than this
+const IDR_REQUEST_MIN_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The channels of 7.1 surround, the most a host can send
+const SURROUND_71_CHANNEL_COUNT: u32 = 8;
+
+mod audio;
+mod buffer;
+mod convert;
+mod diagnostics;
+mod dynamic_ice_servers;
+mod idle;
+mod keyboard;
+mod mouse;
+mod recording;
+mod transport;
+mod video;
+
+/// Runs the streamer until the stream is terminated or the web server closes the ipc.
+/// A streamer running inside the web server uses the logger of the web server, otherwise the logs are written to stderr.
+pub async fn run_streamer(
+    mut ipc_sender: IpcSender<StreamerIpcMessage>,
+    mut ipc_receiver: IpcReceiver<ServerIpcMessage>,
+    log_to_stderr: bool,
+) {
+    // The init of another version might not be decodable, so the version is checked on the hello before it
+    loop {
+        match ipc_receiver.recv().await {
+            Some(ServerIpcMessage::Hello { protocol_version }) => {
+                if protocol_version != IPC_PROTOCOL_VERSION {
+                    warn!(
+                        "[Stream]: the web server uses ipc protocol version {protocol_version}, but this streamer uses version {IPC_PROTOCOL_VERSION}"
+                    );
+                    ipc_sender
+                        .send(StreamerIpcMessage::ProtocolVersionMismatch {
+                            streamer_version: IPC_PROTOCOL_VERSION,
+                        })
+                        .await;
+
+                    // Give the ipc time to send the message
+                    sleep(Duration::from_secs(1)).await;
+                    return;
+                }
+                break;
+            }
+            Some(_) => continue,
+            // The web server closed the ipc before sending hello
+            None => return,
+        }
+    }
+
+    // Send stage
+    ipc_sender
+        .send(StreamerIpcMessage::WebSocket(
+            StreamServerMessage::stage_completed(
+                StreamStage::LaunchStreamer,
+                "Completed Stage: Launch Streamer",
+            ),
+        ))
+        .await;
+
+    let (
+        mut config,
+        host_address,
+        host_http_port,
+        client_unique_id,
+        client_private_key,
+        client_certificate,
+        server_certificate,
+        app_id,
+        video_frame_queue_size,
+        audio_sample_queue_size,
+        permissions,
+        video_mode,
+        audio_mode,
+        client_audio_channels,
+        launch_parameters,
+    ) = loop {
+        match ipc_receiver.recv().await {
+            Some(ServerIpcMessage::Init {
+                config,
+                host_address,
+                host_http_port,
+                client_unique_id,
+                client_private_key,
+                client_certificate,
+                server_certificate,
+                app_id,
+                video_frame_queue_size,
+                audio_sample_queue_size,
+                permissions,
+                video_mode,
+                audio_mode,
+                client_audio_channels,
+                launch_parameters,
+            }) => {
+                break (
+                    config,
+                    host_address,
+                    host_http_port,
+                    client_unique_id,
+                    client_private_key,
+                    client_certificate,
+                    server_certificate,
+                    app_id,
+                    video_frame_queue_size,
+                    audio_sample_queue_size,
+                    permissions,
+                    video_mode,
+                    audio_mode,
+                    client_audio_channels,
+                    launch_parameters,
+                );
+            }
+            Some(_) => continue,
+            // The web server closed the ipc before sending init
+            None => return,
+        }
+    };
+
+    ipc_sender.set_compression_threshold(config.ipc_compression_threshold);
+    ipc_receiver.set_max_frame_size(config.ipc_max_frame_size);
+
+    // -- Init logger
+    if log_to_stderr {
+        init_stderr_logger(config.log_level, &config.log_directives);
+    }
+
+    // print permissions
+    info!("Got Permissions: {permissions:?}");
+
+    // Send stage
+    ipc_sender
+        .send(StreamerIpcMessage::WebSocket(
+            StreamServerMessage::DebugLog {
+                message: "Waiting for Transport to negotiate".to_string(),
+                ty: None,
+            },
+        ))
+        .await;
+
+    // -- Create the host and pair it
+    let host = MoonlightHost::new(host_address, host_http_port, client_unique_id)
+        .expect("failed to create host");
+
+    host.set_identity(
+        ClientIdentifier::from_pem(client_certificate),
+        ClientSecret::from_pem(client_private_key),
+        ServerIdentifier::from_pem(server_certificate),
+    )
+    .await
+    .expect("failed to set pairing info");
+
+    // -- Configure moonlight
+    let moonlight = MoonlightInstance::global().expect("failed to find moonlight");
+
+    // Load dynamic ice servers and append them to the current ice servers
+    let dynamic_ice_servers = load_dynamic_ice_servers(&config.webrtc).await;
+    config
+        .webrtc
+        .ice_servers
+        .extend_from_slice(&dynamic_ice_servers);
+
+    // -- Create and Configure Peer
+    let ice_servers = config.webrtc.ice_servers.clone();
+    let force_relay = config.webrtc.force_relay;
+    let jitter_buffer_target_ms = config
+        .webrtc
+        .jitter_buffer_target
+        .as_millis()
+        .try_into()
+        .unwrap_or(u32::MAX);
+
+    let connection = StreamConnection::new(
+        moonlight,
+        StreamInfo {
+            host,
+            app_id,
+            video_mode,
+            audio_mode,
+            client_audio_channels,
+            launch_query: encode_launch_parameters(&launch_parameters),
+        },
+        ipc_sender.clone(),
+        ipc_receiver,
+        config,
+        video_frame_queue_size,
+        audio_sample_queue_size,
+        permissions,
+    )
+    .await
+    .expect("failed to create connection");
+
+    // Send Info for streamer
+    ipc_sender
+        .send(StreamerIpcMessage::WebSocket(StreamServerMessage::Setup {
+            ice_servers,
+            force_relay,
+            jitter_buffer_target_ms,
+        }))
+        .await;
+
+    // Wait for termination
+    connection.terminate.notified().await;
+
+    // The stream and transport are already stopped, give the ipc time to send the last messages
+    sleep(Duration::from_secs(1)).await;
+
+    info!("Terminating Self");
+}
+
+fn init_stderr_logger(log_level: log::LevelFilter, directives: &[String]) {
+    let config_level_filter = match log_level {
+        log::LevelFilter::Off => LevelFilter::OFF,
+        log::LevelFilter::Error => LevelFilter::ERROR,
+        log::LevelFilter::Info => LevelFilter::INFO,
+        log::LevelFilter::Warn => LevelFilter::WARN,
+        log::LevelFilter::Debug => LevelFilter::DEBUG,
+        log::LevelFilter::Trace => LevelFilter::TRACE,
+    };
+
+    let mut env_filter = EnvFilter::builder()
+        .with_default_directive(config_level_filter.into())
+        .from_env_lossy()
+        .add_directive(
+            "webrtc_sctp=off"
+                .parse()
+                .expect("failed to parse webrtc directive"),
+        );
+    // The web server already validated them
+    for directive in directives {
+        if let Ok(directive) = directive.parse() {
+            env_filter = env_filter.add_directive(directive);
+        }
+    }
+
+    let stderr_output = fmt::layer().with_writer(io::stderr).with_ansi(false);
+
+    Registry::default()
+        .with(env_filter)
+        .with(stderr_output)
+        .init();
+}
+
+struct StreamInfo {
+    host: MoonlightHost<RequestClient>,
+    app_id: u32,
+    /// Overrides the resolution and fps of the stream settings
+    video_mode: Option<StreamVideoMode>,
+    audio_mode: StreamAudioMode,
+    /// The most audio channels the browser can play
+    client_audio_channels: Option<u32>,
+    /// Appended to the launch query of moonlight, starts with `&` if it's not empty
+    launch_query: String,
+}
+
+struct StreamSetup {
+    video: Option<VideoSetup>,
+    audio_config: Option<AudioConfig>,
+    audio: Option<OpusMultistreamConfig>,
+}
+
+struct StreamConnection {
+    pub runtime: Handle,
+    pub moonlight: MoonlightInstance,
+    pub config: StreamerConfig,
+    pub info: StreamInfo,
+    pub ipc_sender: IpcSender<StreamerIpcMessage>,
+    pub permissions: StreamPermissions,
+    // Video
+    pub video_frame_queue_size: usize,
+    pub audio_sample_queue_size: usize,
+    pub stream_setup: Mutex<StreamSetup>,
+    /// The settings of the last started stream, used to restart it
+    pub stream_settings: Mutex<Option<StreamSettings>>,
+    /// The stream already fell back to H264 because the browser couldn't decode the video
+    video_fallback: AtomicBool,
+    /// The client requested a keyframe, the next decode unit asks the host for an idr
+    pub request_idr: AtomicBool,
+    last_idr_request: Mutex<Option<Instant>>,
+    // Bitrate
+    pub bitrate_kbps: AtomicU32,
+    /// The bandwidth estimated by the browser, 0 if unknown
+    pub estimated_bitrate_kbps: AtomicU32,
+    // Stream
+    pub stream: RwLock<Option<MoonlightStream>>,
+    pub active_gamepads: RwLock<ActiveGamepads>,
+    pub relative_mouse: Mutex<RelativeMouse>,
+    pub key_remap: RwLock<KeyRemap>,
+    pub transport_sender: Mutex<Option<Box<dyn TransportSender + Send + Sync + 'static>>>,
+    // Timeout / Terminate
+    pub idle: IdleTracker,
+    pub timeout_terminate_request: Mutex<Option<Instant>>,
+    pub terminate: Notify,
+    is_terminating: AtomicBool,
+}
+
+impl StreamConnection {
+    pub async fn new(
+        moonlight: MoonlightInstance,
+        info: StreamInfo,
+        ipc_sender: IpcSender<StreamerIpcMessage>,
+        mut ipc_receiver: IpcReceiver<ServerIpcMessage>,
+        config: StreamerConfig,
+        video_frame_queue_size: usize,
+        audio_sample_queue_size: usize,
+        permissions: StreamPermissions,
+    ) -> Result<Arc<Self>, anyhow::Error> {
+        let key_remap = KeyRemap::from_host_config(&config.host, None);
+
+        let this = Arc::new(Self {
+            runtime: Handle::current(),
+            moonlight,
+            config,
+            info,
+            ipc_sender,
+            permissions,
+            stream_setup: Mutex::new(StreamSetup {
+                video: None,
+                audio_config: None,
+                audio: None,
+            }),
+            stream_settings: Mutex::new(None),
+            video_fallback: AtomicBool::new(false),
+            request_idr: AtomicBool::new(false),
+            last_idr_request: Mutex::new(None),
+            video_frame_queue_size,
+            audio_sample_queue_size,
+            bitrate_kbps: AtomicU32::new(0),
+            estimated_bitrate_kbps: AtomicU32::new(0),
+            stream: RwLock::new(None),
+            active_gamepads: RwLock::new(ActiveGamepads::empty()),
+            relative_mouse: Mutex::new(RelativeMouse::default()),
+            key_remap: RwLock::new(key_remap),
+            transport_sender: Mutex::new(None),
+            idle: IdleTracker::default(),
+            timeout_terminate_request: Default::default(),
+            terminate: Notify::default(),
+            is_terminating: AtomicBool::new(false),
+        });
+
+        spawn({
+            let this = Arc::downgrade(&this);
+
+            async move {
+                while let Some(message) = ipc_receiver.recv().await {
+                    let Some(this) = this.upgrade() else {
+                        debug!("Received ipc message while the main type is already deallocated");
+                        return;
+                    };
+
+                    if let ServerIpcMessage::Stop = &message {
+                        this.on_ipc_message(ServerIpcMessage::Stop).await;
+                        return;
+                    }
+                    if let ServerIpcMessage::Ping = &message {
+                        this.ipc_sender.clone().send(StreamerIpcMessage::Pong).await;
+                        continue;
+                    }
+                    if let ServerIpcMessage::QueryCapabilities = &message {
+                        this.send_capabilities().await;
+                        continue;
+                    }
+
+                    this.on_ipc_message(message).await;
+                }
+            }
+        });
+
+        if let Some(idle_timeout) = this.config.idle_timeout.clone() {
+            spawn(watch_idle_timeout(Arc::downgrade(&this), idle_timeout));
+        }
+
+        Ok(this)
+    }
+
+    async fn set_transport(
+        self: &Arc<Self>,
+        new_sender: Box<dyn TransportSender + Send + Sync + 'static>,
+        mut events: Box<dyn TransportEvents + Send + Sync + 'static>,
+    ) {
+        let this = self.clone();
+
+        let old_transport = {
+            let mut sender = this.transport_sender.lock().await;
+            sender.replace(new_sender)
+        };
+
+        spawn({
+            let mut ipc_sender = this.ipc_sender.clone();
+            let this = Arc::downgrade(&this);
+
+            async move {
+                loop {
+                    trace!("Polling new transport event");
+                    let event = events.poll_event().await;
+                    trace!("Polled transport event: {event:?}");
+
+                    match event {
+                        Ok(TransportEvent::SendIpc(message)) => {
+                            ipc_sender.send(message).await;
+                        }
+                        Ok(TransportEvent::StartStream { settings }) => {
+                            let Some(this) = this.upgrade() else {
+                                warn!(
+                                    "Failed to get stream connection, stopping listening to events"
+                                );
+                                return;
+                            };
+
+                            let this = this.clone();
+                            spawn(async move {
+                                this.clear_terminate_request().await;
+
+                                // A resumed client negotiates a new transport, the host stream keeps running
+                                if this.reattach_stream().await {
+                                    return;
+                                }
+
+                                if let Err(err) = this.start_stream(settings).await {
+                                    error!("Failed to start stream, stopping: {err}");
+
+                                    this.stop(StreamEndReason::Error).await;
+                                }
+                            });
+                        }
+                        Ok(TransportEvent::BandwidthEstimate { bitrate_kbps }) => {
+                            let Some(this) = this.upgrade() else {
+                                warn!(
+                                    "Failed to get stream connection, stopping listening to events"
+                                );
+                                return;
+                            };
+
+                            this.estimated_bitrate_kbps
+                                .store(bitrate_kbps, Ordering::Relaxed);
+                        }
+                        Ok(TransportEvent::RecvPacket(packet)) => {
+                            let Some(this) = this.upgrade() else {
+                                warn!(
+                                    "Failed to get stream connection, stopping listening to events"
+                                );
+                                return;
+                            };
+
+                            this.on_packet(packet).await;
+                        }
+                        Err(TransportError::Closed) | Ok(TransportEvent::Closed) => {
+                            let Some(this) = this.upgrade() else {
+                                warn!(
+                                    "Failed request session termination because of missing stream (maybe it was already terminated)"
+                                );
+                                return;
+                            };
+
+                            this.request_terminate().await;
+
+                            break;
+                        }
+                        // It wouldn't make sense to return this
+                        Err(TransportError::ChannelClosed) => unreachable!(),
+                        Err(TransportError::Implementation(err)) => {
+                            let Some(this) = this.upgrade() else {
+                                warn!(
+                                    "Failed to get stream connection, stopping listening to events"
+                                );
+                                return;
+                            };
+
+                            info!(
+                                "Stopping stream because of transport implementation error: {err}"
+                            );
+
+                            this.stop(StreamEndReason::Error).await;
+                            break;
+                        }
+                    }
+                }
+            }
+        });
+
+        if let Some(old_transport) = old_transport {
+            spawn(async move {
+                if let Err(err) = old_transport.close().await {
+                    warn!("Failed to close old transport: {err:?}");
+                }
+            });
+        }
+
+        // The audio channels depend on the transport
+        self.send_capabilities().await;
+    }
+
+    /// The capabilities are first queried before a transport is selected, so they're sent again for every new transport
+    async fn send_capabilities(&self) {
+        let supports_surround = self
+            .transport_sender
+            .lock()
+            .await
+            .as_ref()
+            .is_some_and(|sender| sender.supports_surround_audio());
+
+        let mut ipc_sender = self.ipc_sender.clone();
+        ipc_sender
+            .send(StreamerIpcMessage::Capabilities(StreamerCapabilities {
+                video_formats: webrtc::supported_video_formats().bits(),
+                audio_channels: if supports_surround {
+                    SURROUND_71_CHANNEL_COUNT
+                } else {
+                    OpusMultistreamConfig::STEREO.channel_count
+                },
+            }))
+            .await;
+    }
+    async fn send_metrics(&self, metrics: StreamerMetrics) {
+        let mut ipc_sender = self.ipc_sender.clone();
+        ipc_sender.send(StreamerIpcMessage::Metrics(metrics)).await;
+    }
+
+    async fn try_send_packet(&self, packet: OutboundPacket, packet_ty: &str, should_warn: bool) {
+        let mut sender = self.transport_sender.lock().await;
+
+        if let Some(sender) = sender.as_mut() {
+            if let Err(err) = sender.send(packet).await {
+                if should_warn {
+                    warn!("Failed to send outbound packet: {packet_ty}, {err:?}");
+                } else {
+                    debug!("Failed to send outbound packet: {packet_ty}, {err:?}");
+                }
+            }
+        } else {
+            debug!("Dropping packet {packet:?} because no transport is selected!");
+        }
+    }
+
+    async fn on_packet(&self, packet: InboundPacket) {
+        trace!(packet = ?packet, "received packet from client");
+
+        let stream_lock = self.stream.read().await;
+        let Some(stream) = stream_lock.as_ref() else {
+            warn!("Failed to send packet {packet:?} because of missing stream");
+            return;
+        };
+
+        if !matches!(packet, InboundPacket::General { .. }) {
+            self.idle.on_input();
+        }
+
+        let err = match packet {
+            InboundPacket::General { message } => {
+                debug!("General message: {message:?}");
+
+                // currently there are no packets associated with that
+                match message {
+                    GeneralClientMessage::Stop => {
+                        debug!("Received stop from client. Stopping stream now!");
+
+                        drop(stream_lock);
+
+                        self.stop(StreamEndReason::Stopped).await;
+
+                        None
+                    }
+                }
+            }
+            InboundPacket::MousePosition {
+                x,
+                y,
+                reference_width,
+                reference_height,
+            } => stream
+                .send_mouse_position(x, y, reference_width, reference_height)
+                .err(),
+            InboundPacket::MouseButton { action, button } => {
+                stream.send_mouse_button(action, button).err()
+            }
+            InboundPacket::MouseMove { delta_x, delta_y } => {
+                let (delta_x, delta_y) = self.relative_mouse.lock().await.scale(delta_x, delta_y);
+                if delta_x == 0 && delta_y == 0 {
+                    return;
+                }
+
+                stream.send_mouse_move(delta_x, delta_y).err()
+            }
+            InboundPacket::HighResScroll { delta_x, delta_y } => {
+                let mut err = None;
+                if delta_y != 0 {
+                    err = stream.send_high_res_scroll(delta_y).err()
+                }
+                if delta_x != 0 {
+                    err = stream.send_high_res_horizontal_scroll(delta_x).err()
+                }
+                err
+            }
+            InboundPacket::Scroll { delta_x, delta_y } => {
+                let mut err = None;
+                if delta_y != 0 {
+                    err = stream.send_scroll(delta_y).err();
+                }
+                if delta_x != 0 {
+                    err = stream.send_horizontal_scroll(delta_x).err();
+                }
+                err
+            }
+            InboundPacket::Key {
+                action,
+                modifiers,
+                key,
+                flags,
+            } => {
+                let key = self.key_remap.read().await.remap(key);
+
+                stream
+                    .send_keyboard_event_non_standard(key as i16, action, modifiers, flags)
+                    .err()
+            }
+            InboundPacket::Text { text } => stream.send_text(&text).err(),
+            InboundPacket::Touch {
+                pointer_id,
+                x,
+                y,
+                pressure_or_distance,
+                contact_area_major,
+                contact_area_minor,
+                rotation,
+                event_type,
+            } => stream
+                .send_touch(
+                    pointer_id,
+                    x,
+                    y,
+                    pressure_or_distance,
+                    contact_area_major,
+                    contact_area_minor,
+                    rotation,
+                    event_type,
+                )
+                .err(),
+            InboundPacket::ControllerConnected {
+                id,
+                ty,
+                supported_buttons,
+                capabilities,
+            } => {
+                let Some(gamepad) = ActiveGamepads::from_id(id) else {
+                    warn!("Failed to add gamepad because it is out of range: {id}");
+                    return;
+                };
+
+                let mut active_gamepads = self.active_gamepads.write().await;
+
+                active_gamepads.insert(gamepad);
+
+                stream
+                    .send_controller_arrival(
+                        id,
+                        *active_gamepads,
+                        ty,
+                        supported_buttons,
+                        capabilities,
+                    )
+                    .err()
+            }
+            InboundPacket::ControllerDisconnected { id } => {
+                let Some(gamepad) = ActiveGamepads::from_id(id) else {
+                    warn!("Failed to remove gamepad because it is out of range: {id}");
+                    return;
+                };
+
+                let mut active_gamepads = self.active_gamepads.write().await;
+                active_gamepads.remove(gamepad);
+
+                stream
+                    .send_multi_controller(
+                        id,
+                        *active_gamepads,
+                        ControllerButtons::empty(),
+                        0,
+                        0,
+                        0,
+                        0,
+                        0,
+                        0,
+                    )
+                    .err()
+            }
+            InboundPacket::ControllerState {
+                id,
+                buttons,
+                left_trigger,
+                right_trigger,
+                left_stick_x,
+                left_stick_y,
+                right_stick_x,
+                right_stick_y,
+            } => {
+                let Some(gamepad) = ActiveGamepads::from_id(id) else {
+                    warn!("Failed to update gamepad state because it is out of range: {id}");
+                    return;
+                };
+
+                let active_gamepads = self.active_gamepads.read().await;
+                if !active_gamepads.contains(gamepad) {
+                    warn!(
+                        "Failed to send gamepad event for not registered gamepad, gamepad: {id}, currently active: {:?}",
+                        *active_gamepads
+                    );
+                    return;
+                }
+
+                stream
+                    .send_multi_controller(
+                        id,
+                        *active_gamepads,
+                        buttons,
+                        left_trigger,
+                        right_trigger,
+                        left_stick_x,
+                        left_stick_y,
+                        right_stick_x,
+                        right_stick_y,
+                    )
+                    .err()
+            }
+            _ => None,
+        };
+
+        if let Some(err) = err {
+            warn!("Failed to handle packet: {err:?}");
+        }
+    }
+
+    async fn on_ipc_message(self: &Arc<StreamConnection>, mut message: ServerIpcMessage) {
+        match &mut message {
+            ServerIpcMessage::WebSocket(StreamClientMessage::StartStream { settings }) => {
+                // Apply host overrides and restrictions
+                apply_video_mode_to_settings(self.info.video_mode, settings);
+                apply_host_config_to_settings(&self.config.host, settings);
+                apply_permissions_to_settings(&self.permissions, settings);
+                apply_codec_preference_to_settings(&self.config.host, settings);
+                apply_audio_only_to_settings(settings);
+                apply_stream_limits_to_settings(&self.config.limits, settings);
+
+                info!("Applied host config and permissions to settings");
+
+                let mut ipc_sender = self.ipc_sender.clone();
+                ipc_sender
+                    .send(StreamerIpcMessage::WebSocket(
+                        StreamServerMessage::EffectiveStreamSettings {
+                            settings: settings.clone(),
+                        },
+                    ))
+                    .await;
+            }
+            ServerIpcMessage::WebSocket(StreamClientMessage::SetTransport(transport_type)) => {
+                self.clear_terminate_request().await;
+
+                match transport_type {
+                    TransportType::WebRTC if self.permissions.allow_transport_webrtc => {
+                        info!("Trying WebRTC transport");
+
+                        let (sender, events) = match webrtc::new(
+                            &self.config.webrtc,
+                            self.video_frame_queue_size,
+                            self.audio_sample_queue_size,
+                        )
+                        .await
+                        {
+                            Ok(value) => value,
+                            Err(err) => {
+                                error!("Failed to start webrtc transport: {err}");
+                                return;
+                            }
+                        };
+                        self.set_transport(Box::new(sender), Box::new(events)).await;
+                    }
+                    TransportType::WebSocket if self.permissions.allow_transport_websockets => {
+                        info!("Trying Web Socket transport");
+
+                        let (sender, events) = match web_socket::new().await {
+                            Ok(value) => value,
+                            Err(err) => {
+                                error!("Failed to start web socket transport: {err}");
+                                return;
+                            }
+                        };
+                        self.set_transport(Box::new(sender), Box::new(events)).await;
+                    }
+                    transport => {
+                        warn!(
+                            "Client tried to select {transport:?}, but it was specifically disabled in the permissions -> ignoring request."
+                        );
+                    }
+                }
+            }
+            ServerIpcMessage::WebSocket(StreamClientMessage::Clipboard(text)) => {
+                self.paste_clipboard(text).await;
+                return;
+            }
+            ServerIpcMessage::WebSocket(StreamClientMessage::SetMouseConfig(mouse_config)) => {
+                self.relative_mouse.lock().await.set_config(*mouse_config);
+                return;
+            }
+            ServerIpcMessage::WebSocket(StreamClientMessage::SetKeyboardLayout(layout)) => {
+                info!("[Stream]: using the keyboard layout {layout:?}");
+                *self.key_remap.write().await =
+                    KeyRemap::from_host_config(&self.config.host, Some(*layout));
+                return;
+            }
+            ServerIpcMessage::WebSocket(StreamClientMessage::VideoDecodeFailed) => {
+                self.fallback_to_h264().await;
+                return;
+            }
+            ServerIpcMessage::WebSocket(StreamClientMessage::RequestIdr) => {
+                self.request_idr().await;
+                return;
+            }
+            ServerIpcMessage::Stop => {
+                self.stop(StreamEndReason::Stopped).await;
+            }
+            _ => {}
+        }
+
+        let mut sender = self.transport_sender.lock().await;
+        if let Some(sender) = sender.as_mut() {
+            if let Err(err) = sender.on_ipc_message(message).await {
+                warn!("Failed to send ipc message: {err}");
+            }
+        } else {
+            warn!("Failed to process ipc message because of missing transport: {message:?}");
+        }
+    }
+
+    async fn request_idr(&self) {
+        {
+            let mut last_idr_request = self.last_idr_request.lock().await;
+
+            let now = Instant::now();
+            if last_idr_request.is_some_and(|last| now - last < IDR_REQUEST_MIN_INTERVAL) {
+                debug!("[Stream]: ignoring idr request because the last one was too recent");
+
+                let mut ipc_sender = self.ipc_sender.clone();
+                ipc_sender
+                    .send(StreamerIpcMessage::WebSocket(
+                        StreamServerMessage::DebugLog {
+                            message: "A keyframe was already requested, try again in a moment"
+                                .to_string(),
+                            ty: Some(LogMessageType::InformError),
+                        },
+                    ))
+                    .await;
+                return;
+            }
+            *last_idr_request = Some(now);
+        }
+
+        info!("[Stream]: the client requested an idr");
+        self.request_idr.store(true, Ordering::Release);
+    }
+
+    async fn paste_clipboard(&self, text: &str) {
+        let host = &self.config.host;
+        let max_bytes = host
+            .clipboard_max_bytes
+            .unwrap_or(DEFAULT_CLIPBOARD_MAX_BYTES);
+
+        let rejected = if !host.clipboard.unwrap_or(true) {
+            Some("Pasting the clipboard is disabled for this host".to_string())
+        } else if text.len() > max_bytes {
+            Some(format!(
+                "Failed to paste the clipboard because it's too big ({} bytes, at most {max_bytes} bytes)",
+                text.len()
+            ))
+        } else {
+            None
+        };
+        if let Some(message) = rejected {
+            info!("[Stream]: {message}");
+
+            let mut ipc_sender = self.ipc_sender.clone();
+            ipc_sender
+                .send(StreamerIpcMessage::WebSocket(
+                    StreamServerMessage::DebugLog {
+                        message,
+                        ty: Some(LogMessageType::InformError),
+                    },
+                ))
+                .await;
+            return;
+        }
+
+        let stream = self.stream.read().await;
+        let Some(stream) = stream.as_ref() else {
+            warn!("[Stream]: failed to paste the clipboard because of missing stream");
+            return;
+        };
+
+        if let Err(err) = stream.send_text(text) {
+            warn!("[Stream]: failed to paste the clipboard: {err:?}");
+        }
+    }
+
+    /// The audio mode of the stream, surround is only used if the transport supports it
+    async fn audio_mode(&self) -> StreamAudioMode {
+        let audio_mode = self.info.audio_mode;
+
+        let supports_surround = self
+            .transport_sender
+            .lock()
+            .await
+            .as_ref()
+            .is_some_and(|sender| sender.supports_surround_audio());
+        if supports_surround || audio_mode == StreamAudioMode::Stereo {
+            return audio_mode;
+        }
+
+        if audio_mode != StreamAudioMode::MatchHost {
+            info!("[Stream]: the transport doesn't support {audio_mode:?}, using stereo");
+
+            let mut ipc_sender = self.ipc_sender.clone();
+            ipc_sender
+                .send(StreamerIpcMessage::WebSocket(
+                    StreamServerMessage::DebugLog {
+                        message: "Surround audio isn't supported by this transport, using stereo"
+                            .to_string(),
+                        ty: Some(LogMessageType::InformError),
+                    },
+                ))
+                .await;
+        }
+
+        StreamAudioMode::Stereo
+    }
+
+    // Start Moonlight Stream
+    async fn start_stream(self: &Arc<Self>, settings: StreamSettings) -> Result<(), anyhow::Error> {
+        // We might already be streaming -> remove and wait for connection close firstly,
+        // moonlight only allows one connection at a time and the old one must not call back into the new stream
+        let old_stream = self.stream.write().await.take();
+        if let Some(old_stream) = old_stream
+            && let Err(err) = spawn_blocking(move || {
+                old_stream.stop();
+            })
+            .await
+        {
+            warn!("[Stream]: failed to stop the previous moonlight stream: {err}");
+        }
+        info!("Starting Moonlight stream with settings: {settings:?}");
+        self.stream_settings.lock().await.replace(settings.clone());
+
+        // Send stage
+        let mut ipc_sender = self.ipc_sender.clone();
+        ipc_sender
+            .send(StreamerIpcMessage::WebSocket(
+                StreamServerMessage::DebugLog {
+                    message: "Moonlight Stream".to_string(),
+                    ty: None,
+                },
+            ))
+            .await;
+
+        let host = &self.info.host;
+
+        let recording_name = recording_name();
+        let video_decoder = StreamVideoDecoder {
+            stream: Arc::downgrade(self),
+            supported_formats: VideoFormats::from_bits_retain(settings.supported_codecs),
+            audio_only: settings.audio_only,
+            stats: Default::default(),
+            recording_name: recording_name.clone(),
+            recording: None,
+            diagnostics: self
+                .config
+                .frame_diagnostics
+                .clone()
+                .map(FrameDiagnostics::new),
+        };
+
+        let audio_config =
+            audio_mode_config(self.audio_mode().await, self.info.client_audio_channels);
+        let audio_decoder = StreamAudioDecoder {
+            stream: Arc::downgrade(self),
+            audio_config,
+            stats: Default::default(),
+            recording_name,
+            recording: None,
+        };
+
+        let connection_listener = StreamConnectionListener {
+            stream: Arc::downgrade(self),
+        };
+        let connection_listener_c = StreamConnectionListener {
+            stream: Arc::downgrade(self),
+        };
+
+        self.bitrate_kbps
+            .store(settings.bitrate_kbps, Ordering::Relaxed);
+
+        let mut settings = MoonlightStreamSettings {
+            width: settings.width,
+            height: settings.height,
+            fps: settings.fps,
+            fps_x100: settings.fps * 100,
+            hdr: settings.hdr,
+            bitrate: settings.bitrate_kbps,
+            packet_size: 1024,
+            encryption_flags: EncryptionFlags::ALL,
+            streaming_remotely: StreamingConfig::Auto,
+            sops: true,
+            supported_video_formats: VideoFormats::from_bits_truncate(settings.supported_codecs),
+            color_space: ColorSpace::Rec709,
+            color_range: ColorRange::Limited,
+            local_audio_play_mode: settings.play_audio_local,
+            audio_config,
+            gamepads_attached: ActiveGamepads::empty(),
+            gamepads_persist_after_disconnect: false,
+            enable_mic: false,
+        };
+
+        let server_version = host.version().await?;
+        let server_gfe_version = host.gfe_version().await?;
+        let server_codec_mode_support = host.server_codec_mode_support().await?;
+
+        match settings.adjust_for_server(
+            server_version,
+            &server_gfe_version,
+            server_codec_mode_support,
+        ) {
+            Ok(_) => {}
+            Err(StreamConfigError::NotSupportedHdr) => {
+                ipc_sender
+                    .send(StreamerIpcMessage::WebSocket(
+                        StreamServerMessage::DebugLog {
+                            message: "Failed to start stream because this app doesn't support HDR!"
+                                .to_string(),
+                            ty: Some(LogMessageType::FatalDescription),
+                        },
+                    ))
+                    .await;
+                return Err(StreamConfigError::NotSupportedHdr.into());
+            }
+            Err(err) => return Err(err.into()),
+        }
+
+        let aes_key = AesKey::new_random(&OpenSSLCryptoBackend)?;
+        let aes_iv = AesIv::new_random(&OpenSSLCryptoBackend)?;
+
+        let launch_query = format!(
+            "{}{}",
+            self.moonlight.launch_query_parameters(),
+            self.info.launch_query
+        );
+
+        let stream_config = match host
+            .start_stream(self.info.app_id, &settings, aes_key, aes_iv, &launch_query)
+            .await
+        {
+            Ok(value) => value,
+            Err(err) => {
+                warn!("[Stream]: failed to start moonlight stream: {err}");
+
+                #[allow(clippy::single_match)]
+                match err {
+                    MoonlightClientError::Moonlight(MoonlightError::ConnectionAlreadyExists) => {
+                        ipc_sender
+                            .send(StreamerIpcMessage::WebSocket(
+                                StreamServerMessage::DebugLog { message: "Failed to start stream because this streamer is already streaming".to_string(), ty: None },
+                            ))
+                            .await;
+                    }
+                    _ => {}
+                }
+
+                return Err(err.into());
+            }
+        };
+
+        let settings_clone = settings.clone();
+        let moonlight_instance = self.moonlight.clone();
+        let stream = spawn_blocking(move || {
+            moonlight_instance.start_connection(
+                stream_config,
+                settings_clone,
+                connection_listener,
+                connection_listener_c,
+                video_decoder,
+                audio_decoder,
+            )
+        })
+        .await??;
+
+        let (video_setup, audio_setup) = {
+            let setup = self.stream_setup.lock().await;
+
+            let video = setup.video.unwrap_or_else(|| {
+                warn!("failed to query video setup information. Giving the browser guessed information");
+                VideoSetup { format: VideoFormat::H264, width: settings.width, height: settings.height, redraw_rate: settings.fps }
+            });
+
+            let audio = setup.audio.clone().unwrap_or(OpusMultistreamConfig::STEREO);
+
+            (video, audio)
+        };
+
+        info!(
+            "Stream uses these settings: {:?} with {}x{}x{}",
+            video_setup.format, video_setup.width, video_setup.height, video_setup.redraw_rate
+        );
+
+        let message = connection_complete_message(&stream, video_setup, audio_setup);
+        spawn(async move {
+            ipc_sender
+                .send(StreamerIpcMessage::WebSocket(message))
+                .await;
+        });
+
+        let mut stream_guard = self.stream.write().await;
+        stream_guard.replace(stream);
+        self.idle.reset();
+
+        {
+            let mut sender = self.transport_sender.lock().await;
+            match sender.as_mut() {
+                Some(sender) => {
+                    sender.on_setup_complete().await;
+                }
+                None => {
+                    warn!("No transport found after starting stream. Requesting Termination");
+                    self.request_terminate().await;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Sets up the new transport for the moonlight stream which is already running and requests an idr,
+    /// returns false if no stream is running
+    async fn reattach_stream(self: &Arc<Self>) -> bool {
+        let Some(settings) = self.stream_settings.lock().await.clone() else {
+            return false;
+        };
+        let (video_setup, audio_setup) = {
+            let setup = self.stream_setup.lock().await;
+
+            (setup.video, setup.audio_config.zip(setup.audio.clone()))
+        };
+        let (Some(video_setup), Some((audio_config, audio_setup))) = (video_setup, audio_setup)
+        else {
+            return false;
+        };
+
+        let message = {
+            let stream = self.stream.read().await;
+            let Some(stream) = stream.as_ref() else {
+                return false;
+            };
+
+            connection_complete_message(stream, video_setup, audio_setup.clone())
+        };
+
+        info!("[Stream]: reattaching the running stream to the new transport");
+
+        {
+            let sender = self.transport_sender.lock().await;
+            let Some(sender) = sender.as_ref() else {
+                return false;
+            };
+
+            if !settings.audio_only {
+                sender.setup_video(video_setup).await;
+            }
+            sender.setup_audio(audio_config, audio_setup).await;
+        }
+
+        let mut ipc_sender = self.ipc_sender.clone();
+        ipc_sender
+            .send(StreamerIpcMessage::WebSocket(message))
+            .await;
+
+        if let Some(sender) = self.transport_sender.lock().await.as_ref() {
+            sender.on_setup_complete().await;
+        }
+
+        // The new transport has to start with a keyframe
+        self.request_idr.store(true, Ordering::Release);
+
+        true
+    }
+
+    /// Restarts the stream with H264 if the browser couldn't decode the negotiated format, this only happens once
+    async fn fallback_to_h264(self: &Arc<Self>) {
+        if self.video_fallback.swap(true, Ordering::AcqRel) {
+            debug!("[Stream]: already fell back to H264, ignoring the video decode failure");
+            return;
+        }
+
+        let Some(mut settings) = self.stream_settings.lock().await.clone() else {
+            warn!("[Stream]: failed to fall back to H264 because no stream was started");
+            return;
+        };
+        let format = self
+            .stream_setup
+            .lock()
+            .await
+            .video
+            .map(|video| video.format);
+
+        let mut ipc_sender = self.ipc_sender.clone();
+
+        if matches!(format, Some(VideoFormat::H264))
+            || !VideoFormats::from_bits_truncate(settings.supported_codecs)
+                .contains(VideoFormats::H264)
+        {
+            warn!("[Stream]: the browser failed to decode {format:?}, no format to fall back to");
+
+            ipc_sender
+                .send(StreamerIpcMessage::WebSocket(
+                    StreamServerMessage::DebugLog {
+                        message: "The browser failed to decode the video and there's no other video format to fall back to".to_string(),
+                        ty: Some(LogMessageType::InformError),
+                    },
+                ))
+                .await;
+            return;
+        }
+
+        info!("[Stream]: the browser failed to decode {format:?}, restarting the stream with H264");
+
+        ipc_sender
+            .send(StreamerIpcMessage::WebSocket(
+                StreamServerMessage::DebugLog {
+                    message: format!(
+                        "The browser failed to decode {format:?}, restarting the stream with H264"
+                    ),
+                    ty: None,
+                },
+            ))
+            .await;
+
+        settings.supported_codecs = VideoFormats::H264.bits();
+
+        if let Err(err) = self.start_stream(settings).await {
+            error!("Failed to restart stream with H264, stopping: {err}");
+
+            self.stop(StreamEndReason::Error).await;
+        }
+    }
+
+    // -- Termination
+    async fn request_terminate(self: &Arc<Self>) {
+        debug!("Marking for termination");
+
+        let this = self.clone();
+
+        // The client might reconnect with a new transport after resuming the stream
+        let timeout = self
+            .config
+            .resume_timeout
+            .map_or(TIMEOUT_DURATION, |resume_timeout| {
+                resume_timeout.max(TIMEOUT_DURATION)
+            });
+
+        let mut terminate_request = self.timeout_terminate_request.lock().await;
+        *terminate_request = Some(Instant::now());
+        drop(terminate_request);
+
+        spawn(async move {
+            sleep(timeout + Duration::from_millis(200)).await;
+
+            let now = Instant::now();
+
+            let terminate_request = this.timeout_terminate_request.lock().await;
+            if let Some(terminate_request) = *terminate_request
+                && (now - terminate_request) > timeout
+            {
+                info!("Stopping because of timeout");
+
+                this.stop(StreamEndReason::ConnectionLost).await;
+            }
+        });
+    }
+    async fn clear_terminate_request(&self) {
+        debug!("Clearing termination timeout");
+
+        let mut request = self.timeout_terminate_request.lock().await;
+
+        *request = None;
+    }
+
+    async fn stop(&self, reason: StreamEndReason) {
+        if self
+            .is_terminating
+            .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            debug!("[Stream]: stream is already terminating, won't stop twice");
+            return;
+        }
+
+        debug!("[Stream]: Stopping because of {reason:?}...");
+
+        let stream = self.stream.write().await.take();
+        if let Some(stream) = stream {
+            // Wait until the moonlight stream is fully stopped before acknowledging the stop
+            if let Err(err) = spawn_blocking(move || {
+                stream.stop();
+            })
+            .await
+            {
+                warn!("[Stream]: failed to stop moonlight stream: {err}");
+            }
+        }
+
+        let mut transport = self.transport_sender.lock().await;
+        if let Some(transport) = transport.take() {
+            if let Err(err) = transport.close().await {
+                warn!("Error whilst closing transport: {err}");
+            }
+            drop(transport);
+        }
+
+        // This is the acknowledgement for the web server that everything is stopped
+        let mut ipc_sender = self.ipc_sender.clone();
+        ipc_sender.send(StreamerIpcMessage::Stop { reason }).await;
+
+        debug!("Notifying termination");
+        self.terminate.notify_waiters();
+    }
+}
+
+/// Stops the stream once it's idle, this doesn't apply while no stream is running
+async fn watch_idle_timeout(stream: Weak<StreamConnection>, config: StreamIdleTimeoutConfig) {
+    loop {
+        sleep(IDLE_CHECK_INTERVAL).await;
+
+        let Some(stream) = stream.upgrade() else {
+            return;
+        };
+        if stream.is_terminating.load(Ordering::Acquire) {
+            return;
+        }
+        if stream.stream.read().await.is_none() {
+            continue;
+        }
+
+        if let Some(reason) = stream.idle.idle_reason(&config) {
+            info!("[Stream]: stopping because the stream is idle: {reason}");
+
+            stream.stop(StreamEndReason::IdleTimeout).await;
+            return;
+        }
+    }
+}
+
+/// Tells the browser how to set up its decoders for the stream
+fn connection_complete_message(
+    stream: &MoonlightStream,
+    video_setup: VideoSetup,
+    audio_setup: OpusMultistreamConfig,
+) -> StreamServerMessage {
+    let host_features = stream.host_features().unwrap_or_else(|err| {
+        warn!("[Stream]: failed to get host features: {err:?}");
+        HostFeatures::default()
+    });
+
+    StreamServerMessage::ConnectionComplete {
+        capabilities: StreamCapabilities {
+            touch: host_features.controller_touch,
+        },
+        format: video_setup.format as u32,
+        width: video_setup.width,
+        height: video_setup.height,
+        fps: video_setup.redraw_rate,
+        audio_sample_rate: audio_setup.sample_rate,
+        audio_channel_count: audio_setup.channel_count,
+        audio_streams: audio_setup.streams,
+        audio_coupled_streams: audio_setup.coupled_streams,
+        audio_samples_per_frame: audio_setup.samples_per_frame,
+        audio_mapping: audio_setup.mapping,
+    }
+}
+
+struct StreamConnectionListener {
+    stream: Weak<StreamConnection>,
+}
+
+impl ConnectionListener for StreamConnectionListener {
+    fn set_hdr_mode(&mut self, hdr_enabled: bool, sunshine: Option<SunshineHdrMetadata>) {
+        info!(
+            "[HDR] Host called set_hdr_mode with enabled={}",
+            hdr_enabled
+        );
+
+        let metadata = sunshine.map(HdrMetadata::from);
+        if hdr_enabled && metadata.is_none() {
+            info!("[HDR] Host didn't send hdr metadata, the browser will use its defaults");
+        }
+
+        let Some(stream) = self.stream.upgrade() else {
+            warn!("Failed to get stream because it is already deallocated");
+            return;
+        };
+
+        stream.clone().runtime.block_on(async move {
+            info!("[HDR] Sending HdrModeUpdate to client");
+            stream
+                .try_send_packet(
+                    OutboundPacket::General {
+                        message: GeneralServerMessage::HdrModeUpdate {
+                            enabled: hdr_enabled,
+                            metadata,
+                        },
+                    },
+                    "hdr mode update",
+                    true,
+                )
+                .await
+        })
+    }
+
+    fn controller_rumble(
+        &mut self,
+        controller_number: u16,
+        low_frequency_motor: u16,
+        high_frequency_motor: u16,
+    ) {
+        let Some(stream) = self.stream.upgrade() else {
+            warn!("Failed to get stream because it is already deallocated");
+            return;
+        };
+
+        stream.runtime.clone().block_on(async move {
+            stream
+                .try_send_packet(
+                    OutboundPacket::ControllerRumble {
+                        controller_number: controller_number as u8,
+                        low_frequency_motor,
+                        high_frequency_motor,
+                    },
+                    "controller rumble",
+                    true,
+                )
+                .await;
+        });
+    }
+
+    fn controller_rumble_triggers(
+        &mut self,
+        controller_number: u16,
+        left_trigger_motor: u16,
+        right_trigger_motor: u16,
+    ) {
+        let Some(stream) = self.stream.upgrade() else {
+            warn!("Failed to get stream because it is already deallocated");
+            return;
+        };
+
+        stream.runtime.clone().block_on(async move {
+            stream
+                .try_send_packet(
+                    OutboundPacket::ControllerTriggerRumble {
+                        controller_number: controller_number as u8,
+                        left_trigger_motor,
+                        right_trigger_motor,
+                    },
+                    "controller rumble triggers",
+                    true,
+                )
+                .await;
+        });
+    }
+
+    fn controller_set_motion_event_state(
+        &mut self,
+        _controller_number: u16,
+        _motion_type: u8,
+        _report_rate_hz: u16,
+    ) {
+        // unsupported: https://github.com/w3c/gamepad/issues/211
+    }
+
+    fn controller_set_adaptive_triggers(
+        &mut self,
+        _controller_number: u16,
+        _event_flags: u8,
+        _type_left: u8,
+        _type_right: u8,
+        _left: &mut u8,
+        _right: &mut u8,
+    ) {
+        // unsupported
+    }
+
+    fn controller_set_led(&mut self, _controller_number: u16, _r: u8, _g: u8, _b: u8) {
+        // unsupported
+    }
+}
+
+impl ConnectionListenerC for StreamConnectionListener {
+    fn stage_starting(&mut self, stage: Stage) {
+        let Some(stream) = self.stream.upgrade() else {
+            warn!("Failed to get stream because it is already deallocated");
+            return;
+        };
+
+        let mut ipc_sender = stream.ipc_sender.clone();
+
+        stream.runtime.spawn(async move {
+            ipc_sender
+                .send(StreamerIpcMessage::WebSocket(
+                    StreamServerMessage::stage_starting(
+                        StreamStage::HostConnection,
+                        format!("Starting Stage: {}", stage.name()),
+                    ),
+                ))
+                .await;
+        });
+    }
+
+    fn stage_complete(&mut self, stage: Stage) {
+        let Some(stream) = self.stream.upgrade() else {
+            warn!("Failed to get stream because it is already deallocated");
+            return;
+        };
+
+        let mut ipc_sender = stream.ipc_sender.clone();
+        ipc_sender.blocking_send(StreamerIpcMessage::WebSocket(
+            StreamServerMessage::stage_completed(
+                StreamStage::HostConnection,
+                format!("Completed Stage: {}", stage.name()),
+            ),
+        ));
+    }
+
+    fn stage_failed(&mut self, stage: Stage, error_code: i32) {
+        let Some(stream) = self.stream.upgrade() else {
+            warn!("Failed to get stream because it is already deallocated");
+            return;
+        };
+
+        let mut ipc_sender = stream.ipc_sender.clone();
+        ipc_sender.blocking_send(StreamerIpcMessage::WebSocket(
+            StreamServerMessage::stage_failed(
+                StreamStage::HostConnection,
+                StreamStageError::HostConnectionFailed,
+                format!(
+                    "Failed Stage: {} with error code {}",
+                    stage.name(),
+                    error_code
+                ),
+                LogMessageType::Fatal,
+            ),
+        ));
+    }
+
+    fn connection_started(&mut self) {}
+
+    fn connection_terminated(&mut self, error_code: i32) {
+        let Some(stream) = self.stream.upgrade() else {
+            warn!("Failed to get stream because it is already deallocated");
+            return;
+        };
+
+        let mut ipc_sender = stream.ipc_sender.clone();
+        ipc_sender.blocking_send(StreamerIpcMessage::WebSocket(
+            StreamServerMessage::ConnectionTerminated { error_code },
+        ));
+
+        // Moonlight reports 0 if the host ended the stream gracefully
+        let reason = if error_code == 0 {
+            StreamEndReason::HostEnded
+        } else {
+            StreamEndReason::Error
+        };
+        stream.runtime.clone().block_on(async move {
+            stream.stop(reason).await;
+        });
+    }
+
+    fn log_message(&mut self, message: &str) {
+        info!(target: "moonlight", "{}", message.trim());
+    }
+
+    fn connection_status_update(&mut self, status: ConnectionStatus) {
+        let Some(stream) = self.stream.upgrade() else {
+            warn!("Failed to get stream because it is already deallocated");
+            return;
+        };
+
+        stream.clone().runtime.block_on(async move {
+            stream
+                .try_send_packet(
+                    OutboundPacket::General {
+                        message: GeneralServerMessage::ConnectionStatusUpdate {
+                            status: status.into(),
+                        },
+                    },
+                    "connection status update",
+                    true,
+                )
+                .await
+        })
+    }
+}
@@ -0,0 +1,62 @@
+use common::api_bindings::MouseConfig;
+use log::warn;
+
+/// At this speed in pixels per movement event an acceleration of 1 doubles the movement
+const ACCELERATION_REFERENCE_SPEED: f64 = 50.0;
+
+/// Applies the sensitivity and acceleration of the client to relative mouse movement.
+/// The fractional part of each movement is kept so slow movements don't get lost.
+#[derive(Debug)]
+pub struct RelativeMouse {
+    config: MouseConfig,
+    remainder_x: f64,
+    remainder_y: f64,
+}
+
+impl Default for RelativeMouse {
+    fn default() -> Self {
+        Self {
+            config: MouseConfig {
+                sensitivity: 1.0,
+                acceleration: 0.0,
+            },
+            remainder_x: 0.0,
+            remainder_y: 0.0,
+        }
+    }
+}
+
+impl RelativeMouse {
+    pub fn set_config(&mut self, config: MouseConfig) {
+        if !config.sensitivity.is_finite()
+            || config.sensitivity <= 0.0
+            || !config.acceleration.is_finite()
+            || config.acceleration < 0.0
+        {
+            warn!("[Mouse]: ignoring invalid mouse config {config:?}");
+            return;
+        }
+
+        self.config = config;
+        self.remainder_x = 0.0;
+        self.remainder_y = 0.0;
+    }
+
+    pub fn scale(&mut self, delta_x: i16, delta_y: i16) -> (i16, i16) {
+        let (delta_x, delta_y) = (delta_x as f64, delta_y as f64);
+
+        let speed = delta_x.hypot(delta_y);
+        let factor = self.config.sensitivity
+            * (1.0 + self.config.acceleration * speed / ACCELERATION_REFERENCE_SPEED);
+
+        let x = delta_x * factor + self.remainder_x;
+        let y = delta_y * factor + self.remainder_y;
+        self.remainder_x = x.fract();
+        self.remainder_y = y.fract();
+
+        (
+            x.trunc().clamp(i16::MIN as f64, i16::MAX as f64) as i16,
+            y.trunc().clamp(i16::MIN as f64, i16::MAX as f64) as i16,
+        )
+    }
+}
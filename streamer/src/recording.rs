@@ -0,0 +1,160 @@
+use std::{
+    fs::{self, File},
+    io::{BufWriter, Write},
+    path::PathBuf,
+    process,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use common::config::StreamRecordingConfig;
+use log::{info, warn};
+use moonlight_common::stream::video::VideoFormat;
+
+/// The file name without extension shared by the video and audio recording of a stream
+pub fn recording_name() -> String {
+    let unix_secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|time| time.as_secs())
+        .unwrap_or_default();
+
+    format!("stream-{unix_secs}-{}", process::id())
+}
+
+/// The elementary stream of the format is written as is, so the extension tells players how to read it
+pub fn video_extension(format: VideoFormat) -> &'static str {
+    match format {
+        VideoFormat::H264 | VideoFormat::H264High8_444 => "h264",
+        VideoFormat::H265
+        | VideoFormat::H265Main10
+        | VideoFormat::H265Rext8_444
+        | VideoFormat::H265Rext10_444 => "h265",
+        VideoFormat::Av1Main8
+        | VideoFormat::Av1Main10
+        | VideoFormat::Av1High8_444
+        | VideoFormat::Av1High10_444 => "obu",
+    }
+}
+
+/// Writes what the host sent into a file until the size or duration limit of the config is reached
+pub struct RecordingFile {
+    path: PathBuf,
+    writer: Option<BufWriter<File>>,
+    written_bytes: u64,
+    started: Instant,
+    max_bytes: u64,
+    max_duration: Duration,
+}
+
+impl RecordingFile {
+    /// Creates `<directory>/<name>.<extension>`
+    pub fn create(config: &StreamRecordingConfig, name: &str, extension: &str) -> Option<Self> {
+        let path = PathBuf::from(&config.directory).join(format!("{name}.{extension}"));
+
+        let file = match fs::create_dir_all(&config.directory).and_then(|()| File::create(&path)) {
+            Ok(file) => file,
+            Err(err) => {
+                warn!(
+                    "[Recording]: failed to create recording file {}: {err}",
+                    path.display()
+                );
+                return None;
+            }
+        };
+        info!("[Recording]: recording to {}", path.display());
+
+        Some(Self {
+            path,
+            writer: Some(BufWriter::new(file)),
+            written_bytes: 0,
+            started: Instant::now(),
+            max_bytes: config.max_bytes,
+            max_duration: config.max_duration,
+        })
+    }
+
+    /// Writes the buffers after each other, e.g. the annex b nal units of a video frame
+    pub fn write_buffers<'a>(&mut self, buffers: impl IntoIterator<Item = &'a [u8]> + Clone) {
+        let len = buffers.clone().into_iter().map(|buffer| buffer.len()).sum();
+        let Some(writer) = self.writer(len) else {
+            return;
+        };
+
+        let result = buffers
+            .into_iter()
+            .try_for_each(|buffer| writer.write_all(buffer));
+        if let Err(err) = result {
+            self.fail(err);
+        }
+    }
+
+    /// Writes the packet prefixed by its length as a big endian u32 because opus packets don't contain their length
+    pub fn write_packet(&mut self, packet: &[u8]) {
+        let Some(writer) = self.writer(4 + packet.len()) else {
+            return;
+        };
+
+        let result = writer
+            .write_all(&(packet.len() as u32).to_be_bytes())
+            .and_then(|()| writer.write_all(packet));
+        if let Err(err) = result {
+            self.fail(err);
+        }
+    }
+
+    /// The writer if the limits still allow writing `len` bytes
+    fn writer(&mut self, len: usize) -> Option<&mut BufWriter<File>> {
+        self.writer.as_ref()?;
+
+        if self.written_bytes + len as u64 > self.max_bytes {
+            info!(
+                "[Recording]: {} reached the maximum size",
+                self.path.display()
+            );
+            self.finish();
+            return None;
+        }
+        if self.started.elapsed() > self.max_duration {
+            info!(
+                "[Recording]: {} reached the maximum duration",
+                self.path.display()
+            );
+            self.finish();
+            return None;
+        }
+
+        self.written_bytes += len as u64;
+        self.writer.as_mut()
+    }
+
+    fn fail(&mut self, err: std::io::Error) {
+        warn!(
+            "[Recording]: failed to write to {}, stopping the recording: {err}",
+            self.path.display()
+        );
+        self.writer = None;
+    }
+
+    fn finish(&mut self) {
+        let Some(mut writer) = self.writer.take() else {
+            return;
+        };
+
+        match writer.flush() {
+            Ok(()) => info!(
+                "[Recording]: finished {} with {} bytes",
+                self.path.display(),
+                self.written_bytes
+            ),
+            Err(err) => warn!(
+                "[Recording]: failed to write to {}: {err}",
+                self.path.display()
+            ),
+        }
+    }
+}
+
+impl Drop for RecordingFile {
+    fn drop(&mut self) {
+        self.finish();
+    }
+}
@@ -1,4 +1,4 @@
-use std::ops::Range;
+use std::{ops::Range, time::Duration};
 
 use async_trait::async_trait;
 use common::{
@@ -25,6 +25,9 @@ use crate::buffer::ByteBuffer;
 pub mod web_socket;
 pub mod webrtc;
 
+/// How long to wait after an rtt response before sending the next rtt packet to the browser
+pub const RTT_PING_INTERVAL: Duration = Duration::from_millis(200);
+
 /// Look at TransportChannelId
 #[derive(Debug, Clone, Copy)]
 pub struct TransportChannel(pub u8);
@@ -609,9 +612,15 @@ impl OutboundPacket {
 
 #[derive(Debug)]
 pub enum TransportEvent {
-    StartStream { settings: StreamSettings },
+    StartStream {
+        settings: StreamSettings,
+    },
     RecvPacket(InboundPacket),
     SendIpc(StreamerIpcMessage),
+    /// The browser estimated how much bandwidth is available
+    BandwidthEstimate {
+        bitrate_kbps: u32,
+    },
     Closed,
 }
 
@@ -635,6 +644,8 @@ pub trait TransportSender {
         stream_config: OpusMultistreamConfig,
     ) -> i32;
     async fn send_audio_sample(&self, data: &[u8]) -> Result<(), TransportError>;
+    /// The transport can send more than two audio channels to the browser
+    fn supports_surround_audio(&self) -> bool;
 
     async fn on_setup_complete(&self);
 
@@ -3,7 +3,7 @@ use std::{
         Arc,
         atomic::{AtomicBool, Ordering},
     },
-    time::{Duration, Instant},
+    time::Instant,
 };
 
 use async_trait::async_trait;
@@ -29,9 +29,10 @@ use tokio::{
 use crate::{
     buffer::ByteBuffer,
     transport::{
-        InboundPacket, OutboundPacket, TransportChannel, TransportError, TransportEvent,
-        TransportEvents, TransportSender,
+        InboundPacket, OutboundPacket, RTT_PING_INTERVAL, TransportChannel, TransportError,
+        TransportEvent, TransportEvents, TransportSender,
     },
+    video::decode_unit_len,
 };
 
 pub async fn new() -> Result<(WebSocketTransportSender, WebSocketTransportEvents), anyhow::Error> {
@@ -140,7 +141,7 @@ async fn recv_rtt(
     }
 
     // Wait a few ms
-    sleep(Duration::from_millis(200)).await;
+    sleep(RTT_PING_INTERVAL).await;
 
     sequence_number += 1;
     {
@@ -164,7 +165,10 @@ impl TransportSender for WebSocketTransportSender {
         &'a self,
         unit: VideoDecodeUnit<&'a [u8]>,
     ) -> Result<DecodeResult, TransportError> {
-        let mut new_buffer = vec![0; 6];
+        const HEADER_LEN: usize = 6;
+
+        let mut new_buffer = Vec::with_capacity(HEADER_LEN + decode_unit_len(&unit));
+        new_buffer.resize(HEADER_LEN, 0);
 
         let mut byte_buffer = ByteBuffer::new(new_buffer.as_mut_slice());
         byte_buffer.put_u8(TransportChannelId::HOST_VIDEO);
@@ -229,6 +233,10 @@ impl TransportSender for WebSocketTransportSender {
 
         Ok(())
     }
+    fn supports_surround_audio(&self) -> bool {
+        // The browser decodes the opus multistream itself
+        true
+    }
 
     async fn send(&self, packet: OutboundPacket) -> Result<(), TransportError> {
         send_packet(&self.event_sender, packet).await
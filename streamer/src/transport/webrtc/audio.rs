@@ -1,6 +1,7 @@
 use std::{sync::Weak, time::Duration};
 
 use bytes::Bytes;
+use common::config::FrameQueuePolicy;
 use log::{error, warn};
 use moonlight_common::stream::audio::{AudioConfig, OpusMultistreamConfig};
 use tokio::runtime::Handle;
@@ -39,9 +40,14 @@ pub struct WebRtcAudio {
 }
 
 impl WebRtcAudio {
-    pub fn new(runtime: Handle, peer: Weak<RTCPeerConnection>, channel_queue_size: usize) -> Self {
+    pub fn new(
+        runtime: Handle,
+        peer: Weak<RTCPeerConnection>,
+        channel_queue_size: usize,
+        queue_policy: FrameQueuePolicy,
+    ) -> Self {
         Self {
-            sender: TrackLocalSender::new(runtime, peer, channel_queue_size),
+            sender: TrackLocalSender::new(runtime, peer, channel_queue_size, queue_policy),
             config: None,
         }
     }
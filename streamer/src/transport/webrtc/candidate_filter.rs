@@ -0,0 +1,48 @@
+use std::net::IpAddr;
+
+use common::config::{WebRtcCandidateFilterConfig, WebRtcIceCandidateType};
+
+/// Checks a candidate line like `candidate:1 1 udp 2130706431 192.168.1.20 50000 typ host` against the filter.
+/// Addresses which aren't ips, e.g. mDNS names, are only checked by their type.
+pub fn is_candidate_allowed(filter: &WebRtcCandidateFilterConfig, candidate: &str) -> bool {
+    let fields = candidate
+        .trim_start_matches("candidate:")
+        .split_whitespace()
+        .collect::<Vec<_>>();
+
+    if let Some(candidate_types) = &filter.candidate_types {
+        let ty = fields
+            .iter()
+            .position(|field| *field == "typ")
+            .and_then(|index| fields.get(index + 1))
+            .and_then(|ty| parse_candidate_type(ty));
+
+        if !ty.is_some_and(|ty| candidate_types.contains(&ty)) {
+            return false;
+        }
+    }
+
+    let Some(address) = fields
+        .get(4)
+        .and_then(|address| address.parse::<IpAddr>().ok())
+    else {
+        return true;
+    };
+
+    match address {
+        IpAddr::V4(address) => !(filter.exclude_link_local && address.is_link_local()),
+        IpAddr::V6(address) => {
+            !filter.exclude_ipv6 && !(filter.exclude_link_local && address.is_unicast_link_local())
+        }
+    }
+}
+
+fn parse_candidate_type(value: &str) -> Option<WebRtcIceCandidateType> {
+    match value {
+        "host" => Some(WebRtcIceCandidateType::Host),
+        "srflx" => Some(WebRtcIceCandidateType::Srflx),
+        "prflx" => Some(WebRtcIceCandidateType::Prflx),
+        "relay" => Some(WebRtcIceCandidateType::Relay),
+        _ => None,
+    }
+}
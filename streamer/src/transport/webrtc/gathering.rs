@@ -0,0 +1,94 @@
+use std::{
+    net::{Ipv4Addr, SocketAddr},
+    sync::Arc,
+    time::Duration,
+};
+
+use anyhow::bail;
+use common::config::PortRange;
+use tokio::{net::UdpSocket, task::JoinSet, time::timeout};
+use webrtc::{
+    ice::{
+        url::{ProtoType, SchemeType, Url},
+        util::get_xormapped_addr,
+    },
+    util::Conn,
+};
+
+#[derive(Debug, Clone)]
+pub struct IceServerProbe {
+    pub url: String,
+    pub responded: bool,
+}
+
+/// Sends a stun binding request to every stun and turn server reachable over udp, like the ice agent does while gathering.
+/// Every server gets at most `deadline` to respond, including the dns lookup.
+/// The probes are sent from the `port_range`, so they pass the same firewall rules as the ice agent.
+pub async fn probe_ice_servers(
+    urls: &[String],
+    port_range: Option<PortRange>,
+    deadline: Duration,
+) -> Vec<IceServerProbe> {
+    let mut probes = JoinSet::new();
+
+    for raw_url in urls {
+        let Ok(url) = Url::parse_url(raw_url) else {
+            continue;
+        };
+        if !matches!(url.scheme, SchemeType::Stun | SchemeType::Turn)
+            || !matches!(url.proto, ProtoType::Udp)
+        {
+            continue;
+        }
+
+        let raw_url = raw_url.clone();
+        probes.spawn(async move {
+            let responded = matches!(
+                timeout(deadline, probe(&url, port_range, deadline)).await,
+                Ok(Ok(()))
+            );
+
+            IceServerProbe {
+                url: raw_url,
+                responded,
+            }
+        });
+    }
+
+    let mut results = probes.join_all().await;
+    results.sort_by(|a, b| a.url.cmp(&b.url));
+
+    results
+}
+
+async fn probe(
+    url: &Url,
+    port_range: Option<PortRange>,
+    deadline: Duration,
+) -> Result<(), anyhow::Error> {
+    let server_addr = tokio::net::lookup_host((url.host.as_str(), url.port))
+        .await?
+        .find(SocketAddr::is_ipv4)
+        .ok_or_else(|| anyhow::anyhow!("no ipv4 address found for {}", url.host))?;
+
+    let conn: Arc<dyn Conn + Send + Sync> = Arc::new(bind_probe_socket(port_range).await?);
+
+    get_xormapped_addr(&conn, server_addr, deadline).await?;
+
+    Ok(())
+}
+
+/// Binds to the first free port of the range, the ice agent already uses some of them
+async fn bind_probe_socket(port_range: Option<PortRange>) -> Result<UdpSocket, anyhow::Error> {
+    let Some(PortRange { min, max }) = port_range else {
+        return Ok(UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).await?);
+    };
+
+    for port in min..=max {
+        if let Ok(socket) = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, port)).await {
+            return Ok(socket);
+        }
+    }
+
+    bail!("no free port in webrtc.port_range {min} to {max}")
+}
@@ -1,24 +1,33 @@
 use std::{
+    collections::HashMap,
+    fs,
     future::ready,
+    io,
     pin::Pin,
-    sync::{Arc, Weak},
+    sync::{
+        Arc, Weak,
+        atomic::{AtomicBool, AtomicU32, Ordering},
+    },
     time::{Duration, Instant},
 };
 
+use anyhow::anyhow;
 use async_trait::async_trait;
 use bytes::Bytes;
 use common::{
     api_bindings::{
-        RtcIceCandidate, RtcSdpType, RtcSessionDescription, StreamClientMessage,
-        StreamServerMessage, StreamSignalingMessage, TransportChannelId,
+        LogMessageType, RtcIceCandidate, RtcSdpType, RtcSessionDescription, StreamClientMessage,
+        StreamServerMessage, StreamSignalingMessage, StreamStage, StreamStageError,
+        StreamerStatsUpdate, TransportChannelId,
     },
-    config::{PortRange, WebRtcConfig},
-    ipc::{ServerIpcMessage, StreamerIpcMessage},
+    config::{PortRange, WebRtcCandidateFilterConfig, WebRtcConfig},
+    ipc::{ServerIpcMessage, StreamerIpcMessage, StreamerMetrics},
 };
 use moonlight_common::stream::{
     audio::{AudioConfig, OpusMultistreamConfig},
     video::{DecodeResult, VideoDecodeUnit, VideoFormats, VideoSetup},
 };
+use rcgen::KeyPair;
 use tokio::{
     runtime::Handle,
     spawn,
@@ -28,7 +37,7 @@ use tokio::{
     },
     time::sleep,
 };
-use tracing::{debug, error, trace, warn};
+use tracing::{debug, error, info, trace, warn};
 use webrtc::{
     api::{
         APIBuilder, interceptor_registry::register_default_interceptors, media_engine::MediaEngine,
@@ -41,27 +50,36 @@ use webrtc::{
     ice::udp_network::{EphemeralUDP, UDPNetwork},
     ice_transport::{
         ice_candidate::{RTCIceCandidate, RTCIceCandidateInit},
+        ice_candidate_type::RTCIceCandidateType,
         ice_connection_state::RTCIceConnectionState,
+        ice_gathering_state::RTCIceGatheringState,
     },
     interceptor::registry::Registry,
     peer_connection::{
         RTCPeerConnection,
+        certificate::RTCCertificate,
         configuration::RTCConfiguration,
+        offer_answer_options::RTCOfferOptions,
         peer_connection_state::RTCPeerConnectionState,
+        policy::ice_transport_policy::RTCIceTransportPolicy,
         sdp::{sdp_type::RTCSdpType, session_description::RTCSessionDescription},
     },
+    stats::StatsReportType,
 };
 
 use crate::{
     TIMEOUT_DURATION,
     convert::{
         from_webrtc_sdp, into_webrtc_ice, into_webrtc_ice_candidate, into_webrtc_network_type,
+        into_webrtc_srtp_protection_profile,
     },
     transport::{
-        InboundPacket, OutboundPacket, TransportChannel, TransportError, TransportEvent,
-        TransportEvents, TransportSender,
+        InboundPacket, OutboundPacket, RTT_PING_INTERVAL, TransportChannel, TransportError,
+        TransportEvent, TransportEvents, TransportSender,
         webrtc::{
             audio::{WebRtcAudio, register_audio_codecs},
+            candidate_filter::is_candidate_allowed,
+            gathering::probe_ice_servers,
             sender::register_header_extensions,
             video::{WebRtcVideo, register_video_codecs},
         },
@@ -69,47 +87,120 @@ use crate::{
 };
 
 mod audio;
+mod candidate_filter;
+mod gathering;
 mod sender;
 mod video;
 
+pub use video::supported_video_formats;
+
 struct WebRtcInner {
     peer: Arc<RTCPeerConnection>,
     event_sender: Sender<TransportEvent>,
     general_channel: Arc<RTCDataChannel>,
     stats_channel: Arc<RTCDataChannel>,
+    rtt_channel: Arc<RTCDataChannel>,
+    /// Time when it was sent, sequence_number
+    rtt: Mutex<(Instant, u16)>,
     input_channels: Mutex<Vec<Arc<RTCDataChannel>>>,
     video: Mutex<WebRtcVideo>,
     audio: Mutex<WebRtcAudio>,
+    // Negotiation
+    negotiation_timeout: Duration,
+    ice_restart_attempts: u32,
+    connected: AtomicBool,
+    candidate_filter: WebRtcCandidateFilterConfig,
+    ice_server_urls: Vec<String>,
+    ice_gathering_timeout: Duration,
+    gathered_candidates: AtomicU32,
+    port_range: Option<PortRange>,
+    // Stats
+    stats_interval: Duration,
     // Timeout / Terminate
     pub timeout_terminate_request: Mutex<Option<Instant>>,
 }
 
+/// Loads the dtls certificate or generates and saves a new one if the file doesn't exist
+fn load_dtls_certificate(path: &str) -> Result<RTCCertificate, anyhow::Error> {
+    let certificate = match fs::read_to_string(path) {
+        Ok(pem) => RTCCertificate::from_pem(&pem)?,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {
+            let certificate = RTCCertificate::from_key_pair(KeyPair::generate()?)?;
+            fs::write(path, certificate.serialize_pem())?;
+
+            info!("[Stream]: generated a new dtls certificate at {path}");
+            certificate
+        }
+        Err(err) => return Err(err.into()),
+    };
+
+    for fingerprint in certificate.get_fingerprints() {
+        debug!(
+            "[Stream]: dtls certificate fingerprint {} {}",
+            fingerprint.algorithm, fingerprint.value
+        );
+    }
+
+    Ok(certificate)
+}
+
 pub async fn new(
     config: &WebRtcConfig,
     video_frame_queue_size: usize,
     audio_sample_queue_size: usize,
 ) -> Result<(WebRTCTransportSender, WebRTCTransportEvents), anyhow::Error> {
     // -- Configure WebRTC
+    let certificates = match &config.dtls_certificate {
+        Some(path) => match load_dtls_certificate(path) {
+            Ok(certificate) => vec![certificate],
+            Err(err) => {
+                warn!(
+                    "[Stream]: failed to load the dtls certificate {path}, using a new one: {err}"
+                );
+                Vec::new()
+            }
+        },
+        None => Vec::new(),
+    };
+
     let rtc_config = RTCConfiguration {
+        certificates,
         ice_servers: config
             .ice_servers
             .clone()
             .into_iter()
             .map(into_webrtc_ice)
             .collect(),
+        ice_transport_policy: if config.force_relay {
+            RTCIceTransportPolicy::Relay
+        } else {
+            RTCIceTransportPolicy::All
+        },
         ..Default::default()
     };
+
+    if config.force_relay {
+        let has_turn_server = config.ice_servers.iter().any(|server| {
+            server
+                .urls
+                .iter()
+                .any(|url| url.starts_with("turn:") || url.starts_with("turns:"))
+        });
+        if has_turn_server {
+            info!("[Stream]: Forcing all WebRTC traffic over the configured turn servers");
+        } else {
+            warn!(
+                "[Stream]: webrtc.force_relay is enabled but no turn server is configured, the connection will fail"
+            );
+        }
+    }
     let mut api_settings = SettingEngine::default();
 
     if let Some(PortRange { min, max }) = config.port_range {
-        match EphemeralUDP::new(min, max) {
-            Ok(udp) => {
-                api_settings.set_udp_network(UDPNetwork::Ephemeral(udp));
-            }
-            Err(err) => {
-                warn!("[Stream]: Invalid port range in config: {err:?}");
-            }
-        }
+        // Falling back to random ports would silently break firewalls and port forwards
+        let udp = EphemeralUDP::new(min, max)
+            .map_err(|err| anyhow!("invalid webrtc.port_range {min} to {max}: {err}"))?;
+        api_settings.set_udp_network(UDPNetwork::Ephemeral(udp));
     }
     if let Some(mapping) = config.nat_1to1.as_ref() {
         api_settings.set_nat_1to1_ips(
@@ -128,6 +219,16 @@ pub async fn new(
 
     api_settings.set_include_loopback_candidate(config.include_loopback_candidates);
 
+    if let Some(profiles) = &config.srtp_protection_profiles {
+        api_settings.set_srtp_protection_profiles(
+            profiles
+                .iter()
+                .copied()
+                .map(into_webrtc_srtp_protection_profile)
+                .collect(),
+        );
+    }
+
     // -- Register media codecs
     // TODO: register them based on the sdp
     let mut api_media = MediaEngine::default();
@@ -154,6 +255,7 @@ pub async fn new(
 
     let general_channel = peer.create_data_channel("general", None).await?;
     let stats_channel = peer.create_data_channel("stats", None).await?;
+    let rtt_channel = peer.create_data_channel("rtt", None).await?;
 
     let runtime = Handle::current();
     let this_owned = Arc::new(WebRtcInner {
@@ -161,17 +263,34 @@ pub async fn new(
         event_sender,
         general_channel: general_channel.clone(),
         stats_channel,
+        rtt_channel: rtt_channel.clone(),
+        rtt: Mutex::new((Instant::now(), 0)),
         input_channels: Default::default(),
         video: Mutex::new(WebRtcVideo::new(
             runtime.clone(),
             Arc::downgrade(&peer),
             video_frame_queue_size,
+            config.frame_queue_policy,
         )),
         audio: Mutex::new(WebRtcAudio::new(
             runtime,
             Arc::downgrade(&peer),
             audio_sample_queue_size,
+            config.frame_queue_policy,
         )),
+        negotiation_timeout: config.negotiation_timeout,
+        ice_restart_attempts: config.ice_restart_attempts,
+        connected: AtomicBool::new(false),
+        candidate_filter: config.candidate_filter.clone(),
+        ice_server_urls: config
+            .ice_servers
+            .iter()
+            .flat_map(|server| server.urls.iter().cloned())
+            .collect(),
+        ice_gathering_timeout: config.ice_gathering_timeout,
+        gathered_candidates: AtomicU32::new(0),
+        port_range: config.port_range,
+        stats_interval: config.stats_interval,
         timeout_terminate_request: Mutex::new(None),
     });
 
@@ -179,43 +298,27 @@ pub async fn new(
     {
         let this = this_owned.clone();
         this.clone().on_data_channel(general_channel).await;
-
-        struct Options {
-            reliable: bool,
-            ordered: bool,
-        }
-        #[rustfmt::skip]
-        const INPUT_CHANNELS: &[(&str, Options)] = &[
-            ("mouse_reliable", Options { reliable: true , ordered: true  }),
-            ("mouse_absolute", Options { reliable: false, ordered: false }),
-            ("mouse_relative", Options { reliable: true , ordered: false }),
-            ("keyboard",       Options { reliable: true , ordered: true  }),
-            ("touch",          Options { reliable: true , ordered: true  }),
-            ("controllers",    Options { reliable: true , ordered: true  }),
-            ("controller0",    Options { reliable: false, ordered: false }),
-            ("controller1",    Options { reliable: false, ordered: false }),
-            ("controller2",    Options { reliable: false, ordered: false }),
-            ("controller3",    Options { reliable: false, ordered: false }),
-            ("controller4",    Options { reliable: false, ordered: false }),
-            ("controller5",    Options { reliable: false, ordered: false }),
-            ("controller6",    Options { reliable: false, ordered: false }),
-            ("controller7",    Options { reliable: false, ordered: false }),
-            ("controller8",    Options { reliable: false, ordered: false }),
-            ("controller9",    Options { reliable: false, ordered: false }),
-            ("controller10",   Options { reliable: false, ordered: false }),
-            ("controller11",   Options { reliable: false, ordered: false }),
-            ("controller12",   Options { reliable: false, ordered: false }),
-            ("controller13",   Options { reliable: false, ordered: false }),
-            ("controller14",   Options { reliable: false, ordered: false }),
-            ("controller15",   Options { reliable: false, ordered: false }),
+        this.clone().on_data_channel(rtt_channel).await;
+
+        let channels = &config.input_channels;
+        let mut input_channel_configs = vec![
+            ("mouse_reliable".to_string(), channels.mouse_reliable),
+            ("mouse_absolute".to_string(), channels.mouse_absolute),
+            ("mouse_relative".to_string(), channels.mouse_relative),
+            ("keyboard".to_string(), channels.keyboard),
+            ("touch".to_string(), channels.touch),
+            ("controllers".to_string(), channels.controllers),
         ];
+        for id in 0..InboundPacket::CONTROLLER_CHANNELS.len() {
+            input_channel_configs.push((format!("controller{id}"), channels.controller));
+        }
 
         let mut input_channels = this.input_channels.lock().await;
-        for (channel, options) in INPUT_CHANNELS {
+        for (channel, options) in input_channel_configs {
             let data_channel = this
                 .peer
                 .create_data_channel(
-                    channel,
+                    &channel,
                     Some(RTCDataChannelInit {
                         ordered: Some(options.ordered),
                         max_retransmits: (!options.reliable).then_some(0),
@@ -336,15 +439,283 @@ impl WebRtcInner {
             state,
             RTCPeerConnectionState::Failed | RTCPeerConnectionState::Disconnected
         ) {
+            if matches!(state, RTCPeerConnectionState::Failed)
+                && let Err(err) = self
+                    .event_sender
+                    .send(TransportEvent::SendIpc(StreamerIpcMessage::Metrics(
+                        StreamerMetrics {
+                            webrtc_failures: 1,
+                            ..Default::default()
+                        },
+                    )))
+                    .await
+            {
+                debug!("Failed to send webrtc failure metric: {err:?}");
+            }
+
             self.request_terminate().await;
         } else {
+            if matches!(state, RTCPeerConnectionState::Connected)
+                && !self.connected.swap(true, Ordering::AcqRel)
+            {
+                self.send_ws_message(StreamServerMessage::stage_completed(
+                    StreamStage::WebRtcPeerNegotiation,
+                    "Completed Stage: WebRTC Peer Negotiation",
+                ))
+                .await;
+
+                spawn(self.clone().send_peer_stats());
+            }
+
             self.clear_terminate_request().await;
         }
     }
 
+    // -- Stats
+    /// Sends the stats of the peer every `stats_interval` until the peer is closed
+    async fn send_peer_stats(self: Arc<Self>) {
+        // Time, bytes sent and bytes received of the selected candidate pair
+        let mut last_bytes: Option<(Instant, u64, u64)> = None;
+
+        loop {
+            sleep(self.stats_interval).await;
+
+            if matches!(
+                self.peer.connection_state(),
+                RTCPeerConnectionState::Closed | RTCPeerConnectionState::Failed
+            ) {
+                return;
+            }
+
+            let report = self.peer.get_stats().await;
+
+            let mut selected_pair = None;
+            let mut candidate_types = HashMap::new();
+            let mut fraction_lost = None;
+            for stats in report.reports.values() {
+                match stats {
+                    StatsReportType::CandidatePair(pair) if pair.nominated => {
+                        selected_pair = Some(pair);
+                    }
+                    StatsReportType::LocalCandidate(candidate)
+                    | StatsReportType::RemoteCandidate(candidate) => {
+                        candidate_types.insert(candidate.id.as_str(), &candidate.candidate_type);
+                    }
+                    StatsReportType::RemoteInboundRTP(rtp) if rtp.kind == "video" => {
+                        fraction_lost = Some(rtp.fraction_lost);
+                    }
+                    _ => {}
+                }
+            }
+
+            let now = Instant::now();
+            let (sent_kbps, received_kbps) = match (selected_pair, last_bytes) {
+                (Some(pair), Some((last_time, last_sent, last_received))) => {
+                    let secs = now.duration_since(last_time).as_secs_f64();
+
+                    (
+                        pair.bytes_sent.saturating_sub(last_sent) as f64 * 8.0 / 1000.0 / secs,
+                        pair.bytes_received.saturating_sub(last_received) as f64 * 8.0
+                            / 1000.0
+                            / secs,
+                    )
+                }
+                _ => (0.0, 0.0),
+            };
+            last_bytes = selected_pair.map(|pair| (now, pair.bytes_sent, pair.bytes_received));
+
+            let candidate_pair = selected_pair.and_then(|pair| {
+                let local = candidate_types.get(pair.local_candidate_id.as_str())?;
+                let remote = candidate_types.get(pair.remote_candidate_id.as_str())?;
+
+                Some(format!("{local} -> {remote}"))
+            });
+
+            if let Err(err) = self
+                .send_packet(OutboundPacket::Stats(StreamerStatsUpdate::WebRtc {
+                    sent_kbps,
+                    received_kbps,
+                    packet_loss_percent: fraction_lost.map(|fraction| fraction * 100.0),
+                    candidate_pair,
+                }))
+                .await
+            {
+                debug!("Failed to send webrtc stats update: {err}");
+            }
+        }
+    }
+
+    // -- Negotiation
+    /// Restarts ice if the peer didn't connect in time and gives up after the configured attempts
+    async fn watch_negotiation(self: Arc<Self>) {
+        let mut restarts = 0;
+
+        loop {
+            sleep(self.negotiation_timeout).await;
+
+            if self.connected.load(Ordering::Acquire)
+                || matches!(self.peer.connection_state(), RTCPeerConnectionState::Closed)
+            {
+                return;
+            }
+
+            if restarts >= self.ice_restart_attempts {
+                warn!(
+                    "[Stream]: WebRTC peer didn't connect after {restarts} ice restarts, giving up"
+                );
+                self.send_ws_message(StreamServerMessage::stage_failed(
+                    StreamStage::WebRtcPeerNegotiation,
+                    StreamStageError::WebRtcNegotiationFailed,
+                    format!(
+                        "Failed Stage: WebRTC Peer Negotiation, no connection after {restarts} ice restarts"
+                    ),
+                    LogMessageType::IfErrorDescription,
+                ))
+                .await;
+
+                // Closing the peer notifies the stream via the connection state
+                if let Err(err) = self.peer.close().await {
+                    warn!("Failed to close peer after negotiation failed: {err:?}");
+                }
+                return;
+            }
+
+            restarts += 1;
+            info!(
+                "[Stream]: WebRTC peer didn't connect in time, restarting ice ({restarts}/{})",
+                self.ice_restart_attempts
+            );
+            self.send_debug_log(
+                format!(
+                    "Restarting ICE ({restarts}/{}) because the WebRTC Peer Negotiation didn't complete in time",
+                    self.ice_restart_attempts
+                ),
+                None,
+            )
+            .await;
+
+            if !self.send_offer(true).await {
+                error!("Failed to send ice restart offer to client. Requesting Termination");
+                self.request_terminate().await;
+                return;
+            }
+        }
+    }
+
+    /// Reports which ice servers responded while gathering, the negotiation continues with the candidates gathered so far
+    async fn watch_ice_gathering(self: Arc<Self>) {
+        let probes = probe_ice_servers(
+            &self.ice_server_urls,
+            self.port_range,
+            self.ice_gathering_timeout,
+        )
+        .await;
+
+        if !probes.is_empty() {
+            let responded = probes.iter().filter(|probe| probe.responded).count();
+
+            for probe in &probes {
+                if probe.responded {
+                    debug!("[Stream]: Ice server {} responded", probe.url);
+                } else {
+                    warn!(
+                        "[Stream]: Ice server {} didn't respond within {:?}",
+                        probe.url, self.ice_gathering_timeout
+                    );
+                }
+            }
+
+            info!(
+                "[Stream]: {responded}/{} ice servers responded while gathering",
+                probes.len()
+            );
+            let message = format!(
+                "ICE Gathering: {responded}/{} ICE servers responded",
+                probes.len()
+            );
+            // Host candidates may still be enough to connect, so this isn't fatal
+            self.send_ws_message(if responded == 0 {
+                StreamServerMessage::stage_failed(
+                    StreamStage::IceGathering,
+                    StreamStageError::IceGatheringFailed,
+                    message,
+                    LogMessageType::IfErrorDescription,
+                )
+            } else {
+                StreamServerMessage::stage_completed(StreamStage::IceGathering, message)
+            })
+            .await;
+        }
+
+        if !matches!(
+            self.peer.ice_gathering_state(),
+            RTCIceGatheringState::Complete
+        ) {
+            let candidates = self.gathered_candidates.load(Ordering::Acquire);
+
+            info!(
+                "[Stream]: Ice gathering didn't complete within {:?}, continuing with the {candidates} candidates gathered so far",
+                self.ice_gathering_timeout
+            );
+            self.send_debug_log(
+                format!(
+                    "ICE Gathering: not complete after {:?}, continuing with {candidates} candidates",
+                    self.ice_gathering_timeout
+                ),
+                None,
+            )
+            .await;
+        }
+    }
+
+    /// Logs the local port of the candidate, so it can be opened in the firewall
+    fn log_candidate_port(&self, candidate: &RTCIceCandidate) {
+        // Relay candidates use a port of the turn server
+        let local_port = match candidate.typ {
+            RTCIceCandidateType::Host => candidate.port,
+            RTCIceCandidateType::Srflx | RTCIceCandidateType::Prflx => candidate.related_port,
+            _ => return,
+        };
+
+        info!(
+            "[Stream]: Ice candidate {} uses the local {} port {local_port}",
+            candidate.typ, candidate.protocol
+        );
+        if let Some(port_range) = self.port_range
+            && !port_range.contains(local_port)
+        {
+            warn!(
+                "[Stream]: the local port {local_port} is outside of webrtc.port_range {} to {}",
+                port_range.min, port_range.max
+            );
+        }
+    }
+
+    async fn send_debug_log(&self, message: String, ty: Option<LogMessageType>) {
+        self.send_ws_message(StreamServerMessage::DebugLog { message, ty })
+            .await;
+    }
+
+    async fn send_ws_message(&self, message: StreamServerMessage) {
+        if let Err(err) = self
+            .event_sender
+            .send(TransportEvent::SendIpc(StreamerIpcMessage::WebSocket(
+                message,
+            )))
+            .await
+        {
+            warn!("Failed to send message via web socket from peer: {err:?}");
+        }
+    }
+
     // -- Handle Signaling
-    async fn send_offer(&self) -> bool {
-        let local_description = match self.peer.create_offer(None).await {
+    async fn send_offer(&self, ice_restart: bool) -> bool {
+        let options = ice_restart.then(|| RTCOfferOptions {
+            ice_restart: true,
+            ..Default::default()
+        });
+
+        let local_description = match self.peer.create_offer(options).await {
             Err(err) => {
                 error!("[Signaling]: failed to create offer: {err:?}");
                 return false;
@@ -446,6 +817,14 @@ impl WebRtcInner {
             StreamClientMessage::WebRtc(StreamSignalingMessage::AddIceCandidate(description)) => {
                 debug!("[Signaling] Received Ice Candidate");
 
+                if !is_candidate_allowed(&self.candidate_filter, &description.candidate) {
+                    debug!(
+                        "[Signaling] Ignoring filtered Ice Candidate: {}",
+                        description.candidate
+                    );
+                    return;
+                }
+
                 if let Err(err) = self
                     .peer
                     .add_ice_candidate(RTCIceCandidateInit {
@@ -472,10 +851,20 @@ impl WebRtcInner {
             return;
         };
 
+        if !is_candidate_allowed(&self.candidate_filter, &candidate_json.candidate) {
+            debug!(
+                "[Signaling] Not sending filtered Ice Candidate: {}",
+                candidate_json.candidate
+            );
+            return;
+        }
+
         debug!(
             "[Signaling] Sending Ice Candidate: {}",
             candidate_json.candidate
         );
+        self.gathered_candidates.fetch_add(1, Ordering::AcqRel);
+        self.log_candidate_port(&candidate);
 
         let message =
             StreamServerMessage::WebRtc(StreamSignalingMessage::AddIceCandidate(RtcIceCandidate {
@@ -516,6 +905,31 @@ impl WebRtcInner {
                     TransportChannel(TransportChannelId::MOUSE_ABSOLUTE),
                 ));
             }
+            "rtt" => {
+                // The browser answers every rtt packet, the first one is sent once the channel is open
+                let open_inner = inner.clone();
+                channel.on_open(Box::new(move || {
+                    Box::pin(async move {
+                        if let Some(inner) = open_inner.upgrade() {
+                            inner.send_rtt_packet().await;
+                        }
+                    })
+                }));
+
+                channel.on_message(create_event_handler(
+                    inner,
+                    async move |inner, message: DataChannelMessage| {
+                        if let Some(InboundPacket::Rtt { sequence_number }) =
+                            InboundPacket::deserialize(
+                                TransportChannel(TransportChannelId::RTT),
+                                &message.data,
+                            )
+                        {
+                            spawn(inner.on_rtt_response(sequence_number));
+                        }
+                    },
+                ));
+            }
             "touch" => {
                 channel.on_message(create_channel_message_handler(
                     inner,
@@ -548,6 +962,89 @@ impl WebRtcInner {
         };
     }
 
+    // -- Round trip time to the browser
+    async fn send_rtt_packet(&self) {
+        let sequence_number = {
+            let mut rtt = self.rtt.lock().await;
+            rtt.0 = Instant::now();
+            rtt.1
+        };
+
+        if let Err(err) = self
+            .send_packet(OutboundPacket::Rtt { sequence_number })
+            .await
+        {
+            debug!("Failed to send rtt packet with sequence number {sequence_number}: {err}");
+        }
+    }
+    async fn on_rtt_response(self: Arc<Self>, recv_sequence_number: u16) {
+        let (sent, sequence_number) = {
+            let rtt = self.rtt.lock().await;
+            *rtt
+        };
+
+        if recv_sequence_number != sequence_number {
+            warn!(
+                "Expected rtt packet with sequence_number {sequence_number} but got {recv_sequence_number}"
+            );
+            return;
+        }
+
+        if let Err(err) = self
+            .send_packet(OutboundPacket::Stats(StreamerStatsUpdate::BrowserRtt {
+                rtt_ms: sent.elapsed().as_secs_f64() * 1000.0,
+            }))
+            .await
+        {
+            debug!("Failed to send rtt stats update: {err}");
+        }
+
+        sleep(RTT_PING_INTERVAL).await;
+
+        {
+            let mut rtt = self.rtt.lock().await;
+            rtt.1 = rtt.1.wrapping_add(1);
+        }
+        self.send_rtt_packet().await;
+    }
+
+    async fn send_packet(&self, packet: OutboundPacket) -> Result<(), TransportError> {
+        let mut buffer = Vec::new();
+
+        let Some((channel, range)) = packet.serialize(&mut buffer) else {
+            warn!("Failed to serialize packet: {packet:?}");
+            return Ok(());
+        };
+
+        let bytes = Bytes::from(buffer);
+        let bytes = bytes.slice(range);
+
+        match channel.0 {
+            TransportChannelId::GENERAL => match self.general_channel.send(&bytes).await {
+                Ok(_) => {}
+                Err(webrtc::Error::ErrDataChannelNotOpen) => {
+                    return Err(TransportError::ChannelClosed);
+                }
+                _ => {}
+            },
+            TransportChannelId::STATS => {
+                if let Err(err) = self.stats_channel.send(&bytes).await {
+                    debug!(error = ?err, "Failed to send stat message");
+                }
+            }
+            TransportChannelId::RTT => {
+                if let Err(err) = self.rtt_channel.send(&bytes).await {
+                    debug!(error = ?err, "Failed to send rtt message");
+                }
+            }
+            _ => {
+                warn!("Cannot send data on channel {channel:?}");
+                return Err(TransportError::ChannelClosed);
+            }
+        }
+        Ok(())
+    }
+
     // -- Termination
     async fn request_terminate(self: &Arc<Self>) {
         let this = self.clone();
@@ -630,44 +1127,31 @@ impl TransportSender for WebRTCTransportSender {
 
         Ok(())
     }
+    fn supports_surround_audio(&self) -> bool {
+        // Only stereo opus is negotiated with the peer
+        false
+    }
 
     async fn on_setup_complete(&self) {
-        if !self.inner.send_offer().await {
+        self.inner
+            .send_ws_message(StreamServerMessage::stage_starting(
+                StreamStage::WebRtcPeerNegotiation,
+                "Starting Stage: WebRTC Peer Negotiation",
+            ))
+            .await;
+
+        if !self.inner.send_offer(false).await {
             error!("Failed to send offer to client. Requesting Termination");
             self.inner.request_terminate().await;
+            return;
         }
+
+        spawn(self.inner.clone().watch_ice_gathering());
+        spawn(self.inner.clone().watch_negotiation());
     }
 
     async fn send(&self, packet: OutboundPacket) -> Result<(), TransportError> {
-        let mut buffer = Vec::new();
-
-        let Some((channel, range)) = packet.serialize(&mut buffer) else {
-            warn!("Failed to serialize packet: {packet:?}");
-            return Ok(());
-        };
-
-        let bytes = Bytes::from(buffer);
-        let bytes = bytes.slice(range);
-
-        match channel.0 {
-            TransportChannelId::GENERAL => match self.inner.general_channel.send(&bytes).await {
-                Ok(_) => {}
-                Err(webrtc::Error::ErrDataChannelNotOpen) => {
-                    return Err(TransportError::ChannelClosed);
-                }
-                _ => {}
-            },
-            TransportChannelId::STATS => {
-                if let Err(err) = self.inner.stats_channel.send(&bytes).await {
-                    debug!(error = ?err, "Failed to send stat message");
-                }
-            }
-            _ => {
-                warn!("Cannot send data on channel {channel:?}");
-                return Err(TransportError::ChannelClosed);
-            }
-        }
-        Ok(())
+        self.inner.send_packet(packet).await
     }
 
     async fn on_ipc_message(&self, message: ServerIpcMessage) -> Result<(), TransportError> {
@@ -5,7 +5,8 @@ use std::{
 };
 
 use anyhow::anyhow;
-use log::{debug, warn};
+use common::config::FrameQueuePolicy;
+use log::{debug, trace, warn};
 use tokio::{
     runtime::Handle,
     sync::{Mutex, Notify},
@@ -73,7 +74,10 @@ where
     runtime: Handle,
     peer: Weak<RTCPeerConnection>,
     channel_queue_size: usize,
+    policy: FrameQueuePolicy,
     new_samples_notify: Arc<Notify>,
+    /// Notified when a frame was taken out of the queue
+    space_notify: Arc<Notify>,
     queue: Arc<Mutex<VecDeque<FrameSamples<Track>>>>,
 }
 
@@ -89,12 +93,19 @@ impl<Track> TrackLocalSender<Track>
 where
     Track: TrackLike,
 {
-    pub fn new(runtime: Handle, peer: Weak<RTCPeerConnection>, channel_queue_size: usize) -> Self {
+    pub fn new(
+        runtime: Handle,
+        peer: Weak<RTCPeerConnection>,
+        channel_queue_size: usize,
+        policy: FrameQueuePolicy,
+    ) -> Self {
         Self {
             runtime,
             peer,
             channel_queue_size,
+            policy,
             new_samples_notify: Default::default(),
+            space_notify: Default::default(),
             queue: Default::default(),
         }
     }
@@ -113,11 +124,12 @@ where
         let track = Arc::new(track);
 
         let new_samples_notify = self.new_samples_notify.clone();
+        let space_notify = self.space_notify.clone();
         let queue = Arc::downgrade(&self.queue);
         self.runtime.spawn({
             let track = track.clone();
             async move {
-                sample_sender(track, &new_samples_notify, queue).await;
+                sample_sender(track, &new_samples_notify, &space_notify, queue).await;
             }
         });
 
@@ -138,21 +150,48 @@ where
         Ok(())
     }
 
-    /// Returns if the frame will be delivered
+    /// Returns false if a frame was dropped because the queue is full
     pub async fn send_samples(&self, samples: Vec<Track::Sample>, important: bool) -> bool {
         let mut queue = self.queue.lock().await;
 
-        let result = if important {
-            queue.push_front(FrameSamples { important, samples });
-            true
-        } else {
-            if queue.len() > self.channel_queue_size {
-                return false;
+        let mut result = true;
+        if !important {
+            match self.policy {
+                FrameQueuePolicy::DropOldest => {
+                    if queue.len() > self.channel_queue_size {
+                        result = false;
+
+                        // Keyframes are never dropped, the frames after them can't be decoded without them
+                        match queue.iter().rposition(|frame| !frame.important) {
+                            Some(oldest) => {
+                                debug!("[Stream]: frame queue is full, dropping the oldest frame");
+                                queue.remove(oldest);
+                            }
+                            None => {
+                                debug!(
+                                    "[Stream]: frame queue is full of keyframes, dropping the new frame"
+                                );
+                                return result;
+                            }
+                        }
+                    }
+                }
+                FrameQueuePolicy::Block => {
+                    while queue.len() > self.channel_queue_size {
+                        // Register before unlocking so the notification can't be missed
+                        let space_notified = self.space_notify.notified();
+                        drop(queue);
+
+                        trace!("[Stream]: frame queue is full, waiting for space");
+                        space_notified.await;
+
+                        queue = self.queue.lock().await;
+                    }
+                }
             }
+        }
 
-            queue.push_front(FrameSamples { important, samples });
-            true
-        };
+        queue.push_front(FrameSamples { important, samples });
 
         self.new_samples_notify.notify_waiters();
 
@@ -174,6 +213,7 @@ where
 async fn sample_sender<Track>(
     track: Arc<Track>,
     new_samples_notify: &Notify,
+    space_notify: &Notify,
     queue: Weak<Mutex<VecDeque<FrameSamples<Track>>>>,
 ) where
     Track: TrackLike,
@@ -192,6 +232,7 @@ async fn sample_sender<Track>(
                 new_samples_notify.notified().await;
                 continue;
             };
+            space_notify.notify_waiters();
 
             new_frame
         };
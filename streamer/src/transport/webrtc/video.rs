@@ -10,6 +10,7 @@ use std::{
 use bytes::{Bytes, BytesMut};
 use common::{
     api_bindings::{LogMessageType, StreamServerMessage},
+    config::FrameQueuePolicy,
     ipc::StreamerIpcMessage,
 };
 use moonlight_common::stream::video::{
@@ -37,16 +38,19 @@ use webrtc::{
     track::track_local::track_local_static_rtp::TrackLocalStaticRTP,
 };
 
-use crate::transport::{
-    TransportEvent,
-    webrtc::{
-        WebRtcInner,
-        sender::{SequencedTrackLocalStaticRTP, TrackLocalSender},
-        video::{
-            h264::{payloader::H264Payloader, reader::H264Reader},
-            h265::{payloader::H265Payloader, reader::H265Reader},
+use crate::{
+    transport::{
+        TransportEvent,
+        webrtc::{
+            WebRtcInner,
+            sender::{SequencedTrackLocalStaticRTP, TrackLocalSender},
+            video::{
+                h264::{payloader::H264Payloader, reader::H264Reader},
+                h265::{payloader::H265Payloader, reader::H265Reader},
+            },
         },
     },
+    video::decode_unit_len,
 };
 
 mod annexb;
@@ -80,11 +84,16 @@ pub struct WebRtcVideo {
 }
 
 impl WebRtcVideo {
-    pub fn new(runtime: Handle, peer: Weak<RTCPeerConnection>, frame_queue_size: usize) -> Self {
+    pub fn new(
+        runtime: Handle,
+        peer: Weak<RTCPeerConnection>,
+        frame_queue_size: usize,
+        queue_policy: FrameQueuePolicy,
+    ) -> Self {
         Self {
             clock_rate: 0,
             needs_idr: Default::default(),
-            sender: TrackLocalSender::new(runtime, peer, frame_queue_size),
+            sender: TrackLocalSender::new(runtime, peer, frame_queue_size, queue_policy),
             codec: None,
             supported_video_formats: VideoFormats::empty(),
             samples: Default::default(),
@@ -149,6 +158,7 @@ impl WebRtcVideo {
                 .into(),
                 {
                     let needs_idr = needs_idr.clone();
+                    let event_sender = inner.event_sender.clone();
 
                     move |packet| {
                         let packet = packet.as_any();
@@ -156,10 +166,14 @@ impl WebRtcVideo {
                         if packet.is::<PictureLossIndication>() {
                             needs_idr.store(true, Ordering::Release);
                         }
-                        if let Some(_max_bitrate) =
+                        if let Some(max_bitrate) =
                             packet.downcast_ref::<ReceiverEstimatedMaximumBitrate>()
                         {
                             // Moonlight doesn't support dynamic bitrate changing :(
+                            // so the estimate is only reported in the stats
+                            let _ = event_sender.try_send(TransportEvent::BandwidthEstimate {
+                                bitrate_kbps: (max_bitrate.bitrate / 1000.0) as u32,
+                            });
                         }
                     }
                 },
@@ -217,11 +231,6 @@ impl WebRtcVideo {
     pub async fn send_decode_unit(&mut self, unit: &VideoDecodeUnit<&[u8]>) -> DecodeResult {
         let timestamp = (unit.timestamp.as_nanos() * 90000 / 1_000_000_000) as u32;
 
-        let mut full_frame = Vec::new();
-        for buffer in &unit.buffers {
-            full_frame.extend_from_slice(buffer.data);
-        }
-
         let important = matches!(unit.frame_type, FrameType::Idr);
 
         match &mut self.codec {
@@ -230,7 +239,7 @@ impl WebRtcVideo {
                 nal_reader,
                 payloader,
             }) => {
-                nal_reader.reset(Cursor::new(full_frame));
+                nal_reader.reset(Cursor::new(concat_decode_unit(unit)));
 
                 while let Ok(Some(nal)) = nal_reader.next_nal() {
                     trace!(
@@ -273,7 +282,7 @@ impl WebRtcVideo {
                 nal_reader,
                 payloader,
             }) => {
-                nal_reader.reset(Cursor::new(full_frame));
+                nal_reader.reset(Cursor::new(concat_decode_unit(unit)));
 
                 while let Ok(Some(nal)) = nal_reader.next_nal() {
                     trace!(
@@ -308,7 +317,12 @@ impl WebRtcVideo {
             }
             // -- AV1
             Some(VideoCodec::Av1 { payloader }) => {
-                self.samples.push(BytesMut::from(full_frame.as_slice()));
+                // Av1 is sent as one sample, so copy the frame directly into it
+                let mut sample = BytesMut::with_capacity(decode_unit_len(unit));
+                for buffer in &unit.buffers {
+                    sample.extend_from_slice(buffer.data);
+                }
+                self.samples.push(sample);
 
                 send_single_frame(
                     &mut self.samples,
@@ -337,6 +351,15 @@ impl WebRtcVideo {
     }
 }
 
+/// All formats which have a codec registered by [register_video_codecs]
+pub fn supported_video_formats() -> VideoFormats {
+    VideoFormat::all()
+        .filter(|format| video_format_to_codec(*format).is_some())
+        .fold(VideoFormats::empty(), |formats, format| {
+            formats | VideoFormats::from_bits_retain(format as u32)
+        })
+}
+
 pub fn register_video_codecs(media_engine: &mut MediaEngine) -> Result<(), webrtc::Error> {
     for format in VideoFormat::all() {
         let Some(codec) = video_format_to_codec(format) else {
@@ -388,13 +411,22 @@ async fn send_single_frame(
     }
 
     if !sender.send_samples(frame_samples, important).await {
-        sender.clear_queue(true).await;
+        // The following frames can't be decoded without the dropped one, so only keep keyframes
+        sender.clear_queue(false).await;
 
         // We've dropped a frame (likely due to buffering)
         needs_idr.store(true, Ordering::Release);
     }
 }
 
+fn concat_decode_unit(unit: &VideoDecodeUnit<&[u8]>) -> Vec<u8> {
+    let mut full_frame = Vec::with_capacity(decode_unit_len(unit));
+    for buffer in &unit.buffers {
+        full_frame.extend_from_slice(buffer.data);
+    }
+    full_frame
+}
+
 fn packetize(
     payloader: &mut impl Payloader,
     mtu: usize,
@@ -1,10 +1,13 @@
 use std::{
-    sync::{Arc, Weak},
+    sync::{Arc, Weak, atomic::Ordering},
     time::{Duration, Instant},
 };
 
-use common::api_bindings::{StatsHostProcessingLatency, StreamerStatsUpdate};
-use log::{debug, error, warn};
+use common::{
+    api_bindings::{StatsHostProcessingLatency, StreamerStatsUpdate},
+    ipc::StreamerMetrics,
+};
+use log::{debug, error, info, warn};
 use moonlight_common::stream::{
     c::bindings::EstimatedRttInfo,
     video::{
@@ -12,12 +15,27 @@ use moonlight_common::stream::{
     },
 };
 
-use crate::{StreamConnection, transport::OutboundPacket};
+use crate::{
+    StreamConnection,
+    diagnostics::FrameDiagnostics,
+    recording::{RecordingFile, video_extension},
+    transport::OutboundPacket,
+};
+
+/// The size of all buffers of the unit, used to allocate the frame once
+pub(crate) fn decode_unit_len(unit: &VideoDecodeUnit<&[u8]>) -> usize {
+    unit.buffers.iter().map(|buffer| buffer.data.len()).sum()
+}
 
 pub(crate) struct StreamVideoDecoder {
     pub(crate) stream: Weak<StreamConnection>,
     pub(crate) supported_formats: VideoFormats,
+    /// Don't forward the video to the transport, so the browser only gets audio
+    pub(crate) audio_only: bool,
     pub(crate) stats: VideoStats,
+    pub(crate) recording_name: String,
+    pub(crate) recording: Option<RecordingFile>,
+    pub(crate) diagnostics: Option<FrameDiagnostics>,
 }
 
 impl VideoDecoder for StreamVideoDecoder {
@@ -32,6 +50,19 @@ impl VideoDecoder for StreamVideoDecoder {
             stream_info.video = Some(setup);
         }
 
+        if let Some(diagnostics) = &mut self.diagnostics {
+            diagnostics.setup(setup.format);
+        }
+
+        if self.audio_only {
+            info!("[Stream]: audio only mode, the video of the host isn't forwarded");
+            return 0;
+        }
+
+        self.recording = stream.config.recording.as_ref().and_then(|config| {
+            RecordingFile::create(config, &self.recording_name, video_extension(setup.format))
+        });
+
         {
             stream.runtime.clone().block_on(async move {
                 let mut sender = stream.transport_sender.lock().await;
@@ -55,18 +86,41 @@ impl VideoDecoder for StreamVideoDecoder {
             return DecodeResult::Ok;
         };
 
+        if let Some(diagnostics) = &mut self.diagnostics {
+            diagnostics.on_frame(&unit);
+        }
+
+        if self.audio_only {
+            // The host is still streaming, so the stream isn't idle
+            stream.idle.on_frame();
+            return DecodeResult::Ok;
+        }
+
+        if let Some(recording) = &mut self.recording {
+            recording.write_buffers(unit.buffers.iter().map(|buffer| buffer.data));
+        }
+
         let mut sender_guard = stream.transport_sender.blocking_lock();
 
         let start = Instant::now();
 
+        let mut failed = false;
+        let mut sent = false;
         let result = stream.runtime.block_on(async {
             if let Some(sender) = sender_guard.as_mut() {
                 match sender.send_video_unit(unit.as_ref()).await {
                     Err(err) => {
-                        warn!("Failed to send video decode unit: {err}");
-                        DecodeResult::Ok
+                        failed = true;
+
+                        // The client is now missing a frame it might reference later,
+                        // so ask the host for a fresh idr to resync the decoder
+                        warn!("Failed to send video decode unit, requesting idr: {err}");
+                        DecodeResult::NeedIdr
+                    }
+                    Ok(value) => {
+                        sent = true;
+                        value
                     }
-                    Ok(value) => value,
                 }
             } else {
                 debug!("Dropping video packet because of missing transport");
@@ -75,8 +129,22 @@ impl VideoDecoder for StreamVideoDecoder {
             }
         });
 
+        // The client asked for a keyframe, e.g. because its picture is corrupted
+        let result = if stream.request_idr.swap(false, Ordering::AcqRel) {
+            DecodeResult::NeedIdr
+        } else {
+            result
+        };
+
         let frame_processing_time = Instant::now() - start;
-        self.stats.analyze(&stream, &unit, frame_processing_time);
+        if sent {
+            stream.idle.on_frame();
+        }
+        if !failed {
+            self.stats.streamed_bytes += decode_unit_len(&unit) as u64;
+        }
+        self.stats
+            .analyze(&stream, &unit, frame_processing_time, failed);
 
         result
     }
@@ -101,6 +169,8 @@ pub(crate) struct VideoStats {
     max_streamer_processing_time: Duration,
     total_streamer_processing_time: Duration,
     streamer_processing_time_frame_count: usize,
+    failed_frame_count: usize,
+    streamed_bytes: u64,
 }
 
 impl VideoStats {
@@ -109,6 +179,7 @@ impl VideoStats {
         stream: &Arc<StreamConnection>,
         unit: &VideoDecodeUnit<&[u8]>,
         frame_processing_time: Duration,
+        failed: bool,
     ) {
         if let Some(host_processing_latency) = unit.frame_processing_latency {
             self.min_host_processing_latency = self
@@ -128,6 +199,10 @@ impl VideoStats {
         self.total_streamer_processing_time += frame_processing_time;
         self.streamer_processing_time_frame_count += 1;
 
+        if failed {
+            self.failed_frame_count += 1;
+        }
+
         // Send in 1 sec intervall
         if self
             .last_send
@@ -150,6 +225,16 @@ impl VideoStats {
                 .checked_div(self.streamer_processing_time_frame_count as u32)
                 .unwrap_or(Duration::ZERO);
 
+            let received_frames = self.streamer_processing_time_frame_count as u32;
+            let failed_frames = self.failed_frame_count as u32;
+            let streamed_bytes = self.streamed_bytes;
+            let streamed_kbps = self
+                .last_send
+                .map(|last_send| {
+                    streamed_bytes as f64 * 8.0 / 1000.0 / last_send.elapsed().as_secs_f64()
+                })
+                .unwrap_or(0.0);
+
             // Send data
             let runtime = stream.runtime.clone();
 
@@ -180,12 +265,34 @@ impl VideoStats {
                             avg_streamer_processing_time_ms: avg_streamer_processing_time
                                 .as_secs_f64()
                                 * 1000.0,
+                            received_frames,
+                            failed_frames,
                         }),
                         "host / streamer processing latency",
                         false,
                     )
                     .await;
 
+                let estimated_kbps = stream.estimated_bitrate_kbps.load(Ordering::Relaxed);
+                stream
+                    .try_send_packet(
+                        OutboundPacket::Stats(StreamerStatsUpdate::Bitrate {
+                            requested_kbps: stream.bitrate_kbps.load(Ordering::Relaxed),
+                            streamed_kbps,
+                            estimated_kbps: (estimated_kbps > 0).then_some(estimated_kbps),
+                        }),
+                        "bitrate",
+                        false,
+                    )
+                    .await;
+
+                stream
+                    .send_metrics(StreamerMetrics {
+                        streamed_video_bytes: streamed_bytes,
+                        ..Default::default()
+                    })
+                    .await;
+
                 // Send RTT info
                 let ml_stream_lock = stream.stream.read().await;
                 if let Some(ml_stream) = ml_stream_lock.as_ref() {
@@ -221,6 +328,8 @@ impl VideoStats {
             self.max_streamer_processing_time = Duration::ZERO;
             self.total_streamer_processing_time = Duration::ZERO;
             self.streamer_processing_time_frame_count = 0;
+            self.failed_frame_count = 0;
+            self.streamed_bytes = 0;
 
             self.last_send = Some(Instant::now());
         }
@@ -1,4 +1,4 @@
-import { App, DeleteHostQuery, DeleteUserRequest, DetailedHost, DetailedUser, GetAppImageQuery, GetAppsQuery, GetAppsResponse, GetHostQuery, GetHostResponse, GetHostsResponse, GetUserQuery, GetUsersResponse, PatchUserRequest, PostCancelRequest, PostCancelResponse, PostLoginRequest, PostPairRequest, PostPairResponse1, PostPairResponse2, PostUserRequest, PostWakeUpRequest, PostHostRequest, PostHostResponse, UndetailedHost, PatchHostRequest, GetRolesResponse, UndetailedRole, GetRoleResponse, GetRoleQuery, DeleteRoleQuery, PatchRoleRequest, PostRoleResponse, PostRoleRequest, DetailedRole } from "./api_bindings.js";
+import { App, DeleteHostQuery, DeleteUserRequest, DetailedHost, DetailedUser, GetAppImageQuery, GetAppsQuery, GetAppsResponse, GetHostQuery, GetHostResponse, GetHostsResponse, GetUserQuery, GetUsersResponse, PatchUserRequest, PostCancelRequest, PostCancelResponse, PostLoginRequest, PostPairRequest, PostPairResponse1, PostPairResponse2, PostUserRequest, PostWakeUpRequest, PostHostRequest, PostHostResponse, UndetailedHost, PatchHostRequest, GetRolesResponse, UndetailedRole, GetRoleResponse, GetRoleQuery, DeleteRoleQuery, PatchRoleRequest, PostRoleResponse, PostRoleRequest, DetailedRole, DiscoveredHost, GetDiscoverResponse, DeletePairQuery, ApiError } from "./api_bindings.js";
 import { showNotification } from "./component/notification.js";
 import { showMessage, showModal } from "./component/modal/index.js";
 import { ApiUserPasswordPrompt } from "./component/modal/login.js";
@@ -90,7 +90,7 @@ export type ApiFetchInit = {
 }
 
 export function isDetailedHost(host: UndetailedHost | DetailedHost): host is DetailedHost {
-    return (host as DetailedHost).https_port !== undefined
+    return (host as DetailedHost).version !== undefined
 }
 
 function buildRequest(api: Api, endpoint: string, method: string, init?: ApiFetchInit): [string, RequestInit] {
@@ -131,19 +131,21 @@ function buildRequest(api: Api, endpoint: string, method: string, init?: ApiFetc
 
 export class FetchError extends Error {
     private response?: Response
+    private apiError?: ApiError
 
     constructor(type: "timeout", endpoint: string, method: string)
-    constructor(type: "failed", endpoint: string, method: string, response: Response, reason?: string)
+    constructor(type: "failed", endpoint: string, method: string, response: Response, apiError?: ApiError)
     constructor(type: "unknown", endpoint: string, method: string, error: Error)
 
-    constructor(type: "timeout" | "failed" | "unknown", endpoint: string, method: string, responseOrError?: Response | any, reason?: string) {
+    constructor(type: "timeout" | "failed" | "unknown", endpoint: string, method: string, responseOrError?: Response | any, apiError?: ApiError) {
         if (type == "timeout") {
             super(`failed to fetch ${method} at ${endpoint} because of timeout`)
         } else if (type == "failed") {
             const response = responseOrError as Response
-            super(`failed to fetch ${method} at ${endpoint} with code ${response?.status} ${reason ? `because of ${reason}` : ""}`)
+            super(`failed to fetch ${method} at ${endpoint} with code ${response?.status} ${apiError ? `because of ${apiError.error}` : ""}`)
 
             this.response = response
+            this.apiError = apiError
         } else if (type == "unknown") {
             const error = responseOrError as Error
             super(`failed to fetch ${method} at ${endpoint} because of ${error}`)
@@ -153,6 +155,17 @@ export class FetchError extends Error {
     getResponse(): Response | null {
         return this.response ?? null
     }
+    getApiError(): ApiError | null {
+        return this.apiError ?? null
+    }
+}
+
+async function readApiError(response: Response): Promise<ApiError | undefined> {
+    try {
+        return await response.json()
+    } catch (e) {
+        return undefined
+    }
 }
 
 class StreamedJsonResponse<Initial, Other> {
@@ -207,7 +220,7 @@ export async function fetchApi(api: Api, endpoint: string, method: string = GET,
     }
 
     if (!response.ok) {
-        throw new FetchError("failed", endpoint, method, response)
+        throw new FetchError("failed", endpoint, method, response, await readApiError(response))
     }
 
     if (init?.response == "ignore") {
@@ -375,6 +388,11 @@ export async function apiPostHost(api: Api, data: PostHostRequest): Promise<Deta
 
     return (response as PostHostResponse).host
 }
+export async function apiDiscoverHosts(api: Api): Promise<Array<DiscoveredHost>> {
+    const response = await fetchApi(api, "/discover", GET)
+
+    return (response as GetDiscoverResponse).hosts
+}
 export async function apiPatchHost(api: Api, data: PatchHostRequest): Promise<void> {
     await fetchApi(api, "/host", PATCH, {
         json: data,
@@ -393,6 +411,10 @@ export async function apiPostPair(api: Api, request: PostPairRequest): Promise<S
     })
 }
 
+export async function apiDeletePair(api: Api, query: DeletePairQuery): Promise<void> {
+    await fetchApi(api, "/pair", "delete", { query, response: "ignore" })
+}
+
 export async function apiWakeUp(api: Api, request: PostWakeUpRequest): Promise<void> {
     await fetchApi(api, "/host/wake", "post", {
         json: request,
@@ -40,6 +40,7 @@ export class GameList extends FetchListComponent<App, Game> {
     async forceFetch() {
         const apps = await apiGetApps(this.api, {
             host_id: this.hostId,
+            force_refresh: false
         })
 
         this.updateCache(apps)
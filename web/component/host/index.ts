@@ -1,5 +1,5 @@
 import { DetailedHost, DetailedUser, UndetailedHost } from "../../api_bindings.js"
-import { Api, apiDeleteHost, apiGetHost, isDetailedHost, apiPostPair, apiWakeUp, apiGetUser, apiPatchHost } from "../../api.js"
+import { Api, apiDeleteHost, apiGetHost, isDetailedHost, apiPostPair, apiWakeUp, apiGetUser, apiPatchHost, apiDeletePair } from "../../api.js"
 import { Component, ComponentEvent } from "../index.js"
 import { getCurrentLanguage, getTranslations } from "../../i18n.js"
 import { setContextMenu } from "../context_menu.js"
@@ -117,6 +117,12 @@ export class Host implements Component {
                 name: i.pair,
                 callback: this.pair.bind(this)
             })
+        } else if (this.cache?.paired == "Paired") {
+            elements.push({
+                name: i.unpair,
+                callback: this.unpair.bind(this),
+                classes: ["context-menu-element-red"]
+            })
         }
 
         // Make private / global
@@ -214,11 +220,19 @@ export class Host implements Component {
     private async wakeUp() {
         const i = getTranslations(getCurrentLanguage()).host
         await apiWakeUp(this.api, {
-            host_id: this.getHostId()
+            host_id: this.getHostId(),
+            wait_online: false
         })
 
         await showMessage(i.wakeUpSent)
     }
+    private async unpair() {
+        await apiDeletePair(this.api, {
+            host_id: this.getHostId()
+        })
+
+        await this.forceFetch()
+    }
     private async pair() {
         const i = getTranslations(getCurrentLanguage()).host
         if (this.cache?.paired == "Paired") {
@@ -1,6 +1,7 @@
 import { ControllerConfig } from "../stream/gamepad.js";
 import { MouseMode, MouseScrollMode, TouchMode } from "../stream/input.js";
 import { PageStyle } from "../styles/index.js";
+import { AudioPlayerType } from "../stream/audio/pipeline.js";
 import { getLanguageOptions, getTranslations, Language, normalizeLanguage } from "../i18n.js";
 import { Component, ComponentEvent } from "./index.js";
 import { InputComponent, SelectComponent } from "./input.js";
@@ -21,11 +22,19 @@ export type Settings = {
     canvasRenderer: boolean
     canvasVsync: boolean
     playAudioLocal: boolean
+    audioOnly: boolean
+    // null uses the audio mode of the host config
+    audioMode: StreamAudioMode | null
+    audioPlayer: AudioPlayerType
     audioSampleQueueSize: number
     mouseScrollMode: MouseScrollMode
     mouseMode: MouseMode
     touchMode: TouchMode
     localCursorSensitivity: number
+    mouseSensitivity: number
+    mouseAcceleration: number
+    // null uses the layout of the host config
+    keyboardLayout: KeyboardLayout | null
     controllerConfig: ControllerConfig
     dataTransport: TransportType
     language: Language
@@ -40,7 +49,7 @@ export type StreamCodec = "h264" | "auto" | "h265" | "av1"
 export type TransportType = "auto" | "webrtc" | "websocket"
 
 import DEFAULT_SETTINGS from "../default_settings.js"
-import { StreamPermissions } from "../api_bindings.js";
+import { KeyboardLayout, StreamAudioMode, StreamPermissions } from "../api_bindings.js";
 
 /// You should use the role default settings instead!
 export function globalDefaultSettings(): Settings {
@@ -137,6 +146,12 @@ function makeSettingsValid(permissions: StreamPermissions, settings: Settings) {
     if (!Number.isFinite(settings.localCursorSensitivity) || settings.localCursorSensitivity <= 0) {
         settings.localCursorSensitivity = globalDefaultSettings().localCursorSensitivity
     }
+    if (!Number.isFinite(settings.mouseSensitivity) || settings.mouseSensitivity <= 0) {
+        settings.mouseSensitivity = globalDefaultSettings().mouseSensitivity
+    }
+    if (!Number.isFinite(settings.mouseAcceleration) || settings.mouseAcceleration < 0) {
+        settings.mouseAcceleration = globalDefaultSettings().mouseAcceleration
+    }
 }
 
 export class StreamSettingsComponent implements Component {
@@ -165,11 +180,17 @@ export class StreamSettingsComponent implements Component {
 
     private audioHeader: HTMLHeadingElement = document.createElement("h3")
     private playAudioLocal: InputComponent
+    private audioOnly: InputComponent
+    private audioMode: SelectComponent
+    private audioPlayer: SelectComponent
     private audioSampleQueueSize: InputComponent
 
     private mouseHeader: HTMLHeadingElement = document.createElement("h3")
     private mouseScrollMode: SelectComponent
     private mouseMode: SelectComponent
+    private mouseSensitivity: InputComponent
+    private mouseAcceleration: InputComponent
+    private keyboardLayout: SelectComponent
     private touchMode: SelectComponent
     private localCursorSensitivity: InputComponent
 
@@ -358,6 +379,45 @@ export class StreamSettingsComponent implements Component {
         this.playAudioLocal.addChangeListener(this.onSettingsChange.bind(this))
         this.playAudioLocal.mount(this.divElement)
 
+        // Audio only
+        this.audioOnly = new InputComponent("audioOnly", "checkbox", i.audioOnly, {
+            checked: settings?.audioOnly
+        })
+        this.audioOnly.addChangeListener(this.onSettingsChange.bind(this))
+        this.audioOnly.mount(this.divElement)
+
+        // Audio Mode
+        this.audioMode = new SelectComponent("audioMode",
+            [
+                { value: "host", name: i.audioModeHost },
+                { value: "match_host", name: i.audioModeMatchHost },
+                { value: "stereo", name: i.audioModeStereo },
+                { value: "surround_51", name: "5.1 Surround" },
+                { value: "surround_71", name: "7.1 Surround" },
+            ],
+            {
+                displayName: i.audioMode,
+                preSelectedOption: settings?.audioMode ?? "host"
+            }
+        )
+        this.audioMode.addChangeListener(this.onSettingsChange.bind(this))
+        this.audioMode.mount(this.divElement)
+
+        // Audio Player
+        this.audioPlayer = new SelectComponent("audioPlayer",
+            [
+                { value: "auto", name: i.audioPlayerAuto },
+                { value: "audio_element", name: "Audio Element" },
+                { value: "audio_context", name: "Audio Context" },
+            ],
+            {
+                displayName: i.audioPlayer,
+                preSelectedOption: settings?.audioPlayer ?? defaultSettings_.audioPlayer
+            }
+        )
+        this.audioPlayer.addChangeListener(this.onSettingsChange.bind(this))
+        this.audioPlayer.mount(this.divElement)
+
         // Audio Sample Queue Size
         this.audioSampleQueueSize = new InputComponent("audioSampleQueueSize", "number", i.audioSampleQueueSize, {
             defaultValue: defaultSettings_.audioSampleQueueSize.toString(),
@@ -398,6 +458,45 @@ export class StreamSettingsComponent implements Component {
         this.mouseMode.addChangeListener(this.onSettingsChange.bind(this))
         this.mouseMode.mount(this.divElement)
 
+        this.mouseSensitivity = new InputComponent("mouseSensitivity", "number", i.mouseSensitivity, {
+            defaultValue: defaultSettings_.mouseSensitivity.toString(),
+            value: settings?.mouseSensitivity?.toString(),
+            step: "0.1",
+            numberSlider: {
+                range_min: 0.1,
+                range_max: 3
+            }
+        })
+        this.mouseSensitivity.addChangeListener(this.onSettingsChange.bind(this))
+        this.mouseSensitivity.mount(this.divElement)
+
+        this.mouseAcceleration = new InputComponent("mouseAcceleration", "number", i.mouseAcceleration, {
+            defaultValue: defaultSettings_.mouseAcceleration.toString(),
+            value: settings?.mouseAcceleration?.toString(),
+            step: "0.1",
+            numberSlider: {
+                range_min: 0,
+                range_max: 2
+            }
+        })
+        this.mouseAcceleration.addChangeListener(this.onSettingsChange.bind(this))
+        this.mouseAcceleration.mount(this.divElement)
+
+        this.keyboardLayout = new SelectComponent("keyboardLayout",
+            [
+                { value: "host", name: i.keyboardLayoutHost },
+                { value: "qwerty", name: "QWERTY" },
+                { value: "qwertz", name: "QWERTZ" },
+                { value: "azerty", name: "AZERTY" },
+            ],
+            {
+                displayName: i.keyboardLayout,
+                preSelectedOption: settings?.keyboardLayout ?? "host"
+            }
+        )
+        this.keyboardLayout.addChangeListener(this.onSettingsChange.bind(this))
+        this.keyboardLayout.mount(this.divElement)
+
         this.touchMode = new SelectComponent("touchMode",
             [
                 { value: "touch", name: streamI.touch },
@@ -568,12 +667,20 @@ export class StreamSettingsComponent implements Component {
         settings.canvasVsync = this.canvasVsync.isChecked()
 
         settings.playAudioLocal = this.playAudioLocal.isChecked()
+        settings.audioOnly = this.audioOnly.isChecked()
+        const audioMode = this.audioMode.getValue()
+        settings.audioMode = audioMode == "host" ? null : audioMode as StreamAudioMode
+        settings.audioPlayer = this.audioPlayer.getValue() as AudioPlayerType
         settings.audioSampleQueueSize = parseInt(this.audioSampleQueueSize.getValue())
 
         settings.mouseScrollMode = this.mouseScrollMode.getValue() as any
         settings.mouseMode = this.mouseMode.getValue() as MouseMode
         settings.touchMode = this.touchMode.getValue() as TouchMode
         settings.localCursorSensitivity = parseFloat(this.localCursorSensitivity.getValue())
+        settings.mouseSensitivity = parseFloat(this.mouseSensitivity.getValue())
+        settings.mouseAcceleration = parseFloat(this.mouseAcceleration.getValue())
+        const keyboardLayout = this.keyboardLayout.getValue()
+        settings.keyboardLayout = keyboardLayout == "host" ? null : keyboardLayout as KeyboardLayout
 
         settings.controllerConfig.invertAB = this.controllerInvertAB.isChecked()
         settings.controllerConfig.invertXY = this.controllerInvertXY.isChecked()
@@ -24,6 +24,12 @@ const trueDefaultSettings: Settings =
     // Canvas only: when true, draw only on requestAnimationFrame (stable, may add ~0–17 ms). When false, draw on frame submit (low latency).
     "canvasVsync": false,
     "playAudioLocal": false,
+    // Only stream the audio of the host, saves the bandwidth of the video
+    "audioOnly": false,
+    // possible values: null (use the host config), "match_host", "stereo", "surround_51", "surround_71"
+    "audioMode": null,
+    // possible values: "auto", "audio_element", "audio_context", other players are tried if the selected one fails
+    "audioPlayer": "auto",
     "audioSampleQueueSize": 20,
     // possible values: "highres", "normal"
     "mouseScrollMode": "highres",
@@ -32,6 +38,11 @@ const trueDefaultSettings: Settings =
     // possible values: "touch", "mouseRelative", "localCursor", "pointAndDrag"
     "touchMode": "mouseRelative",
     "localCursorSensitivity": 1,
+    // scales relative mouse movement on the streamer, an acceleration of 0 disables it
+    "mouseSensitivity": 1,
+    "mouseAcceleration": 0,
+    // possible values: null (use the host config), "qwerty", "qwertz", "azerty"
+    "keyboardLayout": null,
     "controllerConfig": {
         "invertAB": false,
         "invertXY": false,
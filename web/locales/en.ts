@@ -25,6 +25,9 @@ export const en = {
         hideKeyboard: "Hide Keyboard",
         fullscreen: "Fullscreen",
         stats: "Stats",
+        volume: "Volume",
+        mute: "Mute",
+        requestIdr: "Fix Picture",
         exit: "Exit",
         mouseMode: "Mouse Mode",
         touchMode: "Touch Mode",
@@ -61,12 +64,23 @@ export const en = {
         enableHdr: "Enable HDR",
         audio: "Audio",
         playAudioLocal: "Play Audio Local",
+        audioOnly: "Audio Only (no video)",
+        audioMode: "Audio Channels",
+        audioModeHost: "Host Default",
+        audioModeMatchHost: "Match Host",
+        audioModeStereo: "Stereo",
+        audioPlayer: "Audio Player",
+        audioPlayerAuto: "Automatic",
         audioSampleQueueSize: "Audio Sample Queue Size",
         mouse: "Mouse",
         scrollMode: "Scroll Mode",
         startupMouseMode: "Mouse Mode On Stream Start",
         startupTouchMode: "Touch Mode On Stream Start",
         localCursorSensitivity: "Local Cursor Sensitivity",
+        mouseSensitivity: "Relative Mouse Sensitivity",
+        mouseAcceleration: "Relative Mouse Acceleration",
+        keyboardLayout: "Keyboard Layout",
+        keyboardLayoutHost: "Host Default",
         highRes: "High Res",
         normal: "Normal",
         controller: "Controller",
@@ -136,6 +150,7 @@ export const en = {
         sendWakeUpPacket: "Send Wake Up Packet",
         reload: "Reload",
         pair: "Pair",
+        unpair: "Unpair",
         makePrivate: "Make Private",
         makeGlobal: "Make Global",
         removeHost: "Remove Host",
@@ -27,6 +27,9 @@ export const frFr: Translations = {
         hideKeyboard: "Masquer le clavier",
         fullscreen: "Plein écran",
         stats: "Stats",
+        volume: "Volume",
+        mute: "Muet",
+        requestIdr: "Corriger l'image",
         exit: "Quitter",
         mouseMode: "Mode souris",
         touchMode: "Mode tactile",
@@ -63,12 +66,23 @@ export const frFr: Translations = {
         enableHdr: "Activer HDR",
         audio: "Audio",
         playAudioLocal: "Jouer l'auudio localement",
+        audioOnly: "Audio uniquement (sans vidéo)",
+        audioMode: "Canaux audio",
+        audioModeHost: "Par défaut de l'hôte",
+        audioModeMatchHost: "Comme l'hôte",
+        audioModeStereo: "Stéréo",
+        audioPlayer: "Lecteur audio",
+        audioPlayerAuto: "Automatique",
         audioSampleQueueSize: "Taille de la file d'attente audio",
         mouse: "Souris",
         scrollMode: "Mode de défilement",
         startupMouseMode: "Mode de la souris au démarrage",
         startupTouchMode: "Mode tactile au démarrage",
         localCursorSensitivity: "Sensibilité du curseur local",
+        mouseSensitivity: "Sensibilité de la souris relative",
+        mouseAcceleration: "Accélération de la souris relative",
+        keyboardLayout: "Disposition du clavier",
+        keyboardLayoutHost: "Par défaut de l'hôte",
         highRes: "Haute résolution",
         normal: "Normal",
         controller: "Contrôleur",
@@ -138,6 +152,7 @@ export const frFr: Translations = {
         sendWakeUpPacket: "Envoyer le signal de réveil",
         reload: "Recharger",
         pair: "Appairer",
+        unpair: "Désappairer",
         makePrivate: "Rendre prvivé",
         makeGlobal: "Rendre global",
         removeHost: "Retirer l'hôte",
@@ -27,6 +27,9 @@ export const koKR: Translations = {
         hideKeyboard: "키보드 숨기기",
         fullscreen: "전체 화면",
         stats: "통계",
+        volume: "볼륨",
+        mute: "음소거",
+        requestIdr: "화면 복구",
         exit: "종료",
         mouseMode: "마우스 모드",
         touchMode: "터치 모드",
@@ -63,12 +66,23 @@ export const koKR: Translations = {
         enableHdr: "HDR 활성화",
         audio: "오디오",
         playAudioLocal: "로컬 오디오 재생",
+        audioOnly: "오디오만 (비디오 없음)",
+        audioMode: "오디오 채널",
+        audioModeHost: "호스트 기본값",
+        audioModeMatchHost: "호스트와 동일",
+        audioModeStereo: "스테레오",
+        audioPlayer: "오디오 플레이어",
+        audioPlayerAuto: "자동",
         audioSampleQueueSize: "오디오 샘플 대기열 크기",
         mouse: "마우스",
         scrollMode: "스크롤 모드",
         startupMouseMode: "스트리밍 시작 시 마우스 모드",
         startupTouchMode: "스트리밍 시작 시 터치 모드",
         localCursorSensitivity: "로컬 커서 감도",
+        mouseSensitivity: "상대 마우스 감도",
+        mouseAcceleration: "상대 마우스 가속",
+        keyboardLayout: "키보드 레이아웃",
+        keyboardLayoutHost: "호스트 기본값",
         highRes: "고해상도",
         normal: "일반",
         controller: "컨트롤러",
@@ -138,6 +152,7 @@ export const koKR: Translations = {
         sendWakeUpPacket: "Wake on LAN 패킷 전송",
         reload: "새로고침",
         pair: "페어링",
+        unpair: "페어링 해제",
         makePrivate: "개인용으로 설정",
         makeGlobal: "공용으로 설정",
         removeHost: "호스트 제거",
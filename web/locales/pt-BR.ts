@@ -27,6 +27,9 @@ export const ptBR: Translations = {
         hideKeyboard: "Ocultar Teclado",
         fullscreen: "Tela Cheia",
         stats: "Estatísticas",
+        volume: "Volume",
+        mute: "Mudo",
+        requestIdr: "Corrigir Imagem",
         exit: "Sair",
         mouseMode: "Modo do Mouse",
         touchMode: "Modo de Toque",
@@ -63,12 +66,23 @@ export const ptBR: Translations = {
         enableHdr: "Ativar HDR",
         audio: "Áudio",
         playAudioLocal: "Reproduzir Áudio Localmente",
+        audioOnly: "Somente Áudio (sem vídeo)",
+        audioMode: "Canais de Áudio",
+        audioModeHost: "Padrão do Host",
+        audioModeMatchHost: "Igual ao Host",
+        audioModeStereo: "Estéreo",
+        audioPlayer: "Reprodutor de Áudio",
+        audioPlayerAuto: "Automático",
         audioSampleQueueSize: "Tamanho da Fila de Amostras de Áudio",
         mouse: "Mouse",
         scrollMode: "Modo de Rolagem",
         startupMouseMode: "Modo do Mouse ao Iniciar Stream",
         startupTouchMode: "Modo de Toque ao Iniciar Stream",
         localCursorSensitivity: "Sensibilidade do Cursor Local",
+        mouseSensitivity: "Sensibilidade do Mouse Relativo",
+        mouseAcceleration: "Aceleração do Mouse Relativo",
+        keyboardLayout: "Layout do Teclado",
+        keyboardLayoutHost: "Padrão do Host",
         highRes: "Alta Resolução",
         normal: "Normal",
         controller: "Controle",
@@ -138,6 +152,7 @@ export const ptBR: Translations = {
         sendWakeUpPacket: "Enviar Pacote Wake-on-LAN",
         reload: "Recarregar",
         pair: "Parear",
+        unpair: "Desparear",
         makePrivate: "Tornar Privado",
         makeGlobal: "Tornar Global",
         removeHost: "Remover Host",
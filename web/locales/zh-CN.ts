@@ -27,6 +27,9 @@ export const zhCN: Translations = {
         hideKeyboard: "隐藏键盘",
         fullscreen: "全屏",
         stats: "统计",
+        volume: "音量",
+        mute: "静音",
+        requestIdr: "修复画面",
         exit: "退出",
         mouseMode: "鼠标模式",
         touchMode: "触摸模式",
@@ -63,12 +66,23 @@ export const zhCN: Translations = {
         enableHdr: "启用 HDR",
         audio: "音频",
         playAudioLocal: "本地播放音频",
+        audioOnly: "仅音频（无视频）",
+        audioMode: "音频声道",
+        audioModeHost: "主机默认",
+        audioModeMatchHost: "跟随主机",
+        audioModeStereo: "立体声",
+        audioPlayer: "音频播放器",
+        audioPlayerAuto: "自动",
         audioSampleQueueSize: "音频采样队列大小",
         mouse: "鼠标",
         scrollMode: "滚动模式",
         startupMouseMode: "串流启动后鼠标模式",
         startupTouchMode: "串流启动后触摸模式",
         localCursorSensitivity: "本地光标灵敏度",
+        mouseSensitivity: "相对鼠标灵敏度",
+        mouseAcceleration: "相对鼠标加速",
+        keyboardLayout: "键盘布局",
+        keyboardLayoutHost: "主机默认",
         highRes: "高精度",
         normal: "普通",
         controller: "手柄",
@@ -138,6 +152,7 @@ export const zhCN: Translations = {
         sendWakeUpPacket: "发送唤醒包",
         reload: "刷新",
         pair: "配对",
+        unpair: "取消配对",
         makePrivate: "设为私有",
         makeGlobal: "设为全局",
         removeHost: "移除主机",
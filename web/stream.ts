@@ -7,7 +7,7 @@ import { getModalBackground, Modal, showMessage, showModal } from "./component/m
 import { getSidebarRoot, setSidebar, setSidebarExtended, setSidebarStyle, Sidebar } from "./component/sidebar/index.js";
 import { defaultStreamInputConfig, MouseMode, ScreenKeyboardSetVisibleEvent, StreamInputConfig } from "./stream/input.js";
 import { getLocalStreamSettings, Settings, TransportType} from "./component/settings_menu.js";
-import { SelectComponent } from "./component/input.js";
+import { InputComponent, SelectComponent } from "./component/input.js";
 import { DetailedRole, LogMessageType, StreamCapabilities, StreamKeys, StreamPermissions } from "./api_bindings.js";
 import { KeyboardModeEvent, KeyboardModeWillChangeEvent, ScreenKeyboard, TextEvent } from "./screen_keyboard.js";
 import { FormModal } from "./component/modal/form.js";
@@ -59,7 +59,7 @@ async function startApp() {
     }
 
     // Start and Mount App
-    const app = new ViewerApp(api, hostId, appId, bootstrapRole.role, parseSettingsFromQuery(queryParams))
+    const app = new ViewerApp(api, hostId, appId, bootstrapRole.role, parseSettingsFromQuery(queryParams), parseLaunchParametersFromQuery(queryParams))
     app.mount(rootElement);
 
     (window as any)["app"] = app
@@ -114,6 +114,19 @@ function parseSettingsFromQuery(queryParams: URLSearchParams): Partial<Settings>
     return settings
 }
 
+// e.g. "launch.game=portal" is sent as the launch parameter "game"
+function parseLaunchParametersFromQuery(queryParams: URLSearchParams): Record<string, string> {
+    const launchParameters: Record<string, string> = {}
+
+    for (const [key, value] of queryParams) {
+        if (key.startsWith("launch.")) {
+            launchParameters[key.slice("launch.".length)] = value
+        }
+    }
+
+    return launchParameters
+}
+
 function parseLanguageFromQuery(queryParams: URLSearchParams): Language | undefined {
     const language = queryParams.get("language")
     return language ? normalizeLanguage(language) : undefined
@@ -146,7 +159,7 @@ class ViewerApp implements Component {
     private keyboardViewportBaselineHeight: number | null = null
     private streamVideoTopOffsetPx: number = 0
 
-    constructor(api: Api, hostId: number, appId: number, bootstrapRole: DetailedRole, options?: Partial<Settings>) {
+    constructor(api: Api, hostId: number, appId: number, bootstrapRole: DetailedRole, options?: Partial<Settings>, launchParameters?: Record<string, string>) {
         this.api = api
 
         const defaultSettings = getLocalStreamSettings(bootstrapRole.default_settings)
@@ -200,7 +213,7 @@ class ViewerApp implements Component {
         this.autoEnterFullscreenOnStart = settings.enterFullscreenOnStreamStart
         this.toggleFullscreenWithKeybind = settings.toggleFullscreenWithKeybind
 
-        this.stream = new Stream(this.api, hostId, appId, settings, [browserWidth, browserHeight], bootstrapRole.permissions)
+        this.stream = new Stream(this.api, hostId, appId, settings, [browserWidth, browserHeight], bootstrapRole.permissions, launchParameters)
         this.startStream(hostId, appId, bootstrapRole.permissions, settings, [browserWidth, browserHeight])
 
         // Configure input
@@ -209,10 +222,12 @@ class ViewerApp implements Component {
 
         window.addEventListener("blur", () => {
             this.stream.getInput().raiseAllKeys()
+            this.stream.getInput().raiseAllMouseButtons()
         })
         document.addEventListener("visibilitychange", () => {
             if (document.visibilityState !== "visible") {
                 this.stream.getInput().raiseAllKeys()
+                this.stream.getInput().raiseAllMouseButtons()
             }
         })
 
@@ -411,7 +426,7 @@ class ViewerApp implements Component {
     onPaste(event: ClipboardEvent) {
         this.onUserInteraction()
 
-        this.stream.getInput().onPaste(event)
+        this.stream.onPaste(event)
 
         event.stopPropagation()
     }
@@ -698,6 +713,9 @@ class ViewerApp implements Component {
         this.checkFullyImmersed()
 
         if (!document.pointerLockElement) {
+            // Buttons held while the lock was lost would otherwise stay pressed on the host
+            this.stream.getInput().raiseAllMouseButtons()
+
             this.inputConfig.mouseMode = this.previousMouseMode
             this.setInputConfig(this.inputConfig)
         }
@@ -1017,10 +1035,13 @@ class ViewerSidebar implements Component, Sidebar {
     private fullscreenButton = document.createElement("button")
 
     private statsButton = document.createElement("button")
+    private requestIdrButton = document.createElement("button")
     private exitStreamButton = document.createElement("button")
 
     private mouseMode: SelectComponent
     private touchMode: SelectComponent
+    private volume: InputComponent
+    private mute: InputComponent
 
     constructor(app: ViewerApp) {
         this.app = app
@@ -1100,6 +1121,13 @@ class ViewerSidebar implements Component, Sidebar {
         })
         this.buttonDiv.appendChild(this.statsButton)
 
+        // Request keyframe
+        this.requestIdrButton.innerText = I.stream.requestIdr
+        this.requestIdrButton.addEventListener("click", () => {
+            this.app.getStream()?.requestIdr()
+        })
+        this.buttonDiv.appendChild(this.requestIdrButton)
+
         // Close stream
         this.exitStreamButton.innerText = I.stream.exit
         this.exitStreamButton.addEventListener("click", async () => {
@@ -1145,6 +1173,25 @@ class ViewerSidebar implements Component, Sidebar {
         })
         this.touchMode.addChangeListener(this.onTouchModeChange.bind(this))
         this.touchMode.mount(this.div)
+
+        // Volume
+        this.volume = new InputComponent("volume", "number", I.stream.volume, {
+            defaultValue: "100",
+            value: "100",
+            step: "1",
+            numberSlider: {
+                range_min: 0,
+                range_max: 100
+            }
+        })
+        this.volume.addChangeListener(this.onVolumeChange.bind(this))
+        this.volume.mount(this.div)
+
+        this.mute = new InputComponent("mute", "checkbox", I.stream.mute, {
+            checked: false
+        })
+        this.mute.addChangeListener(this.onVolumeChange.bind(this))
+        this.mute.mount(this.div)
     }
 
     onCapabilitiesChange(capabilities: StreamCapabilities) {
@@ -1188,6 +1235,16 @@ class ViewerSidebar implements Component, Sidebar {
         this.app.setInputConfig(config)
     }
 
+    // -- Volume
+    private onVolumeChange() {
+        const volume = parseInt(this.volume.getValue())
+        if (isNaN(volume)) {
+            return
+        }
+
+        this.app.getStream()?.setVolume(volume / 100, this.mute.isChecked())
+    }
+
     extended(): void {
 
     }
@@ -15,6 +15,8 @@ export class ContextDestinationNodeAudioPlayer extends AudioContextBasePipe impl
     static readonly type = "audionode"
 
     private destination: AudioNode | null = null
+    private gain: GainNode | null = null
+    private volume: number = 1
     private currentSource: AudioNode | null = null
 
     constructor(logger?: Logger) {
@@ -26,7 +28,13 @@ export class ContextDestinationNodeAudioPlayer extends AudioContextBasePipe impl
     setup(setup: AudioPlayerSetup) {
         const result = super.setup(setup)
 
-        this.destination = this.getAudioContext().destination;
+        // The source is connected to the gain so the volume can be changed
+        const audioContext = this.getAudioContext()
+        this.gain = audioContext.createGain()
+        this.gain.gain.value = this.volume
+        this.gain.connect(audioContext.destination)
+
+        this.destination = this.gain
 
         if (this.currentSource) {
             this.currentSource.connect(this.destination)
@@ -47,6 +55,14 @@ export class ContextDestinationNodeAudioPlayer extends AudioContextBasePipe impl
         }
     }
 
+    setVolume(volume: number): void {
+        this.volume = volume
+
+        if (this.gain) {
+            this.gain.gain.value = volume
+        }
+    }
+
     mount(_parent: HTMLElement): void { }
     unmount(_parent: HTMLElement): void { }
 
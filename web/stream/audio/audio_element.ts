@@ -57,6 +57,10 @@ export class AudioElementPlayer implements TrackAudioPlayer {
         this.audioElement.muted = false
     }
 
+    setVolume(volume: number): void {
+        this.audioElement.volume = volume
+    }
+
     mount(parent: HTMLElement): void {
         parent.appendChild(this.audioElement)
     }
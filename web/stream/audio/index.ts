@@ -1,6 +1,19 @@
 import { Component } from "../../component/index.js"
 import { Pipe } from "../pipeline/index.js"
 
+// The most channels the audio output of the browser can play, null if it's unknown
+export function getMaxAudioChannels(): number | null {
+    try {
+        const audioContext = new AudioContext()
+        const channels = audioContext.destination.maxChannelCount
+        audioContext.close()
+
+        return channels > 0 ? channels : null
+    } catch (e) {
+        return null
+    }
+}
+
 export type AudioPlayerSetup = {
     channels: number
     sampleRate: number
@@ -18,6 +31,9 @@ export interface AudioPlayer extends Component, Pipe {
 
     onUserInteraction(): void
 
+    // 0 is muted and 1 plays the audio as loud as the host sends it
+    setVolume(volume: number): void
+
     mount(parent: HTMLElement): void
     unmount(parent: HTMLElement): void
 }
@@ -20,7 +20,15 @@ type PipelineResult<T> = { audioPlayer: T, error: false } | { audioPlayer: null,
 
 interface AudioPlayerStatic extends PipeInfoStatic, OutputPipeStatic { }
 
+export type AudioPlayerType = "auto" | "audio_element" | "audio_context"
+
 export type AudioPipelineOptions = {
+    audioPlayer: AudioPlayerType
+}
+
+const FORCED_AUDIO_PLAYERS: Record<Exclude<AudioPlayerType, "auto">, AudioPlayerStatic> = {
+    audio_element: AudioElementPlayer,
+    audio_context: ContextDestinationNodeAudioPlayer,
 }
 
 type Pipeline = { input: string, pipes: Array<PipeStatic>, player: AudioPlayerStatic }
@@ -63,6 +71,15 @@ export async function buildAudioPipeline(type: string, settings: AudioPipelineOp
 
     let pipelines = PIPELINES
 
+    // Forced player, the other players are still tried if it's not available
+    const forcedPlayer = settings.audioPlayer && settings.audioPlayer != "auto" ? FORCED_AUDIO_PLAYERS[settings.audioPlayer] : null
+    if (forcedPlayer) {
+        logger?.debug(`Forcing audio player ${forcedPlayer.name}`)
+
+        pipelines = PIPELINES.filter(pipeline => pipeline.player == forcedPlayer)
+            .concat(PIPELINES.filter(pipeline => pipeline.player != forcedPlayer))
+    }
+
     pipelineLoop: for (const pipeline of pipelines) {
         if (pipeline.input != type) {
             continue
@@ -91,13 +108,20 @@ export async function buildAudioPipeline(type: string, settings: AudioPipelineOp
             continue pipelineLoop
         }
 
-        // Build that pipeline
-        const audioPlayer = buildPipeline(pipeline.player, { pipes: pipeline.pipes }, logger)
+        // Build that pipeline, if it fails the next one is tried
+        let audioPlayer
+        try {
+            audioPlayer = buildPipeline(pipeline.player, { pipes: pipeline.pipes }, logger)
+        } catch (err) {
+            logger?.debug(`Failed to build audio pipeline with player ${pipeline.player.name}: ${err}`)
+            continue pipelineLoop
+        }
         if (!audioPlayer) {
-            logger?.debug("Failed to build audio pipeline")
-            return { audioPlayer: null, error: true }
+            logger?.debug(`Failed to build audio pipeline with player ${pipeline.player.name}, trying the next one`)
+            continue pipelineLoop
         }
 
+        logger?.debug(`Selected audio player ${pipeline.player.name}`)
         return { audioPlayer: audioPlayer as AudioPlayer, error: false }
     }
 
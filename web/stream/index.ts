@@ -1,15 +1,15 @@
 import { Api } from "../api.js"
-import { App, ConnectionStatus, GeneralClientMessage, GeneralServerMessage, StreamCapabilities, StreamClientMessage, StreamPermissions, StreamServerMessage, StreamSettings, TransportChannelId } from "../api_bindings.js"
+import { App, ConnectionStatus, GeneralClientMessage, GeneralServerMessage, HdrMetadata, StreamCapabilities, StreamClientMessage, StreamPermissions, StreamProtocol, StreamServerMessage, StreamSettings, StreamStage, StreamStageError, StreamStageState, StreamCloseCode, TransportChannelId } from "../api_bindings.js"
 import { showNotification } from "../component/notification.js"
 import { Component } from "../component/index.js"
 import { Settings, TransportType } from "../component/settings_menu.js"
-import { AudioPlayer } from "./audio/index.js"
+import { AudioPlayer, AudioPlayerSetup, getMaxAudioChannels } from "./audio/index.js"
 import { buildAudioPipeline } from "./audio/pipeline.js"
 import { BIG_BUFFER, ByteBuffer } from "./buffer.js"
 import { defaultStreamInputConfig, StreamInput } from "./input.js"
 import { Logger, LogMessageInfo } from "./log.js"
 import { gatherPipeInfo } from "./pipeline/index.js"
-import { StreamStats } from "./stats.js"
+import { hdrMetadataToText, StreamStats } from "./stats.js"
 import { Transport, TransportShutdown } from "./transport/index.js"
 import { WebSocketTransport } from "./transport/web_socket.js"
 import { WebRTCTransport } from "./transport/webrtc.js"
@@ -28,6 +28,7 @@ export type InfoEvent = CustomEvent<
     { type: "connectionComplete", capabilities: StreamCapabilities } |
     { type: "videoReady" } |
     { type: "connectionStatus", status: ConnectionStatus } |
+    { type: "stage", stage: StreamStage, state: StreamStageState, error: StreamStageError | null } |
     { type: "addDebugLine", line: string, additional?: LogMessageInfo }
 >
 export type InfoEventListener = (event: InfoEvent) => void
@@ -89,6 +90,27 @@ function isFirefox(): boolean {
 
 const WEBRTC_CONNECT_TIMEOUT_MS = 15000
 const FALLBACK_RECONNECT_DELAY_MS = 500
+const RESUME_RECONNECT_DELAY_MS = 1000
+const RESUME_MAX_ATTEMPTS = 5
+const VIDEO_DECODE_TIMEOUT_MS = 10000
+// Close codes from 4000 are sent by the server with the reason the stream ended
+const STREAM_CLOSE_CODE_MIN = 4000
+
+function streamEndMessage(code: number): string | null {
+    switch (code) {
+        case StreamCloseCode.HOST_ENDED:
+            return "The host ended the session"
+        case StreamCloseCode.IDLE_TIMEOUT:
+            return "The stream was stopped because it was idle for too long"
+        case StreamCloseCode.CONNECTION_LOST:
+            return "Connection lost"
+        case StreamCloseCode.SERVER_SHUTDOWN:
+            return "The server is shutting down"
+        // The server already described errors and crashes, a stopped stream was requested
+        default:
+            return null
+    }
+}
 
 export class Stream implements Component {
     private logger: Logger = new Logger()
@@ -97,6 +119,7 @@ export class Stream implements Component {
 
     private hostId: number
     private appId: number
+    private launchParameters: Record<string, string>
 
     private permissions: StreamPermissions
     private settings: Settings
@@ -105,11 +128,20 @@ export class Stream implements Component {
     private eventTarget = new EventTarget()
 
     private ws: WebSocket
+    private sessionToken: string | null = null
+    private resumeAttempts = 0
     private iceServers: Array<RTCIceServer> | null = null
+    private forceRelay: boolean = false
+    private jitterBufferTargetMs: number = 0
+    private requestedStreamSettings: StreamSettings | null = null
+    // The video formats the streamer can send over WebRTC
+    private streamerVideoFormats: number | null = null
     private transportOverride: TransportType | null = null
 
     private videoRenderer: VideoRenderer | null = null
     private audioPlayer: AudioPlayer | null = null
+    private volume: number = 1
+    private muted: boolean = false
 
     private input: StreamInput
     private stats: StreamStats
@@ -118,8 +150,9 @@ export class Stream implements Component {
     private hasConnectionComplete = false
     private hasVideoReady = false
     private hasDispatchedVideoReady = false
+    private hasRequestedVideoFallback = false
 
-    constructor(api: Api, hostId: number, appId: number, settings: Settings, viewerScreenSize: [number, number], permissions: StreamPermissions) {
+    constructor(api: Api, hostId: number, appId: number, settings: Settings, viewerScreenSize: [number, number], permissions: StreamPermissions, launchParameters: Record<string, string> = {}) {
         this.logger.addInfoListener((info, type) => {
             this.debugLog(info, { type: type ?? undefined })
         })
@@ -128,6 +161,7 @@ export class Stream implements Component {
 
         this.hostId = hostId
         this.appId = appId
+        this.launchParameters = launchParameters
 
         this.permissions = permissions
         this.settings = settings
@@ -186,13 +220,50 @@ export class Stream implements Component {
         this.eventTarget.dispatchEvent(event)
     }
 
+    // Some host encoders (e.g. nvenc H265) produce video the browser can't decode,
+    // in that case the streamer restarts the stream with H264
+    private async checkVideoDecoding() {
+        if (this.hasRequestedVideoFallback || this.transport?.implementationName != "webrtc") {
+            return
+        }
+
+        const stats = await this.transport.getStats()
+        if (typeof stats.webrtcPacketsReceived != "number" || stats.webrtcPacketsReceived <= 0 || stats.webrtcFramesDecoded !== 0) {
+            return
+        }
+
+        this.hasRequestedVideoFallback = true
+        this.debugLog(`No video frame was decoded within ${VIDEO_DECODE_TIMEOUT_MS / 1000} seconds, requesting H264`)
+
+        this.sendWsMessage("VideoDecodeFailed")
+    }
+
     private async onMessage(message: StreamServerMessage) {
-        if ("DebugLog" in message) {
+        if ("VersionMismatch" in message) {
+            // Resuming would fail with the same mismatch
+            this.sessionToken = null
+
+            this.debugLog(`The server was updated (protocol version ${message.VersionMismatch.server_version}, this page uses ${StreamProtocol.VERSION}), please reload the page`, { type: "fatalDescription" })
+        } else if ("DebugLog" in message) {
             const debugLog = message.DebugLog
 
             this.debugLog(debugLog.message, {
                 type: debugLog.ty ?? undefined
             })
+        } else if ("Stage" in message) {
+            const stage = message.Stage
+
+            this.debugLog(stage.message, {
+                type: stage.ty ?? undefined
+            })
+            if (stage.error == "hostUnreachable") {
+                this.debugLog("Make sure the host is turned on and reachable from the server Moonlight Web runs on", { type: "fatalDescription" })
+            }
+
+            const event: InfoEvent = new CustomEvent("stream-info", {
+                detail: { type: "stage", stage: stage.stage, state: stage.state, error: stage.error }
+            })
+            this.eventTarget.dispatchEvent(event)
         } else if ("UpdateApp" in message) {
             const event: InfoEvent = new CustomEvent("stream-info", {
                 detail: { type: "app", app: message.UpdateApp.app }
@@ -240,18 +311,18 @@ export class Stream implements Component {
                 showNotification("Failed to find supported audio player -> audio is missing.")
             }
 
-            if (!this.videoRenderer || !this.audioPlayer) {
-                throw "Video renderer or audio player not initialized!"
+            if (!this.videoRenderer && !this.settings.audioOnly) {
+                throw "Video renderer not initialized!"
             }
 
             await Promise.all([
-                this.videoRenderer.setup({
+                this.videoRenderer?.setup({
                     codec: format,
                     fps,
                     width,
                     height,
                 }),
-                this.audioPlayer.setup({
+                this.setupAudioPlayer({
                     sampleRate: audioSampleRate,
                     channels: audioChannelCount,
                     streams: audioStreams,
@@ -262,21 +333,61 @@ export class Stream implements Component {
             ])
 
             this.markConnectionComplete()
+
+            if (this.settings.audioOnly) {
+                // There's no video that could get ready
+                this.markVideoReady()
+            } else if (format != "H264" && !this.hasRequestedVideoFallback) {
+                window.setTimeout(() => this.checkVideoDecoding(), VIDEO_DECODE_TIMEOUT_MS)
+            }
         } else if ("ConnectionTerminated" in message) {
             const code = message.ConnectionTerminated.error_code
 
+            // The host ended the stream, there's nothing to resume
+            this.sessionToken = null
+
             this.debugLog(`ConnectionTerminated with code ${code}`, { type: "fatalDescription" })
         }
+        else if ("Resumable" in message) {
+            this.sessionToken = message.Resumable.session_token
+        }
+        else if ("EffectiveStreamSettings" in message) {
+            const effective = message.EffectiveStreamSettings.settings
+            const requested = this.requestedStreamSettings
+
+            this.debugLog(`Effective stream settings: ${effective.width}x${effective.height}x${effective.fps}, ${effective.bitrate_kbps} kbps`)
+            if (requested && (
+                effective.width != requested.width
+                || effective.height != requested.height
+                || effective.fps != requested.fps
+                || effective.bitrate_kbps != requested.bitrate_kbps
+            )) {
+                this.debugLog(`The server limited the stream from ${requested.width}x${requested.height}x${requested.fps}, ${requested.bitrate_kbps} kbps to ${effective.width}x${effective.height}x${effective.fps}, ${effective.bitrate_kbps} kbps`, { type: "informError" })
+            }
+        }
+        else if ("StreamerCapabilities" in message) {
+            this.streamerVideoFormats = message.StreamerCapabilities.video_formats
+
+            this.debugLog(`Streamer supports ${message.StreamerCapabilities.audio_channels} audio channels`)
+        }
         // -- WebRTC Config
         else if ("Setup" in message) {
             const iceServers = message.Setup.ice_servers
 
             this.iceServers = iceServers
+            this.forceRelay = message.Setup.force_relay
+            this.jitterBufferTargetMs = message.Setup.jitter_buffer_target_ms
 
             this.debugLog(`window.isSecureContext: ${window.isSecureContext}`)
             this.debugLog(`Using WebRTC Ice Servers: ${createPrettyList(
                 iceServers.map(server => server.urls).reduce((list, url) => list.concat(url), [])
             )}`)
+            if (this.forceRelay) {
+                this.debugLog("Forcing WebRTC to only use relay (turn) candidates")
+            }
+            if (this.jitterBufferTargetMs > 0) {
+                this.debugLog(`Using a WebRTC jitter buffer target of ${this.jitterBufferTargetMs}ms`)
+            }
 
             await this.startConnection()
         }
@@ -316,9 +427,12 @@ export class Stream implements Component {
 
     private transport: Transport | null = null
 
-    private createControlWebSocket(): WebSocket {
+    private createControlWebSocket(sessionToken?: string): WebSocket {
         const wsApiHost = this.api.host_url.replace(/^http(s)?:/, "ws$1:")
-        const ws = new WebSocket(`${wsApiHost}/host/stream`)
+        // The signed session token resumes the stream instead of starting a new one
+        const query = sessionToken ? `?session_token=${encodeURIComponent(sessionToken)}` : ""
+        // The server checks the protocol to tell us when this page is outdated
+        const ws = new WebSocket(`${wsApiHost}/host/stream${query}`, `moonlight-web-stream.v${StreamProtocol.VERSION}`)
 
         ws.addEventListener("error", (event) => {
             if (this.ws !== ws) {
@@ -332,11 +446,11 @@ export class Stream implements Component {
             }
             this.onWsOpen()
         })
-        ws.addEventListener("close", () => {
+        ws.addEventListener("close", (event) => {
             if (this.ws !== ws) {
                 return
             }
-            this.onWsClose()
+            this.onWsClose(event)
         })
         ws.addEventListener("message", (event) => {
             if (this.ws !== ws) {
@@ -354,12 +468,42 @@ export class Stream implements Component {
                 app_id: this.appId,
                 video_frame_queue_size: this.settings.videoFrameQueueSize,
                 audio_sample_queue_size: this.settings.audioSampleQueueSize,
+                video_mode: {
+                    width: this.streamerSize[0],
+                    height: this.streamerSize[1],
+                    fps: this.settings.fps,
+                },
+                audio_mode: this.settings.audioMode ?? null,
+                audio_channels: getMaxAudioChannels(),
+                launch_parameters: Object.keys(this.launchParameters).length > 0 ? this.launchParameters : null,
             }
         })
     }
+    private async resumeStream(sessionToken: string) {
+        this.resumeAttempts += 1
+        this.debugLog(`Lost the connection to the server, trying to resume the stream (attempt ${this.resumeAttempts} of ${RESUME_MAX_ATTEMPTS})`, { type: "informError" })
+
+        await new Promise((resolve) => window.setTimeout(resolve, RESUME_RECONNECT_DELAY_MS))
+
+        // The transport most likely broke together with the connection, so negotiate a new one with the resumed streamer
+        if (this.transport) {
+            this.transport.onclose = null
+            await this.transport.close()
+            this.transport = null
+        }
+        this.resetVideoReadyState()
+        this.wsSendBuffer.length = 0
+
+        this.ws = this.createControlWebSocket(sessionToken)
+        this.sessionToken = sessionToken
+
+        await this.startConnection()
+    }
     private async restartWithFreshTransportFallback(transport: TransportType): Promise<void> {
         this.transportOverride = transport
         this.resetVideoReadyState()
+        // A fresh streamer is started, the old one stops when its web socket closes
+        this.sessionToken = null
 
         if (this.transport) {
             await this.transport.close()
@@ -455,7 +599,7 @@ export class Stream implements Component {
             if (hdrUpdate) {
                 const enabled = hdrUpdate.enabled
                 this.debugLog(`HDR mode ${enabled ? "enabled" : "disabled"}`)
-                this.setHdrMode(enabled)
+                this.setHdrMode(enabled, hdrUpdate.metadata)
             }
         } else if ("ConnectionStatusUpdate" in message) {
             const statusUpdate = message.ConnectionStatusUpdate
@@ -469,11 +613,21 @@ export class Stream implements Component {
         }
     }
 
-    private setHdrMode(enabled: boolean) {
-        this.stats.setHdrEnabled(enabled)
+    private setHdrMode(enabled: boolean, metadata: HdrMetadata | null) {
+        this.stats.setHdrEnabled(enabled, metadata)
+
+        if (enabled) {
+            if (metadata) {
+                this.debugLog(`HDR metadata: ${hdrMetadataToText(metadata)}`)
+            }
+            if (!window.matchMedia("(dynamic-range: high)").matches) {
+                this.debugLog("This display doesn't support HDR, the browser will tone map the stream to SDR and colors might look washed out", { type: "informError" })
+            }
+        }
+
         if (this.videoRenderer) {
             if ("setHdrMode" in this.videoRenderer && typeof this.videoRenderer.setHdrMode === "function") {
-                this.videoRenderer.setHdrMode(enabled)
+                this.videoRenderer.setHdrMode(enabled, metadata)
             }
         }
     }
@@ -515,11 +669,12 @@ export class Stream implements Component {
             return "failednoconnect"
         }
 
-        const transport = new WebRTCTransport(this.logger)
+        const transport = new WebRTCTransport(this.logger, this.jitterBufferTargetMs)
         transport.onsendmessage = (message) => this.sendWsMessage({ WebRtc: message })
 
         transport.initPeer({
-            iceServers: this.iceServers
+            iceServers: this.iceServers,
+            iceTransportPolicy: this.forceRelay ? "relay" : "all"
         })
         this.setTransport(transport)
 
@@ -532,7 +687,7 @@ export class Stream implements Component {
         }
 
         // Starting the stream will start negotiation
-        await this.startStream(videoCodecSupport)
+        await this.startStream(videoCodecSupport, this.streamerVideoFormats)
 
         // Wait for negotiation, but don't let a stuck ICE check block fallback forever.
         const result = await new Promise<boolean>((resolve) => {
@@ -609,7 +764,10 @@ export class Stream implements Component {
         this.logger.debug(`}`)
 
         // Create pipelines
-        const [supportedVideoCodecs] = await Promise.all([this.createVideoRenderer(), this.createAudioPlayer()])
+        const [supportedVideoCodecs] = await Promise.all([
+            this.settings.audioOnly ? this.createAudioOnlyVideoCodecs() : this.createVideoRenderer(),
+            this.createAudioPlayer()
+        ])
 
         const videoPipelineName = `${this.transport?.getChannel(TransportChannelId.HOST_VIDEO).type} (transport) -> ${this.videoRenderer?.implementationName} (renderer)`
         this.debugLog(`Using video pipeline: ${videoPipelineName}`)
@@ -622,6 +780,21 @@ export class Stream implements Component {
 
         return supportedVideoCodecs
     }
+    private async createAudioOnlyVideoCodecs(): Promise<VideoCodecSupport> {
+        this.debugLog("Audio only mode: not creating a video renderer, the streamer won't send any video")
+
+        if (this.videoRenderer) {
+            this.videoRenderer.unmount(this.divElement)
+            this.videoRenderer.cleanup()
+            this.videoRenderer = null
+        }
+
+        // The host still needs a codec to encode the video it never sends us
+        const codecs = emptyVideoCodecs()
+        codecs.H264 = true
+
+        return codecs
+    }
     private async createVideoRenderer(): Promise<VideoCodecSupport | null> {
         if (this.videoRenderer) {
             this.debugLog("Found an old video renderer -> cleaning it up")
@@ -756,19 +929,46 @@ export class Stream implements Component {
             return false
         }
 
+        this.audioPlayer.setVolume(this.muted ? 0 : this.volume)
+
         return true
     }
-    private async startStream(videoCodecSupport: VideoCodecSupport): Promise<void> {
+    // A broken audio player shouldn't take the video down with it
+    private setupAudioPlayer(setup: AudioPlayerSetup) {
+        if (!this.audioPlayer) {
+            return
+        }
+
+        try {
+            this.audioPlayer.setup(setup)
+        } catch (err) {
+            this.debugLog(`Failed to setup the audio player ${this.audioPlayer.implementationName}, continuing without audio: ${err}`, { type: "informError" })
+
+            this.audioPlayer.unmount(this.divElement)
+            this.audioPlayer.cleanup()
+            this.audioPlayer = null
+        }
+    }
+    private async startStream(videoCodecSupport: VideoCodecSupport, streamerVideoFormats: number | null = null): Promise<void> {
+        let supportedCodecs = createSupportedVideoFormatsBits(videoCodecSupport)
+        if (streamerVideoFormats != null) {
+            // Only request formats the streamer can actually send
+            supportedCodecs = (supportedCodecs & streamerVideoFormats) >>> 0
+        }
+
         const settings: StreamSettings = {
             bitrate_kbps: this.settings.bitrate,
             fps: this.settings.fps,
             width: this.streamerSize[0],
             height: this.streamerSize[1],
             play_audio_local: this.settings.playAudioLocal,
-            supported_codecs: createSupportedVideoFormatsBits(videoCodecSupport),
+            supported_codecs: supportedCodecs,
             hdr: this.settings.hdr ?? false,
+            audio_only: this.settings.audioOnly ?? false,
         }
 
+        this.requestedStreamSettings = settings
+
         const message: StreamClientMessage = {
             StartStream: {
                 settings
@@ -788,6 +988,18 @@ export class Stream implements Component {
         }
 
         this.sendWsMessage(message)
+
+        this.sendWsMessage({
+            SetMouseConfig: {
+                sensitivity: this.settings.mouseSensitivity,
+                acceleration: this.settings.mouseAcceleration,
+            }
+        })
+        if (this.settings.keyboardLayout) {
+            this.sendWsMessage({
+                SetKeyboardLayout: this.settings.keyboardLayout
+            })
+        }
     }
 
     mount(parent: HTMLElement): void {
@@ -804,18 +1016,49 @@ export class Stream implements Component {
         return this.audioPlayer
     }
 
+    // The volume is applied in the browser, the host keeps its own volume
+    setVolume(volume: number, muted: boolean) {
+        this.volume = Math.min(Math.max(volume, 0), 1)
+        this.muted = muted
+
+        this.audioPlayer?.setVolume(this.muted ? 0 : this.volume)
+    }
+
     // -- Raw Web Socket stuff
     private wsSendBuffer: Array<string> = []
 
     private onWsOpen() {
         this.debugLog(`Web Socket Open`)
+        this.resumeAttempts = 0
 
         for (const raw of this.wsSendBuffer.splice(0)) {
             this.ws.send(raw)
         }
     }
-    private onWsClose() {
+    private onWsClose(event: CloseEvent) {
         this.debugLog(`Web Socket Closed`)
+
+        // The server sends why the stream ended, there's nothing to resume
+        if (event.code >= STREAM_CLOSE_CODE_MIN) {
+            this.sessionToken = null
+
+            const message = streamEndMessage(event.code)
+            if (message) {
+                this.debugLog(message, { type: "fatalDescription" })
+            } else if (event.reason) {
+                this.debugLog(`Stream ended: ${event.reason}`)
+            }
+            return
+        }
+
+        // A clean close means the server or we ended the stream, otherwise the connection dropped
+        const sessionToken = this.sessionToken
+        if (!event.wasClean && sessionToken && this.resumeAttempts < RESUME_MAX_ATTEMPTS) {
+            this.sessionToken = null
+            this.resumeStream(sessionToken)
+        } else if (!event.wasClean) {
+            this.debugLog("Connection lost", { type: "fatalDescription" })
+        }
     }
     private onError(event: Event) {
         this.debugLog(`Web Socket or WebRtcPeer Error`)
@@ -840,7 +1083,28 @@ export class Stream implements Component {
         }
     }
 
+    onPaste(event: ClipboardEvent) {
+        // Only text can be pasted into the host
+        const text = event.clipboardData?.getData("text/plain")
+        if (!text) {
+            return
+        }
+
+        // Before sending text raise all keys
+        this.input.raiseAllKeys()
+
+        this.sendWsMessage({ Clipboard: text })
+    }
+
+    requestIdr() {
+        this.debugLog("Requesting a keyframe")
+
+        this.sendWsMessage("RequestIdr")
+    }
+
     stop(): Promise<boolean> {
+        this.sessionToken = null
+
         if (!this.sendGeneralMessage("Stop")) {
             return Promise.resolve(false)
         }
@@ -91,6 +91,8 @@ export class StreamInput {
     private controllers: DataTransportChannel | null = null
     private controllerInputs: Array<DataTransportChannel | null> = []
 
+    private pressedMouseButtons: Set<number> = new Set()
+
     private touchSupported: boolean | null = null
     private localCursorPosition: [number, number] | null = null
 
@@ -177,26 +179,6 @@ export class StreamInput {
         this.sendKeyEvent(false, event)
     }
 
-    onPaste(event: ClipboardEvent) {
-
-        const data = event.clipboardData
-        if (!data) {
-            return
-        }
-
-        console.debug("PASTE", data)
-
-        const text = data.getData("text/plain")
-        if (text) {
-            console.debug("PASTE TEXT", text)
-
-            // Before sending text raise all keys
-            this.raiseAllKeys()
-
-            this.sendText(text)
-        }
-    }
-
     private sendKeyEvent(isDown: boolean, event: KeyboardEvent) {
         const key = convertToKey(event)
         if (key == null) {
@@ -406,8 +388,21 @@ export class StreamInput {
         this.clampLocalCursorPosition()
         this.sendLocalCursorPosition(reliable)
     }
+    raiseAllMouseButtons() {
+        for (const button of this.pressedMouseButtons) {
+            this.sendMouseButton(false, button)
+        }
+    }
+
     // Note: button = StreamMouseButton.
     sendMouseButton(isDown: boolean, button: number) {
+        // Remember what's held so it can be released when the pointer lock or focus is lost
+        if (isDown) {
+            this.pressedMouseButtons.add(button)
+        } else {
+            this.pressedMouseButtons.delete(button)
+        }
+
         this.buffer.reset()
 
         this.buffer.putU8(2)
@@ -23,6 +23,7 @@ export function addPipePassthrough(pipe: Pipe, overwrite?: Array<string>) {
     passthrough("pollRequestIdr", false)
     passthrough("getStreamRect", false)
     passthrough("onUserInteraction", false)
+    passthrough("setVolume", false)
     passthrough("mount", false)
     passthrough("unmount", false)
     passthrough("reportStats", false)
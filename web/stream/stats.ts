@@ -1,4 +1,4 @@
-import { StreamerStatsUpdate, TransportChannelId } from "../api_bindings.js"
+import { HdrMetadata, StreamerStatsUpdate, TransportChannelId } from "../api_bindings.js"
 import { BIG_BUFFER, ByteBuffer } from "./buffer.js"
 import { Logger } from "./log.js"
 import { Pipe } from "./pipeline/index.js"
@@ -14,6 +14,7 @@ export type StreamStatsData = {
     videoPipeline: string | null
     audioPipeline: string | null
     hdrEnabled: boolean | null
+    hdrMetadata: HdrMetadata | null
     streamerRttMs: number | null
     streamerRttVarianceMs: number | null
     minHostProcessingLatencyMs: number | null
@@ -22,7 +23,18 @@ export type StreamStatsData = {
     minStreamerProcessingTimeMs: number | null
     maxStreamerProcessingTimeMs: number | null
     avgStreamerProcessingTimeMs: number | null
+    streamerReceivedFrames: number | null
+    streamerFailedFrames: number | null
+    streamerReceivedAudioSamples: number | null
+    streamerFailedAudioSamples: number | null
     browserRtt: number | null
+    requestedBitrateKbps: number | null
+    streamedBitrateKbps: number | null
+    estimatedBitrateKbps: number | null
+    webrtcSentKbps: number | null
+    webrtcReceivedKbps: number | null
+    webrtcPacketLossPercent: number | null
+    webrtcCandidatePair: string | null
     transport: Record<string, StatValue>
     video: Record<string, StatValue>
     audio: Record<string, StatValue>
@@ -36,16 +48,29 @@ function num(value: number | null | undefined, suffix?: string): string | null {
     }
 }
 
+export function hdrMetadataToText(metadata: HdrMetadata): string {
+    // Chromaticity coordinates are in units of 0.00002
+    const coord = ([x, y]: Array<number>) => `(${(x * 0.00002).toFixed(4)}, ${(y * 0.00002).toFixed(4)})`
+    const [red, green, blue] = metadata.display_primaries
+
+    return `primaries r/g/b: ${coord(red)} / ${coord(green)} / ${coord(blue)}, white point: ${coord(metadata.white_point)}, display luminance min/max: ${(metadata.min_display_luminance * 0.0001).toFixed(4)} / ${metadata.max_display_luminance} nits, MaxCLL: ${metadata.max_content_light_level} nits, MaxFALL: ${metadata.max_frame_average_light_level} nits`
+}
+
 export function streamStatsToText(statsData: StreamStatsData): string {
     let text = `stats:
 video information: ${statsData.videoCodec}, ${statsData.videoWidth}x${statsData.videoHeight}, ${statsData.videoFps} fps
-HDR: ${statsData.hdrEnabled === true ? "Enabled" : statsData.hdrEnabled === false ? "Disabled" : "Unknown"}
+HDR: ${statsData.hdrEnabled === true ? "Enabled" : statsData.hdrEnabled === false ? "Disabled" : "Unknown"}${statsData.hdrMetadata ? ` (${hdrMetadataToText(statsData.hdrMetadata)})` : ""}
 video pipeline: ${statsData.videoPipeline}
 audio pipeline: ${statsData.audioPipeline}
 streamer round trip time: ${num(statsData.streamerRttMs, "ms")} (variance: ${num(statsData.streamerRttVarianceMs, "ms")})
 host processing latency min/max/avg: ${num(statsData.minHostProcessingLatencyMs, "ms")} / ${num(statsData.maxHostProcessingLatencyMs, "ms")} / ${num(statsData.avgHostProcessingLatencyMs, "ms")}
 streamer processing latency min/max/avg: ${num(statsData.minStreamerProcessingTimeMs, "ms")} / ${num(statsData.maxStreamerProcessingTimeMs, "ms")} / ${num(statsData.avgStreamerProcessingTimeMs, "ms")}
-streamer to browser rtt (ws only): ${num(statsData.browserRtt, "ms")}
+streamer video frames received/failed (per second): ${statsData.streamerReceivedFrames} / ${statsData.streamerFailedFrames}
+streamer audio samples received/failed (per second): ${statsData.streamerReceivedAudioSamples} / ${statsData.streamerFailedAudioSamples}
+streamer to browser rtt (network): ${num(statsData.browserRtt, "ms")}
+bitrate requested/streamed/estimated: ${statsData.requestedBitrateKbps} kbps / ${num(statsData.streamedBitrateKbps, " kbps")} / ${statsData.estimatedBitrateKbps != null ? `${statsData.estimatedBitrateKbps} kbps` : null}
+streamer webrtc sent/received: ${num(statsData.webrtcSentKbps, " kbps")} / ${num(statsData.webrtcReceivedKbps, " kbps")}, packet loss: ${num(statsData.webrtcPacketLossPercent, "%")}
+streamer webrtc candidate pair (streamer -> browser): ${statsData.webrtcCandidatePair}
 `
     for (const key in statsData.transport) {
         const value = statsData.transport[key]
@@ -102,6 +127,7 @@ export class StreamStats {
         videoPipeline: null,
         audioPipeline: null,
         hdrEnabled: null,
+        hdrMetadata: null,
         streamerRttMs: null,
         streamerRttVarianceMs: null,
         minHostProcessingLatencyMs: null,
@@ -110,7 +136,18 @@ export class StreamStats {
         minStreamerProcessingTimeMs: null,
         maxStreamerProcessingTimeMs: null,
         avgStreamerProcessingTimeMs: null,
+        streamerReceivedFrames: null,
+        streamerFailedFrames: null,
+        streamerReceivedAudioSamples: null,
+        streamerFailedAudioSamples: null,
         browserRtt: null,
+        requestedBitrateKbps: null,
+        streamedBitrateKbps: null,
+        estimatedBitrateKbps: null,
+        webrtcSentKbps: null,
+        webrtcReceivedKbps: null,
+        webrtcPacketLossPercent: null,
+        webrtcCandidatePair: null,
         transport: {},
         video: {},
         audio: {}
@@ -197,8 +234,23 @@ export class StreamStats {
             this.statsData.minStreamerProcessingTimeMs = msg.Video.min_streamer_processing_time_ms
             this.statsData.maxStreamerProcessingTimeMs = msg.Video.max_streamer_processing_time_ms
             this.statsData.avgStreamerProcessingTimeMs = msg.Video.avg_streamer_processing_time_ms
+
+            this.statsData.streamerReceivedFrames = msg.Video.received_frames
+            this.statsData.streamerFailedFrames = msg.Video.failed_frames
+        } else if ("Audio" in msg) {
+            this.statsData.streamerReceivedAudioSamples = msg.Audio.received_samples
+            this.statsData.streamerFailedAudioSamples = msg.Audio.failed_samples
         } else if ("BrowserRtt" in msg) {
             this.statsData.browserRtt = msg.BrowserRtt.rtt_ms
+        } else if ("Bitrate" in msg) {
+            this.statsData.requestedBitrateKbps = msg.Bitrate.requested_kbps
+            this.statsData.streamedBitrateKbps = msg.Bitrate.streamed_kbps
+            this.statsData.estimatedBitrateKbps = msg.Bitrate.estimated_kbps
+        } else if ("WebRtc" in msg) {
+            this.statsData.webrtcSentKbps = msg.WebRtc.sent_kbps
+            this.statsData.webrtcReceivedKbps = msg.WebRtc.received_kbps
+            this.statsData.webrtcPacketLossPercent = msg.WebRtc.packet_loss_percent
+            this.statsData.webrtcCandidatePair = msg.WebRtc.candidate_pair
         }
     }
 
@@ -255,8 +307,9 @@ export class StreamStats {
         this.statsData.audioPipeline = name
         this.audioPipe = pipe
     }
-    setHdrEnabled(enabled: boolean) {
+    setHdrEnabled(enabled: boolean, metadata: HdrMetadata | null) {
         this.statsData.hdrEnabled = enabled
+        this.statsData.hdrMetadata = enabled ? metadata : null
     }
 
     getCurrentStats(): StreamStatsData {
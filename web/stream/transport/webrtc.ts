@@ -11,8 +11,12 @@ export class WebRTCTransport implements Transport {
 
     private peer: RTCPeerConnection | null = null
 
-    constructor(logger?: Logger) {
+    /// How long the browser buffers received frames to smooth out jitter, 0 plays them as soon as possible
+    private jitterBufferTargetMs: number
+
+    constructor(logger?: Logger, jitterBufferTargetMs?: number) {
         this.logger = logger ?? null
+        this.jitterBufferTargetMs = jitterBufferTargetMs ?? 0
     }
 
     async initPeer(configuration?: RTCConfiguration) {
@@ -263,9 +267,10 @@ export class WebRTCTransport implements Transport {
             this.videoReceiver = receiver
         }
 
-        receiver.jitterBufferTarget = 0
+        receiver.jitterBufferTarget = this.jitterBufferTargetMs
         if ("playoutDelayHint" in receiver) {
-            receiver.playoutDelayHint = 0
+            // The playout delay hint is in seconds
+            receiver.playoutDelayHint = this.jitterBufferTargetMs / 1000
         }
 
         this.logger?.debug(`Adding receiver: ${track.kind}, ${track.id}, ${track.label}`)
@@ -415,8 +420,15 @@ export class WebRTCTransport implements Transport {
             if ("jitter" in value && value.jitter != null) {
                 statsData.webrtcJitterMs = value.jitter
             }
+            if ("framesDecoded" in value && value.framesDecoded != null) {
+                statsData.webrtcFramesDecoded = value.framesDecoded
+            }
             if ("totalDecodeTime" in value && value.totalDecodeTime != null) {
                 statsData.webrtcTotalDecodeTimeMs = value.totalDecodeTime
+
+                if ("framesDecoded" in value && value.framesDecoded > 0) {
+                    statsData.webrtcAvgDecodeTimeMs = value.totalDecodeTime / value.framesDecoded * 1000
+                }
             }
             if ("totalAssemblyTime" in value && value.totalAssemblyTime != null) {
                 statsData.webrtcTotalAssemblyTimeMs = value.totalAssemblyTime
@@ -1,5 +1,5 @@
 import { Component } from "../../component/index.js"
-import { StreamSupportedVideoCodecs } from "../../api_bindings.js"
+import { HdrMetadata, StreamSupportedVideoCodecs } from "../../api_bindings.js"
 import { Pipe } from "../pipeline/index.js"
 
 export type VideoRendererSetup = {
@@ -29,8 +29,8 @@ export interface VideoRenderer extends Component, Pipe {
     /// Don't work inside a worker
     unmount(parent: HTMLElement): void
 
-    /// Optional: Set HDR mode (enabled/disabled)
-    setHdrMode?(enabled: boolean): void
+    /// Optional: Set HDR mode (enabled/disabled) and the static HDR metadata of the host, if it sent any
+    setHdrMode?(enabled: boolean, metadata: HdrMetadata | null): void
 }
 
 export function getStreamRectCorrected(boundingRect: DOMRect, videoSize: [number, number]): DOMRect {